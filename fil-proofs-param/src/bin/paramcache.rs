@@ -8,7 +8,10 @@ use filecoin_proofs::{
         DefaultPieceHasher, POREP_PARTITIONS, PUBLISHED_SECTOR_SIZES, WINDOW_POST_CHALLENGE_COUNT,
         WINDOW_POST_SECTOR_COUNT, WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
     },
-    parameters::{public_params, window_post_public_params, winning_post_public_params},
+    parameters::{
+        empty_sector_update_public_params, public_params, window_post_public_params,
+        winning_post_public_params,
+    },
     types::{PaddedBytesAmount, PoRepConfig, PoRepProofPartitions, PoStConfig, SectorSize},
     with_shape, PoStType,
 };
@@ -20,7 +23,10 @@ use storage_proofs_core::{
     api_version::ApiVersion, compound_proof::CompoundProof, merkle::MerkleTreeTrait,
     parameter_cache::CacheableParameters,
 };
-use storage_proofs_porep::stacked::{StackedCircuit, StackedCompound, StackedDrg};
+use storage_proofs_porep::{
+    stacked::{StackedCircuit, StackedCompound, StackedDrg},
+    update::{EmptySectorUpdate, EmptySectorUpdateCircuit, EmptySectorUpdateCompound},
+};
 use storage_proofs_post::fallback::{FallbackPoSt, FallbackPoStCircuit, FallbackPoStCompound};
 use structopt::StructOpt;
 
@@ -32,6 +38,8 @@ fn cache_porep_params<Tree: 'static + MerkleTreeTrait>(porep_config: PoRepConfig
         usize::from(PoRepProofPartitions::from(porep_config)),
         porep_config.porep_id,
         porep_config.api_version,
+        porep_config.non_interactive,
+        porep_config.synthetic,
     )
     .expect("failed to get public params from config");
 
@@ -61,6 +69,39 @@ fn cache_porep_params<Tree: 'static + MerkleTreeTrait>(porep_config: PoRepConfig
     .expect("failed to get verifying key");
 }
 
+fn cache_empty_sector_update_params<Tree: 'static + MerkleTreeTrait>(porep_config: PoRepConfig) {
+    info!("generating empty sector update groth params");
+
+    let public_params =
+        empty_sector_update_public_params::<Tree>(PaddedBytesAmount::from(porep_config))
+            .expect("failed to get public params from config");
+
+    let circuit = <EmptySectorUpdateCompound<Tree, DefaultPieceHasher> as CompoundProof<
+        EmptySectorUpdate<Tree, DefaultPieceHasher>,
+        EmptySectorUpdateCircuit<Tree, DefaultPieceHasher>,
+    >>::blank_circuit(&public_params);
+
+    let _ = EmptySectorUpdateCompound::<Tree, DefaultPieceHasher>::get_param_metadata(
+        circuit.clone(),
+        &public_params,
+    )
+    .expect("failed to get metadata");
+
+    let _ = EmptySectorUpdateCompound::<Tree, DefaultPieceHasher>::get_groth_params(
+        Some(&mut OsRng),
+        circuit.clone(),
+        &public_params,
+    )
+    .expect("failed to get groth params");
+
+    let _ = EmptySectorUpdateCompound::<Tree, DefaultPieceHasher>::get_verifying_key(
+        Some(&mut OsRng),
+        circuit,
+        &public_params,
+    )
+    .expect("failed to get verifying key");
+}
+
 fn cache_winning_post_params<Tree: 'static + MerkleTreeTrait>(post_config: &PoStConfig) {
     info!("generating Winning-PoSt groth params");
 
@@ -116,11 +157,16 @@ fn cache_window_post_params<Tree: 'static + MerkleTreeTrait>(post_config: &PoStC
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "paramcache",
-    about = "generates and caches SDR PoRep, Winning-PoSt, and Window-PoSt groth params"
+    about = "generates and caches SDR PoRep, empty sector update, Winning-PoSt, and Window-PoSt groth params"
 )]
 struct Opt {
     #[structopt(long, help = "Only cache PoSt groth params.")]
     only_post: bool,
+    #[structopt(
+        long,
+        help = "Cache the non-interactive instead of the interactive PoRep groth params."
+    )]
+    non_interactive: bool,
     #[structopt(
         short = "z",
         long,
@@ -169,10 +215,20 @@ fn generate_params_post(sector_size: u64, api_version: ApiVersion) {
     );
 }
 
-fn generate_params_porep(sector_size: u64, api_version: ApiVersion) {
+fn generate_params_porep(sector_size: u64, api_version: ApiVersion, non_interactive: bool) {
+    let porep_config = if non_interactive {
+        PoRepConfig::new_non_interactive(sector_size, [0; 32], api_version)
+    } else {
+        PoRepConfig::new_groth16(sector_size, [0; 32], api_version)
+    };
+
+    with_shape!(sector_size, cache_porep_params, porep_config);
+}
+
+fn generate_params_empty_sector_update(sector_size: u64, api_version: ApiVersion) {
     with_shape!(
         sector_size,
-        cache_porep_params,
+        cache_empty_sector_update_params,
         PoRepConfig {
             sector_size: SectorSize(sector_size),
             partitions: PoRepProofPartitions(
@@ -184,6 +240,8 @@ fn generate_params_porep(sector_size: u64, api_version: ApiVersion) {
             ),
             porep_id: [0; 32],
             api_version,
+            non_interactive: false,
+            synthetic: false,
         }
     );
 }
@@ -256,7 +314,8 @@ pub fn main() {
         generate_params_post(sector_size, api_version);
 
         if !opts.only_post {
-            generate_params_porep(sector_size, api_version);
+            generate_params_porep(sector_size, api_version, opts.non_interactive);
+            generate_params_empty_sector_update(sector_size, api_version);
         }
 
         spinner.finish_with_message(&format!("✔ {}", &message));
@@ -317,6 +317,8 @@ fn generate_params(i: &ProdbenchInputs) {
         partitions,
         porep_id: dummy_porep_id,
         api_version: i.api_version(),
+        non_interactive: false,
+        synthetic: false,
     });
 }
 
@@ -326,6 +328,8 @@ fn cache_porep_params(porep_config: PoRepConfig) {
         usize::from(PoRepProofPartitions::from(porep_config)),
         porep_config.porep_id,
         porep_config.api_version,
+        porep_config.non_interactive,
+        porep_config.synthetic,
     )
     .expect("failed to get public_params");
 
@@ -96,6 +96,8 @@ fn get_porep_config(sector_size: u64, api_version: ApiVersion) -> PoRepConfig {
         ),
         porep_id: arbitrary_porep_id,
         api_version,
+        non_interactive: false,
+        synthetic: false,
     }
 }
 
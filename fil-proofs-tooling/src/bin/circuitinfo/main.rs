@@ -43,6 +43,8 @@ fn get_porep_info<Tree: 'static + MerkleTreeTrait>(porep_config: PoRepConfig) ->
         usize::from(PoRepProofPartitions::from(porep_config)),
         porep_config.porep_id,
         porep_config.api_version,
+        porep_config.non_interactive,
+        porep_config.synthetic,
     )
     .expect("failed to get public params from config");
 
@@ -147,6 +149,8 @@ fn porep_info(sector_size: u64, api_version: ApiVersion) -> (CircuitInfo, usize)
             partitions,
             porep_id: [0; 32],
             api_version,
+            non_interactive: false,
+            synthetic: false,
         }
     );
     (info, partitions.into())
@@ -0,0 +1,250 @@
+use std::collections::HashSet;
+
+use anyhow::{ensure, Result};
+use clap::{values_t, App, Arg, ArgGroup, SubCommand};
+use filecoin_proofs::{
+    gc_parent_caches, parent_cache_path, repair_parent_cache, PoRepConfig, SECTOR_SIZE_2_KIB,
+    SECTOR_SIZE_32_GIB, SECTOR_SIZE_512_MIB, SECTOR_SIZE_64_GIB, SECTOR_SIZE_8_MIB,
+};
+use storage_proofs_core::api_version::ApiVersion;
+use storage_proofs_porep::stacked::{list_parent_caches, parent_cache_dir, verify_parent_cache};
+
+/// Returns the porep configs of the registered seal proofs, whose parent caches are kept.
+///
+/// Each registered proof is included with its interactive, non-interactive and synthetic
+/// variants. Parent caches which none of them can be attributed to are kept by
+/// `gc_parent_caches` as well.
+///
+/// NOTE: As in gen_graph_cache, the porep_ids are tied to the versioned values provided in
+/// filecoin-proofs-api:src/registry [porep_id()], which start with the registered proof id.
+fn registered_porep_configs() -> Vec<PoRepConfig> {
+    let sector_sizes = [
+        SECTOR_SIZE_2_KIB,
+        SECTOR_SIZE_8_MIB,
+        SECTOR_SIZE_512_MIB,
+        SECTOR_SIZE_32_GIB,
+        SECTOR_SIZE_64_GIB,
+    ];
+
+    [ApiVersion::V1_0_0, ApiVersion::V1_1_0]
+        .iter()
+        .flat_map(|api_version| {
+            sector_sizes
+                .iter()
+                .map(move |sector_size| (*sector_size, *api_version))
+        })
+        .enumerate()
+        .flat_map(|(registered_proof_id, (sector_size, api_version))| {
+            let mut porep_id = [0u8; 32];
+            porep_id[0] = registered_proof_id as u8;
+
+            vec![
+                PoRepConfig::new_groth16(sector_size, porep_id, api_version),
+                PoRepConfig::new_non_interactive(sector_size, porep_id, api_version),
+                PoRepConfig::new_synthetic(sector_size, porep_id, api_version),
+            ]
+        })
+        .collect()
+}
+
+/// Returns the registered porep configs of the given sector sizes, or all of them if no sector
+/// size is given.
+fn porep_configs_for(sector_sizes: &[u64]) -> Result<Vec<PoRepConfig>> {
+    let porep_configs = registered_porep_configs();
+    for sector_size in sector_sizes {
+        ensure!(
+            porep_configs
+                .iter()
+                .any(|porep_config| u64::from(porep_config.sector_size) == *sector_size),
+            "Unsupported sector size {}",
+            sector_size
+        );
+    }
+
+    Ok(porep_configs
+        .into_iter()
+        .filter(|porep_config| {
+            sector_sizes.is_empty() || sector_sizes.contains(&u64::from(porep_config.sector_size))
+        })
+        .collect())
+}
+
+fn run_list(json: bool) -> Result<()> {
+    let caches = list_parent_caches()?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&caches)?);
+    } else {
+        println!("Parent caches in {:?}:", parent_cache_dir());
+        for cache in &caches {
+            let sector_size = cache
+                .sector_size
+                .map(|sector_size| sector_size.to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            println!(
+                "{:?} ({} bytes, sector size {})",
+                cache.path, cache.len, sector_size
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn run_verify(json: bool) -> Result<()> {
+    let mut failures = 0;
+    let mut statuses = Vec::new();
+    for cache in list_parent_caches()? {
+        let status = verify_parent_cache(&cache.path)?;
+        if !status.is_usable() {
+            failures += 1;
+        }
+        if !json {
+            println!("{:?} {}", cache.path, status);
+        }
+        statuses.push((cache, status));
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&statuses)?);
+    }
+
+    ensure!(failures == 0, "{} parent cache(s) are invalid", failures);
+    println!("All parent caches are valid");
+
+    Ok(())
+}
+
+fn run_repair(sector_sizes: &[u64]) -> Result<()> {
+    // The variants of a registered proof share its graph, so each cache is only checked once.
+    let mut repaired = HashSet::new();
+    for porep_config in porep_configs_for(sector_sizes)? {
+        if !repaired.insert(parent_cache_path(porep_config)?) {
+            continue;
+        }
+
+        let status = repair_parent_cache(porep_config)?;
+        let action = if status.is_usable() {
+            "kept"
+        } else {
+            "regenerated"
+        };
+        println!(
+            "sector size {}, api version {}: {} ({})",
+            u64::from(porep_config.sector_size),
+            porep_config.api_version,
+            action,
+            status
+        );
+    }
+
+    Ok(())
+}
+
+/// Removes the parent caches which are not used by the registered seal proofs of `keep_sizes`,
+/// or of any sector size if `keep_sizes` is empty. Caches which are not part of the parent cache
+/// manifest are never removed.
+fn run_gc(keep_sizes: &[u64], dry_run: bool) -> Result<()> {
+    let porep_configs = porep_configs_for(keep_sizes)?;
+    let removed = gc_parent_caches(&porep_configs, dry_run)?;
+
+    let action = if dry_run { "Would remove" } else { "Removed" };
+    for cache in &removed {
+        println!("{} {:?} ({} bytes)", action, cache.path, cache.len);
+    }
+    println!(
+        "{} {} parent cache(s), {} bytes",
+        action,
+        removed.len(),
+        removed.iter().map(|cache| cache.len).sum::<u64>()
+    );
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    fil_logger::init();
+
+    let size_arg = Arg::with_name("size")
+        .long("size")
+        .help("The sector size in bytes, can be given multiple times")
+        .multiple(true)
+        .number_of_values(1)
+        .takes_value(true);
+    let json_arg = Arg::with_name("json")
+        .long("json")
+        .help("Print the result as JSON");
+
+    let list_cmd = SubCommand::with_name("list")
+        .about("List the parent caches in the parent cache directory")
+        .arg(json_arg.clone());
+
+    let verify_cmd = SubCommand::with_name("verify")
+        .about("Verify all parent caches against the parent cache manifest")
+        .arg(json_arg);
+
+    let repair_cmd = SubCommand::with_name("repair")
+        .about("Regenerate the missing or invalid parent caches of the given sector sizes")
+        .arg(size_arg.required(true));
+
+    let gc_cmd = SubCommand::with_name("gc")
+        .about(
+            "Remove the parent caches which are not used by the registered seal proofs, either \
+             of all sector sizes (--all) or only of the sector sizes to keep (--keep-size). \
+             Caches which are not part of the parent cache manifest are kept",
+        )
+        .arg(
+            Arg::with_name("all")
+                .long("all")
+                .help("Keep the parent caches of all registered sector sizes"),
+        )
+        .arg(
+            Arg::with_name("keep-size")
+                .long("keep-size")
+                .help(
+                    "The sector size in bytes whose parent caches are kept, can be given \
+                     multiple times. The parent caches of all other sector sizes are removed",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .group(
+            ArgGroup::with_name("keep")
+                .args(&["all", "keep-size"])
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Only print the parent caches which would be removed"),
+        );
+
+    let matches = App::new("parent_cache")
+        .version("0.1")
+        .subcommand(list_cmd)
+        .subcommand(verify_cmd)
+        .subcommand(repair_cmd)
+        .subcommand(gc_cmd)
+        .get_matches();
+
+    match matches.subcommand() {
+        ("list", Some(m)) => run_list(m.is_present("json"))?,
+        ("verify", Some(m)) => run_verify(m.is_present("json"))?,
+        ("repair", Some(m)) => {
+            let sector_sizes = values_t!(m, "size", u64)?;
+            run_repair(&sector_sizes)?;
+        }
+        ("gc", Some(m)) => {
+            let keep_sizes = if m.is_present("all") {
+                Vec::new()
+            } else {
+                values_t!(m, "keep-size", u64)?
+            };
+            run_gc(&keep_sizes, m.is_present("dry-run"))?;
+        }
+        _ => panic!("Unrecognized subcommand"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Result};
+use clap::{value_t, App, Arg, SubCommand};
+use filecoin_proofs::{verify_sector_cache, with_shape, MerkleTreeTrait, SectorCacheReport};
+
+fn verify_cache<Tree: 'static + MerkleTreeTrait>(
+    cache: &Path,
+    replica_path: &Path,
+) -> Result<SectorCacheReport> {
+    verify_sector_cache::<_, _, Tree>(cache, replica_path)
+}
+
+fn run_verify(sector_size: usize, cache: &Path, replica_path: &Path, json: bool) -> Result<()> {
+    let report = with_shape!(sector_size as u64, verify_cache, cache, replica_path)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for artifact in &report.artifacts {
+            println!(
+                "{:<16} {} ({:?})",
+                artifact.name, artifact.status, artifact.path
+            );
+        }
+        println!();
+    }
+
+    ensure!(
+        report.is_valid(),
+        "{} artifact(s) of the sector cache are invalid",
+        report.failures().count()
+    );
+    println!("Sector cache is valid");
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    fil_logger::init();
+
+    let verify_cmd = SubCommand::with_name("verify")
+        .about("Verify all artifacts in a sector cache directory")
+        .arg(
+            Arg::with_name("size")
+                .required(true)
+                .long("size")
+                .default_value("34359738368")
+                .help("The data size in bytes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("replica")
+                .long("replica")
+                .help("The replica file")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cache")
+                .long("cache")
+                .help("The cache directory of the sector")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Print the report as JSON"),
+        );
+
+    let matches = App::new("sector_cache")
+        .version("0.1")
+        .subcommand(verify_cmd)
+        .get_matches();
+
+    match matches.subcommand() {
+        ("verify", Some(m)) => {
+            let cache = value_t!(m, "cache", PathBuf)?;
+            let replica = value_t!(m, "replica", PathBuf)?;
+            let size = value_t!(m, "size", usize)
+                .expect("could not convert `size` CLI argument to `usize`");
+            run_verify(
+                size,
+                cache.as_path(),
+                replica.as_path(),
+                m.is_present("json"),
+            )?;
+        }
+        _ => panic!("Unrecognized subcommand"),
+    }
+
+    Ok(())
+}
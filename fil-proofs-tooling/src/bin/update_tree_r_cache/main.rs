@@ -1,4 +1,4 @@
-use std::fs::{self, create_dir_all, remove_dir_all, OpenOptions};
+use std::fs::{self, create_dir_all, remove_dir_all, remove_file, rename, OpenOptions};
 use std::path::{Path, PathBuf};
 
 use anyhow::{ensure, Context, Result};
@@ -6,9 +6,10 @@ use bincode::deserialize;
 use clap::{value_t, App, Arg, SubCommand};
 use filecoin_hashers::Hasher;
 use filecoin_proofs::{
-    is_sector_shape_base, is_sector_shape_sub2, is_sector_shape_sub8, is_sector_shape_top2,
-    with_shape, DefaultTreeDomain, PersistentAux, SectorShapeBase, SectorShapeSub2,
-    SectorShapeSub8, SectorShapeTop2, OCT_ARITY,
+    get_tree_r_last_rows_to_discard, is_sector_shape_base, is_sector_shape_sub2,
+    is_sector_shape_sub8, is_sector_shape_top2, persist_sector_meta, regenerate_missing_trees,
+    with_shape, Commitment, DefaultTreeDomain, PersistentAux, SectorMeta, SectorShapeBase,
+    SectorShapeSub2, SectorShapeSub8, SectorShapeTop2, SectorSize, OCT_ARITY,
 };
 use generic_array::typenum::Unsigned;
 use memmap::MmapOptions;
@@ -24,12 +25,13 @@ use storage_proofs_core::{
     },
     util::{default_rows_to_discard, NODE_SIZE},
 };
-use tempfile::tempdir;
+use tempfile::{tempdir, tempdir_in};
 
 fn get_tree_r_info(
     sector_size: usize,
     cache: &Path,
     replica_path: &Path,
+    rows_to_discard: usize,
 ) -> Result<(usize, usize, Vec<StoreConfig>, ReplicaConfig)> {
     let tree_count = with_shape!(sector_size as u64, get_base_tree_count);
 
@@ -42,11 +44,8 @@ fn get_tree_r_info(
     }
 
     // Create a StoreConfig from the provided cache path
-    let tree_r_last_config = StoreConfig::new(
-        &cache,
-        CacheKey::CommRLastTree.to_string(),
-        default_rows_to_discard(base_tree_leafs, OCT_ARITY),
-    );
+    let tree_r_last_config =
+        StoreConfig::new(&cache, CacheKey::CommRLastTree.to_string(), rows_to_discard);
 
     // Split the config based on the number of nodes required
     let (configs, replica_config) = split_config_and_replica(
@@ -117,13 +116,31 @@ fn get_persistent_aux(cache: &Path) -> Result<PersistentAux<DefaultTreeDomain>>
     Ok(p_aux)
 }
 
+/// Returns the rows_to_discard of the cached tree_r_last, as recorded in or detected from the
+/// cache.
+fn get_rows_to_discard(sector_size: usize, cache: &Path) -> Result<usize> {
+    with_shape!(
+        sector_size as u64,
+        get_tree_r_last_rows_to_discard,
+        cache,
+        SectorSize(sector_size as u64),
+    )
+}
+
+fn get_default_rows_to_discard(sector_size: usize) -> usize {
+    let tree_count = with_shape!(sector_size as u64, get_base_tree_count);
+
+    default_rows_to_discard(sector_size / NODE_SIZE / tree_count, OCT_ARITY)
+}
+
 fn build_tree_r_last<Tree: MerkleTreeTrait>(
     sector_size: usize,
     cache: &Path,
     replica_path: &Path,
+    rows_to_discard: usize,
 ) -> Result<(<Tree::Hasher as Hasher>::Domain, Vec<DefaultTreeDomain>)> {
     let (tree_count, base_tree_leafs, configs, replica_config) =
-        get_tree_r_info(sector_size, cache, replica_path)?;
+        get_tree_r_info(sector_size, cache, replica_path, rows_to_discard)?;
 
     let f_data = OpenOptions::new()
         .read(true)
@@ -171,19 +188,85 @@ fn run_rebuild(
     sector_size: usize,
     cache: &Path,
     replica_path: &Path,
+    rows_to_discard: usize,
 ) -> Result<(DefaultTreeDomain, Vec<DefaultTreeDomain>)> {
-    with_shape!(
+    let result = with_shape!(
         sector_size as u64,
         build_tree_r_last,
         sector_size,
         cache,
-        replica_path
-    )
+        replica_path,
+        rows_to_discard,
+    )?;
+
+    persist_sector_meta(
+        cache,
+        &SectorMeta {
+            tree_r_last_rows_to_discard: rows_to_discard,
+        },
+    )?;
+
+    Ok(result)
+}
+
+/// Re-derives the cached rows of the tree_r_last in `cache` for a new `rows_to_discard`.
+///
+/// The trees are rebuilt in a temporary directory and only replace the cached ones once their
+/// root matches comm_r_last from p_aux.
+fn run_update(
+    sector_size: usize,
+    cache: &Path,
+    replica_path: &Path,
+    rows_to_discard: usize,
+) -> Result<()> {
+    let p_aux = get_persistent_aux(&cache)?;
+    let current = get_rows_to_discard(sector_size, cache)?;
+    println!(
+        "Updating tree_r_last rows_to_discard from {} to {}",
+        current, rows_to_discard
+    );
+
+    let tmp_dir = tempdir_in(&cache)?;
+    let (tree_r_last_root, _) =
+        run_rebuild(sector_size, tmp_dir.path(), replica_path, rows_to_discard)?;
+    ensure!(
+        tree_r_last_root == p_aux.comm_r_last,
+        "rebuilt tree_r_last root {:?} does not match comm_r_last {:?} in p_aux",
+        tree_r_last_root,
+        p_aux.comm_r_last
+    );
+
+    // Without metadata the layout is detected from the trees, so a partial update is still
+    // read correctly.
+    let meta_path = cache.join(CacheKey::SectorMeta.to_string());
+    if meta_path.exists() {
+        remove_file(&meta_path)?;
+    }
+
+    let (_, _, configs, _) = get_tree_r_info(sector_size, cache, replica_path, rows_to_discard)?;
+    for config in &configs {
+        let file_name = StoreConfig::data_path(&config.path, &config.id)
+            .file_name()
+            .map(ToOwned::to_owned)
+            .context("invalid tree_r_last store path")?;
+        rename(tmp_dir.path().join(&file_name), cache.join(&file_name))?;
+    }
+
+    persist_sector_meta(
+        cache,
+        &SectorMeta {
+            tree_r_last_rows_to_discard: rows_to_discard,
+        },
+    )?;
+    println!("Updated tree_r_last in {:?}", cache);
+
+    Ok(())
 }
 
 fn run_inspect(sector_size: usize, cache: &Path, replica_path: &Path) -> Result<()> {
+    let rows_to_discard = get_rows_to_discard(sector_size, cache)?;
     let (_tree_count, base_tree_leafs, configs, replica_config) =
-        get_tree_r_info(sector_size, cache, replica_path)?;
+        get_tree_r_info(sector_size, cache, replica_path, rows_to_discard)?;
     let tree_r_last_root = get_tree_r_last_root(
         base_tree_leafs,
         sector_size as u64,
@@ -208,8 +291,9 @@ fn run_inspect(sector_size: usize, cache: &Path, replica_path: &Path) -> Result<
 }
 
 fn run_verify(sector_size: usize, cache: &Path, replica_path: &Path) -> Result<()> {
+    let rows_to_discard = get_rows_to_discard(sector_size, cache)?;
     let (tree_count, base_tree_leafs, configs, replica_config) =
-        get_tree_r_info(sector_size, cache, replica_path)?;
+        get_tree_r_info(sector_size, cache, replica_path, rows_to_discard)?;
     let base_tree_len = get_merkle_tree_len(base_tree_leafs, OCT_ARITY)?;
 
     let match_str = |a, b| -> &str {
@@ -255,8 +339,12 @@ fn run_verify(sector_size: usize, cache: &Path, replica_path: &Path) -> Result<(
     let tmp_path = tmp_dir.path();
     create_dir_all(&tmp_path)?;
 
-    let (rebuilt_tree_r_last_root, rebuilt_base_tree_roots) =
-        run_rebuild(sector_size, &tmp_path, &replica_path)?;
+    let (rebuilt_tree_r_last_root, rebuilt_base_tree_roots) = run_rebuild(
+        sector_size,
+        &tmp_path,
+        &replica_path,
+        get_default_rows_to_discard(sector_size),
+    )?;
 
     remove_dir_all(&tmp_path)?;
 
@@ -310,6 +398,43 @@ fn run_verify(sector_size: usize, cache: &Path, replica_path: &Path) -> Result<(
     Ok(())
 }
 
+fn regenerate_trees<Tree: 'static + MerkleTreeTrait>(
+    cache: &Path,
+    replica_path: &Path,
+    comm_d: Commitment,
+) -> Result<Vec<CacheKey>> {
+    regenerate_missing_trees::<_, _, Tree>(cache, replica_path, comm_d)
+}
+
+fn run_regenerate(
+    sector_size: usize,
+    cache: &Path,
+    replica_path: &Path,
+    comm_d: &str,
+) -> Result<()> {
+    let bytes = hex::decode(comm_d).context("could not decode `comm-d` as hex")?;
+    ensure!(bytes.len() == 32, "`comm-d` must be 32 bytes");
+    let mut comm_d: Commitment = [0; 32];
+    comm_d.copy_from_slice(&bytes);
+
+    let regenerated = with_shape!(
+        sector_size as u64,
+        regenerate_trees,
+        cache,
+        replica_path,
+        comm_d
+    )?;
+
+    if regenerated.is_empty() {
+        println!("No trees are missing from {:?}", cache);
+    }
+    for key in regenerated {
+        println!("Regenerated {} and matched it against its commitment", key);
+    }
+
+    Ok(())
+}
+
 fn main() -> Result<()> {
     fil_logger::init();
 
@@ -336,6 +461,44 @@ fn main() -> Result<()> {
                 .help("The cache directory for the output trees")
                 .required(true)
                 .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rows-to-discard")
+                .long("rows-to-discard")
+                .help("The number of rows to discard from each base tree [default: the configured value]")
+                .takes_value(true),
+        );
+
+    let update_cmd = SubCommand::with_name("update")
+        .about("Re-derive the cached tree_r_last trees of a sector for a new rows_to_discard")
+        .arg(
+            Arg::with_name("size")
+                .required(true)
+                .long("size")
+                .default_value("34359738368")
+                .help("The data size in bytes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("replica")
+                .long("replica")
+                .help("The replica file")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cache")
+                .long("cache")
+                .help("The cache directory containing p_aux and the trees to update")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rows-to-discard")
+                .long("rows-to-discard")
+                .help("The number of rows to discard from each base tree")
+                .required(true)
+                .takes_value(true),
         );
 
     let inspect_cmd = SubCommand::with_name("inspect")
@@ -388,11 +551,45 @@ fn main() -> Result<()> {
                 .takes_value(true),
         );
 
+    let regenerate_cmd = SubCommand::with_name("regenerate")
+        .about("Regenerate missing tree_c and tree_d trees from the labels and replica")
+        .arg(
+            Arg::with_name("size")
+                .required(true)
+                .long("size")
+                .default_value("34359738368")
+                .help("The data size in bytes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("replica")
+                .long("replica")
+                .help("The replica file")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cache")
+                .long("cache")
+                .help("The cache directory containing the labels and output trees")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("comm-d")
+                .long("comm-d")
+                .help("The hex encoded comm_d the regenerated tree_d must match")
+                .required(true)
+                .takes_value(true),
+        );
+
     let matches = App::new("update_tree_r_cache")
         .version("0.1")
         .subcommand(rebuild_cmd)
+        .subcommand(update_cmd)
         .subcommand(inspect_cmd)
         .subcommand(verify_cmd)
+        .subcommand(regenerate_cmd)
         .get_matches();
 
     match matches.subcommand() {
@@ -401,7 +598,20 @@ fn main() -> Result<()> {
             let replica = value_t!(m, "replica", PathBuf)?;
             let size = value_t!(m, "size", usize)
                 .expect("could not convert `size` CLI argument to `usize`");
-            run_rebuild(size, cache.as_path(), replica.as_path())?;
+            let rows_to_discard = if m.is_present("rows-to-discard") {
+                value_t!(m, "rows-to-discard", usize)?
+            } else {
+                get_default_rows_to_discard(size)
+            };
+            run_rebuild(size, cache.as_path(), replica.as_path(), rows_to_discard)?;
+        }
+        ("update", Some(m)) => {
+            let cache = value_t!(m, "cache", PathBuf)?;
+            let replica = value_t!(m, "replica", PathBuf)?;
+            let size = value_t!(m, "size", usize)
+                .expect("could not convert `size` CLI argument to `usize`");
+            let rows_to_discard = value_t!(m, "rows-to-discard", usize)?;
+            run_update(size, cache.as_path(), replica.as_path(), rows_to_discard)?;
         }
         ("inspect", Some(m)) => {
             let cache = value_t!(m, "cache", PathBuf)?;
@@ -417,6 +627,14 @@ fn main() -> Result<()> {
                 .expect("could not convert `size` CLI argument to `usize`");
             run_verify(size, cache.as_path(), replica.as_path())?;
         }
+        ("regenerate", Some(m)) => {
+            let cache = value_t!(m, "cache", PathBuf)?;
+            let replica = value_t!(m, "replica", PathBuf)?;
+            let comm_d = value_t!(m, "comm-d", String)?;
+            let size = value_t!(m, "size", usize)
+                .expect("could not convert `size` CLI argument to `usize`");
+            run_regenerate(size, cache.as_path(), replica.as_path(), &comm_d)?;
+        }
         _ => panic!("Unrecognized subcommand"),
     }
 
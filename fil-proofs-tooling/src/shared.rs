@@ -109,6 +109,8 @@ pub fn create_replicas<Tree: 'static + MerkleTreeTrait>(
         ),
         porep_id,
         api_version,
+        non_interactive: false,
+        synthetic: false,
     };
 
     let mut out: Vec<(SectorId, PreCommitReplicaOutput<Tree>)> = Default::default();
@@ -39,6 +39,8 @@ fn bench_seal_inputs(c: &mut Criterion) {
         ),
         porep_id,
         api_version: ApiVersion::V1_1_0,
+        non_interactive: false,
+        synthetic: false,
     };
     let comm_r = [5u8; 32];
     let comm_d = [6u8; 32];
@@ -90,6 +92,8 @@ fn bench_stacked_srs_key(c: &mut Criterion) {
         ),
         porep_id,
         api_version: ApiVersion::V1_1_0,
+        non_interactive: false,
+        synthetic: false,
     };
 
     let mut group = c.benchmark_group("bench-stacked-srs-key");
@@ -131,6 +135,8 @@ fn bench_stacked_srs_verifier_key(c: &mut Criterion) {
         ),
         porep_id,
         api_version: ApiVersion::V1_1_0,
+        non_interactive: false,
+        synthetic: false,
     };
 
     let mut group = c.benchmark_group("bench-stacked-srs-verifier-key");
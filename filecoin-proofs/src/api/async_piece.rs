@@ -0,0 +1,172 @@
+use std::cmp::min;
+use std::io::{self, Cursor, Read};
+
+use anyhow::{ensure, Context, Result};
+use fr32::Fr32Reader;
+use log::info;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+
+use crate::{
+    api::ensure_piece_size,
+    commitment_reader::CommitmentReader,
+    pieces::{get_piece_alignment, sum_piece_bytes_with_alignment},
+    types::{PaddedBytesAmount, PieceInfo, UnpaddedBytesAmount},
+};
+
+/// The number of unpadded bytes read from the source at once. This is a multiple of the
+/// 127 byte Fr32 block size, so that every chunk is bit-padded independently.
+const UNPADDED_CHUNK_SIZE: usize = 127 * 8 * 1024;
+
+/// The size of the buffer used to write alignment bytes.
+const ZERO_CHUNK_SIZE: usize = 4096;
+
+/// Asynchronous counterpart of `add_piece`.
+///
+/// Reads unpadded bytes from `source`, writes the bit-padded bytes to `target` and computes
+/// the piece commitment in the same pass. Returns the same `PieceInfo` and number of written
+/// bytes as `add_piece`.
+///
+/// The operation can be cancelled by dropping the returned future. `target` is written to as the
+/// piece is processed, so it then contains a partially written piece, which is not removed since
+/// `target` is an arbitrary writer. Callers appending to a staged sector file should truncate it
+/// back to its previous length, or write into a temporary file and only append that once the
+/// future completed.
+///
+/// # Arguments
+///
+/// * `source` - an asynchronous source of unprocessed piece bytes.
+/// * `target` - an asynchronous writer where we will write the processed piece bytes.
+/// * `piece_size` - the number of unpadded user-bytes which can be read from source before EOF.
+/// * `piece_lengths` - the number of bytes for each previous piece in the sector.
+pub async fn add_piece_async<R, W>(
+    mut source: R,
+    target: W,
+    piece_size: UnpaddedBytesAmount,
+    piece_lengths: &[UnpaddedBytesAmount],
+) -> Result<(PieceInfo, UnpaddedBytesAmount)>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    info!("add_piece_async:start");
+
+    ensure_piece_size(piece_size)?;
+
+    let mut target = BufWriter::new(target);
+
+    let written_bytes = sum_piece_bytes_with_alignment(&piece_lengths);
+    let piece_alignment = get_piece_alignment(written_bytes, piece_size);
+
+    // write left alignment
+    write_zeroes(
+        &mut target,
+        PaddedBytesAmount::from(piece_alignment.left_bytes),
+    )
+    .await?;
+
+    let mut commitment_reader = CommitmentReader::new(Cursor::new(Vec::new()));
+    let mut chunk = vec![0u8; UNPADDED_CHUNK_SIZE];
+    let mut padded_chunk = Vec::new();
+    let mut n = 0;
+
+    loop {
+        let read = read_chunk(&mut source, &mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+
+        // Bit-pad the chunk and pipe it through the commitment reader.
+        let padded = commitment_reader.get_mut();
+        padded.get_mut().clear();
+        padded.set_position(0);
+        Fr32Reader::new(&chunk[..read])
+            .read_to_end(padded.get_mut())
+            .context("failed to preprocess bytes")?;
+
+        padded_chunk.clear();
+        io::copy(&mut commitment_reader, &mut padded_chunk)
+            .context("failed to compute commitment")?;
+
+        target
+            .write_all(&padded_chunk)
+            .await
+            .context("failed to write preprocessed bytes")?;
+        n += padded_chunk.len() as u64;
+    }
+
+    ensure!(n != 0, "add_piece: read 0 bytes before EOF from source");
+    let n = PaddedBytesAmount(n);
+    let n: UnpaddedBytesAmount = n.into();
+
+    ensure!(n == piece_size, "add_piece: invalid bytes amount written");
+
+    // write right alignment
+    write_zeroes(
+        &mut target,
+        PaddedBytesAmount::from(piece_alignment.right_bytes),
+    )
+    .await?;
+    target.flush().await?;
+
+    let commitment = commitment_reader.finish()?;
+    let mut comm = [0u8; 32];
+    comm.copy_from_slice(commitment.as_ref());
+
+    let written = piece_alignment.left_bytes + piece_alignment.right_bytes + piece_size;
+
+    info!("add_piece_async:finish");
+    Ok((PieceInfo::new(comm, n)?, written))
+}
+
+/// Asynchronous counterpart of `write_and_preprocess`.
+///
+/// WARNING: This function neither prepends nor appends alignment bytes to the
+/// `target`; it is the caller's responsibility to ensure properly sized
+/// and ordered writes to `target` such that `source`-bytes occupy whole
+/// subtrees of the final merkle tree built over `target`.
+///
+/// # Arguments
+///
+/// * `source` - an asynchronous source of unprocessed piece bytes.
+/// * `target` - an asynchronous writer where we will write the processed piece bytes.
+/// * `piece_size` - the number of unpadded user-bytes which can be read from source before EOF.
+pub async fn write_and_preprocess_async<R, W>(
+    source: R,
+    target: W,
+    piece_size: UnpaddedBytesAmount,
+) -> Result<(PieceInfo, UnpaddedBytesAmount)>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    add_piece_async(source, target, piece_size, Default::default()).await
+}
+
+/// Reads from `source` until `buf` is full or EOF is reached, returning the number of bytes read.
+async fn read_chunk<R: AsyncRead + Unpin>(source: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let read = source.read(&mut buf[filled..]).await?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+
+    Ok(filled)
+}
+
+async fn write_zeroes<W: AsyncWrite + Unpin>(
+    target: &mut W,
+    amount: PaddedBytesAmount,
+) -> io::Result<()> {
+    let zeroes = [0u8; ZERO_CHUNK_SIZE];
+    let mut remaining = usize::from(amount);
+    while remaining > 0 {
+        let len = min(remaining, zeroes.len());
+        target.write_all(&zeroes[..len]).await?;
+        remaining -= len;
+    }
+
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use std::fs::{self, File, OpenOptions};
-use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
 use anyhow::{ensure, Context, Result};
@@ -15,7 +15,7 @@ use storage_proofs_core::{
     merkle::get_base_tree_count,
     pieces::generate_piece_commitment_bytes_from_source,
     sector::SectorId,
-    util::default_rows_to_discard,
+    util::{default_rows_to_discard, NODE_SIZE},
 };
 use storage_proofs_porep::{
     stacked::{generate_replica_id, PersistentAux, StackedDrg, TemporaryAux},
@@ -38,16 +38,34 @@ use crate::{
     },
 };
 
+#[cfg(feature = "async-io")]
+mod async_piece;
 mod fake_seal;
+mod parent_cache;
+mod piece_inclusion;
+mod post_batch;
 mod post_util;
+mod regenerate;
 mod seal;
+mod sector_cache;
+mod sector_meta;
+mod update;
 mod util;
 mod window_post;
 mod winning_post;
 
+#[cfg(feature = "async-io")]
+pub use async_piece::*;
 pub use fake_seal::*;
+pub use parent_cache::*;
+pub use piece_inclusion::*;
+pub use post_batch::*;
 pub use post_util::*;
+pub use regenerate::*;
 pub use seal::*;
+pub use sector_cache::*;
+pub use sector_meta::*;
+pub use update::*;
 pub use util::*;
 pub use window_post::*;
 pub use winning_post::*;
@@ -292,6 +310,8 @@ where
         usize::from(PoRepProofPartitions::from(porep_config)),
         porep_config.porep_id,
         porep_config.api_version,
+        porep_config.non_interactive,
+        porep_config.synthetic,
     )?;
 
     let offset_padded: PaddedBytesAmount = UnpaddedBytesAmount::from(offset).into();
@@ -314,6 +334,155 @@ where
     Ok(amount)
 }
 
+/// Unseals the sector at `sealed_path` and returns the bytes for a piece
+/// whose first (unpadded) byte begins at `offset` and ends at `offset` plus
+/// `num_bytes`, inclusive. Unlike `get_unsealed_range`, only the sealed nodes
+/// covering the range are read, and no labels are written to the cache. All
+/// layers but the last are still labeled in full, as the expander parents of
+/// a node are spread over the whole previous layer.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration containing the sector size.
+/// * `sealed_path` - path to the sealed sector file that we will unseal and read a byte range.
+/// * `output_path` - path to a file that we will write the requested byte range to.
+/// * `prover_id` - the prover-id that sealed the sector.
+/// * `sector_id` - the sector-id of the sealed sector.
+/// * `comm_d` - the commitment to the sector's data.
+/// * `ticket` - the ticket that was used to generate the sector's replica-id.
+/// * `offset` - the byte index in the unsealed sector of the first byte that we want to read.
+/// * `num_bytes` - the number of bytes that we want to read.
+#[allow(clippy::too_many_arguments)]
+pub fn get_unsealed_range_partial<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    sealed_path: T,
+    output_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<UnpaddedBytesAmount> {
+    info!("get_unsealed_range_partial:start");
+
+    let f_in = File::open(&sealed_path)
+        .with_context(|| format!("could not open sealed_path={:?}", sealed_path.as_ref()))?;
+    let f_out = File::create(&output_path)
+        .with_context(|| format!("could not create output_path={:?}", output_path.as_ref()))?;
+
+    let result = unseal_range_partial::<_, _, Tree>(
+        porep_config,
+        BufReader::new(f_in),
+        BufWriter::new(f_out),
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        offset,
+        num_bytes,
+    );
+
+    info!("get_unsealed_range_partial:finish");
+    result
+}
+
+/// Unseals the sector read from `sealed_sector` and returns the bytes for a
+/// piece whose first (unpadded) byte begins at `offset` and ends at `offset`
+/// plus `num_bytes`, inclusive. The last layer is only labeled up to the end
+/// of the requested range, see `get_unsealed_range_partial`.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration containing the sector size.
+/// * `sealed_sector` - a seekable byte source from which we read sealed sector data.
+/// * `unsealed_output` - a byte sink to which we write unsealed, un-bit-padded sector bytes.
+/// * `prover_id` - the prover-id that sealed the sector.
+/// * `sector_id` - the sector-id of the sealed sector.
+/// * `comm_d` - the commitment to the sector's data.
+/// * `ticket` - the ticket that was used to generate the sector's replica-id.
+/// * `offset` - the byte index in the unsealed sector of the first byte that we want to read.
+/// * `num_bytes` - the number of bytes that we want to read.
+#[allow(clippy::too_many_arguments)]
+pub fn unseal_range_partial<R, W, Tree>(
+    porep_config: PoRepConfig,
+    mut sealed_sector: R,
+    mut unsealed_output: W,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<UnpaddedBytesAmount>
+where
+    R: Read + Seek,
+    W: Write,
+    Tree: 'static + MerkleTreeTrait,
+{
+    info!("unseal_range_partial:start");
+    ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
+
+    let comm_d =
+        as_safe_commitment::<<DefaultPieceHasher as Hasher>::Domain, _>(&comm_d, "comm_d")?;
+
+    let replica_id = generate_replica_id::<Tree::Hasher, _>(
+        &prover_id,
+        sector_id.into(),
+        &ticket,
+        comm_d,
+        &porep_config.porep_id,
+    );
+
+    let pp = public_params(
+        PaddedBytesAmount::from(porep_config),
+        usize::from(PoRepProofPartitions::from(porep_config)),
+        porep_config.porep_id,
+        porep_config.api_version,
+        porep_config.non_interactive,
+        porep_config.synthetic,
+    )?;
+
+    let offset_padded: PaddedBytesAmount = UnpaddedBytesAmount::from(offset).into();
+    let num_bytes_padded: PaddedBytesAmount = num_bytes.into();
+
+    let start: usize = offset_padded.into();
+    let end = start + usize::from(num_bytes_padded);
+    ensure!(
+        end <= usize::from(PaddedBytesAmount::from(porep_config)),
+        "requested range exceeds the sector size"
+    );
+
+    // Only the sealed nodes which overlap the requested range are read and decoded.
+    let first_node = start / NODE_SIZE;
+    let end_node = (end + NODE_SIZE - 1) / NODE_SIZE;
+
+    let mut data = vec![0u8; (end_node - first_node) * NODE_SIZE];
+    sealed_sector.seek(SeekFrom::Start((first_node * NODE_SIZE) as u64))?;
+    sealed_sector
+        .read_exact(&mut data)
+        .context("failed to read sealed range")?;
+
+    StackedDrg::<Tree, DefaultPieceHasher>::extract_range(
+        &pp.graph,
+        &pp.layer_challenges,
+        &replica_id,
+        &mut data,
+        first_node..end_node,
+    )?;
+
+    let unsealed_start = start - first_node * NODE_SIZE;
+    let unsealed = &data[unsealed_start..unsealed_start + usize::from(num_bytes_padded)];
+
+    let written = write_unpadded(unsealed, &mut unsealed_output, 0, num_bytes.into())
+        .context("write_unpadded failed")?;
+
+    let amount = UnpaddedBytesAmount(written as u64);
+
+    info!("unseal_range_partial:finish");
+    Ok(amount)
+}
+
 /// Generates a piece commitment for the provided byte source. Returns an error
 /// if the byte source produced more than `piece_size` bytes.
 ///
@@ -684,12 +853,19 @@ where
 
         // Switch t_aux to the passed in cache_path
         res.set_cache_path(&cache_path);
+        if let Some(meta) = read_sector_meta(&cache_path)? {
+            res.tree_r_last_config.rows_to_discard = meta.tree_r_last_rows_to_discard;
+        }
         res
     };
 
-    // Verify all stores/labels within the Labels object.
+    // Verify all stores/labels within the Labels object, unless they were discarded after
+    // generating the synthetic proofs.
     let cache = cache_path.as_ref().to_path_buf();
-    t_aux.labels.verify_stores(verify_store, &cache)?;
+    let synthetic_proofs_path = cache.join(CacheKey::SyntheticPoRepProofs.to_string());
+    if !synthetic_proofs_path.exists() {
+        t_aux.labels.verify_stores(verify_store, &cache)?;
+    }
 
     // Verify each tree disk store.
     verify_store(
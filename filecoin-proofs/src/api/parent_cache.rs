@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use log::info;
+use storage_proofs_core::{merkle::MerkleTreeTrait, parameter_cache::with_exclusive_read_lock};
+use storage_proofs_porep::stacked::{
+    list_parent_caches, verify_parent_cache, ParentCacheInfo, ParentCacheStatus, StackedBucketGraph,
+};
+
+use crate::{
+    parameters::public_params,
+    types::{PaddedBytesAmount, PoRepConfig, PoRepProofPartitions},
+    with_shape,
+};
+
+/// Returns the path of the parent cache used when sealing with `porep_config`.
+pub fn parent_cache_path(porep_config: PoRepConfig) -> Result<PathBuf> {
+    with_shape!(
+        u64::from(porep_config.sector_size),
+        parent_cache_path_inner,
+        porep_config,
+    )
+}
+
+fn parent_cache_path_inner<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<PathBuf> {
+    Ok(porep_graph::<Tree>(porep_config)?.parent_cache_path())
+}
+
+fn porep_graph<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<StackedBucketGraph<Tree::Hasher>> {
+    let public_params = public_params::<Tree>(
+        PaddedBytesAmount::from(porep_config),
+        usize::from(PoRepProofPartitions::from(porep_config)),
+        porep_config.porep_id,
+        porep_config.api_version,
+        porep_config.non_interactive,
+        porep_config.synthetic,
+    )?;
+
+    Ok(public_params.graph)
+}
+
+/// Checks the parent cache used when sealing with `porep_config` against the parent cache
+/// manifest. Note that this reads the whole cache.
+pub fn check_parent_cache(porep_config: PoRepConfig) -> Result<ParentCacheStatus> {
+    verify_parent_cache(&parent_cache_path(porep_config)?)
+}
+
+/// Checks the parent cache used when sealing with `porep_config`, and generates it again if it
+/// is missing or invalid. Returns the status of the cache before it was repaired.
+pub fn repair_parent_cache(porep_config: PoRepConfig) -> Result<ParentCacheStatus> {
+    let status = check_parent_cache(porep_config)?;
+    if !status.is_usable() {
+        info!("repair_parent_cache: regenerating cache ({:?})", status);
+        with_shape!(
+            u64::from(porep_config.sector_size),
+            regenerate_parent_cache,
+            porep_config,
+        )?;
+    }
+
+    Ok(status)
+}
+
+fn regenerate_parent_cache<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<()> {
+    porep_graph::<Tree>(porep_config)?.regenerate_parent_cache()?;
+
+    Ok(())
+}
+
+/// Removes the parent caches which are not used by any of `porep_configs`, e.g. the ones of
+/// previous versions or of sector sizes no longer sealed on this host. Returns the removed
+/// caches, or the ones which would be removed if `dry_run` is set.
+///
+/// Only the caches of the parent cache manifest are removed. Any other cache, e.g. the one of a
+/// porep id the manifest doesn't know about, cannot be attributed to a seal proof and is kept.
+///
+/// Each cache is removed while holding an exclusive lock on it, so a cache which is being
+/// generated, or read by a seal in progress, is only removed once it is no longer in use.
+pub fn gc_parent_caches(
+    porep_configs: &[PoRepConfig],
+    dry_run: bool,
+) -> Result<Vec<ParentCacheInfo>> {
+    let used = porep_configs
+        .iter()
+        .map(|porep_config| parent_cache_path(*porep_config))
+        .collect::<Result<HashSet<_>>>()?;
+
+    let unused = list_parent_caches()?
+        .into_iter()
+        .filter(|cache| cache.sector_size.is_some() && !used.contains(&cache.path))
+        .collect::<Vec<_>>();
+
+    if !dry_run {
+        for cache in &unused {
+            info!("gc_parent_caches: removing {:?}", cache.path);
+            with_exclusive_read_lock(&cache.path, |_| fs::remove_file(&cache.path))
+                .with_context(|| format!("could not remove parent cache {:?}", cache.path))?;
+        }
+    }
+
+    Ok(unused)
+}
@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use filecoin_hashers::Hasher;
+use log::info;
+use merkletree::store::{DiskStore, StoreConfig};
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    error::Error,
+    merkle::{MerkleProofTrait, MerkleTreeTrait},
+    pieces::PieceSpec,
+    util::{default_rows_to_discard, NODE_SIZE},
+};
+
+use crate::{
+    api::{get_base_tree_leafs, get_base_tree_size},
+    constants::{DefaultBinaryTree, DefaultPieceHasher},
+    pieces::piece_hash,
+    types::{
+        Commitment, DataTree, PaddedBytesAmount, PieceInclusionProof, PieceInfo, SectorSize,
+        UnpaddedByteIndex, UnpaddedBytesAmount, BINARY_ARITY,
+    },
+};
+
+/// Generates a proof that the piece described by `piece_info` starts at `offset` in the
+/// sector whose `tree-d` is stored in `cache_path`.
+///
+/// # Arguments
+///
+/// * `cache_path` - path to the directory in which the sector data's Merkle Tree is written.
+/// * `sector_size` - the size of the sector.
+/// * `piece_info` - the commitment and size of the piece.
+/// * `offset` - the byte index in the unsealed sector of the first byte of the piece.
+pub fn generate_piece_inclusion_proof<T: AsRef<Path>>(
+    cache_path: T,
+    sector_size: SectorSize,
+    piece_info: &PieceInfo,
+    offset: UnpaddedByteIndex,
+) -> Result<PieceInclusionProof> {
+    info!("generate_piece_inclusion_proof:start");
+
+    let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
+    let piece_spec = piece_spec(piece_info, offset)?;
+    let (_, proof_len) = piece_spec.compute_packing(base_tree_leafs)?;
+
+    let config = StoreConfig::new(
+        cache_path.as_ref(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
+    );
+    let tree_d_store: DiskStore<<DefaultPieceHasher as Hasher>::Domain> =
+        DiskStore::new_from_disk(base_tree_size, BINARY_ARITY, &config).context("tree_d_store")?;
+    let tree_d = DataTree::from_data_store(tree_d_store, base_tree_leafs).context("tree_d")?;
+
+    // The path of the first leaf of the piece passes through the root of the piece subtree.
+    let leaf_proof = tree_d.gen_proof(piece_spec.position)?;
+    let leaf_path: Vec<Commitment> = leaf_proof
+        .path()
+        .into_iter()
+        .map(|(siblings, _)| {
+            let mut sibling = [0u8; 32];
+            sibling.copy_from_slice(AsRef::<[u8]>::as_ref(&siblings[0]));
+            sibling
+        })
+        .collect();
+    let piece_height = leaf_path.len() - proof_len;
+
+    let mut leaf = [0u8; 32];
+    leaf.copy_from_slice(AsRef::<[u8]>::as_ref(&leaf_proof.leaf()));
+    let piece_root = compute_root(leaf, piece_spec.position, &leaf_path[..piece_height]);
+    ensure!(
+        piece_root == piece_info.commitment,
+        Error::BadPieceCommitment
+    );
+
+    let proof = PieceInclusionProof {
+        path: leaf_path[piece_height..].to_vec(),
+    };
+
+    info!("generate_piece_inclusion_proof:finish");
+    Ok(proof)
+}
+
+/// Verifies that the piece described by `piece_info` starts at `offset` in the sector
+/// committed to by `comm_d`.
+///
+/// # Arguments
+///
+/// * `sector_size` - the size of the sector.
+/// * `comm_d` - the commitment to the sector's data.
+/// * `piece_info` - the commitment and size of the piece.
+/// * `offset` - the byte index in the unsealed sector of the first byte of the piece.
+/// * `proof` - the piece inclusion proof.
+pub fn verify_piece_inclusion_proof(
+    sector_size: SectorSize,
+    comm_d: &Commitment,
+    piece_info: &PieceInfo,
+    offset: UnpaddedByteIndex,
+    proof: &PieceInclusionProof,
+) -> Result<bool> {
+    info!("verify_piece_inclusion_proof:start");
+
+    let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
+    let piece_spec = piece_spec(piece_info, offset)?;
+    let (_, proof_len) = piece_spec.compute_packing(base_tree_leafs)?;
+
+    let valid = proof.path.len() == proof_len
+        && compute_root(
+            piece_info.commitment,
+            piece_spec.position / piece_spec.number_of_leaves,
+            &proof.path,
+        ) == *comm_d;
+
+    info!("verify_piece_inclusion_proof:finish");
+    Ok(valid)
+}
+
+/// Describes the piece in leaf units of `tree-d`.
+fn piece_spec(piece_info: &PieceInfo, offset: UnpaddedByteIndex) -> Result<PieceSpec> {
+    let padded_offset = PaddedBytesAmount::from(UnpaddedBytesAmount::from(offset));
+    let padded_size = PaddedBytesAmount::from(piece_info.size);
+
+    ensure!(
+        u64::from(padded_offset) % NODE_SIZE as u64 == 0,
+        Error::UnalignedPiece
+    );
+
+    Ok(PieceSpec {
+        comm_p: piece_info.commitment,
+        position: usize::from(padded_offset) / NODE_SIZE,
+        number_of_leaves: usize::from(padded_size) / NODE_SIZE,
+    })
+}
+
+/// Hashes `node`, found at `index` within its row, up through the siblings in `path`.
+fn compute_root(node: Commitment, mut index: usize, path: &[Commitment]) -> Commitment {
+    let mut current = node;
+    for sibling in path {
+        let hash = if index & 1 == 0 {
+            piece_hash(&current, sibling)
+        } else {
+            piece_hash(sibling, &current)
+        };
+        current.copy_from_slice(AsRef::<[u8]>::as_ref(&hash));
+        index >>= 1;
+    }
+
+    current
+}
@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure, Result};
+use log::{info, warn};
+use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use storage_proofs_core::{
+    cancel::CancellationToken,
+    error::Error,
+    merkle::MerkleTreeTrait,
+    sector::SectorId,
+    settings::{self, with_config},
+};
+use storage_proofs_post::fallback;
+
+use crate::{
+    types::{
+        FallbackPoStSectorProof, FaultReason, PoStConfig, PrivateReplicaInfo, SectorFault,
+        VanillaProofBatch, VanillaProofBatchConfig,
+    },
+    PoStType,
+};
+
+/// Identifies the storage a replica or cache directory is read from, so that reads can be
+/// scheduled per device.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum StorageKey {
+    Device(u64),
+    Dir(PathBuf),
+}
+
+impl StorageKey {
+    #[cfg(unix)]
+    fn of(path: &Path) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        match std::fs::metadata(path) {
+            Ok(metadata) => StorageKey::Device(metadata.dev()),
+            Err(_) => Self::dir_of(path),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn of(path: &Path) -> Self {
+        Self::dir_of(path)
+    }
+
+    fn dir_of(path: &Path) -> Self {
+        StorageKey::Dir(path.parent().unwrap_or(path).to_path_buf())
+    }
+}
+
+type SectorJob<'a, Tree> = (SectorId, &'a PrivateReplicaInfo<Tree>, &'a [u64]);
+
+type SectorResult<Tree> = std::result::Result<FallbackPoStSectorProof<Tree>, FaultReason>;
+
+/// Generates the vanilla proofs of many sectors at once, for either Window proof-of-spacetime
+/// or Winning proof-of-spacetime.
+///
+/// A proof reads the cached rows of `tree-r-last` from the cache directory and the challenged
+/// leaves from the replica. Sectors are therefore grouped by the storage devices holding both
+/// their cache directory and their replica, and at most `max_concurrent_per_path` sectors of a
+/// group are proven at the same time. Within a group, sectors are proven in the order of their
+/// cache directory and replica paths, and within a sector, the challenged leaves are read in
+/// ascending order. Sectors which cannot be proven are
+/// returned as faults, classified by `FaultReason`, instead of failing the whole batch.
+///
+/// # Arguments
+///
+/// * `post_config` - post configuration that specifies the sector size and challenge counts.
+/// * `replicas` - the replicas to prove, by sector id.
+/// * `challenges` - the challenged leaves of every replica, as returned by
+/// `generate_fallback_sector_challenges`.
+/// * `batch_config` - the scheduling limits.
+pub fn generate_vanilla_proofs_batch<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    challenges: &BTreeMap<SectorId, Vec<u64>>,
+    batch_config: &VanillaProofBatchConfig,
+) -> Result<VanillaProofBatch<Tree>> {
+    info!("generate_vanilla_proofs_batch:start");
+    ensure!(
+        post_config.typ == PoStType::Window || post_config.typ == PoStType::Winning,
+        "invalid post config type"
+    );
+    ensure!(
+        batch_config.max_concurrent_per_path > 0,
+        "max_concurrent_per_path must be at least 1"
+    );
+
+    let mut groups: BTreeMap<(StorageKey, StorageKey), Vec<SectorJob<'_, Tree>>> = BTreeMap::new();
+    for (sector_id, replica) in replicas {
+        let sector_challenges = challenges
+            .get(sector_id)
+            .ok_or_else(|| anyhow!("missing challenges for sector {:?}", sector_id))?;
+
+        groups
+            .entry((
+                StorageKey::of(replica.cache_dir_path()),
+                StorageKey::of(replica.replica_path()),
+            ))
+            .or_insert_with(Vec::new)
+            .push((*sector_id, replica, sector_challenges.as_slice()));
+    }
+
+    // Neighbouring caches and replicas are likely to be close on disk, so prove them in path
+    // order.
+    let groups: Vec<Vec<SectorJob<'_, Tree>>> = groups
+        .into_iter()
+        .map(|(_, mut jobs)| {
+            jobs.sort_by(|a, b| {
+                (a.1.cache_dir_path(), a.1.replica_path())
+                    .cmp(&(b.1.cache_dir_path(), b.1.replica_path()))
+            });
+            jobs
+        })
+        .collect();
+
+    let lanes = batch_config.max_concurrent_per_path;
+    let timeout = batch_config.sector_timeout;
+    let config = settings::current();
+    let config = &config;
+    let mut results: Vec<(SectorId, SectorResult<Tree>)> = groups
+        .par_iter()
+        .flat_map(|jobs| {
+            // Each lane proves its share of the group's sectors one after another.
+            (0..lanes.min(jobs.len()))
+                .into_par_iter()
+                .flat_map(move |lane| {
+                    jobs.iter()
+                        .skip(lane)
+                        .step_by(lanes)
+                        .map(|(sector_id, replica, sector_challenges)| {
+                            let proof = with_config(Some(config), || {
+                                prove_sector_with_timeout(
+                                    post_config,
+                                    *sector_id,
+                                    replica,
+                                    sector_challenges,
+                                    timeout,
+                                )
+                            });
+                            (*sector_id, proof)
+                        })
+                        .collect::<Vec<_>>()
+                })
+        })
+        .collect();
+    results.sort_by_key(|(sector_id, _)| *sector_id);
+
+    let mut proofs = Vec::with_capacity(results.len());
+    let mut faults = Vec::new();
+    for (sector_id, result) in results {
+        match result {
+            Ok(proof) => proofs.push(proof),
+            Err(reason) => {
+                warn!("sector {:?} is faulty: {}", sector_id, reason);
+                faults.push(SectorFault { sector_id, reason });
+            }
+        }
+    }
+
+    info!(
+        "generate_vanilla_proofs_batch:finish: {} proofs, {} faults",
+        proofs.len(),
+        faults.len()
+    );
+
+    Ok(VanillaProofBatch { proofs, faults })
+}
+
+/// Runs `prove_sector` on its own thread if a timeout is set, so that a sector stuck on a hung
+/// read can be reported instead of blocking the batch. A timed out sector is cancelled, so its
+/// thread stops proving as soon as the read it is waiting for returns.
+fn prove_sector_with_timeout<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    sector_id: SectorId,
+    replica: &PrivateReplicaInfo<Tree>,
+    challenges: &[u64],
+    timeout: Option<Duration>,
+) -> SectorResult<Tree> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => {
+            return prove_sector(
+                post_config,
+                sector_id,
+                replica,
+                challenges,
+                &CancellationToken::default(),
+            )
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let cancel = CancellationToken::default();
+    let worker = {
+        let post_config = post_config.clone();
+        let replica = replica.clone();
+        let challenges = challenges.to_vec();
+        let cancel = cancel.clone();
+        let config = settings::current();
+        thread::spawn(move || {
+            let result = with_config(Some(&config), || {
+                prove_sector(&post_config, sector_id, &replica, &challenges, &cancel)
+            });
+            // The receiver is gone if the sector already timed out.
+            let _ = sender.send(result);
+        })
+    };
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => {
+            // The worker returns right after sending its result.
+            let _ = worker.join();
+            result
+        }
+        Err(RecvTimeoutError::Timeout) => {
+            cancel.cancel();
+            Err(FaultReason::Timeout)
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            let _ = worker.join();
+            Err(FaultReason::Other(format!(
+                "proving sector {:?} panicked",
+                sector_id
+            )))
+        }
+    }
+}
+
+fn prove_sector<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    sector_id: SectorId,
+    replica: &PrivateReplicaInfo<Tree>,
+    challenges: &[u64],
+    cancel: &CancellationToken,
+) -> SectorResult<Tree> {
+    if !replica.replica_path().exists() || !replica.cache_dir_path().exists() {
+        return Err(FaultReason::MissingCache);
+    }
+
+    let tree = &replica
+        .merkle_tree(post_config.sector_size)
+        .map_err(|err| classify(&err))?;
+    let comm_r = replica.safe_comm_r().map_err(|err| classify(&err))?;
+    let comm_c = replica.safe_comm_c();
+    let comm_r_last = replica.safe_comm_r_last();
+
+    if tree.root() != comm_r_last {
+        return Err(FaultReason::RootMismatch);
+    }
+
+    let rows_to_discard = replica
+        .tree_r_last_rows_to_discard(post_config.sector_size)
+        .map_err(|err| classify(&err))?;
+
+    let priv_sectors = vec![fallback::PrivateSector {
+        tree,
+        comm_c,
+        comm_r_last,
+        rows_to_discard,
+    }];
+
+    let priv_inputs = fallback::PrivateInputs::<Tree> {
+        sectors: &priv_sectors,
+    };
+
+    let vanilla_proof =
+        fallback::vanilla_proof_sequential(sector_id, &priv_inputs, challenges, cancel)
+            .map_err(|err| classify(&err))?;
+
+    Ok(FallbackPoStSectorProof {
+        sector_id,
+        comm_r,
+        vanilla_proof,
+    })
+}
+
+/// Classifies the error a sector failed to be proven with by the type of its causes: an
+/// `Error::InvalidCommitment` is a root mismatch, and an `io::Error` is classified by its kind.
+fn classify(err: &anyhow::Error) -> FaultReason {
+    for cause in err.chain() {
+        if let Some(Error::InvalidCommitment) = cause.downcast_ref::<Error>() {
+            return FaultReason::RootMismatch;
+        }
+        if let Some(io_err) = cause.downcast_ref::<io::Error>() {
+            return match io_err.kind() {
+                io::ErrorKind::NotFound => FaultReason::MissingCache,
+                io::ErrorKind::TimedOut => FaultReason::Timeout,
+                _ => FaultReason::Io(format!("{:#}", err)),
+            };
+        }
+    }
+
+    FaultReason::Other(format!("{:#}", err))
+}
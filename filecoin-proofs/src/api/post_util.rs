@@ -12,7 +12,7 @@ use storage_proofs_core::{
 use storage_proofs_post::fallback::{self, generate_leaf_challenge, FallbackPoSt, SectorProof};
 
 use crate::{
-    api::as_safe_commitment,
+    api::{as_safe_commitment, clear_synthetic_proofs},
     constants::DefaultPieceHasher,
     types::{
         ChallengeSeed, FallbackPoStSectorProof, PoStConfig, PrivateReplicaInfo, ProverId,
@@ -33,7 +33,8 @@ pub fn clear_cache<Tree: MerkleTreeTrait>(cache_dir: &Path) -> Result<()> {
         deserialize(&aux_bytes)
     }?;
 
-    let result = TemporaryAux::<Tree, DefaultPieceHasher>::clear_temp(t_aux);
+    let result = TemporaryAux::<Tree, DefaultPieceHasher>::clear_temp(t_aux)
+        .and_then(|_| clear_synthetic_proofs(cache_dir));
 
     info!("clear_cache:finish");
 
@@ -146,11 +147,13 @@ pub fn generate_single_vanilla_proof<Tree: 'static + MerkleTreeTrait>(
     })?;
     let comm_c = replica.safe_comm_c();
     let comm_r_last = replica.safe_comm_r_last();
+    let rows_to_discard = replica.tree_r_last_rows_to_discard(post_config.sector_size)?;
 
     let priv_sectors = vec![fallback::PrivateSector {
         tree,
         comm_c,
         comm_r_last,
+        rows_to_discard,
     }];
 
     let priv_inputs = fallback::PrivateInputs::<Tree> {
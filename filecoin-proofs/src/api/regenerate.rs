@@ -0,0 +1,136 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use bincode::deserialize;
+use filecoin_hashers::Hasher;
+use log::info;
+use merkletree::store::StoreConfig;
+use serde::de::DeserializeOwned;
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::{get_base_tree_count, split_config, MerkleTreeTrait},
+};
+use storage_proofs_porep::stacked::{PersistentAux, StackedDrg, TemporaryAux};
+
+use crate::{api::as_safe_commitment, constants::DefaultPieceHasher, types::Commitment};
+
+/// Regenerates whichever of `tree-c` and `tree-d` are missing from the sector cache in
+/// `cache_path`, and returns the cache keys of the trees that were rebuilt.
+///
+/// See `regenerate_tree_c` and `regenerate_tree_d` for how each tree is rebuilt and checked.
+///
+/// # Arguments
+///
+/// * `cache_path` - path to the directory in which the sector's cache is written.
+/// * `replica_path` - path to the sealed sector replica.
+/// * `comm_d` - the data commitment returned by `seal_pre_commit_phase2`.
+pub fn regenerate_missing_trees<R, T, Tree: 'static + MerkleTreeTrait>(
+    cache_path: R,
+    replica_path: T,
+    comm_d: Commitment,
+) -> Result<Vec<CacheKey>>
+where
+    R: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    info!("regenerate_missing_trees:start");
+
+    let t_aux = load_t_aux::<Tree>(cache_path.as_ref())?;
+    let mut regenerated = Vec::new();
+
+    let tree_c_configs = split_config(t_aux.tree_c_config.clone(), get_base_tree_count::<Tree>())?;
+    if tree_c_configs.iter().any(|config| !store_exists(config)) {
+        regenerate_tree_c::<_, Tree>(&cache_path)?;
+        regenerated.push(CacheKey::CommCTree);
+    }
+
+    if !store_exists(&t_aux.tree_d_config) {
+        regenerate_tree_d::<_, _, Tree>(&cache_path, &replica_path, comm_d)?;
+        regenerated.push(CacheKey::CommDTree);
+    }
+
+    info!("regenerate_missing_trees:finish");
+    Ok(regenerated)
+}
+
+/// Rebuilds `tree-c` in `cache_path` from the persisted layer labels, and replaces any existing
+/// `tree-c` files with it if its root matches `comm_c` in `p_aux`. Otherwise, the existing files
+/// are left untouched.
+///
+/// # Arguments
+///
+/// * `cache_path` - path to the directory in which the sector's cache is written.
+pub fn regenerate_tree_c<R, Tree: 'static + MerkleTreeTrait>(cache_path: R) -> Result<()>
+where
+    R: AsRef<Path>,
+{
+    info!("regenerate_tree_c:start");
+
+    let cache = cache_path.as_ref();
+    let p_aux: PersistentAux<<Tree::Hasher as Hasher>::Domain> =
+        load_aux(&cache.join(CacheKey::PAux.to_string()))?;
+    let t_aux = load_t_aux::<Tree>(cache)?;
+
+    StackedDrg::<Tree, DefaultPieceHasher>::regenerate_tree_c(&t_aux, &p_aux.comm_c)?;
+
+    info!("regenerate_tree_c:finish");
+    Ok(())
+}
+
+/// Rebuilds `tree-d` in `cache_path` by decoding the replica with the persisted last layer
+/// labels, and replaces any existing `tree-d` file with it if its root matches `comm_d`.
+/// Otherwise, the existing file is left untouched.
+///
+/// The replica is only read, so this also works once the staged sector has been removed.
+///
+/// # Arguments
+///
+/// * `cache_path` - path to the directory in which the sector's cache is written.
+/// * `replica_path` - path to the sealed sector replica.
+/// * `comm_d` - the data commitment returned by `seal_pre_commit_phase2`.
+pub fn regenerate_tree_d<R, T, Tree: 'static + MerkleTreeTrait>(
+    cache_path: R,
+    replica_path: T,
+    comm_d: Commitment,
+) -> Result<()>
+where
+    R: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    info!("regenerate_tree_d:start");
+
+    let comm_d_safe =
+        as_safe_commitment::<<DefaultPieceHasher as Hasher>::Domain, _>(&comm_d, "comm_d")?;
+    let t_aux = load_t_aux::<Tree>(cache_path.as_ref())?;
+
+    StackedDrg::<Tree, DefaultPieceHasher>::regenerate_tree_d(
+        &t_aux,
+        replica_path.as_ref(),
+        &comm_d_safe,
+    )?;
+
+    info!("regenerate_tree_d:finish");
+    Ok(())
+}
+
+fn load_t_aux<Tree: MerkleTreeTrait>(
+    cache: &Path,
+) -> Result<TemporaryAux<Tree, DefaultPieceHasher>> {
+    let mut t_aux: TemporaryAux<Tree, DefaultPieceHasher> =
+        load_aux(&cache.join(CacheKey::TAux.to_string()))?;
+
+    // Switch t_aux to the passed in cache_path
+    t_aux.set_cache_path(cache);
+    Ok(t_aux)
+}
+
+fn load_aux<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let bytes = fs::read(path).with_context(|| format!("could not read file {:?}", path))?;
+
+    deserialize(&bytes).with_context(|| format!("could not deserialize {:?}", path))
+}
+
+fn store_exists(config: &StoreConfig) -> bool {
+    StoreConfig::data_path(&config.path, &config.id).exists()
+}
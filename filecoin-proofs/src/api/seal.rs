@@ -16,11 +16,12 @@ use storage_proofs_core::{
     cache_key::CacheKey,
     compound_proof::{self, CompoundProof},
     drgraph::Graph,
-    measurements::{measure_op, Operation},
+    measurements::{measure_op, with_tags, Operation},
     merkle::{create_base_merkle_tree, BinaryMerkleTree, MerkleTreeTrait},
     multi_proof::MultiProof,
     parameter_cache::SRS_MAX_PROOFS_TO_AGGREGATE,
     proof::ProofScheme,
+    run_options::RunOptions,
     sector::SectorId,
     util::default_rows_to_discard,
     Data,
@@ -31,7 +32,10 @@ use storage_proofs_porep::stacked::{
 };
 
 use crate::{
-    api::{as_safe_commitment, commitment_from_fr, get_base_tree_leafs, get_base_tree_size},
+    api::{
+        as_safe_commitment, commitment_from_fr, get_base_tree_leafs, get_base_tree_size,
+        persist_sector_meta, read_sector_meta,
+    },
     caches::{
         get_stacked_params, get_stacked_srs_key, get_stacked_srs_verifier_key,
         get_stacked_verifying_key,
@@ -40,15 +44,23 @@ use crate::{
         DefaultBinaryTree, DefaultPieceDomain, DefaultPieceHasher, POREP_MINIMUM_CHALLENGES,
         SINGLE_PARTITION_PROOF_LEN,
     },
-    parameters::setup_params,
+    parameters::{public_params, setup_params},
     pieces::{self, verify_pieces},
     types::{
-        AggregateSnarkProof, Commitment, PaddedBytesAmount, PieceInfo, PoRepConfig,
-        PoRepProofPartitions, ProverId, SealCommitOutput, SealCommitPhase1Output,
-        SealPreCommitOutput, SealPreCommitPhase1Output, SectorSize, Ticket, BINARY_ARITY,
+        AggregateProofGroup, AggregateSnarkProof, Commitment, HeterogeneousAggregateProof,
+        PaddedBytesAmount, PieceInfo, PoRepConfig, PoRepProofPartitions, ProverId,
+        SealAggregationSession, SealCommitOutput, SealCommitPhase1Output, SealPreCommitOutput,
+        SealPreCommitPhase1Output, SectorMeta, SectorSize, Ticket, BINARY_ARITY,
     },
+    with_shape,
 };
 
+/// Generates the labels of all layers for the sector at `in_path`.
+///
+/// The labeling is resumable: each completed layer is stored in `cache_path` and recorded in
+/// a per-layer digest manifest. If a previous call for the same sector was interrupted,
+/// the layers that are complete and match their recorded digest are reused, and labeling
+/// continues from the next layer.
 #[allow(clippy::too_many_arguments)]
 pub fn seal_pre_commit_phase1<R, S, T, Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
@@ -60,12 +72,49 @@ pub fn seal_pre_commit_phase1<R, S, T, Tree: 'static + MerkleTreeTrait>(
     ticket: Ticket,
     piece_infos: &[PieceInfo],
 ) -> Result<SealPreCommitPhase1Output<Tree>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    seal_pre_commit_phase1_with_options(
+        porep_config,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+        &RunOptions::default(),
+    )
+}
+
+/// Same as `seal_pre_commit_phase1`, reporting the labeling progress to `options.progress`.
+///
+/// Returns `Error::Cancelled` once `options.cancel` is observed, which labeling checks
+/// regularly. The layer being generated when it is cancelled is dropped, while completed layers
+/// are kept so that a later call resumes from them.
+#[allow(clippy::too_many_arguments)]
+pub fn seal_pre_commit_phase1_with_options<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+    options: &RunOptions<'_>,
+) -> Result<SealPreCommitPhase1Output<Tree>>
 where
     R: AsRef<Path>,
     S: AsRef<Path>,
     T: AsRef<Path>,
 {
     info!("seal_pre_commit_phase1:start: {:?}", sector_id);
+    let _config = options.enter_config();
+    options.cancel.check()?;
 
     // Sanity check all input path types.
     ensure!(
@@ -118,6 +167,8 @@ where
             usize::from(PoRepProofPartitions::from(porep_config)),
             porep_config.porep_id,
             porep_config.api_version,
+            porep_config.non_interactive,
+            porep_config.synthetic,
         )?,
         partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
         priority: false,
@@ -181,11 +232,14 @@ where
         &porep_config.porep_id,
     );
 
-    let labels = StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase1(
-        &compound_public_params.vanilla_params,
-        &replica_id,
-        config.clone(),
-    )?;
+    let labels = with_tags(&[("sector_id", sector_id.to_string())], || {
+        StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase1_with_options(
+            &compound_public_params.vanilla_params,
+            &replica_id,
+            config.clone(),
+            options,
+        )
+    })?;
 
     let out = SealPreCommitPhase1Output {
         labels,
@@ -204,11 +258,39 @@ pub fn seal_pre_commit_phase2<R, S, Tree: 'static + MerkleTreeTrait>(
     cache_path: S,
     replica_path: R,
 ) -> Result<SealPreCommitOutput>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+{
+    seal_pre_commit_phase2_with_options(
+        porep_config,
+        phase1_output,
+        cache_path,
+        replica_path,
+        &RunOptions::default(),
+    )
+}
+
+/// Same as `seal_pre_commit_phase2`, reporting every finished base tree to `options.progress`.
+///
+/// Returns `Error::Cancelled` once `options.cancel` is observed, which tree building checks
+/// between trees and between the batches of a tree. On cancellation the trees built by this call
+/// are removed, and the replica is left as it was: the nodes already encoded while building
+/// tree_r_last are decoded back.
+pub fn seal_pre_commit_phase2_with_options<R, S, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    phase1_output: SealPreCommitPhase1Output<Tree>,
+    cache_path: S,
+    replica_path: R,
+    options: &RunOptions<'_>,
+) -> Result<SealPreCommitOutput>
 where
     R: AsRef<Path>,
     S: AsRef<Path>,
 {
     info!("seal_pre_commit_phase2:start");
+    let _config = options.enter_config();
+    options.cancel.check()?;
 
     // Sanity check all input path types.
     ensure!(
@@ -277,6 +359,8 @@ where
             usize::from(PoRepProofPartitions::from(porep_config)),
             porep_config.porep_id,
             porep_config.api_version,
+            porep_config.non_interactive,
+            porep_config.synthetic,
         )?,
         partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
         priority: false,
@@ -287,14 +371,16 @@ where
         _,
     >>::setup(&compound_setup_params)?;
 
-    let (tau, (p_aux, t_aux)) = StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase2(
-        &compound_public_params.vanilla_params,
-        labels,
-        data,
-        data_tree,
-        config,
-        replica_path.as_ref().to_path_buf(),
-    )?;
+    let (tau, (p_aux, t_aux)) =
+        StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase2_with_options(
+            &compound_public_params.vanilla_params,
+            labels,
+            data,
+            data_tree,
+            config,
+            replica_path.as_ref().to_path_buf(),
+            options,
+        )?;
 
     let comm_r = commitment_from_fr(tau.comm_r.into());
 
@@ -315,25 +401,48 @@ where
         .write_all(&t_aux_bytes)
         .with_context(|| format!("could not write to file t_aux={:?}", t_aux_path))?;
 
+    persist_sector_meta(
+        &cache_path,
+        &SectorMeta {
+            tree_r_last_rows_to_discard: t_aux.tree_r_last_config.rows_to_discard,
+        },
+    )?;
+
     let out = SealPreCommitOutput { comm_r, comm_d };
 
     info!("seal_pre_commit_phase2:finish");
     Ok(out)
 }
 
+/// Generates the vanilla proofs of all synthetic challenges of a sector sealed with a synthetic
+/// `porep_config`, and persists them in `cache_path`.
+///
+/// This is meant to be called right after `seal_pre_commit_phase2`. Once it returns, the layers
+/// can be discarded with `clear_layer_data`, since `seal_commit_phase1` only selects from the
+/// persisted proofs. The proofs can be discarded with `clear_synthetic_proofs` once the commit
+/// proof has been generated.
+///
+/// `seal_pre_commit_phase2` does not call this itself: the synthetic challenges are derived from
+/// the replica id, which needs the prover id, sector id, ticket and pieces that phase 2 is not
+/// given. Passing them would change its signature, or the serialized
+/// `SealPreCommitPhase1Output`, for every existing caller.
 #[allow(clippy::too_many_arguments)]
-pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+pub fn generate_synthetic_proofs<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
     cache_path: T,
     replica_path: T,
     prover_id: ProverId,
     sector_id: SectorId,
     ticket: Ticket,
-    seed: Ticket,
     pre_commit: SealPreCommitOutput,
     piece_infos: &[PieceInfo],
-) -> Result<SealCommitPhase1Output<Tree>> {
-    info!("seal_commit_phase1:start: {:?}", sector_id);
+) -> Result<()> {
+    info!("generate_synthetic_proofs:start: {:?}", sector_id);
+
+    ensure!(
+        porep_config.synthetic,
+        "synthetic proofs require a synthetic porep_config"
+    );
 
     // Sanity check all input path types.
     ensure!(
@@ -354,6 +463,101 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
         "pieces and comm_d do not match"
     );
 
+    let comm_r_safe = as_safe_commitment(&comm_r, "comm_r")?;
+    let comm_d_safe = DefaultPieceDomain::try_from_bytes(&comm_d)?;
+
+    let replica_id = generate_replica_id::<Tree::Hasher, _>(
+        &prover_id,
+        sector_id.into(),
+        &ticket,
+        comm_d_safe,
+        &porep_config.porep_id,
+    );
+
+    // The synthetic challenges do not depend on the seed.
+    let public_inputs = stacked::PublicInputs {
+        replica_id,
+        tau: Some(stacked::Tau {
+            comm_d: comm_d_safe,
+            comm_r: comm_r_safe,
+        }),
+        k: None,
+        seed: [0; 32],
+    };
+
+    let private_inputs = read_private_inputs::<_, _, Tree>(&cache_path, &replica_path)?;
+
+    let public_params = public_params::<Tree>(
+        PaddedBytesAmount::from(porep_config),
+        usize::from(PoRepProofPartitions::from(porep_config)),
+        porep_config.porep_id,
+        porep_config.api_version,
+        porep_config.non_interactive,
+        porep_config.synthetic,
+    )?;
+
+    let proofs_path = cache_path
+        .as_ref()
+        .join(CacheKey::SyntheticPoRepProofs.to_string());
+    with_tags(&[("sector_id", sector_id.to_string())], || {
+        measure_op(Operation::VanillaProofs, || {
+            StackedDrg::<Tree, DefaultPieceHasher>::prove_synthetic_challenges(
+                &public_params,
+                &public_inputs,
+                &private_inputs,
+                &proofs_path,
+            )
+        })
+    })?;
+
+    info!("generate_synthetic_proofs:finish: {:?}", sector_id);
+    Ok(())
+}
+
+/// Discards the layers of the sector cached in `cache_path`, which are not needed anymore once
+/// the synthetic proofs have been generated.
+pub fn clear_layer_data<Tree: MerkleTreeTrait>(cache_path: &Path) -> Result<()> {
+    info!("clear_layer_data:start");
+
+    let t_aux: TemporaryAux<Tree, DefaultPieceHasher> = {
+        let t_aux_path = cache_path.join(CacheKey::TAux.to_string());
+        let t_aux_bytes = fs::read(&t_aux_path)
+            .with_context(|| format!("could not read file t_aux={:?}", t_aux_path))?;
+
+        let mut res: TemporaryAux<_, _> = deserialize(&t_aux_bytes)?;
+        res.set_cache_path(cache_path);
+        res
+    };
+    let result = t_aux.clear_labels();
+
+    info!("clear_layer_data:finish");
+    result
+}
+
+/// Discards the synthetic proofs of the sector cached in `cache_path`, once the commit proof
+/// has been generated.
+pub fn clear_synthetic_proofs(cache_path: &Path) -> Result<()> {
+    info!("clear_synthetic_proofs:start");
+
+    let proofs_path = cache_path.join(CacheKey::SyntheticPoRepProofs.to_string());
+    if proofs_path.exists() {
+        fs::remove_file(&proofs_path)
+            .with_context(|| format!("could not remove file {:?}", proofs_path))?;
+    }
+
+    info!("clear_synthetic_proofs:finish");
+    Ok(())
+}
+
+/// Restores the private inputs of the vanilla proofs from the aux files in `cache_path`.
+fn read_private_inputs<R, T, Tree: 'static + MerkleTreeTrait>(
+    cache_path: R,
+    replica_path: T,
+) -> Result<stacked::PrivateInputs<Tree, DefaultPieceHasher>>
+where
+    R: AsRef<Path>,
+    T: AsRef<Path>,
+{
     let p_aux = {
         let p_aux_path = cache_path.as_ref().join(CacheKey::PAux.to_string());
         let p_aux_bytes = fs::read(&p_aux_path)
@@ -370,7 +574,11 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
         let mut res: TemporaryAux<_, _> = deserialize(&t_aux_bytes)?;
 
         // Switch t_aux to the passed in cache_path
-        res.set_cache_path(cache_path);
+        res.set_cache_path(&cache_path);
+        // The cached rows of tree_r_last may have been re-derived since t_aux was written.
+        if let Some(meta) = read_sector_meta(&cache_path)? {
+            res.tree_r_last_config.rows_to_discard = meta.tree_r_last_rows_to_discard;
+        }
         res
     };
 
@@ -380,6 +588,45 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
         TemporaryAuxCache::new(&t_aux, replica_path.as_ref().to_path_buf())
             .context("failed to restore contents of t_aux")?;
 
+    Ok(stacked::PrivateInputs {
+        p_aux,
+        t_aux: t_aux_cache,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: T,
+    replica_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    seed: Ticket,
+    pre_commit: SealPreCommitOutput,
+    piece_infos: &[PieceInfo],
+) -> Result<SealCommitPhase1Output<Tree>> {
+    info!("seal_commit_phase1:start: {:?}", sector_id);
+
+    // Sanity check all input path types.
+    ensure!(
+        metadata(cache_path.as_ref())?.is_dir(),
+        "cache_path must be a directory"
+    );
+    ensure!(
+        metadata(replica_path.as_ref())?.is_file(),
+        "replica_path must be a file"
+    );
+
+    let SealPreCommitOutput { comm_d, comm_r } = pre_commit;
+
+    ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
+    ensure!(comm_r != [0; 32], "Invalid all zero commitment (comm_r)");
+    ensure!(
+        verify_pieces(&comm_d, piece_infos, porep_config.into())?,
+        "pieces and comm_d do not match"
+    );
+
     let comm_r_safe = as_safe_commitment(&comm_r, "comm_r")?;
     let comm_d_safe = DefaultPieceDomain::try_from_bytes(&comm_d)?;
 
@@ -401,17 +648,14 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
         seed,
     };
 
-    let private_inputs = stacked::PrivateInputs::<Tree, DefaultPieceHasher> {
-        p_aux,
-        t_aux: t_aux_cache,
-    };
-
     let compound_setup_params = compound_proof::SetupParams {
         vanilla_params: setup_params(
             PaddedBytesAmount::from(porep_config),
             usize::from(PoRepProofPartitions::from(porep_config)),
             porep_config.porep_id,
             porep_config.api_version,
+            porep_config.non_interactive,
+            porep_config.synthetic,
         )?,
         partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
         priority: false,
@@ -422,12 +666,31 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
         _,
     >>::setup(&compound_setup_params)?;
 
-    let vanilla_proofs = StackedDrg::prove_all_partitions(
-        &compound_public_params.vanilla_params,
-        &public_inputs,
-        &private_inputs,
-        StackedCompound::partition_count(&compound_public_params),
-    )?;
+    let vanilla_proofs = with_tags(&[("sector_id", sector_id.to_string())], || {
+        measure_op(Operation::VanillaProofs, || {
+            if porep_config.synthetic {
+                // The synthetic proofs were generated ahead of the seed, so the layers
+                // are not required anymore.
+                let proofs_path = cache_path
+                    .as_ref()
+                    .join(CacheKey::SyntheticPoRepProofs.to_string());
+                StackedDrg::<Tree, DefaultPieceHasher>::read_synthetic_proofs(
+                    &compound_public_params.vanilla_params,
+                    &public_inputs,
+                    StackedCompound::partition_count(&compound_public_params),
+                    &proofs_path,
+                )
+            } else {
+                let private_inputs = read_private_inputs::<_, _, Tree>(&cache_path, &replica_path)?;
+                StackedDrg::prove_all_partitions(
+                    &compound_public_params.vanilla_params,
+                    &public_inputs,
+                    &private_inputs,
+                    StackedCompound::partition_count(&compound_public_params),
+                )
+            }
+        })
+    })?;
 
     let sanity_check = StackedDrg::<Tree, DefaultPieceHasher>::verify_all_partitions(
         &compound_public_params.vanilla_params,
@@ -455,8 +718,29 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
     phase1_output: SealCommitPhase1Output<Tree>,
     prover_id: ProverId,
     sector_id: SectorId,
+) -> Result<SealCommitOutput> {
+    seal_commit_phase2_with_options(
+        porep_config,
+        phase1_output,
+        prover_id,
+        sector_id,
+        &RunOptions::default(),
+    )
+}
+
+/// Same as `seal_commit_phase2`, reporting every proved partition to `options.progress`.
+/// Returns `Error::Cancelled` if `options.cancel` is observed before or during circuit
+/// synthesis.
+pub fn seal_commit_phase2_with_options<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    phase1_output: SealCommitPhase1Output<Tree>,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    options: &RunOptions<'_>,
 ) -> Result<SealCommitOutput> {
     info!("seal_commit_phase2:start: {:?}", sector_id);
+    let _config = options.enter_config();
+    options.cancel.check()?;
 
     let SealCommitPhase1Output {
         vanilla_proofs,
@@ -496,6 +780,8 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
             usize::from(PoRepProofPartitions::from(porep_config)),
             porep_config.porep_id,
             porep_config.api_version,
+            porep_config.non_interactive,
+            porep_config.synthetic,
         )?,
         partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
         priority: false,
@@ -507,13 +793,16 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
     >>::setup(&compound_setup_params)?;
 
     info!("snark_proof:start");
-    let groth_proofs = StackedCompound::<Tree, DefaultPieceHasher>::circuit_proofs(
-        &public_inputs,
-        vanilla_proofs,
-        &compound_public_params.vanilla_params,
-        &groth_params,
-        compound_public_params.priority,
-    )?;
+    let groth_proofs = with_tags(&[("sector_id", sector_id.to_string())], || {
+        StackedCompound::<Tree, DefaultPieceHasher>::circuit_proofs_with_options(
+            &public_inputs,
+            vanilla_proofs,
+            &compound_public_params.vanilla_params,
+            &groth_params,
+            compound_public_params.priority,
+            options,
+        )
+    })?;
     info!("snark_proof:finish");
 
     let proof = MultiProof::new(groth_proofs, &groth_params.pvk);
@@ -559,7 +848,7 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
 /// * `prover_id` - the prover_id used to seal this sector.
 /// * `sector_id` - the sector_id of this sector.
 /// * `ticket` - the ticket used to generate this sector's replica-id.
-/// * `seed` - the seed used to derive the porep challenges.
+/// * `seed` - the seed used to derive the porep challenges, unused by the non-interactive PoRep.
 pub fn get_seal_inputs<Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
     comm_r: Commitment,
@@ -601,6 +890,8 @@ pub fn get_seal_inputs<Tree: 'static + MerkleTreeTrait>(
             usize::from(PoRepProofPartitions::from(porep_config)),
             porep_config.porep_id,
             porep_config.api_version,
+            porep_config.non_interactive,
+            porep_config.synthetic,
         )?,
         partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
         priority: false,
@@ -634,7 +925,7 @@ pub fn get_seal_inputs<Tree: 'static + MerkleTreeTrait>(
 }
 
 /// Given a value, get one suitable for aggregation.
-fn get_aggregate_target_len(len: usize) -> usize {
+pub(crate) fn get_aggregate_target_len(len: usize) -> usize {
     if len == 1 {
         2
     } else {
@@ -643,7 +934,10 @@ fn get_aggregate_target_len(len: usize) -> usize {
 }
 
 /// Given a list of proofs and a target_len, make sure that the proofs list is padded to the target_len size.
-fn pad_proofs_to_target(proofs: &mut Vec<groth16::Proof<Bls12>>, target_len: usize) -> Result<()> {
+pub(crate) fn pad_proofs_to_target(
+    proofs: &mut Vec<groth16::Proof<Bls12>>,
+    target_len: usize,
+) -> Result<()> {
     trace!(
         "pad_proofs_to_target target_len {}, proofs len {}",
         target_len,
@@ -680,7 +974,7 @@ fn pad_proofs_to_target(proofs: &mut Vec<groth16::Proof<Bls12>>, target_len: usi
 }
 
 /// Given a list of public inputs and a target_len, make sure that the inputs list is padded to the target_len size.
-fn pad_inputs_to_target(
+pub(crate) fn pad_inputs_to_target(
     commit_inputs: &[Vec<Fr>],
     num_inputs_per_proof: usize,
     target_len: usize,
@@ -734,7 +1028,7 @@ pub fn aggregate_seal_commit_proofs<Tree: 'static + MerkleTreeTrait>(
 
     let partitions = usize::from(PoRepProofPartitions::from(porep_config));
     let verifying_key = get_stacked_verifying_key::<Tree>(porep_config)?;
-    let mut proofs: Vec<_> =
+    let proofs: Vec<_> =
         commit_outputs
             .iter()
             .try_fold(Vec::new(), |mut acc, commit_output| -> Result<_> {
@@ -755,6 +1049,101 @@ pub fn aggregate_seal_commit_proofs<Tree: 'static + MerkleTreeTrait>(
         proofs.len(),
     );
 
+    let aggregate_proof_bytes =
+        aggregate_partition_proofs::<Tree>(porep_config, comm_rs, seeds, proofs)?;
+
+    info!("aggregate_seal_commit_proofs:finish");
+
+    Ok(aggregate_proof_bytes)
+}
+
+/// Adds a seal proof to an incremental aggregation `session`. The first added proof determines
+/// the porep config of the session, and proofs with a different porep config are rejected.
+///
+/// # Arguments
+///
+/// * `porep_config` - this sector's porep config that contains the number of bytes in the sector.
+/// * `session` - the aggregation session the proof is added to.
+/// * `comm_r` - a commitment to this sector's replica.
+/// * `seed` - the seed used to derive the PoRep challenges.
+/// * `commit_output` - the seal proof output returned from 'seal_commit_phase2'.
+pub fn seal_aggregation_add_proof(
+    porep_config: PoRepConfig,
+    session: &mut SealAggregationSession,
+    comm_r: Commitment,
+    seed: Ticket,
+    commit_output: &SealCommitOutput,
+) -> Result<()> {
+    if let Some(session_config) = session.porep_config {
+        ensure!(
+            session_config == porep_config,
+            "porep config {:?} does not match the session's porep config {:?}",
+            porep_config,
+            session_config
+        );
+    }
+
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    ensure!(
+        commit_output.proof.len() == partitions * SINGLE_PARTITION_PROOF_LEN,
+        "invalid seal proof length {}, expected {} partitions",
+        commit_output.proof.len(),
+        partitions
+    );
+    ensure!(
+        session.partition_proofs_len() + partitions <= SRS_MAX_PROOFS_TO_AGGREGATE,
+        "proof count for aggregation is larger than the max supported value"
+    );
+
+    // Reject malformed proofs now instead of failing the whole aggregation later.
+    groth16::Proof::<Bls12>::read_many(&commit_output.proof, partitions)?;
+
+    session.porep_config = Some(porep_config);
+    session.comm_rs.push(comm_r);
+    session.seeds.push(seed);
+    session.proofs.extend_from_slice(&commit_output.proof);
+
+    trace!(
+        "seal_aggregation_add_proof: session holds {} seal proofs",
+        session.len()
+    );
+
+    Ok(())
+}
+
+/// Aggregates the seal proofs added to `session`, with the session's porep config. The aggregate
+/// proof is verified with `verify_aggregate_seal_commit_proofs`, given the session's porep
+/// config, comm_rs and seeds.
+///
+/// The partition proofs are padded to `session.padded_partition_proofs_len()` by duplicating the
+/// last one. As SnarkPack only aggregates a power of two number of proofs, this is the least
+/// padding possible.
+pub fn seal_aggregation_finalize<Tree: 'static + MerkleTreeTrait>(
+    session: &SealAggregationSession,
+) -> Result<AggregateSnarkProof> {
+    info!("seal_aggregation_finalize:start");
+
+    let porep_config = session
+        .porep_config
+        .context("cannot aggregate with empty outputs")?;
+
+    let proofs =
+        groth16::Proof::<Bls12>::read_many(&session.proofs, session.partition_proofs_len())?;
+    let aggregate_proof_bytes =
+        aggregate_partition_proofs::<Tree>(porep_config, &session.comm_rs, &session.seeds, proofs)?;
+
+    info!("seal_aggregation_finalize:finish");
+
+    Ok(aggregate_proof_bytes)
+}
+
+/// Aggregates the partition proofs of the seal proofs for `comm_rs` and `seeds`.
+fn aggregate_partition_proofs<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    comm_rs: &[[u8; 32]],
+    seeds: &[[u8; 32]],
+    mut proofs: Vec<groth16::Proof<Bls12>>,
+) -> Result<AggregateSnarkProof> {
     let target_proofs_len = get_aggregate_target_len(proofs.len());
     ensure!(
         target_proofs_len > 1,
@@ -788,8 +1177,6 @@ pub fn aggregate_seal_commit_proofs<Tree: 'static + MerkleTreeTrait>(
     let mut aggregate_proof_bytes = Vec::new();
     aggregate_proof.write(&mut aggregate_proof_bytes)?;
 
-    info!("aggregate_seal_commit_proofs:finish");
-
     Ok(aggregate_proof_bytes)
 }
 
@@ -886,6 +1273,174 @@ pub fn verify_aggregate_seal_commit_proofs<Tree: 'static + MerkleTreeTrait>(
     Ok(result)
 }
 
+/// Given the porep configs, comm_rs, seeds and seal proof outputs of sectors which may differ in
+/// sector size and porep_id, aggregates the seal proofs of each group of sectors sharing a
+/// verifying key and returns the groups as one aggregate proof. The sectors of a group do not need to be
+/// consecutive.
+///
+/// # Arguments
+///
+/// * `porep_configs` - the porep config of each sector, in the order of the seal proofs.
+/// * `comm_rs` - an ordered list of commitments to each sector's replica.
+/// * `seeds` - an ordered list of seeds used to derive the PoRep challenges.
+/// * `commit_outputs` - an ordered list of seal proof outputs returned from 'seal_commit_phase2'.
+pub fn aggregate_seal_commit_proofs_heterogeneous(
+    porep_configs: &[PoRepConfig],
+    comm_rs: &[[u8; 32]],
+    seeds: &[[u8; 32]],
+    commit_outputs: &[SealCommitOutput],
+) -> Result<AggregateSnarkProof> {
+    info!("aggregate_seal_commit_proofs_heterogeneous:start");
+
+    ensure!(
+        !commit_outputs.is_empty(),
+        "cannot aggregate with empty outputs"
+    );
+    ensure!(
+        porep_configs.len() == commit_outputs.len()
+            && comm_rs.len() == commit_outputs.len()
+            && seeds.len() == commit_outputs.len(),
+        "invalid porep_configs, comm_rs, seeds and commit_outputs len mismatch"
+    );
+
+    let groups = aggregation_groups(porep_configs)
+        .into_iter()
+        .map(|indices| -> Result<_> {
+            let porep_config = porep_configs[indices[0]];
+            let group_comm_rs: Vec<_> = indices.iter().map(|&i| comm_rs[i]).collect();
+            let group_seeds: Vec<_> = indices.iter().map(|&i| seeds[i]).collect();
+            let group_commit_outputs: Vec<_> =
+                indices.iter().map(|&i| commit_outputs[i].clone()).collect();
+            let proof = with_shape!(
+                u64::from(porep_config.sector_size),
+                aggregate_seal_commit_proofs,
+                porep_config,
+                &group_comm_rs,
+                &group_seeds,
+                &group_commit_outputs,
+            )?;
+
+            Ok(AggregateProofGroup {
+                sector_size: u64::from(porep_config.sector_size),
+                indices,
+                proof,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    trace!(
+        "aggregate_seal_commit_proofs_heterogeneous aggregated {} groups",
+        groups.len()
+    );
+
+    let aggregate_proof_bytes = serialize(&HeterogeneousAggregateProof { groups })?;
+
+    info!("aggregate_seal_commit_proofs_heterogeneous:finish");
+
+    Ok(aggregate_proof_bytes)
+}
+
+/// Given the porep configs, comm_rs, seeds and public inputs of sectors which may differ in
+/// sector size and porep_id, verifies the aggregate proof of each group with the group's
+/// verifying key.
+///
+/// # Arguments
+///
+/// * `porep_configs` - the porep config of each sector, in the order of the seal proofs.
+/// * `aggregate_proof_bytes` - the aggregate proof from 'aggregate_seal_commit_proofs_heterogeneous'.
+/// * `comm_rs` - an ordered list of commitments to each sector's replica.
+/// * `seeds` - an ordered list of seeds used to derive the PoRep challenges.
+/// * `commit_inputs` - an ordered list of each sector's public inputs, as returned by
+///    'get_seal_inputs'.
+pub fn verify_aggregate_seal_commit_proofs_heterogeneous(
+    porep_configs: &[PoRepConfig],
+    aggregate_proof_bytes: AggregateSnarkProof,
+    comm_rs: &[[u8; 32]],
+    seeds: &[[u8; 32]],
+    commit_inputs: &[Vec<Vec<Fr>>],
+) -> Result<bool> {
+    info!("verify_aggregate_seal_commit_proofs_heterogeneous:start");
+
+    ensure!(
+        porep_configs.len() == commit_inputs.len()
+            && comm_rs.len() == commit_inputs.len()
+            && seeds.len() == commit_inputs.len(),
+        "invalid porep_configs, comm_rs, seeds and commit_inputs len mismatch"
+    );
+
+    let aggregate_proof: HeterogeneousAggregateProof = deserialize(&aggregate_proof_bytes)?;
+    let expected_groups = aggregation_groups(porep_configs);
+    ensure!(
+        aggregate_proof.groups.len() == expected_groups.len(),
+        "invalid number of aggregate proof groups"
+    );
+
+    for (group, indices) in aggregate_proof.groups.into_iter().zip(expected_groups) {
+        let porep_config = porep_configs[indices[0]];
+        ensure!(
+            group.indices == indices,
+            "invalid aggregate proof group indices"
+        );
+        ensure!(
+            group.sector_size == u64::from(porep_config.sector_size),
+            "aggregate proof group does not match its porep config"
+        );
+
+        let group_comm_rs: Vec<_> = indices.iter().map(|&i| comm_rs[i]).collect();
+        let group_seeds: Vec<_> = indices.iter().map(|&i| seeds[i]).collect();
+        let group_inputs: Vec<Vec<Fr>> = indices
+            .iter()
+            .flat_map(|&i| commit_inputs[i].iter().cloned())
+            .collect();
+        let verified = with_shape!(
+            group.sector_size,
+            verify_aggregate_seal_commit_proofs,
+            porep_config,
+            group.proof,
+            &group_comm_rs,
+            &group_seeds,
+            group_inputs,
+        )?;
+        if !verified {
+            info!(
+                "verify_aggregate_seal_commit_proofs_heterogeneous: group {:?} failed",
+                indices
+            );
+            return Ok(false);
+        }
+    }
+
+    info!("verify_aggregate_seal_commit_proofs_heterogeneous:finish");
+
+    Ok(true)
+}
+
+/// Splits the sectors into groups of sectors which share a verifying key and can therefore be
+/// aggregated together, wherever they are in `porep_configs`. Each group is returned as the
+/// ascending indices of its sectors, and the groups are ordered by their first sector.
+fn aggregation_groups(porep_configs: &[PoRepConfig]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (i, porep_config) in porep_configs.iter().enumerate() {
+        match groups
+            .iter_mut()
+            .find(|group| same_verifying_key(&porep_configs[group[0]], porep_config))
+        {
+            Some(group) => group.push(i),
+            None => groups.push(vec![i]),
+        }
+    }
+
+    groups
+}
+
+/// Returns whether the seal proofs of `a` and `b` share a verifying key. The porep_id and the api
+/// version only enter the public inputs, and the synthetic PoRep uses the parameters of the
+/// interactive PoRep.
+fn same_verifying_key(a: &PoRepConfig, b: &PoRepConfig) -> bool {
+    a.sector_size == b.sector_size
+        && a.partitions == b.partitions
+        && a.non_interactive == b.non_interactive
+}
+
 /// Computes a sectors's `comm_d` given its pieces.
 ///
 /// # Arguments
@@ -911,7 +1466,7 @@ pub fn compute_comm_d(sector_size: SectorSize, piece_infos: &[PieceInfo]) -> Res
 /// * `prover_id` - the prover-id that sealed this sector.
 /// * `sector_id` - this sector's sector-id.
 /// * `ticket` - the ticket that was used to generate this sector's replica-id.
-/// * `seed` - the seed used to derive the porep challenges.
+/// * `seed` - the seed used to derive the porep challenges, unused by the non-interactive PoRep.
 /// * `proof_vec` - the porep circuit proof serialized into a vector of bytes.
 #[allow(clippy::too_many_arguments)]
 pub fn verify_seal<Tree: 'static + MerkleTreeTrait>(
@@ -945,6 +1500,8 @@ pub fn verify_seal<Tree: 'static + MerkleTreeTrait>(
             usize::from(PoRepProofPartitions::from(porep_config)),
             porep_config.porep_id,
             porep_config.api_version,
+            porep_config.non_interactive,
+            porep_config.synthetic,
         )?,
         partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
         priority: false,
@@ -1006,7 +1563,8 @@ pub fn verify_seal<Tree: 'static + MerkleTreeTrait>(
 /// * `[prover_ids]` - list of prover-ids that sealed this sector.
 /// * `[sector_ids]` - list of the sector's sector-id.
 /// * `[tickets]` - list of tickets that was used to generate this sector's replica-id.
-/// * `[seeds]` - list of seeds used to derive the porep challenges.
+/// * `[seeds]` - list of seeds used to derive the porep challenges, unused by the non-interactive
+///   PoRep.
 /// * `[proof_vecs]` - list of porep circuit proofs serialized into a vector of bytes.
 #[allow(clippy::too_many_arguments)]
 pub fn verify_batch_seal<Tree: 'static + MerkleTreeTrait>(
@@ -1057,6 +1615,8 @@ pub fn verify_batch_seal<Tree: 'static + MerkleTreeTrait>(
             usize::from(PoRepProofPartitions::from(porep_config)),
             porep_config.porep_id,
             porep_config.api_version,
+            porep_config.non_interactive,
+            porep_config.synthetic,
         )?,
         partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
         priority: false,
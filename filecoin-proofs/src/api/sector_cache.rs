@@ -0,0 +1,375 @@
+use std::cmp::min;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::Result;
+use bincode::deserialize;
+use filecoin_hashers::{Domain, Hasher, PoseidonArity};
+use generic_array::typenum::{Unsigned, U0, U2};
+use log::info;
+use memmap::MmapOptions;
+use merkletree::{
+    merkle::get_merkle_tree_leafs,
+    store::{DiskStore, ExternalReader, Store, StoreConfig},
+};
+use serde::de::DeserializeOwned;
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::{
+        create_disk_tree, create_lc_tree, get_base_tree_count, split_config,
+        split_config_and_replica, DiskTree, LCStore, LCTree, MerkleTreeTrait,
+    },
+    util::NODE_SIZE,
+};
+use storage_proofs_porep::stacked::{
+    create_label::{layer_file_digest, LayerManifest},
+    PersistentAux, TemporaryAux,
+};
+
+use crate::{
+    api::read_sector_meta,
+    constants::DefaultPieceHasher,
+    types::{ArtifactStatus, SectorCacheReport},
+};
+
+/// The number of leaves read at once when recomputing a tree root.
+const LEAF_CHUNK_SIZE: usize = 1 << 16;
+
+type CheckResult<T> = std::result::Result<T, ArtifactStatus>;
+
+/// Checks every artifact of the sector cache in `cache_path`, and returns a report of what is
+/// missing, corrupt or mismatched.
+///
+/// The roots of `tree-d`, and of each `tree-c` and `tree-r-last` sub-tree, are recomputed from
+/// their leaves and compared against the cached roots. The combined `tree-c` and `tree-r-last`
+/// roots are compared against the commitments stored in `p_aux`, and the layer files against
+/// the layer manifest, if present. Note that recomputing the roots reads all of the trees.
+///
+/// # Arguments
+///
+/// * `cache_path` - path to the directory in which the sector's cache is written.
+/// * `replica_path` - path to the sealed sector replica.
+pub fn verify_sector_cache<R, T, Tree: 'static + MerkleTreeTrait>(
+    cache_path: R,
+    replica_path: T,
+) -> Result<SectorCacheReport>
+where
+    R: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    info!("verify_sector_cache:start");
+
+    let cache = cache_path.as_ref();
+    let mut report = SectorCacheReport::default();
+
+    let p_aux_path = cache.join(CacheKey::PAux.to_string());
+    let p_aux = read_aux::<PersistentAux<<Tree::Hasher as Hasher>::Domain>>(&p_aux_path);
+    report.push(CacheKey::PAux.to_string(), &p_aux_path, status_of(&p_aux));
+
+    let t_aux_path = cache.join(CacheKey::TAux.to_string());
+    let t_aux = read_aux::<TemporaryAux<Tree, DefaultPieceHasher>>(&t_aux_path);
+    report.push(CacheKey::TAux.to_string(), &t_aux_path, status_of(&t_aux));
+
+    // Without t_aux, the layout of the remaining artifacts is unknown.
+    let mut t_aux = match t_aux {
+        Ok(t_aux) => t_aux,
+        Err(_) => {
+            info!("verify_sector_cache:finish");
+            return Ok(report);
+        }
+    };
+    t_aux.set_cache_path(cache);
+    let p_aux = p_aux.ok();
+
+    // Sectors sealed without metadata are laid out as described by t_aux.
+    let meta_path = cache.join(CacheKey::SectorMeta.to_string());
+    match read_sector_meta(cache) {
+        Ok(Some(meta)) => {
+            t_aux.tree_r_last_config.rows_to_discard = meta.tree_r_last_rows_to_discard;
+            report.push(
+                CacheKey::SectorMeta.to_string(),
+                meta_path,
+                ArtifactStatus::Valid,
+            );
+        }
+        Ok(None) => {}
+        Err(err) => report.push(CacheKey::SectorMeta.to_string(), meta_path, corrupt(err)),
+    }
+
+    verify_layers(&t_aux, cache, &mut report);
+    verify_tree_d(&t_aux, &mut report);
+    verify_tree_c(&t_aux, p_aux.as_ref(), cache, &mut report);
+    verify_tree_r_last(
+        &t_aux,
+        p_aux.as_ref(),
+        cache,
+        replica_path.as_ref(),
+        &mut report,
+    );
+
+    info!("verify_sector_cache:finish");
+    Ok(report)
+}
+
+fn verify_layers<Tree: MerkleTreeTrait>(
+    t_aux: &TemporaryAux<Tree, DefaultPieceHasher>,
+    cache: &Path,
+    report: &mut SectorCacheReport,
+) {
+    let manifest = LayerManifest::load(cache).ok().flatten();
+
+    for (layer, config) in (1..).zip(t_aux.labels.labels.iter()) {
+        let path = StoreConfig::data_path(&config.path, &config.id);
+        let digest = manifest
+            .as_ref()
+            .and_then(|manifest| manifest.layers.get(&layer));
+        let status = flatten(verify_layer(config, digest));
+
+        report.push(config.id.clone(), path, status);
+    }
+}
+
+/// Checks the size of a layer file and, if a digest was recorded for it, its digest.
+fn verify_layer(config: &StoreConfig, digest: Option<&[u8; 32]>) -> CheckResult<ArtifactStatus> {
+    let path = StoreConfig::data_path(&config.path, &config.id);
+    check_file(&path, config.size.map(|size| size * NODE_SIZE))?;
+
+    match digest {
+        Some(expected) => {
+            let actual = layer_file_digest(config).map_err(corrupt)?;
+            Ok(compare(hex::encode(expected), hex::encode(actual)))
+        }
+        None => Ok(ArtifactStatus::Valid),
+    }
+}
+
+fn verify_tree_d<Tree: MerkleTreeTrait>(
+    t_aux: &TemporaryAux<Tree, DefaultPieceHasher>,
+    report: &mut SectorCacheReport,
+) {
+    let config = &t_aux.tree_d_config;
+    let path = StoreConfig::data_path(&config.path, &config.id);
+    let status = verify_disk_tree::<DefaultPieceHasher, U2>(config);
+
+    report.push(config.id.clone(), path, flatten(status));
+}
+
+fn verify_tree_c<Tree: 'static + MerkleTreeTrait>(
+    t_aux: &TemporaryAux<Tree, DefaultPieceHasher>,
+    p_aux: Option<&PersistentAux<<Tree::Hasher as Hasher>::Domain>>,
+    cache: &Path,
+    report: &mut SectorCacheReport,
+) {
+    let tree_count = get_base_tree_count::<Tree>();
+    let configs = match split_config(t_aux.tree_c_config.clone(), tree_count) {
+        Ok(configs) => configs,
+        Err(err) => {
+            report.push(CacheKey::CommCTree.to_string(), cache, corrupt(err));
+            return;
+        }
+    };
+
+    let mut valid = true;
+    for config in &configs {
+        let path = StoreConfig::data_path(&config.path, &config.id);
+        let status = flatten(verify_disk_tree::<Tree::Hasher, Tree::Arity>(config));
+        valid &= status == ArtifactStatus::Valid;
+
+        report.push(config.id.clone(), path, status);
+    }
+
+    // The combined root can only be checked against p_aux if all sub-trees are intact.
+    if let (true, Some(p_aux)) = (valid, p_aux) {
+        let status = t_aux
+            .tree_c_config
+            .size
+            .ok_or_else(|| corrupt("tree_c config has no size"))
+            .and_then(|size| {
+                create_disk_tree::<
+                    DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+                >(size, &configs)
+                .map_err(corrupt)
+            })
+            .map(|tree_c| compare_domain(p_aux.comm_c, tree_c.root()));
+
+        report.push("comm_c", cache, flatten(status));
+    }
+}
+
+fn verify_tree_r_last<Tree: 'static + MerkleTreeTrait>(
+    t_aux: &TemporaryAux<Tree, DefaultPieceHasher>,
+    p_aux: Option<&PersistentAux<<Tree::Hasher as Hasher>::Domain>>,
+    cache: &Path,
+    replica_path: &Path,
+    report: &mut SectorCacheReport,
+) {
+    let tree_count = get_base_tree_count::<Tree>();
+    let arity = Tree::Arity::to_usize();
+
+    let layout = t_aux
+        .tree_r_last_config
+        .size
+        .ok_or_else(|| corrupt("tree_r_last config has no size"))
+        .and_then(|size| {
+            let leafs = get_merkle_tree_leafs(size, arity).map_err(corrupt)?;
+            let (configs, replica_config) = split_config_and_replica(
+                t_aux.tree_r_last_config.clone(),
+                replica_path.to_path_buf(),
+                leafs,
+                tree_count,
+            )
+            .map_err(corrupt)?;
+
+            Ok((size, leafs, configs, replica_config))
+        });
+    let (size, leafs, configs, replica_config) = match layout {
+        Ok(layout) => layout,
+        Err(status) => {
+            report.push(CacheKey::CommRLastTree.to_string(), cache, status);
+            return;
+        }
+    };
+
+    let replica = check_file(replica_path, Some(leafs * tree_count * NODE_SIZE)).and_then(|_| {
+        let file = File::open(replica_path).map_err(corrupt)?;
+        unsafe { MmapOptions::new().map(&file) }.map_err(corrupt)
+    });
+    report.push("replica", replica_path, status_of(&replica));
+
+    let mut valid = replica.is_ok();
+    for (i, config) in configs.iter().enumerate() {
+        let path = StoreConfig::data_path(&config.path, &config.id);
+        let status = check_file(&path, None).and_then(|_| {
+            let reader = ExternalReader::new_from_config(&replica_config, i).map_err(corrupt)?;
+            let store = LCStore::<<Tree::Hasher as Hasher>::Domain>::new_from_disk_with_reader(
+                size, arity, config, reader,
+            )
+            .map_err(corrupt)?;
+            let cached_root = store.last().map_err(corrupt)?;
+
+            // The leaves of tree-r-last are the nodes of the replica itself.
+            let replica = replica.as_ref().map_err(Clone::clone)?;
+            let offset = replica_config.offsets[i];
+            let rebuilt_root = compute_base_root::<Tree::Hasher, Tree::Arity, _>(leafs, |range| {
+                replica[offset + range.start * NODE_SIZE..offset + range.end * NODE_SIZE]
+                    .chunks(NODE_SIZE)
+                    .map(<Tree::Hasher as Hasher>::Domain::try_from_bytes)
+                    .collect()
+            })
+            .map_err(corrupt)?;
+
+            Ok(compare_domain(rebuilt_root, cached_root))
+        });
+        let status = flatten(status);
+        valid &= status == ArtifactStatus::Valid;
+
+        report.push(config.id.clone(), path, status);
+    }
+
+    // The combined root can only be checked against p_aux if all sub-trees are intact.
+    if let (true, Some(p_aux)) = (valid, p_aux) {
+        let status = create_lc_tree::<
+            LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+        >(size, &configs, &replica_config)
+        .map(|tree_r_last| compare_domain(p_aux.comm_r_last, tree_r_last.root()))
+        .map_err(corrupt);
+
+        report.push("comm_r_last", cache, flatten(status));
+    }
+}
+
+/// Recomputes the root of a single base tree stored on disk, and compares it against the root
+/// stored with the tree.
+fn verify_disk_tree<H: Hasher, A: 'static + PoseidonArity>(
+    config: &StoreConfig,
+) -> CheckResult<ArtifactStatus> {
+    let path = StoreConfig::data_path(&config.path, &config.id);
+    let size = config
+        .size
+        .ok_or_else(|| corrupt(format!("{} config has no size", config.id)))?;
+    check_file(&path, Some(size * NODE_SIZE))?;
+
+    let leafs = get_merkle_tree_leafs(size, A::to_usize()).map_err(corrupt)?;
+    let store: DiskStore<H::Domain> =
+        DiskStore::new_from_disk(size, A::to_usize(), config).map_err(corrupt)?;
+    let cached_root = store.last().map_err(corrupt)?;
+    let rebuilt_root =
+        compute_base_root::<H, A, _>(leafs, |range| store.read_range(range)).map_err(corrupt)?;
+
+    Ok(compare_domain(rebuilt_root, cached_root))
+}
+
+/// Builds a base tree over `leafs` leaves, which are read in chunks through `read_leaves`,
+/// and returns its root.
+fn compute_base_root<H, A, F>(leafs: usize, mut read_leaves: F) -> Result<H::Domain>
+where
+    H: Hasher,
+    A: 'static + PoseidonArity,
+    F: FnMut(Range<usize>) -> Result<Vec<H::Domain>>,
+{
+    let mut chunk = Vec::new();
+    let leaves = (0..leafs).map(|i| {
+        if i % LEAF_CHUNK_SIZE == 0 {
+            chunk = read_leaves(i..min(i + LEAF_CHUNK_SIZE, leafs))?;
+        }
+        Ok(chunk[i % LEAF_CHUNK_SIZE])
+    });
+    let tree = DiskTree::<H, A, U0, U0>::try_from_iter(leaves)?;
+
+    Ok(tree.root())
+}
+
+fn read_aux<T: DeserializeOwned>(path: &Path) -> CheckResult<T> {
+    check_file(path, None)?;
+    let bytes = fs::read(path).map_err(corrupt)?;
+
+    deserialize(&bytes).map_err(corrupt)
+}
+
+/// Checks that the file at `path` exists and, if given, has the expected length in bytes.
+fn check_file(path: &Path, expected_len: Option<usize>) -> CheckResult<()> {
+    if !path.exists() {
+        return Err(ArtifactStatus::Missing);
+    }
+
+    let len = fs::metadata(path).map_err(corrupt)?.len() as usize;
+    match expected_len {
+        Some(expected_len) if expected_len != len => Err(corrupt(format!(
+            "expected {} bytes, found {}",
+            expected_len, len
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn corrupt<E: Display>(err: E) -> ArtifactStatus {
+    ArtifactStatus::Corrupt(err.to_string())
+}
+
+fn compare(expected: String, actual: String) -> ArtifactStatus {
+    if expected == actual {
+        ArtifactStatus::Valid
+    } else {
+        ArtifactStatus::Mismatch { expected, actual }
+    }
+}
+
+fn compare_domain<D: Domain>(expected: D, actual: D) -> ArtifactStatus {
+    compare(
+        hex::encode(AsRef::<[u8]>::as_ref(&expected)),
+        hex::encode(AsRef::<[u8]>::as_ref(&actual)),
+    )
+}
+
+fn status_of<T>(result: &CheckResult<T>) -> ArtifactStatus {
+    match result {
+        Ok(_) => ArtifactStatus::Valid,
+        Err(status) => status.clone(),
+    }
+}
+
+fn flatten(result: CheckResult<ArtifactStatus>) -> ArtifactStatus {
+    result.unwrap_or_else(|status| status)
+}
@@ -0,0 +1,108 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use bincode::{deserialize, serialize};
+use generic_array::typenum::Unsigned;
+use log::{info, trace};
+use merkletree::{
+    merkle::{get_merkle_tree_cache_size, get_merkle_tree_row_count},
+    store::StoreConfig,
+};
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::{get_base_tree_count, split_config, MerkleTreeTrait},
+    util::{default_rows_to_discard, NODE_SIZE},
+};
+
+use crate::{
+    api::{get_base_tree_leafs, get_base_tree_size},
+    types::{SectorMeta, SectorSize},
+};
+
+/// Writes the metadata of the sector cache in `cache_path`, replacing any existing metadata.
+pub fn persist_sector_meta<P: AsRef<Path>>(cache_path: P, meta: &SectorMeta) -> Result<()> {
+    let path = cache_path.as_ref().join(CacheKey::SectorMeta.to_string());
+    let tmp_path = path.with_extension("tmp");
+
+    fs::write(&tmp_path, serialize(meta)?)
+        .with_context(|| format!("could not write file sector_meta={:?}", tmp_path))?;
+    fs::rename(&tmp_path, &path)
+        .with_context(|| format!("could not rename file sector_meta={:?}", tmp_path))?;
+
+    Ok(())
+}
+
+/// Reads the metadata of the sector cache in `cache_path`. Returns `None` for sectors sealed
+/// without metadata.
+pub fn read_sector_meta<P: AsRef<Path>>(cache_path: P) -> Result<Option<SectorMeta>> {
+    let path = cache_path.as_ref().join(CacheKey::SectorMeta.to_string());
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("could not read file sector_meta={:?}", path))
+        }
+    };
+
+    let meta = deserialize(&bytes)
+        .with_context(|| format!("could not deserialize file sector_meta={:?}", path))?;
+
+    Ok(Some(meta))
+}
+
+/// Returns the number of rows discarded from the `tree-r-last` base trees in `cache_path`.
+///
+/// The value recorded in the sector metadata is used if there is one. Otherwise it is detected
+/// from the size of the cached trees, and the current default is used only if there are no
+/// cached trees either.
+pub fn get_tree_r_last_rows_to_discard<Tree: MerkleTreeTrait>(
+    cache_path: &Path,
+    sector_size: SectorSize,
+) -> Result<usize> {
+    if let Some(meta) = read_sector_meta(cache_path)? {
+        return Ok(meta.tree_r_last_rows_to_discard);
+    }
+
+    let base_tree_size = get_base_tree_size::<Tree>(sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+    let arity = Tree::Arity::to_usize();
+
+    let mut config = StoreConfig::new(cache_path, CacheKey::CommRLastTree.to_string(), 0);
+    config.size = Some(base_tree_size);
+    let configs = split_config(config, get_base_tree_count::<Tree>())?;
+
+    match detect_rows_to_discard(&configs[0], base_tree_leafs, arity) {
+        Some(rows_to_discard) => {
+            trace!(
+                "detected tree_r_last rows_to_discard {} in {:?}",
+                rows_to_discard,
+                cache_path
+            );
+            Ok(rows_to_discard)
+        }
+        None => {
+            let rows_to_discard = default_rows_to_discard(base_tree_leafs, arity);
+            info!(
+                "no tree_r_last metadata in {:?}, using rows_to_discard {}",
+                cache_path, rows_to_discard
+            );
+            Ok(rows_to_discard)
+        }
+    }
+}
+
+/// Finds the number of discarded rows which leads to the size of the cached tree in `config`.
+fn detect_rows_to_discard(config: &StoreConfig, leafs: usize, arity: usize) -> Option<usize> {
+    let path = StoreConfig::data_path(&config.path, &config.id);
+    let len = fs::metadata(&path).ok()?.len() as usize;
+
+    // The base row and the root are always kept.
+    let row_count = get_merkle_tree_row_count(leafs, arity);
+    (0..row_count.saturating_sub(1)).find(|&rows_to_discard| {
+        get_merkle_tree_cache_size(leafs, arity, rows_to_discard)
+            .map(|cache_size| cache_size * NODE_SIZE == len)
+            .unwrap_or(false)
+    })
+}
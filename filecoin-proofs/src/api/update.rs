@@ -0,0 +1,584 @@
+use std::fs::{self, metadata, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use bincode::{deserialize, serialize};
+use filecoin_hashers::{HashFunction, Hasher};
+use log::{info, trace};
+use memmap::MmapOptions;
+use merkletree::store::{DiskStore, StoreConfig};
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    compound_proof::{self, CompoundProof},
+    merkle::{
+        create_base_merkle_tree, create_lc_tree, get_base_tree_count, split_config_and_replica,
+        BinaryMerkleTree, LCTree, MerkleTreeTrait,
+    },
+    multi_proof::MultiProof,
+    util::default_rows_to_discard,
+};
+use storage_proofs_porep::{
+    stacked::{ChallengeRequirements, PersistentAux},
+    update::{self, EmptySectorUpdate, EmptySectorUpdateCompound},
+};
+use typenum::Unsigned;
+
+use crate::{
+    api::{
+        as_safe_commitment, commitment_from_fr, get_base_tree_leafs, get_base_tree_size,
+        get_tree_r_last_rows_to_discard, persist_sector_meta,
+    },
+    caches::{get_empty_sector_update_params, get_empty_sector_update_verifying_key},
+    constants::{DefaultBinaryTree, DefaultPieceDomain, DefaultPieceHasher},
+    parameters::empty_sector_update_setup_params,
+    pieces::verify_pieces,
+    types::{
+        Commitment, EmptySectorUpdateEncoded, PaddedBytesAmount, PieceInfo, PoRepConfig,
+        PoRepProofPartitions, SectorMeta, SectorSize, BINARY_ARITY,
+    },
+};
+
+/// Encodes the data staged at `staged_data_path` into the sealed empty sector at
+/// `sector_key_path`, and writes the updated replica to `new_replica_path`.
+///
+/// The sector key and its cache are only read; the sector key is required again to prove the
+/// update and to decode the data. The `tree-d`, `tree-r-last`, `p_aux` and metadata of the
+/// updated sector are written to `new_cache_path`.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration containing the sector size.
+/// * `new_replica_path` - path where the updated replica is written.
+/// * `new_cache_path` - directory in which the cache of the updated sector is written.
+/// * `sector_key_path` - path to the replica of the sealed empty sector.
+/// * `sector_key_cache_path` - path to the cache of the sealed empty sector.
+/// * `staged_data_path` - path to the new, zero-padded, unsealed data.
+/// * `piece_infos` - the piece info (commitment and byte length) for each piece in the new data.
+pub fn encode_into<P: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    new_replica_path: P,
+    new_cache_path: P,
+    sector_key_path: P,
+    sector_key_cache_path: P,
+    staged_data_path: P,
+    piece_infos: &[PieceInfo],
+) -> Result<EmptySectorUpdateEncoded> {
+    info!("encode_into:start");
+
+    // Sanity check all input path types.
+    ensure!(
+        metadata(new_cache_path.as_ref())?.is_dir(),
+        "new_cache_path must be a directory"
+    );
+    ensure!(
+        metadata(sector_key_path.as_ref())?.is_file(),
+        "sector_key_path must be a file"
+    );
+    ensure!(
+        metadata(sector_key_cache_path.as_ref())?.is_dir(),
+        "sector_key_cache_path must be a directory"
+    );
+    ensure!(
+        metadata(staged_data_path.as_ref())?.is_file(),
+        "staged_data_path must be a file"
+    );
+
+    let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
+    ensure!(
+        metadata(staged_data_path.as_ref())?.len() == sector_bytes as u64,
+        "staged data must be {} bytes",
+        sector_bytes
+    );
+
+    let p_aux = read_p_aux::<Tree>(sector_key_cache_path.as_ref())?;
+    let comm_r_old = <Tree::Hasher as Hasher>::Function::hash2(&p_aux.comm_c, &p_aux.comm_r_last);
+
+    let f_data = File::open(&staged_data_path).with_context(|| {
+        format!(
+            "could not open staged_data_path={:?}",
+            staged_data_path.as_ref().display()
+        )
+    })?;
+    let data = unsafe {
+        MmapOptions::new().map(&f_data).with_context(|| {
+            format!(
+                "could not mmap staged_data_path={:?}",
+                staged_data_path.as_ref().display()
+            )
+        })?
+    };
+
+    info!("building merkle tree for the new data");
+    let comm_d_new = {
+        let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
+        let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
+
+        let config = StoreConfig::new(
+            new_cache_path.as_ref(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
+        );
+
+        let data_tree = create_base_merkle_tree::<BinaryMerkleTree<DefaultPieceHasher>>(
+            Some(config),
+            base_tree_leafs,
+            &data,
+        )?;
+
+        data_tree.root()
+    };
+    let comm_d = commitment_from_fr(comm_d_new.into());
+
+    info!("verifying pieces");
+    ensure!(
+        verify_pieces(&comm_d, piece_infos, porep_config.into())?,
+        "pieces and comm_d do not match"
+    );
+
+    let f_sector_key = File::open(&sector_key_path).with_context(|| {
+        format!(
+            "could not open sector_key_path={:?}",
+            sector_key_path.as_ref().display()
+        )
+    })?;
+    let sector_key = unsafe {
+        MmapOptions::new().map(&f_sector_key).with_context(|| {
+            format!(
+                "could not mmap sector_key_path={:?}",
+                sector_key_path.as_ref().display()
+            )
+        })?
+    };
+
+    let f_replica = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&new_replica_path)
+        .with_context(|| {
+            format!(
+                "could not open new_replica_path={:?}",
+                new_replica_path.as_ref().display()
+            )
+        })?;
+    f_replica.set_len(sector_bytes as u64)?;
+    let mut replica = unsafe {
+        MmapOptions::new().map_mut(&f_replica).with_context(|| {
+            format!(
+                "could not mmap new_replica_path={:?}",
+                new_replica_path.as_ref().display()
+            )
+        })?
+    };
+
+    let base_tree_size = get_base_tree_size::<Tree>(porep_config.sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+    let rows_to_discard = default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize());
+    let tree_r_last_config = StoreConfig::new(
+        new_cache_path.as_ref(),
+        CacheKey::CommRLastTree.to_string(),
+        rows_to_discard,
+    );
+
+    let (comm_r_last_new, comm_r_new) = EmptySectorUpdate::<Tree, DefaultPieceHasher>::encode_into(
+        sector_bytes / 32,
+        tree_r_last_config,
+        new_replica_path.as_ref().to_path_buf(),
+        p_aux.comm_c,
+        comm_r_old,
+        comm_d_new,
+        &sector_key,
+        &data,
+        &mut replica,
+    )?;
+    replica.flush()?;
+
+    // Persist p_aux and the sector metadata of the updated sector.
+    let p_aux_new = PersistentAux {
+        comm_c: p_aux.comm_c,
+        comm_r_last: comm_r_last_new,
+    };
+    let p_aux_path = new_cache_path.as_ref().join(CacheKey::PAux.to_string());
+    let mut f_p_aux = File::create(&p_aux_path)
+        .with_context(|| format!("could not create file p_aux={:?}", p_aux_path))?;
+    f_p_aux
+        .write_all(&serialize(&p_aux_new)?)
+        .with_context(|| format!("could not write to file p_aux={:?}", p_aux_path))?;
+
+    persist_sector_meta(
+        &new_cache_path,
+        &SectorMeta {
+            tree_r_last_rows_to_discard: rows_to_discard,
+        },
+    )?;
+
+    let out = EmptySectorUpdateEncoded {
+        comm_r_new: commitment_from_fr(comm_r_new.into()),
+        comm_r_last_new: commitment_from_fr(comm_r_last_new.into()),
+        comm_d_new: comm_d,
+    };
+
+    info!("encode_into:finish");
+    Ok(out)
+}
+
+/// Recovers the data encoded into the updated replica at `replica_path`, and writes it to
+/// `out_data_path`.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration containing the sector size.
+/// * `out_data_path` - path where the decoded data is written.
+/// * `replica_path` - path to the updated replica.
+/// * `sector_key_path` - path to the replica of the sealed empty sector.
+/// * `sector_key_cache_path` - path to the cache of the sealed empty sector.
+/// * `comm_d_new` - the commitment to the data encoded into the replica.
+pub fn decode_from<P: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    out_data_path: P,
+    replica_path: P,
+    sector_key_path: P,
+    sector_key_cache_path: P,
+    comm_d_new: Commitment,
+) -> Result<()> {
+    info!("decode_from:start");
+    ensure!(
+        comm_d_new != [0; 32],
+        "Invalid all zero commitment (comm_d)"
+    );
+
+    let comm_d_new: DefaultPieceDomain = as_safe_commitment(&comm_d_new, "comm_d_new")?;
+    let p_aux = read_p_aux::<Tree>(sector_key_cache_path.as_ref())?;
+    let comm_r_old = <Tree::Hasher as Hasher>::Function::hash2(&p_aux.comm_c, &p_aux.comm_r_last);
+
+    let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
+
+    let f_sector_key = File::open(&sector_key_path).with_context(|| {
+        format!(
+            "could not open sector_key_path={:?}",
+            sector_key_path.as_ref().display()
+        )
+    })?;
+    let sector_key = unsafe {
+        MmapOptions::new().map(&f_sector_key).with_context(|| {
+            format!(
+                "could not mmap sector_key_path={:?}",
+                sector_key_path.as_ref().display()
+            )
+        })?
+    };
+
+    let f_replica = File::open(&replica_path).with_context(|| {
+        format!(
+            "could not open replica_path={:?}",
+            replica_path.as_ref().display()
+        )
+    })?;
+    let replica = unsafe {
+        MmapOptions::new().map(&f_replica).with_context(|| {
+            format!(
+                "could not mmap replica_path={:?}",
+                replica_path.as_ref().display()
+            )
+        })?
+    };
+
+    ensure!(
+        sector_key.len() == sector_bytes && replica.len() == sector_bytes,
+        "sector key and replica must be {} bytes",
+        sector_bytes
+    );
+
+    let f_data = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&out_data_path)
+        .with_context(|| {
+            format!(
+                "could not open out_data_path={:?}",
+                out_data_path.as_ref().display()
+            )
+        })?;
+    f_data.set_len(sector_bytes as u64)?;
+    let mut data = unsafe {
+        MmapOptions::new().map_mut(&f_data).with_context(|| {
+            format!(
+                "could not mmap out_data_path={:?}",
+                out_data_path.as_ref().display()
+            )
+        })?
+    };
+
+    EmptySectorUpdate::<Tree, DefaultPieceHasher>::decode_from(
+        comm_r_old,
+        comm_d_new,
+        &sector_key,
+        &replica,
+        &mut data,
+    )?;
+    data.flush()?;
+
+    info!("decode_from:finish");
+    Ok(())
+}
+
+/// Proves that the replica at `replica_path` is the sector key at `sector_key_path` with the
+/// data committed to by `comm_d_new` encoded into it.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration containing the sector size and partition count.
+/// * `comm_r_old` - the commitment to the sector key.
+/// * `comm_r_new` - the commitment to the updated replica.
+/// * `comm_d_new` - the commitment to the data encoded into the replica.
+/// * `sector_key_path` - path to the replica of the sealed empty sector.
+/// * `sector_key_cache_path` - path to the cache of the sealed empty sector.
+/// * `replica_path` - path to the updated replica.
+/// * `replica_cache_path` - path to the cache of the updated sector.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_empty_sector_update_proof<P: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+    sector_key_path: P,
+    sector_key_cache_path: P,
+    replica_path: P,
+    replica_cache_path: P,
+) -> Result<Vec<u8>> {
+    info!("generate_empty_sector_update_proof:start");
+
+    ensure!(
+        comm_r_old != [0; 32],
+        "Invalid all zero commitment (comm_r_old)"
+    );
+    ensure!(
+        comm_r_new != [0; 32],
+        "Invalid all zero commitment (comm_r_new)"
+    );
+    ensure!(
+        comm_d_new != [0; 32],
+        "Invalid all zero commitment (comm_d_new)"
+    );
+
+    let p_aux_old = read_p_aux::<Tree>(sector_key_cache_path.as_ref())?;
+    let p_aux_new = read_p_aux::<Tree>(replica_cache_path.as_ref())?;
+    ensure!(
+        p_aux_old.comm_c == p_aux_new.comm_c,
+        "the sector key and the replica have different comm_c"
+    );
+
+    let (tree_r_old, tree_r_old_rows_to_discard) = open_tree_r_last::<Tree>(
+        porep_config.sector_size,
+        sector_key_cache_path.as_ref(),
+        sector_key_path.as_ref(),
+    )?;
+    let (tree_r_new, tree_r_new_rows_to_discard) = open_tree_r_last::<Tree>(
+        porep_config.sector_size,
+        replica_cache_path.as_ref(),
+        replica_path.as_ref(),
+    )?;
+    let tree_d_new = open_tree_d(porep_config.sector_size, replica_cache_path.as_ref())?;
+
+    let public_inputs = update::PublicInputs {
+        comm_r_old: as_safe_commitment(&comm_r_old, "comm_r_old")?,
+        comm_d_new: as_safe_commitment(&comm_d_new, "comm_d_new")?,
+        comm_r_new: as_safe_commitment(&comm_r_new, "comm_r_new")?,
+        k: None,
+    };
+
+    let private_inputs = update::PrivateInputs::<Tree, DefaultPieceHasher> {
+        comm_c: p_aux_new.comm_c,
+        tree_r_old,
+        tree_r_old_rows_to_discard,
+        tree_d_new,
+        tree_r_new,
+        tree_r_new_rows_to_discard,
+    };
+
+    let compound_setup_params = compound_proof::SetupParams {
+        vanilla_params: empty_sector_update_setup_params(PaddedBytesAmount::from(porep_config))?,
+        partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
+        priority: false,
+    };
+
+    let compound_public_params =
+        <EmptySectorUpdateCompound<Tree, DefaultPieceHasher> as CompoundProof<
+            EmptySectorUpdate<'_, Tree, DefaultPieceHasher>,
+            _,
+        >>::setup(&compound_setup_params)?;
+
+    let groth_params = get_empty_sector_update_params::<Tree>(porep_config)?;
+
+    info!(
+        "got groth params ({}) while updating",
+        u64::from(PaddedBytesAmount::from(porep_config))
+    );
+
+    let proof = EmptySectorUpdateCompound::prove(
+        &compound_public_params,
+        &public_inputs,
+        &private_inputs,
+        &groth_params,
+    )?;
+
+    let mut buf = Vec::new();
+    proof.write(&mut buf)?;
+
+    // Verification is cheap when parameters are cached,
+    // and it is never correct to return a proof which does not verify.
+    ensure!(
+        verify_empty_sector_update_proof::<Tree>(
+            porep_config,
+            &buf,
+            comm_r_old,
+            comm_r_new,
+            comm_d_new,
+        )
+        .context("post-update verification sanity check failed")?,
+        "post-update verification sanity check failed"
+    );
+
+    info!("generate_empty_sector_update_proof:finish");
+    Ok(buf)
+}
+
+/// Verifies a proof generated by `generate_empty_sector_update_proof`.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration containing the sector size and partition count.
+/// * `proof` - the serialized update proof.
+/// * `comm_r_old` - the commitment to the sector key.
+/// * `comm_r_new` - the commitment to the updated replica.
+/// * `comm_d_new` - the commitment to the data encoded into the replica.
+pub fn verify_empty_sector_update_proof<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    proof: &[u8],
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+) -> Result<bool> {
+    info!("verify_empty_sector_update_proof:start");
+
+    ensure!(
+        comm_r_old != [0; 32],
+        "Invalid all zero commitment (comm_r_old)"
+    );
+    ensure!(
+        comm_r_new != [0; 32],
+        "Invalid all zero commitment (comm_r_new)"
+    );
+    ensure!(
+        comm_d_new != [0; 32],
+        "Invalid all zero commitment (comm_d_new)"
+    );
+
+    let public_inputs = update::PublicInputs {
+        comm_r_old: as_safe_commitment(&comm_r_old, "comm_r_old")?,
+        comm_d_new: as_safe_commitment(&comm_d_new, "comm_d_new")?,
+        comm_r_new: as_safe_commitment(&comm_r_new, "comm_r_new")?,
+        k: None,
+    };
+
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    let compound_setup_params = compound_proof::SetupParams {
+        vanilla_params: empty_sector_update_setup_params(PaddedBytesAmount::from(porep_config))?,
+        partitions: Some(partitions),
+        priority: false,
+    };
+
+    let compound_public_params: compound_proof::PublicParams<
+        '_,
+        EmptySectorUpdate<'_, Tree, DefaultPieceHasher>,
+    > = EmptySectorUpdateCompound::setup(&compound_setup_params)?;
+
+    let verifying_key = get_empty_sector_update_verifying_key::<Tree>(porep_config)?;
+    let proof = MultiProof::new_from_reader(Some(partitions), proof, &verifying_key)?;
+
+    let result = EmptySectorUpdateCompound::verify(
+        &compound_public_params,
+        &public_inputs,
+        &proof,
+        &ChallengeRequirements {
+            minimum_challenges: compound_public_params.vanilla_params.challenges_count * partitions,
+        },
+    );
+
+    info!("verify_empty_sector_update_proof:finish");
+    result
+}
+
+fn read_p_aux<Tree: MerkleTreeTrait>(
+    cache_path: &Path,
+) -> Result<PersistentAux<<Tree::Hasher as Hasher>::Domain>> {
+    let p_aux_path = cache_path.join(CacheKey::PAux.to_string());
+    let p_aux_bytes = fs::read(&p_aux_path)
+        .with_context(|| format!("could not read file p_aux={:?}", p_aux_path))?;
+
+    deserialize(&p_aux_bytes)
+        .with_context(|| format!("could not deserialize file p_aux={:?}", p_aux_path))
+}
+
+/// Opens the `tree-r-last` in `cache_path` of the replica at `replica_path`, and returns it with
+/// its number of discarded rows.
+fn open_tree_r_last<Tree: MerkleTreeTrait>(
+    sector_size: SectorSize,
+    cache_path: &Path,
+    replica_path: &Path,
+) -> Result<(
+    LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    usize,
+)> {
+    let base_tree_size = get_base_tree_size::<Tree>(sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+    let rows_to_discard = get_tree_r_last_rows_to_discard::<Tree>(cache_path, sector_size)?;
+    trace!(
+        "opening tree_r_last in {:?}: base tree size {}, base tree leafs {}, rows_to_discard {}",
+        cache_path,
+        base_tree_size,
+        base_tree_leafs,
+        rows_to_discard,
+    );
+
+    let mut config = StoreConfig::new(
+        cache_path,
+        CacheKey::CommRLastTree.to_string(),
+        rows_to_discard,
+    );
+    config.size = Some(base_tree_size);
+
+    let (configs, replica_config) = split_config_and_replica(
+        config,
+        replica_path.to_path_buf(),
+        base_tree_leafs,
+        get_base_tree_count::<Tree>(),
+    )?;
+
+    let tree = create_lc_tree::<
+        LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    >(base_tree_size, &configs, &replica_config)?;
+
+    Ok((tree, rows_to_discard))
+}
+
+fn open_tree_d(
+    sector_size: SectorSize,
+    cache_path: &Path,
+) -> Result<BinaryMerkleTree<DefaultPieceHasher>> {
+    let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
+
+    let mut config = StoreConfig::new(
+        cache_path,
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
+    );
+    config.size = Some(base_tree_size);
+
+    let store: DiskStore<DefaultPieceDomain> =
+        DiskStore::new_from_disk(base_tree_size, BINARY_ARITY, &config)?;
+    BinaryMerkleTree::<DefaultPieceHasher>::from_data_store(store, base_tree_leafs)
+}
@@ -1,25 +1,37 @@
 use std::collections::BTreeMap;
 
 use anyhow::{ensure, Context, Result};
+use bellperson::groth16;
 use filecoin_hashers::Hasher;
-use log::info;
+use log::{info, trace};
+use sha2::{Digest, Sha256};
 use storage_proofs_core::{
     compound_proof::{self, CompoundProof},
     merkle::MerkleTreeTrait,
     multi_proof::MultiProof,
+    run_options::RunOptions,
     sector::SectorId,
+    settings::{self, with_config},
 };
 use storage_proofs_post::fallback::{
     self, FallbackPoSt, FallbackPoStCompound, PrivateSector, PublicSector,
 };
 
 use crate::{
-    api::{as_safe_commitment, get_partitions_for_window_post, partition_vanilla_proofs},
-    caches::{get_post_params, get_post_verifying_key},
+    api::{
+        as_safe_commitment, generate_fallback_sector_challenges, generate_vanilla_proofs_batch,
+        get_aggregate_target_len, get_partitions_for_window_post, pad_inputs_to_target,
+        pad_proofs_to_target, partition_vanilla_proofs,
+    },
+    caches::{
+        get_post_params, get_post_srs_key, get_post_srs_verifier_key, get_post_verifying_key,
+    },
+    constants::SINGLE_PARTITION_PROOF_LEN,
     parameters::window_post_setup_params,
     types::{
-        ChallengeSeed, FallbackPoStSectorProof, PoStConfig, PrivateReplicaInfo, ProverId,
-        PublicReplicaInfo, SnarkProof,
+        AggregateSnarkProof, ChallengeSeed, FallbackPoStSectorProof, FaultPolicy, PoStConfig,
+        PrivateReplicaInfo, ProverId, PublicReplicaInfo, SectorFault, SnarkProof,
+        VanillaProofBatchConfig,
     },
     PoStType,
 };
@@ -39,6 +51,26 @@ pub fn generate_window_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
         "invalid post config type"
     );
 
+    let proof = prove_window_post_with_vanilla(
+        post_config,
+        randomness,
+        prover_id,
+        vanilla_proofs,
+        &RunOptions::default(),
+    )?;
+
+    info!("generate_window_post_with_vanilla:finish");
+
+    Ok(proof)
+}
+
+fn prove_window_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    prover_id: ProverId,
+    vanilla_proofs: Vec<FallbackPoStSectorProof<Tree>>,
+    options: &RunOptions<'_>,
+) -> Result<SnarkProof> {
     let randomness_safe: <Tree::Hasher as Hasher>::Domain =
         as_safe_commitment(randomness, "randomness")?;
     let prover_id_safe: <Tree::Hasher as Hasher>::Domain =
@@ -82,15 +114,14 @@ pub fn generate_window_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
         &vanilla_proofs,
     )?;
 
-    let proof = FallbackPoStCompound::prove_with_vanilla(
+    let proof = FallbackPoStCompound::prove_with_vanilla_and_options(
         &pub_params,
         &pub_inputs,
         partitioned_proofs,
         &groth_params,
+        options,
     )?;
 
-    info!("generate_window_post_with_vanilla:finish");
-
     proof.to_vec()
 }
 
@@ -101,12 +132,71 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
     replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
     prover_id: ProverId,
 ) -> Result<SnarkProof> {
+    let (proof, _) = generate_window_post_with_options(
+        post_config,
+        randomness,
+        replicas,
+        prover_id,
+        FaultPolicy::Abort,
+        &RunOptions::default(),
+    )?;
+
+    Ok(proof)
+}
+
+/// Same as `generate_window_post`, handling sectors which cannot be proven according to
+/// `fault_policy` and reporting every proved partition to `options.progress`. Returns
+/// `Error::Cancelled` if `options.cancel` is observed before the vanilla proofs or before or
+/// during circuit synthesis.
+///
+/// With `FaultPolicy::Abort` no faults are returned. With `FaultPolicy::Skip`, faulty sectors are
+/// left out of the proof and returned alongside it, and the proof must be verified against the
+/// remaining sectors only. Since the challenges depend on the set of proven sectors, they are
+/// regenerated after each round of faults, until a round finds none.
+pub fn generate_window_post_with_options<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    prover_id: ProverId,
+    fault_policy: FaultPolicy,
+    options: &RunOptions<'_>,
+) -> Result<(SnarkProof, Vec<SectorFault>)> {
     info!("generate_window_post:start");
+    let _config = options.enter_config();
+    options.cancel.check()?;
     ensure!(
         post_config.typ == PoStType::Window,
         "invalid post config type"
     );
 
+    let (proof, faults) = match fault_policy {
+        FaultPolicy::Abort => (
+            prove_window_post(post_config, randomness, replicas, prover_id, options)?,
+            Vec::new(),
+        ),
+        FaultPolicy::Skip(batch_config) => prove_window_post_skipping_faults(
+            post_config,
+            randomness,
+            replicas,
+            prover_id,
+            &batch_config,
+            options,
+        )?,
+    };
+
+    info!("generate_window_post:finish");
+
+    Ok((proof, faults))
+}
+
+/// Proves all `replicas`, failing with `Error::FaultySectors` if any of them cannot be proven.
+fn prove_window_post<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    prover_id: ProverId,
+    options: &RunOptions<'_>,
+) -> Result<SnarkProof> {
     let randomness_safe = as_safe_commitment(randomness, "randomness")?;
     let prover_id_safe = as_safe_commitment(&prover_id, "prover_id")?;
 
@@ -125,15 +215,15 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
     let groth_params = get_post_params::<Tree>(&post_config)?;
 
     info!("generate_window_post:par_iter");
+    let config = settings::current();
     //modified by maven, replace iter() with par_iter() 
     let trees: Vec<_> = replicas
         .par_iter()
         .map(|(sector_id, replica)| {
-            replica
-                .merkle_tree(post_config.sector_size)
-                .with_context(|| {
-                    format!("generate_window_post: merkle_tree failed: {:?}", sector_id)
-                })
+            with_config(Some(&config), || {
+                replica.merkle_tree(post_config.sector_size)
+            })
+            .with_context(|| format!("generate_window_post: merkle_tree failed: {:?}", sector_id))
         })
         .collect::<Result<_>>()?;
 
@@ -146,6 +236,14 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
         })?;
         let comm_c = replica.safe_comm_c();
         let comm_r_last = replica.safe_comm_r_last();
+        let rows_to_discard = replica
+            .tree_r_last_rows_to_discard(post_config.sector_size)
+            .with_context(|| {
+                format!(
+                    "generate_window_post: tree_r_last_rows_to_discard failed: {:?}",
+                    sector_id
+                )
+            })?;
 
         pub_sectors.push(PublicSector {
             id: *sector_id,
@@ -155,6 +253,7 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
             tree,
             comm_c,
             comm_r_last,
+            rows_to_discard,
         });
     }
 
@@ -169,13 +268,69 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
         sectors: &priv_sectors,
     };
 
-    let proof = FallbackPoStCompound::prove(&pub_params, &pub_inputs, &priv_inputs, &groth_params)?;
-
-    info!("generate_window_post:finish");
+    let proof = FallbackPoStCompound::prove_with_options(
+        &pub_params,
+        &pub_inputs,
+        &priv_inputs,
+        &groth_params,
+        options,
+    )?;
 
     proof.to_vec()
 }
 
+/// Proves the `replicas` which can be proven, and returns the faults of the others.
+fn prove_window_post_skipping_faults<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    prover_id: ProverId,
+    batch_config: &VanillaProofBatchConfig,
+    options: &RunOptions<'_>,
+) -> Result<(SnarkProof, Vec<SectorFault>)> {
+    let mut replicas = replicas.clone();
+    let mut faults = Vec::new();
+
+    let vanilla_proofs = loop {
+        options.cancel.check()?;
+
+        let sector_ids: Vec<SectorId> = replicas.keys().copied().collect();
+        let challenges = generate_fallback_sector_challenges::<Tree>(
+            post_config,
+            randomness,
+            &sector_ids,
+            prover_id,
+        )?;
+        let batch =
+            generate_vanilla_proofs_batch(post_config, &replicas, &challenges, batch_config)?;
+
+        if batch.faults.is_empty() {
+            break batch.proofs;
+        }
+
+        for fault in batch.faults {
+            replicas.remove(&fault.sector_id);
+            faults.push(fault);
+        }
+    };
+    ensure!(
+        !vanilla_proofs.is_empty(),
+        "all {} sectors are faulty",
+        faults.len()
+    );
+
+    let proof = prove_window_post_with_vanilla(
+        post_config,
+        randomness,
+        prover_id,
+        vanilla_proofs,
+        options,
+    )?;
+    faults.sort_by_key(|fault| fault.sector_id);
+
+    Ok((proof, faults))
+}
+
 /// Verifies a window proof-of-spacetime.
 pub fn verify_window_post<Tree: 'static + MerkleTreeTrait>(
     post_config: &PoStConfig,
@@ -246,3 +401,201 @@ pub fn verify_window_post<Tree: 'static + MerkleTreeTrait>(
 
     Ok(true)
 }
+
+/// Given a post_config and a list of Window proofs-of-spacetime, this method aggregates the
+/// partition proofs of all of them (naively padding the count if necessary up to a power of 2)
+/// and returns the aggregate proof bytes.
+///
+/// # Arguments
+///
+/// * `post_config` - the post config shared by all proofs, which determines the circuit.
+/// * `randomnesses` - an ordered list of the randomness each proof was generated with.
+/// * `proofs` - an ordered list of proofs returned from `generate_window_post`.
+pub fn aggregate_window_post_proofs<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomnesses: &[ChallengeSeed],
+    proofs: &[SnarkProof],
+) -> Result<AggregateSnarkProof> {
+    info!("aggregate_window_post_proofs:start");
+
+    ensure!(
+        post_config.typ == PoStType::Window,
+        "invalid post config type"
+    );
+    ensure!(!proofs.is_empty(), "cannot aggregate with empty proofs");
+    ensure!(
+        proofs.len() == randomnesses.len(),
+        "invalid proofs and randomnesses len mismatch"
+    );
+
+    let verifying_key = get_post_verifying_key::<Tree>(&post_config)?;
+    let mut circuit_proofs: Vec<_> =
+        proofs
+            .iter()
+            .try_fold(Vec::new(), |mut acc, proof| -> Result<_> {
+                ensure!(
+                    !proof.is_empty() && proof.len() % SINGLE_PARTITION_PROOF_LEN == 0,
+                    "invalid window post proof length {}",
+                    proof.len()
+                );
+                let partitions = proof.len() / SINGLE_PARTITION_PROOF_LEN;
+                acc.extend(
+                    MultiProof::new_from_reader(Some(partitions), &proof[..], &verifying_key)?
+                        .circuit_proofs,
+                );
+
+                Ok(acc)
+            })?;
+    trace!(
+        "aggregate_window_post_proofs called with {} proofs containing {} partition proofs",
+        proofs.len(),
+        circuit_proofs.len(),
+    );
+
+    let target_proofs_len = get_aggregate_target_len(circuit_proofs.len());
+    ensure!(
+        target_proofs_len > 1,
+        "cannot aggregate less than two proofs"
+    );
+
+    // If we're not at the pow2 target, duplicate the last proof until we are.
+    pad_proofs_to_target(&mut circuit_proofs, target_proofs_len)?;
+
+    let hashed_randomnesses = hash_randomnesses(randomnesses);
+    let srs_prover_key = get_post_srs_key::<Tree>(&post_config, circuit_proofs.len())?;
+    let aggregate_proof = FallbackPoStCompound::<Tree>::aggregate_proofs(
+        &srs_prover_key,
+        &hashed_randomnesses,
+        circuit_proofs.as_slice(),
+    )?;
+    let mut aggregate_proof_bytes = Vec::new();
+    aggregate_proof.write(&mut aggregate_proof_bytes)?;
+
+    info!("aggregate_window_post_proofs:finish");
+
+    Ok(aggregate_proof_bytes)
+}
+
+/// Verifies an aggregate of Window proofs-of-spacetime, as returned from
+/// `aggregate_window_post_proofs`.
+///
+/// # Arguments
+///
+/// * `post_config` - the post config shared by all proofs, which determines the circuit.
+/// * `prover_id` - the prover_id of all proofs.
+/// * `aggregate_proof_bytes` - the aggregate proof.
+/// * `randomnesses` - an ordered list of the randomness each proof was generated with.
+/// * `replicas` - an ordered list of the replicas each proof was generated for.
+pub fn verify_aggregate_window_post_proofs<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    prover_id: ProverId,
+    aggregate_proof_bytes: AggregateSnarkProof,
+    randomnesses: &[ChallengeSeed],
+    replicas: &[BTreeMap<SectorId, PublicReplicaInfo>],
+) -> Result<bool> {
+    info!("verify_aggregate_window_post_proofs:start");
+
+    ensure!(
+        post_config.typ == PoStType::Window,
+        "invalid post config type"
+    );
+    ensure!(
+        replicas.len() == randomnesses.len(),
+        "invalid replicas and randomnesses len mismatch"
+    );
+
+    let aggregate_proof =
+        groth16::aggregate::AggregateProof::read(std::io::Cursor::new(&aggregate_proof_bytes))?;
+    let aggregated_proofs_len = aggregate_proof.tmipp.gipa.nproofs as usize;
+
+    ensure!(
+        aggregated_proofs_len > 1,
+        "cannot verify less than two proofs"
+    );
+    ensure!(
+        aggregated_proofs_len == aggregated_proofs_len.next_power_of_two(),
+        "cannot verify non-pow2 aggregate window post proofs"
+    );
+
+    let prover_id_safe = as_safe_commitment(&prover_id, "prover_id")?;
+
+    // The public inputs of each partition proof, in the order they were aggregated in.
+    let mut inputs = Vec::new();
+    for (randomness, replicas) in randomnesses.iter().zip(replicas.iter()) {
+        let randomness_safe = as_safe_commitment(randomness, "randomness")?;
+
+        let vanilla_params = window_post_setup_params(&post_config);
+        let partitions = get_partitions_for_window_post(replicas.len(), &post_config);
+        let setup_params = compound_proof::SetupParams {
+            vanilla_params,
+            partitions,
+            priority: false,
+        };
+        let pub_params: compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>> =
+            FallbackPoStCompound::setup(&setup_params)?;
+
+        let pub_sectors: Vec<_> = replicas
+            .iter()
+            .map(|(sector_id, replica)| {
+                let comm_r = replica.safe_comm_r().with_context(|| {
+                    format!(
+                        "verify_aggregate_window_post_proofs: safe_comm_r failed: {:?}",
+                        sector_id
+                    )
+                })?;
+                Ok(PublicSector {
+                    id: *sector_id,
+                    comm_r,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let pub_inputs = fallback::PublicInputs {
+            randomness: randomness_safe,
+            prover_id: prover_id_safe,
+            sectors: pub_sectors,
+            k: None,
+        };
+
+        for k in 0..partitions.unwrap_or(1) {
+            inputs.push(FallbackPoStCompound::<Tree>::generate_public_inputs(
+                &pub_inputs,
+                &pub_params.vanilla_params,
+                Some(k),
+            )?);
+        }
+    }
+
+    ensure!(
+        get_aggregate_target_len(inputs.len()) == aggregated_proofs_len,
+        "invalid number of inputs provided"
+    );
+
+    // Pad public inputs if needed.
+    let inputs = pad_inputs_to_target(&inputs, 1, aggregated_proofs_len)?;
+
+    let verifying_key = get_post_verifying_key::<Tree>(&post_config)?;
+    let srs_verifier_key = get_post_srs_verifier_key::<Tree>(&post_config, aggregated_proofs_len)?;
+    let hashed_randomnesses = hash_randomnesses(randomnesses);
+
+    let result = FallbackPoStCompound::<Tree>::verify_aggregate_proofs(
+        &srs_verifier_key,
+        &verifying_key,
+        &hashed_randomnesses,
+        inputs.as_slice(),
+        &aggregate_proof,
+    )?;
+
+    info!("verify_aggregate_window_post_proofs:finish");
+
+    Ok(result)
+}
+
+/// Hashes all randomnesses into a digest for the aggregate proof method.
+fn hash_randomnesses(randomnesses: &[ChallengeSeed]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for randomness in randomnesses {
+        hasher.update(randomness);
+    }
+    hasher.finalize().into()
+}
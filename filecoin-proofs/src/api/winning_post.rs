@@ -6,6 +6,7 @@ use storage_proofs_core::{
     merkle::MerkleTreeTrait,
     multi_proof::MultiProof,
     sector::SectorId,
+    settings::{self, with_config},
 };
 use storage_proofs_post::fallback::{
     self, generate_sector_challenges, FallbackPoSt, FallbackPoStCompound, PrivateSector,
@@ -132,15 +133,15 @@ pub fn generate_winning_post<Tree: 'static + MerkleTreeTrait>(
     let groth_params = get_post_params::<Tree>(&post_config)?;
 
     info!("generate_winning_post:par_iter");
+    let config = settings::current();
     //modified by maven, replace iter() with par_iter() 
     let trees = replicas
         .par_iter()
         .map(|(sector_id, replica)| {
-            replica
-                .merkle_tree(post_config.sector_size)
-                .with_context(|| {
-                    format!("generate_winning_post: merkle_tree failed: {:?}", sector_id)
-                })
+            with_config(Some(&config), || {
+                replica.merkle_tree(post_config.sector_size)
+            })
+            .with_context(|| format!("generate_winning_post: merkle_tree failed: {:?}", sector_id))
         })
         .collect::<Result<Vec<_>>>()?;
 
@@ -154,6 +155,14 @@ pub fn generate_winning_post<Tree: 'static + MerkleTreeTrait>(
             })?;
             let comm_c = replica.safe_comm_c();
             let comm_r_last = replica.safe_comm_r_last();
+            let rows_to_discard = replica
+                .tree_r_last_rows_to_discard(post_config.sector_size)
+                .with_context(|| {
+                    format!(
+                        "generate_winning_post: tree_r_last_rows_to_discard failed: {:?}",
+                        sector_id
+                    )
+                })?;
 
             pub_sectors.push(PublicSector::<<Tree::Hasher as Hasher>::Domain> {
                 id: *sector_id,
@@ -163,6 +172,7 @@ pub fn generate_winning_post<Tree: 'static + MerkleTreeTrait>(
                 tree,
                 comm_c,
                 comm_r_last,
+                rows_to_discard,
             });
         }
     }
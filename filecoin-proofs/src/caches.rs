@@ -11,12 +11,20 @@ use log::{info, trace};
 use once_cell::sync::OnceCell;
 use rand::rngs::OsRng;
 use storage_proofs_core::{compound_proof::CompoundProof, merkle::MerkleTreeTrait};
-use storage_proofs_porep::stacked::{StackedCompound, StackedDrg};
-use storage_proofs_post::fallback::{FallbackPoSt, FallbackPoStCircuit, FallbackPoStCompound};
+use storage_proofs_porep::{
+    stacked::{StackedCompound, StackedDrg},
+    update::{EmptySectorUpdate, EmptySectorUpdateCompound},
+};
+use storage_proofs_post::fallback::{
+    self, FallbackPoSt, FallbackPoStCircuit, FallbackPoStCompound,
+};
 
 use crate::{
     constants::{DefaultPieceHasher, PUBLISHED_SECTOR_SIZES},
-    parameters::{public_params, window_post_public_params, winning_post_public_params},
+    parameters::{
+        empty_sector_update_public_params, public_params, window_post_public_params,
+        winning_post_public_params,
+    },
     types::{PaddedBytesAmount, PoRepConfig, PoRepProofPartitions, PoStConfig, PoStType},
 };
 
@@ -37,6 +45,9 @@ const FIP0013_MAX_SNARKS: usize = 8192;
 const PROOFS_TESTS_MIN_SNARKS: usize = FIP0013_MIN_SNARKS >> 5;
 const PROOFS_TESTS_MAX_SNARKS: usize = FIP0013_MAX_SNARKS << 1;
 
+/// The circuits whose proofs can be aggregated.
+const SRS_CIRCUITS: [&str; 3] = ["STACKED", "WINNING_POST", "WINDOW_POST"];
+
 const SRS_IDENTIFIER: &str = "srs-key";
 const SRS_VERIFIER_IDENTIFIER: &str = "srs-verifying-key";
 
@@ -65,13 +76,15 @@ impl<G> SRSCache<G> {
         let mut num_proofs_to_aggregate = PROOFS_TESTS_MIN_SNARKS;
 
         loop {
-            for sector_size in &PUBLISHED_SECTOR_SIZES {
-                let key = format!(
-                    "STACKED[{}-{}]-{}",
-                    sector_size, num_proofs_to_aggregate, identifier,
-                );
-                trace!("inserting placeholder srs key with hash key {}", key);
-                data.insert(key, OnceCell::new());
+            for circuit in &SRS_CIRCUITS {
+                for sector_size in &PUBLISHED_SECTOR_SIZES {
+                    let key = format!(
+                        "{}[{}-{}]-{}",
+                        circuit, sector_size, num_proofs_to_aggregate, identifier,
+                    );
+                    trace!("inserting placeholder srs key with hash key {}", key);
+                    data.insert(key, OnceCell::new());
+                }
             }
 
             num_proofs_to_aggregate <<= 1;
@@ -193,6 +206,17 @@ where
     )
 }
 
+/// Returns the key of the stacked groth params and verifying key in the memory caches, which
+/// differ between the interactive and the non-interactive PoRep.
+fn stacked_cache_key(porep_config: PoRepConfig) -> String {
+    let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
+    if porep_config.non_interactive {
+        format!("STACKED_NON_INTERACTIVE[{}]", sector_bytes)
+    } else {
+        format!("STACKED[{}]", sector_bytes)
+    }
+}
+
 pub fn get_stacked_params<Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
 ) -> Result<Arc<Bls12GrothParams>> {
@@ -201,6 +225,8 @@ pub fn get_stacked_params<Tree: 'static + MerkleTreeTrait>(
         usize::from(PoRepProofPartitions::from(porep_config)),
         porep_config.porep_id,
         porep_config.api_version,
+        porep_config.non_interactive,
+        porep_config.synthetic,
     )?;
 
     let parameters_generator = || {
@@ -211,13 +237,7 @@ pub fn get_stacked_params<Tree: 'static + MerkleTreeTrait>(
         .map_err(Into::into)
     };
 
-    lookup_groth_params(
-        format!(
-            "STACKED[{}]",
-            usize::from(PaddedBytesAmount::from(porep_config))
-        ),
-        parameters_generator,
-    )
+    lookup_groth_params(stacked_cache_key(porep_config), parameters_generator)
 }
 
 pub fn get_post_params<Tree: 'static + MerkleTreeTrait>(
@@ -273,6 +293,8 @@ pub fn get_stacked_verifying_key<Tree: 'static + MerkleTreeTrait>(
         usize::from(PoRepProofPartitions::from(porep_config)),
         porep_config.porep_id,
         porep_config.api_version,
+        porep_config.non_interactive,
+        porep_config.synthetic,
     )?;
 
     let vk_generator = || {
@@ -283,13 +305,7 @@ pub fn get_stacked_verifying_key<Tree: 'static + MerkleTreeTrait>(
         Ok(prepare_verifying_key(&vk))
     };
 
-    lookup_verifying_key(
-        format!(
-            "STACKED[{}]",
-            usize::from(PaddedBytesAmount::from(porep_config))
-        ),
-        vk_generator,
-    )
+    lookup_verifying_key(stacked_cache_key(porep_config), vk_generator)
 }
 
 pub fn get_post_verifying_key<Tree: 'static + MerkleTreeTrait>(
@@ -337,6 +353,52 @@ pub fn get_post_verifying_key<Tree: 'static + MerkleTreeTrait>(
     }
 }
 
+pub fn get_empty_sector_update_params<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<Arc<Bls12GrothParams>> {
+    let public_params =
+        empty_sector_update_public_params::<Tree>(PaddedBytesAmount::from(porep_config))?;
+
+    let parameters_generator = || {
+        <EmptySectorUpdateCompound<Tree, DefaultPieceHasher> as CompoundProof<
+            EmptySectorUpdate<'_, Tree, DefaultPieceHasher>,
+            _,
+        >>::groth_params::<OsRng>(None, &public_params)
+        .map_err(Into::into)
+    };
+
+    lookup_groth_params(
+        format!(
+            "EMPTY_SECTOR_UPDATE[{}]",
+            usize::from(PaddedBytesAmount::from(porep_config))
+        ),
+        parameters_generator,
+    )
+}
+
+pub fn get_empty_sector_update_verifying_key<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<Arc<Bls12PreparedVerifyingKey>> {
+    let public_params =
+        empty_sector_update_public_params::<Tree>(PaddedBytesAmount::from(porep_config))?;
+
+    let vk_generator = || {
+        let vk = <EmptySectorUpdateCompound<Tree, DefaultPieceHasher> as CompoundProof<
+            EmptySectorUpdate<'_, Tree, DefaultPieceHasher>,
+            _,
+        >>::verifying_key::<OsRng>(None, &public_params)?;
+        Ok(prepare_verifying_key(&vk))
+    };
+
+    lookup_verifying_key(
+        format!(
+            "EMPTY_SECTOR_UPDATE[{}]",
+            usize::from(PaddedBytesAmount::from(porep_config))
+        ),
+        vk_generator,
+    )
+}
+
 pub fn get_stacked_srs_key<Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
     num_proofs_to_aggregate: usize,
@@ -346,6 +408,8 @@ pub fn get_stacked_srs_key<Tree: 'static + MerkleTreeTrait>(
         usize::from(PoRepProofPartitions::from(porep_config)),
         porep_config.porep_id,
         porep_config.api_version,
+        porep_config.non_interactive,
+        porep_config.synthetic,
     )?;
 
     let srs_generator = || {
@@ -379,6 +443,8 @@ pub fn get_stacked_srs_verifier_key<Tree: 'static + MerkleTreeTrait>(
         usize::from(PoRepProofPartitions::from(porep_config)),
         porep_config.porep_id,
         porep_config.api_version,
+        porep_config.non_interactive,
+        porep_config.synthetic,
     )?;
 
     let srs_verifier_generator = || {
@@ -404,3 +470,62 @@ pub fn get_stacked_srs_verifier_key<Tree: 'static + MerkleTreeTrait>(
         srs_verifier_generator,
     )
 }
+
+/// Returns the key of the PoSt srs keys in the memory caches.
+fn post_srs_cache_key(post_config: &PoStConfig, num_proofs_to_aggregate: usize) -> String {
+    let circuit = match post_config.typ {
+        PoStType::Winning => "WINNING_POST",
+        PoStType::Window => "WINDOW_POST",
+    };
+    format!(
+        "{}[{}-{}]",
+        circuit,
+        usize::from(post_config.padded_sector_size()),
+        num_proofs_to_aggregate,
+    )
+}
+
+fn post_public_params<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+) -> Result<fallback::PublicParams> {
+    match post_config.typ {
+        PoStType::Winning => winning_post_public_params::<Tree>(post_config),
+        PoStType::Window => window_post_public_params::<Tree>(post_config),
+    }
+}
+
+pub fn get_post_srs_key<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    num_proofs_to_aggregate: usize,
+) -> Result<Arc<Bls12ProverSRSKey>> {
+    let post_public_params = post_public_params::<Tree>(post_config)?;
+    let cache_key = post_srs_cache_key(post_config, num_proofs_to_aggregate);
+
+    let srs_generator = || {
+        trace!("get_post_srs_key specializing {}", cache_key);
+        <FallbackPoStCompound<Tree> as CompoundProof<
+            FallbackPoSt<'_, Tree>,
+            FallbackPoStCircuit<Tree>,
+        >>::srs_key::<OsRng>(None, &post_public_params, num_proofs_to_aggregate)
+    };
+
+    lookup_srs_key(cache_key.clone(), srs_generator)
+}
+
+pub fn get_post_srs_verifier_key<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    num_proofs_to_aggregate: usize,
+) -> Result<Arc<Bls12VerifierSRSKey>> {
+    let post_public_params = post_public_params::<Tree>(post_config)?;
+    let cache_key = post_srs_cache_key(post_config, num_proofs_to_aggregate);
+
+    let srs_verifier_generator = || {
+        trace!("get_post_srs_verifier_key specializing {}", cache_key);
+        <FallbackPoStCompound<Tree> as CompoundProof<
+            FallbackPoSt<'_, Tree>,
+            FallbackPoStCircuit<Tree>,
+        >>::srs_verifier_key::<OsRng>(None, &post_public_params, num_proofs_to_aggregate)
+    };
+
+    lookup_srs_verifier_key(cache_key.clone(), srs_verifier_generator)
+}
@@ -26,6 +26,11 @@ impl<R: Read> CommitmentReader<R> {
         }
     }
 
+    /// Returns a mutable reference to the underlying source.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.source
+    }
+
     /// Attempt to generate the next hash, but only if the buffers are full.
     fn try_hash(&mut self) {
         if self.buffer_pos < 63 {
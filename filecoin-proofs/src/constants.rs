@@ -82,6 +82,62 @@ lazy_static! {
         .copied()
         .collect()
     );
+    /// Minimum number of challenges of the non-interactive PoRep, which are derived from CommR
+    /// instead of the interactive seed and therefore need to be more numerous.
+    pub static ref NON_INTERACTIVE_POREP_MINIMUM_CHALLENGES: RwLock<HashMap<u64, u64>> = RwLock::new(
+        [
+            (SECTOR_SIZE_2_KIB, 4),
+            (SECTOR_SIZE_4_KIB, 4),
+            (SECTOR_SIZE_16_KIB, 4),
+            (SECTOR_SIZE_32_KIB, 4),
+            (SECTOR_SIZE_8_MIB, 4),
+            (SECTOR_SIZE_16_MIB, 4),
+            (SECTOR_SIZE_512_MIB, 4),
+            (SECTOR_SIZE_1_GIB, 4),
+            (SECTOR_SIZE_32_GIB, 2253),
+            (SECTOR_SIZE_64_GIB, 2253),
+        ]
+        .iter()
+        .copied()
+        .collect()
+    );
+    /// Partitions of the non-interactive PoRep, chosen so that each partition circuit has the
+    /// size of an interactive one.
+    pub static ref NON_INTERACTIVE_POREP_PARTITIONS: RwLock<HashMap<u64, u8>> = RwLock::new(
+        [
+            (SECTOR_SIZE_2_KIB, 1),
+            (SECTOR_SIZE_4_KIB, 1),
+            (SECTOR_SIZE_16_KIB, 1),
+            (SECTOR_SIZE_32_KIB, 1),
+            (SECTOR_SIZE_8_MIB, 1),
+            (SECTOR_SIZE_16_MIB, 1),
+            (SECTOR_SIZE_512_MIB, 1),
+            (SECTOR_SIZE_1_GIB, 1),
+            (SECTOR_SIZE_32_GIB, 126),
+            (SECTOR_SIZE_64_GIB, 126),
+        ]
+        .iter()
+        .copied()
+        .collect()
+    );
+    /// The number of synthetic challenges proven ahead of the seed by the synthetic PoRep.
+    pub static ref SYNTHETIC_POREP_CHALLENGES: RwLock<HashMap<u64, u64>> = RwLock::new(
+        [
+            (SECTOR_SIZE_2_KIB, 32),
+            (SECTOR_SIZE_4_KIB, 32),
+            (SECTOR_SIZE_16_KIB, 32),
+            (SECTOR_SIZE_32_KIB, 32),
+            (SECTOR_SIZE_8_MIB, 1 << 10),
+            (SECTOR_SIZE_16_MIB, 1 << 10),
+            (SECTOR_SIZE_512_MIB, 1 << 10),
+            (SECTOR_SIZE_1_GIB, 1 << 10),
+            (SECTOR_SIZE_32_GIB, 1 << 18),
+            (SECTOR_SIZE_64_GIB, 1 << 18),
+        ]
+        .iter()
+        .copied()
+        .collect()
+    );
     pub static ref LAYERS: RwLock<HashMap<u64, usize>> = RwLock::new(
         [
             (SECTOR_SIZE_2_KIB, 2),
@@ -99,6 +155,24 @@ lazy_static! {
         .copied()
         .collect()
     );
+    /// Number of challenges in each partition of an empty sector update proof.
+    pub static ref EMPTY_SECTOR_UPDATE_CHALLENGES: RwLock<HashMap<u64, usize>> = RwLock::new(
+        [
+            (SECTOR_SIZE_2_KIB, 2),
+            (SECTOR_SIZE_4_KIB, 2),
+            (SECTOR_SIZE_16_KIB, 2),
+            (SECTOR_SIZE_32_KIB, 2),
+            (SECTOR_SIZE_8_MIB, 2),
+            (SECTOR_SIZE_16_MIB, 2),
+            (SECTOR_SIZE_512_MIB, 2),
+            (SECTOR_SIZE_1_GIB, 2),
+            (SECTOR_SIZE_32_GIB, 138),
+            (SECTOR_SIZE_64_GIB, 138),
+        ]
+        .iter()
+        .copied()
+        .collect()
+    );
     // These numbers must match those used for Window PoSt scheduling in the miner actor.
     // Please coordinate changes with actor code.
     // https://github.com/filecoin-project/specs-actors/blob/master/actors/abi/sector.go
@@ -12,8 +12,10 @@ pub mod types;
 
 mod api;
 mod commitment_reader;
+mod sector_store;
 
 pub use api::*;
 pub use commitment_reader::*;
 pub use constants::*;
+pub use sector_store::*;
 pub use types::*;
@@ -1,10 +1,17 @@
 use anyhow::{ensure, Result};
 use storage_proofs_core::{api_version::ApiVersion, proof::ProofScheme};
-use storage_proofs_porep::stacked::{self, LayerChallenges, StackedDrg};
+use storage_proofs_porep::{
+    stacked::{self, LayerChallenges, StackedDrg},
+    update::{self, EmptySectorUpdate},
+};
 use storage_proofs_post::fallback::{self, FallbackPoSt};
 
 use crate::{
-    constants::{DefaultPieceHasher, DRG_DEGREE, EXP_DEGREE, LAYERS, POREP_MINIMUM_CHALLENGES},
+    constants::{
+        DefaultPieceHasher, DRG_DEGREE, EMPTY_SECTOR_UPDATE_CHALLENGES, EXP_DEGREE, LAYERS,
+        NON_INTERACTIVE_POREP_MINIMUM_CHALLENGES, POREP_MINIMUM_CHALLENGES,
+        SYNTHETIC_POREP_CHALLENGES,
+    },
     types::{MerkleTreeTrait, PaddedBytesAmount, PoStConfig},
 };
 
@@ -19,12 +26,16 @@ pub fn public_params<Tree: 'static + MerkleTreeTrait>(
     partitions: usize,
     porep_id: [u8; 32],
     api_version: ApiVersion,
+    non_interactive: bool,
+    synthetic: bool,
 ) -> Result<stacked::PublicParams<Tree>> {
     StackedDrg::<Tree, DefaultPieceHasher>::setup(&setup_params(
         sector_bytes,
         partitions,
         porep_id,
         api_version,
+        non_interactive,
+        synthetic,
     )?)
 }
 
@@ -79,20 +90,51 @@ pub fn setup_params(
     partitions: usize,
     porep_id: [u8; 32],
     api_version: ApiVersion,
+    non_interactive: bool,
+    synthetic: bool,
 ) -> Result<stacked::SetupParams> {
-    let layer_challenges = select_challenges(
-        partitions,
-        *POREP_MINIMUM_CHALLENGES
+    ensure!(
+        !(non_interactive && synthetic),
+        "the non-interactive PoRep cannot use synthetic challenges"
+    );
+
+    let minimum_challenges = if non_interactive {
+        NON_INTERACTIVE_POREP_MINIMUM_CHALLENGES
+            .read()
+            .expect("NON_INTERACTIVE_POREP_MINIMUM_CHALLENGES poisoned")
+            .get(&u64::from(sector_bytes))
+            .copied()
+    } else {
+        POREP_MINIMUM_CHALLENGES
             .read()
             .expect("POREP_MINIMUM_CHALLENGES poisoned")
             .get(&u64::from(sector_bytes))
-            .expect("unknown sector size") as usize,
-        *LAYERS
+            .copied()
+    }
+    .expect("unknown sector size") as usize;
+    let layers = *LAYERS
+        .read()
+        .expect("LAYERS poisoned")
+        .get(&u64::from(sector_bytes))
+        .expect("unknown sector size");
+
+    let layer_challenges = select_challenges(partitions, minimum_challenges, layers);
+    let layer_challenges = if non_interactive {
+        LayerChallenges::new_non_interactive(layers, layer_challenges.challenges_count_all())
+    } else if synthetic {
+        let synthetic_count = *SYNTHETIC_POREP_CHALLENGES
             .read()
-            .expect("LAYERS poisoned")
+            .expect("SYNTHETIC_POREP_CHALLENGES poisoned")
             .get(&u64::from(sector_bytes))
-            .expect("unknown sector size"),
-    );
+            .expect("unknown sector size") as usize;
+        LayerChallenges::new_synthetic(
+            layers,
+            layer_challenges.challenges_count_all(),
+            synthetic_count,
+        )
+    } else {
+        layer_challenges
+    };
     let sector_bytes = u64::from(sector_bytes);
 
     ensure!(
@@ -115,6 +157,36 @@ pub fn setup_params(
     })
 }
 
+pub fn empty_sector_update_public_params<Tree: 'static + MerkleTreeTrait>(
+    sector_bytes: PaddedBytesAmount,
+) -> Result<update::PublicParams> {
+    EmptySectorUpdate::<Tree, DefaultPieceHasher>::setup(&empty_sector_update_setup_params(
+        sector_bytes,
+    )?)
+}
+
+pub fn empty_sector_update_setup_params(
+    sector_bytes: PaddedBytesAmount,
+) -> Result<update::SetupParams> {
+    let challenges_count = *EMPTY_SECTOR_UPDATE_CHALLENGES
+        .read()
+        .expect("EMPTY_SECTOR_UPDATE_CHALLENGES poisoned")
+        .get(&u64::from(sector_bytes))
+        .expect("unknown sector size");
+    let sector_bytes = u64::from(sector_bytes);
+
+    ensure!(
+        sector_bytes % 32 == 0,
+        "sector_bytes ({}) must be a multiple of 32",
+        sector_bytes,
+    );
+
+    Ok(update::SetupParams {
+        nodes: (sector_bytes / 32) as usize,
+        challenges_count,
+    })
+}
+
 fn select_challenges(
     partitions: usize,
     minimum_total_challenges: usize,
@@ -134,7 +206,7 @@ fn select_challenges(
 mod tests {
     use super::*;
 
-    use crate::{DefaultOctLCTree, PoRepProofPartitions, PoStType};
+    use crate::{constants::SECTOR_SIZE_32_GIB, DefaultOctLCTree, PoRepProofPartitions, PoStType};
 
     #[test]
     fn partition_layer_challenges_test() {
@@ -147,6 +219,35 @@ mod tests {
         assert_eq!(3, f(4));
     }
 
+    #[test]
+    fn test_non_interactive_setup_params() {
+        let sector_bytes = PaddedBytesAmount(SECTOR_SIZE_32_GIB);
+
+        let interactive = setup_params(sector_bytes, 10, [0; 32], ApiVersion::V1_1_0, false, false)
+            .expect("failed to get setup params");
+        assert!(!interactive.layer_challenges.is_non_interactive());
+        assert_eq!(interactive.layer_challenges.challenges_count_all(), 18);
+
+        let non_interactive =
+            setup_params(sector_bytes, 126, [0; 32], ApiVersion::V1_1_0, true, false)
+                .expect("failed to get setup params");
+        assert!(non_interactive.layer_challenges.is_non_interactive());
+        assert_eq!(non_interactive.layer_challenges.challenges_count_all(), 18);
+    }
+
+    #[test]
+    fn test_synthetic_setup_params() {
+        let sector_bytes = PaddedBytesAmount(SECTOR_SIZE_32_GIB);
+
+        let synthetic = setup_params(sector_bytes, 10, [0; 32], ApiVersion::V1_1_0, false, true)
+            .expect("failed to get setup params");
+        assert!(synthetic.layer_challenges.is_synthetic());
+        assert_eq!(synthetic.layer_challenges.synthetic_count(), 1 << 18);
+        assert_eq!(synthetic.layer_challenges.challenges_count_all(), 18);
+
+        assert!(setup_params(sector_bytes, 10, [0; 32], ApiVersion::V1_1_0, true, true).is_err());
+    }
+
     #[test]
     fn test_winning_post_params() {
         let config = PoStConfig {
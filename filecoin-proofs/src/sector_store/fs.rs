@@ -0,0 +1,105 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::sector_store::{SectorArtifact, SectorStore};
+
+/// A `SectorStore` using the filesystem layout the path based APIs expect: all artifacts but
+/// the replica live in the cache directory.
+#[derive(Debug, Clone)]
+pub struct FsSectorStore {
+    cache_dir: PathBuf,
+    replica_path: PathBuf,
+}
+
+impl FsSectorStore {
+    pub fn new<C: AsRef<Path>, R: AsRef<Path>>(cache_dir: C, replica_path: R) -> Self {
+        FsSectorStore {
+            cache_dir: cache_dir.as_ref().to_path_buf(),
+            replica_path: replica_path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    pub fn replica_path(&self) -> &Path {
+        &self.replica_path
+    }
+
+    /// Returns the path of the artifact on disk.
+    pub fn path(&self, artifact: &SectorArtifact) -> PathBuf {
+        match artifact.file_name() {
+            Some(name) => self.cache_dir.join(name),
+            None => self.replica_path.clone(),
+        }
+    }
+}
+
+impl SectorStore for FsSectorStore {
+    fn exists(&self, artifact: &SectorArtifact) -> Result<bool> {
+        Ok(self.path(artifact).exists())
+    }
+
+    fn size(&self, artifact: &SectorArtifact) -> Result<u64> {
+        let path = self.path(artifact);
+        let metadata = fs::metadata(&path).with_context(|| format!("could not stat {:?}", path))?;
+
+        Ok(metadata.len())
+    }
+
+    fn reader<'a>(&'a self, artifact: &SectorArtifact) -> Result<Box<dyn Read + Send + 'a>> {
+        let path = self.path(artifact);
+        let file = File::open(&path).with_context(|| format!("could not open {:?}", path))?;
+
+        Ok(Box::new(file))
+    }
+
+    fn write(&self, artifact: &SectorArtifact, data: &mut dyn Read) -> Result<u64> {
+        let path = self.path(artifact);
+        let mut file =
+            File::create(&path).with_context(|| format!("could not create {:?}", path))?;
+        let written =
+            io::copy(data, &mut file).with_context(|| format!("could not write {:?}", path))?;
+        file.sync_all()?;
+
+        Ok(written)
+    }
+
+    fn remove(&self, artifact: &SectorArtifact) -> Result<()> {
+        let path = self.path(artifact);
+        if path.exists() {
+            fs::remove_file(&path).with_context(|| format!("could not remove {:?}", path))?;
+        }
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<SectorArtifact>> {
+        let mut artifacts = Vec::new();
+
+        if self.replica_path.exists() {
+            artifacts.push(SectorArtifact::Replica);
+        }
+
+        if self.cache_dir.exists() {
+            for entry in fs::read_dir(&self.cache_dir)
+                .with_context(|| format!("could not read cache dir {:?}", self.cache_dir))?
+            {
+                let name = entry?.file_name();
+                if let Some(artifact) = name.to_str().and_then(SectorArtifact::from_file_name) {
+                    artifacts.push(artifact);
+                }
+            }
+        }
+
+        Ok(artifacts)
+    }
+
+    fn local_paths(&self) -> Option<(&Path, &Path)> {
+        Some((self.cache_dir.as_path(), self.replica_path.as_path()))
+    }
+}
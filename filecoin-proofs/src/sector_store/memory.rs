@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::RwLock;
+
+use anyhow::{anyhow, Result};
+
+use crate::sector_store::{SectorArtifact, SectorStore};
+
+/// A `SectorStore` keeping all artifacts in memory, intended for tests.
+#[derive(Debug, Default)]
+pub struct MemorySectorStore {
+    artifacts: RwLock<HashMap<SectorArtifact, Vec<u8>>>,
+}
+
+impl MemorySectorStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of the artifact's content, if present.
+    pub fn get(&self, artifact: &SectorArtifact) -> Option<Vec<u8>> {
+        self.artifacts
+            .read()
+            .expect("sector store lock poisoned")
+            .get(artifact)
+            .cloned()
+    }
+
+    /// Stores `data` as the artifact's content.
+    pub fn insert(&self, artifact: SectorArtifact, data: Vec<u8>) {
+        self.artifacts
+            .write()
+            .expect("sector store lock poisoned")
+            .insert(artifact, data);
+    }
+}
+
+impl SectorStore for MemorySectorStore {
+    fn exists(&self, artifact: &SectorArtifact) -> Result<bool> {
+        Ok(self
+            .artifacts
+            .read()
+            .expect("sector store lock poisoned")
+            .contains_key(artifact))
+    }
+
+    fn size(&self, artifact: &SectorArtifact) -> Result<u64> {
+        self.artifacts
+            .read()
+            .expect("sector store lock poisoned")
+            .get(artifact)
+            .map(|data| data.len() as u64)
+            .ok_or_else(|| anyhow!("missing artifact {:?}", artifact))
+    }
+
+    fn reader<'a>(&'a self, artifact: &SectorArtifact) -> Result<Box<dyn Read + Send + 'a>> {
+        let data = self
+            .get(artifact)
+            .ok_or_else(|| anyhow!("missing artifact {:?}", artifact))?;
+
+        Ok(Box::new(Cursor::new(data)))
+    }
+
+    fn write(&self, artifact: &SectorArtifact, data: &mut dyn Read) -> Result<u64> {
+        let mut buf = Vec::new();
+        data.read_to_end(&mut buf)?;
+        let written = buf.len() as u64;
+        self.insert(artifact.clone(), buf);
+
+        Ok(written)
+    }
+
+    fn remove(&self, artifact: &SectorArtifact) -> Result<()> {
+        self.artifacts
+            .write()
+            .expect("sector store lock poisoned")
+            .remove(artifact);
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<SectorArtifact>> {
+        Ok(self
+            .artifacts
+            .read()
+            .expect("sector store lock poisoned")
+            .keys()
+            .cloned()
+            .collect())
+    }
+}
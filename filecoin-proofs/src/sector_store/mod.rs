@@ -0,0 +1,310 @@
+//! Storage backends for the artifacts of a sealed sector.
+//!
+//! The seal and PoSt APIs operate on a cache directory and a replica path. A `SectorStore`
+//! abstracts where those artifacts actually live, and `with_local_sector` bridges any store to
+//! the path based APIs.
+//!
+//! The seal and PoSt APIs themselves stay path based: they memory map and seek into the layers
+//! and trees, which a generic store can't offer. A store which keeps the sector on the local
+//! filesystem, such as `FsSectorStore`, is used in place. Any other store is worked on through a
+//! local copy of the artifacts the call needs, see `with_local_artifacts`.
+
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::warn;
+use merkletree::store::StoreConfig;
+use sha2::{Digest, Sha256};
+use storage_proofs_core::cache_key::CacheKey;
+use tempfile::Builder;
+
+mod fs;
+mod memory;
+
+pub use self::fs::FsSectorStore;
+pub use self::memory::MemorySectorStore;
+
+/// The cache keys of the files stored directly in the cache directory.
+const AUX_KEYS: [CacheKey; 5] = [
+    CacheKey::PAux,
+    CacheKey::TAux,
+    CacheKey::LabelManifest,
+    CacheKey::SectorMeta,
+    CacheKey::SyntheticPoRepProofs,
+];
+
+/// The cache keys of the tree stores, which may be split into several sub-trees.
+const TREE_KEYS: [CacheKey; 3] = [
+    CacheKey::CommDTree,
+    CacheKey::CommCTree,
+    CacheKey::CommRLastTree,
+];
+
+/// Identifies one artifact of a sector.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SectorArtifact {
+    /// The sealed replica.
+    Replica,
+    /// A file stored under its cache key, such as `p_aux` or `t_aux`.
+    Aux(CacheKey),
+    /// The labels of a layer, starting at 1.
+    Layer(usize),
+    /// A tree store, or one of its sub-trees when `index` is set.
+    Tree { key: CacheKey, index: Option<usize> },
+}
+
+impl SectorArtifact {
+    /// Returns the name of this artifact in the filesystem cache layout, or `None` for the
+    /// replica, which lives outside of the cache directory.
+    pub fn file_name(&self) -> Option<String> {
+        let store_id = match self {
+            SectorArtifact::Replica => return None,
+            SectorArtifact::Aux(key) => return Some(key.to_string()),
+            SectorArtifact::Layer(layer) => CacheKey::label_layer(*layer),
+            SectorArtifact::Tree { key, index: None } => key.to_string(),
+            SectorArtifact::Tree {
+                key,
+                index: Some(index),
+            } => format!("{}-{}", key, index),
+        };
+
+        Some(store_file_name(&store_id))
+    }
+
+    /// Parses a file name of the filesystem cache layout. Returns `None` for files which are
+    /// not sector artifacts.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        if let Some(key) = AUX_KEYS.iter().find(|key| key.to_string() == name) {
+            return Some(SectorArtifact::Aux(*key));
+        }
+
+        let prefix = store_file_name("");
+        let prefix = prefix.strip_suffix(".dat")?;
+        let store_id = name.strip_prefix(prefix)?.strip_suffix(".dat")?;
+
+        if let Some(layer) = store_id.strip_prefix("layer-") {
+            return layer.parse().ok().map(SectorArtifact::Layer);
+        }
+
+        TREE_KEYS.iter().find_map(|key| {
+            let key_name = key.to_string();
+            if store_id == key_name {
+                return Some(SectorArtifact::Tree {
+                    key: *key,
+                    index: None,
+                });
+            }
+
+            store_id
+                .strip_prefix(&key_name)?
+                .strip_prefix('-')?
+                .parse()
+                .ok()
+                .map(|index| SectorArtifact::Tree {
+                    key: *key,
+                    index: Some(index),
+                })
+        })
+    }
+}
+
+/// Reads and writes the artifacts of a single sector.
+///
+/// Implementations must be safe to share between threads, and writing an artifact replaces
+/// any previous content.
+pub trait SectorStore: Send + Sync {
+    /// Returns whether the artifact is present.
+    fn exists(&self, artifact: &SectorArtifact) -> Result<bool>;
+
+    /// Returns the length of the artifact in bytes.
+    fn size(&self, artifact: &SectorArtifact) -> Result<u64>;
+
+    /// Opens the artifact for reading.
+    fn reader<'a>(&'a self, artifact: &SectorArtifact) -> Result<Box<dyn Read + Send + 'a>>;
+
+    /// Replaces the artifact with everything read from `data`, and returns the number of bytes
+    /// written.
+    fn write(&self, artifact: &SectorArtifact, data: &mut dyn Read) -> Result<u64>;
+
+    /// Removes the artifact, if present.
+    fn remove(&self, artifact: &SectorArtifact) -> Result<()>;
+
+    /// Lists all artifacts present in the store.
+    fn list(&self) -> Result<Vec<SectorArtifact>>;
+
+    /// Returns the cache directory and replica path of the sector, if the store keeps it on the
+    /// local filesystem in the layout of the path based APIs, so that they can use it in place.
+    fn local_paths(&self) -> Option<(&Path, &Path)> {
+        None
+    }
+}
+
+/// Copies every artifact of `from` into `to`, and removes the artifacts of `to` which are not
+/// present in `from`, so that both hold the same sector afterwards.
+pub fn sync_sector<F, T>(from: &F, to: &T) -> Result<()>
+where
+    F: SectorStore + ?Sized,
+    T: SectorStore + ?Sized,
+{
+    let artifacts = from.list()?;
+
+    for artifact in to.list()? {
+        if !artifacts.contains(&artifact) {
+            to.remove(&artifact)?;
+        }
+    }
+
+    for artifact in &artifacts {
+        let mut reader = from.reader(artifact)?;
+        to.write(artifact, &mut reader)
+            .with_context(|| format!("failed to copy {:?}", artifact))?;
+    }
+
+    Ok(())
+}
+
+/// Runs `f` on the sector held in `store`, see `with_local_artifacts`. All artifacts of the
+/// sector are copied if the store is not local.
+pub fn with_local_sector<S, F, T>(store: &S, work_dir: &Path, f: F) -> Result<T>
+where
+    S: SectorStore + ?Sized,
+    F: FnOnce(&Path, &Path) -> Result<T>,
+{
+    with_local_artifacts(store, work_dir, |_| true, f)
+}
+
+/// Runs `f` with the cache directory and replica path of the sector held in `store`, so that it
+/// can use any of the path based APIs.
+///
+/// If the store keeps the sector on the local filesystem, `f` is called with its paths directly.
+/// Otherwise, the artifacts for which `needed` returns true are copied into a new directory
+/// within `work_dir`, and `f` is called with the paths of that copy. The artifacts which `f`
+/// created, modified or removed are then written back into `store` if `f` succeeds, and the
+/// copy is removed whether `f` succeeds or not. Nothing else in `work_dir` is touched.
+pub fn with_local_artifacts<S, N, F, T>(store: &S, work_dir: &Path, needed: N, f: F) -> Result<T>
+where
+    S: SectorStore + ?Sized,
+    N: Fn(&SectorArtifact) -> bool,
+    F: FnOnce(&Path, &Path) -> Result<T>,
+{
+    if let Some((cache_dir, replica_path)) = store.local_paths() {
+        return f(cache_dir, replica_path);
+    }
+
+    create_dir_all(work_dir)
+        .with_context(|| format!("could not create work dir {:?}", work_dir))?;
+    let local_dir = Builder::new()
+        .prefix("sector-")
+        .tempdir_in(work_dir)
+        .with_context(|| format!("could not create a sector dir in {:?}", work_dir))?;
+    let local_path = local_dir.path().to_path_buf();
+
+    let result = run_local_sector(store, &local_path, needed, f);
+
+    if let Err(err) = local_dir.close() {
+        warn!("failed to remove sector dir {:?}: {}", local_path, err);
+    }
+
+    result
+}
+
+fn run_local_sector<S, N, F, T>(store: &S, local_dir: &Path, needed: N, f: F) -> Result<T>
+where
+    S: SectorStore + ?Sized,
+    N: Fn(&SectorArtifact) -> bool,
+    F: FnOnce(&Path, &Path) -> Result<T>,
+{
+    let cache_dir = local_dir.join("cache");
+    let replica_path = local_dir.join("replica");
+    create_dir_all(&cache_dir)
+        .with_context(|| format!("could not create cache dir {:?}", cache_dir))?;
+
+    let local = FsSectorStore::new(&cache_dir, &replica_path);
+    let mut copied = HashMap::new();
+    for artifact in store.list()? {
+        if !needed(&artifact) {
+            continue;
+        }
+        let mut reader = HashingReader::new(store.reader(&artifact)?);
+        let len = local
+            .write(&artifact, &mut reader)
+            .with_context(|| format!("failed to copy {:?}", artifact))?;
+        copied.insert(artifact, (len, reader.finish()));
+    }
+
+    let result = f(&cache_dir, &replica_path)?;
+
+    let current = local.list()?;
+    for artifact in copied.keys() {
+        if !current.contains(artifact) {
+            store.remove(artifact)?;
+        }
+    }
+    for artifact in &current {
+        let unchanged = match copied.get(artifact) {
+            Some((len, digest)) => {
+                local.size(artifact)? == *len && file_digest(&local.path(artifact))? == *digest
+            }
+            None => false,
+        };
+        if !unchanged {
+            let mut reader = local.reader(artifact)?;
+            store
+                .write(artifact, &mut reader)
+                .with_context(|| format!("failed to copy back {:?}", artifact))?;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Hashes the data read through it, so that the artifacts changed by a path based API can be
+/// told apart from the copied ones by their content.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        HashingReader {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    fn finish(self) -> [u8; 32] {
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&self.hasher.finalize());
+
+        digest
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+
+        Ok(read)
+    }
+}
+
+/// Returns the SHA-256 digest of the file's content.
+fn file_digest(path: &Path) -> Result<[u8; 32]> {
+    let file = File::open(path).with_context(|| format!("could not open {:?}", path))?;
+    let mut reader = HashingReader::new(file);
+    io::copy(&mut reader, &mut io::sink()).with_context(|| format!("could not read {:?}", path))?;
+
+    Ok(reader.finish())
+}
+
+/// The name of the data file of the store with the given id.
+fn store_file_name(store_id: &str) -> String {
+    StoreConfig::data_path(&PathBuf::new(), store_id)
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::AggregateSnarkProof;
+
+/// An aggregate of seal proofs of sectors which differ in sector size or porep_id.
+///
+/// Proofs can only be aggregated together if they share a verifying key, so the seal proofs are
+/// aggregated per group of sectors sharing one, regardless of their order. See
+/// `aggregate_seal_commit_proofs_heterogeneous`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeterogeneousAggregateProof {
+    pub groups: Vec<AggregateProofGroup>,
+}
+
+/// The aggregate proof of a single group of sectors sharing a verifying key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AggregateProofGroup {
+    pub sector_size: u64,
+    /// The indices of the group's sectors in the aggregated sectors, in ascending order.
+    pub indices: Vec<usize>,
+    pub proof: AggregateSnarkProof,
+}
@@ -1,6 +1,18 @@
 pub use merkletree::store::StoreConfig;
-pub use storage_proofs_core::merkle::{MerkleProof, MerkleTreeTrait};
-pub use storage_proofs_porep::stacked::{Labels, PersistentAux, TemporaryAux};
+pub use storage_proofs_core::{
+    cancel::CancellationToken,
+    measurements::{
+        set_measurement_sink, with_tags, CollectorSink, JsonLinesSink, MeasurementSink,
+        PrometheusSink,
+    },
+    merkle::{MerkleProof, MerkleTreeTrait},
+    progress::{NoProgress, ProgressEvent, ProgressObserver},
+    run_options::RunOptions,
+    settings::{with_config, ProofsConfig},
+};
+pub use storage_proofs_porep::stacked::{
+    Labels, ParentCacheInfo, ParentCacheStatus, PersistentAux, TemporaryAux,
+};
 
 use filecoin_hashers::Hasher;
 use serde::{Deserialize, Serialize};
@@ -11,6 +23,8 @@ use storage_proofs_post::fallback;
 use crate::constants::DefaultPieceHasher;
 
 mod bytes_amount;
+mod heterogeneous_aggregate_proof;
+mod piece_inclusion_proof;
 mod piece_info;
 mod porep_config;
 mod porep_proof_partitions;
@@ -18,10 +32,17 @@ mod post_config;
 mod post_proof_partitions;
 mod private_replica_info;
 mod public_replica_info;
+mod seal_aggregation_session;
+mod sector_cache_report;
 mod sector_class;
+mod sector_fault;
+mod sector_meta;
 mod sector_size;
+mod vanilla_proof_batch;
 
 pub use bytes_amount::*;
+pub use heterogeneous_aggregate_proof::*;
+pub use piece_inclusion_proof::*;
 pub use piece_info::*;
 pub use porep_config::*;
 pub use porep_proof_partitions::*;
@@ -29,8 +50,13 @@ pub use post_config::*;
 pub use post_proof_partitions::*;
 pub use private_replica_info::*;
 pub use public_replica_info::*;
+pub use seal_aggregation_session::*;
+pub use sector_cache_report::*;
 pub use sector_class::*;
+pub use sector_fault::*;
+pub use sector_meta::*;
 pub use sector_size::*;
+pub use vanilla_proof_batch::*;
 
 pub type Commitment = [u8; 32];
 pub type ChallengeSeed = [u8; 32];
@@ -71,6 +97,14 @@ pub struct SealCommitOutput {
     pub proof: Vec<u8>,
 }
 
+/// Commitments of a sector after new data was encoded into it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmptySectorUpdateEncoded {
+    pub comm_r_new: Commitment,
+    pub comm_r_last_new: Commitment,
+    pub comm_d_new: Commitment,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SealPreCommitPhase1Output<Tree: MerkleTreeTrait> {
     #[serde(bound(
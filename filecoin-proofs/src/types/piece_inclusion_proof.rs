@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::Commitment;
+
+/// Proves that a piece commitment is the root of a subtree of a sector's comm_d.
+///
+/// The path holds the sibling of each node on the way from the piece commitment up to,
+/// but excluding, comm_d. The position of the piece in the sector determines on which side
+/// each sibling is hashed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PieceInclusionProof {
+    pub path: Vec<Commitment>,
+}
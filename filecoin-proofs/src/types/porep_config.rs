@@ -1,6 +1,9 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::RwLock;
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use storage_proofs_core::{
     api_version::ApiVersion,
     merkle::MerkleTreeTrait,
@@ -12,17 +15,23 @@ use storage_proofs_core::{
 use storage_proofs_porep::stacked::{StackedCircuit, StackedCompound};
 
 use crate::{
-    constants::DefaultPieceHasher,
+    constants::{DefaultPieceHasher, NON_INTERACTIVE_POREP_PARTITIONS, POREP_PARTITIONS},
     parameters::public_params,
     types::{PaddedBytesAmount, PoRepProofPartitions, SectorSize, UnpaddedBytesAmount},
 };
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PoRepConfig {
     pub sector_size: SectorSize,
     pub partitions: PoRepProofPartitions,
     pub porep_id: [u8; 32],
     pub api_version: ApiVersion,
+    /// Derive the PoRep challenges from CommR instead of the interactive seed, see
+    /// `new_non_interactive` for the matching partition counts.
+    pub non_interactive: bool,
+    /// Prove the seed's selection of precomputed synthetic challenges, see
+    /// `generate_synthetic_proofs`. The synthetic PoRep uses the interactive PoRep's parameters.
+    pub synthetic: bool,
 }
 
 impl From<PoRepConfig> for PaddedBytesAmount {
@@ -54,6 +63,45 @@ impl From<PoRepConfig> for SectorSize {
 }
 
 impl PoRepConfig {
+    /// Returns the config of the interactive PoRep for `sector_size`, with the partitions of
+    /// `POREP_PARTITIONS`.
+    pub fn new_groth16(sector_size: u64, porep_id: [u8; 32], api_version: ApiVersion) -> Self {
+        PoRepConfig {
+            sector_size: SectorSize(sector_size),
+            partitions: PoRepProofPartitions(partitions(&POREP_PARTITIONS, sector_size)),
+            porep_id,
+            api_version,
+            non_interactive: false,
+            synthetic: false,
+        }
+    }
+
+    /// Returns the config of the non-interactive PoRep for `sector_size`, with the partitions of
+    /// `NON_INTERACTIVE_POREP_PARTITIONS`.
+    pub fn new_non_interactive(
+        sector_size: u64,
+        porep_id: [u8; 32],
+        api_version: ApiVersion,
+    ) -> Self {
+        PoRepConfig {
+            partitions: PoRepProofPartitions(partitions(
+                &NON_INTERACTIVE_POREP_PARTITIONS,
+                sector_size,
+            )),
+            non_interactive: true,
+            ..Self::new_groth16(sector_size, porep_id, api_version)
+        }
+    }
+
+    /// Returns the config of the synthetic PoRep for `sector_size`, which has the partitions of
+    /// the interactive PoRep.
+    pub fn new_synthetic(sector_size: u64, porep_id: [u8; 32], api_version: ApiVersion) -> Self {
+        PoRepConfig {
+            synthetic: true,
+            ..Self::new_groth16(sector_size, porep_id, api_version)
+        }
+    }
+
     /// Returns the cache identifier as used by `storage-proofs::paramater_cache`.
     pub fn get_cache_identifier<Tree: 'static + MerkleTreeTrait>(&self) -> Result<String> {
         let params = public_params::<Tree>(
@@ -61,6 +109,8 @@ impl PoRepConfig {
             self.partitions.into(),
             self.porep_id,
             self.api_version,
+            self.non_interactive,
+            self.synthetic,
         )?;
 
         Ok(
@@ -86,3 +136,11 @@ impl PoRepConfig {
         Ok(parameter_cache_params_path(&id))
     }
 }
+
+fn partitions(partitions: &RwLock<HashMap<u64, u8>>, sector_size: u64) -> u8 {
+    *partitions
+        .read()
+        .expect("partitions poisoned")
+        .get(&sector_size)
+        .expect("unknown sector size")
+}
@@ -16,12 +16,14 @@ use storage_proofs_core::{
         create_tree, get_base_tree_count, split_config_and_replica, MerkleTreeTrait,
         MerkleTreeWrapper,
     },
-    util::default_rows_to_discard,
 };
 
 use crate::{
-    api::{as_safe_commitment, get_base_tree_leafs, get_base_tree_size},
-    types::{Commitment, PersistentAux, SectorSize},
+    api::{
+        as_safe_commitment, get_base_tree_leafs, get_base_tree_size,
+        get_tree_r_last_rows_to_discard, read_sector_meta,
+    },
+    types::{Commitment, PersistentAux, SectorMeta, SectorSize},
 };
 
 /// The minimal information required about a replica, in order to be able to generate
@@ -36,6 +38,8 @@ pub struct PrivateReplicaInfo<Tree: MerkleTreeTrait> {
     aux: PersistentAux<<Tree::Hasher as Hasher>::Domain>,
     /// Contains sector-specific (e.g. merkle trees) assets
     pub cache_dir: PathBuf,
+    /// The sector metadata, if the sector was sealed with it.
+    meta: Option<SectorMeta>,
 
     _t: PhantomData<Tree>,
 }
@@ -47,6 +51,7 @@ impl<Tree: MerkleTreeTrait> Clone for PrivateReplicaInfo<Tree> {
             comm_r: self.comm_r,
             aux: self.aux.clone(),
             cache_dir: self.cache_dir.clone(),
+            meta: self.meta,
             _t: Default::default(),
         }
     }
@@ -96,6 +101,8 @@ impl<Tree: 'static + MerkleTreeTrait> PrivateReplicaInfo<Tree> {
             deserialize(&aux_bytes)
         }?;
 
+        let meta = read_sector_meta(&cache_dir)?;
+
         ensure!(replica.exists(), "Sealed replica does not exist");
 
         Ok(PrivateReplicaInfo {
@@ -103,6 +110,7 @@ impl<Tree: 'static + MerkleTreeTrait> PrivateReplicaInfo<Tree> {
             comm_r,
             aux,
             cache_dir,
+            meta,
             _t: Default::default(),
         })
     }
@@ -127,6 +135,14 @@ impl<Tree: 'static + MerkleTreeTrait> PrivateReplicaInfo<Tree> {
         self.aux.comm_r_last
     }
 
+    /// The number of rows discarded from the cached `tree_r_last` base trees of this replica.
+    pub fn tree_r_last_rows_to_discard(&self, sector_size: SectorSize) -> Result<usize> {
+        match self.meta {
+            Some(meta) => Ok(meta.tree_r_last_rows_to_discard),
+            None => get_tree_r_last_rows_to_discard::<Tree>(self.cache_dir_path(), sector_size),
+        }
+    }
+
     /// Generate the merkle tree of this particular replica.
     pub fn merkle_tree(
         &self,
@@ -142,11 +158,12 @@ impl<Tree: 'static + MerkleTreeTrait> PrivateReplicaInfo<Tree> {
     > {
         let base_tree_size = get_base_tree_size::<Tree>(sector_size)?;
         let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+        let rows_to_discard = self.tree_r_last_rows_to_discard(sector_size)?;
         trace!(
             "post: base tree size {}, base tree leafs {}, rows_to_discard {}, arities [{}, {}, {}]",
             base_tree_size,
             base_tree_leafs,
-            default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize()),
+            rows_to_discard,
             Tree::Arity::to_usize(),
             Tree::SubTreeArity::to_usize(),
             Tree::TopTreeArity::to_usize(),
@@ -155,7 +172,7 @@ impl<Tree: 'static + MerkleTreeTrait> PrivateReplicaInfo<Tree> {
         let mut config = StoreConfig::new(
             self.cache_dir_path(),
             CacheKey::CommRLastTree.to_string(),
-            default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize()),
+            rows_to_discard,
         );
         config.size = Some(base_tree_size);
 
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::get_aggregate_target_len,
+    constants::SINGLE_PARTITION_PROOF_LEN,
+    types::{Commitment, PoRepConfig, Ticket},
+};
+
+/// The state of an incremental seal proof aggregation.
+///
+/// Seal proofs are added with `seal_aggregation_add_proof` as they arrive, and aggregated once
+/// with `seal_aggregation_finalize`. Only the partition proofs, comm_rs and seeds are kept, so
+/// the session can be serialized and resumed in between.
+///
+/// All proofs of a session share the `PoRepConfig` of the first added proof.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealAggregationSession {
+    pub(crate) porep_config: Option<PoRepConfig>,
+    pub(crate) comm_rs: Vec<Commitment>,
+    pub(crate) seeds: Vec<Ticket>,
+    /// The partition proofs of all added seal proofs, in the order they were added.
+    pub(crate) proofs: Vec<u8>,
+}
+
+impl SealAggregationSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of seal proofs added to this session.
+    pub fn len(&self) -> usize {
+        self.comm_rs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comm_rs.is_empty()
+    }
+
+    /// Returns the porep config of the added seal proofs, or `None` if no proof was added yet.
+    pub fn porep_config(&self) -> Option<PoRepConfig> {
+        self.porep_config
+    }
+
+    /// Returns the number of partition proofs added to this session, which are the proofs
+    /// being aggregated.
+    pub fn partition_proofs_len(&self) -> usize {
+        self.proofs.len() / SINGLE_PARTITION_PROOF_LEN
+    }
+
+    /// Returns the number of partition proofs which are aggregated once the session is
+    /// finalized, including the padding.
+    ///
+    /// SnarkPack can only aggregate a power of two number of proofs, and at least two, so this is
+    /// the minimal padding. Finalizing when it equals `partition_proofs_len` avoids padding.
+    pub fn padded_partition_proofs_len(&self) -> usize {
+        get_aggregate_target_len(self.partition_proofs_len())
+    }
+
+    pub fn comm_rs(&self) -> &[Commitment] {
+        &self.comm_rs
+    }
+
+    pub fn seeds(&self) -> &[Ticket] {
+        &self.seeds
+    }
+}
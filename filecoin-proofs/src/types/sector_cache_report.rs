@@ -0,0 +1,73 @@
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// The outcome of checking a single artifact of a sector cache.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArtifactStatus {
+    Valid,
+    Missing,
+    /// The artifact exists, but could not be read or has an invalid size.
+    Corrupt(String),
+    /// The artifact is readable, but a recomputed value does not match the expected one.
+    Mismatch {
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Display for ArtifactStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ArtifactStatus::Valid => write!(f, "valid"),
+            ArtifactStatus::Missing => write!(f, "missing"),
+            ArtifactStatus::Corrupt(reason) => write!(f, "corrupt: {}", reason),
+            ArtifactStatus::Mismatch { expected, actual } => {
+                write!(f, "mismatch: expected {}, got {}", expected, actual)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactReport {
+    pub name: String,
+    pub path: PathBuf,
+    pub status: ArtifactStatus,
+}
+
+/// The result of `verify_sector_cache`, with one entry per checked artifact.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectorCacheReport {
+    pub artifacts: Vec<ArtifactReport>,
+}
+
+impl SectorCacheReport {
+    pub fn push<S: Into<String>, P: Into<PathBuf>>(
+        &mut self,
+        name: S,
+        path: P,
+        status: ArtifactStatus,
+    ) {
+        self.artifacts.push(ArtifactReport {
+            name: name.into(),
+            path: path.into(),
+            status,
+        });
+    }
+
+    /// Returns true if every checked artifact is valid.
+    pub fn is_valid(&self) -> bool {
+        self.artifacts
+            .iter()
+            .all(|artifact| artifact.status == ArtifactStatus::Valid)
+    }
+
+    /// Returns the artifacts which are not valid.
+    pub fn failures(&self) -> impl Iterator<Item = &ArtifactReport> {
+        self.artifacts
+            .iter()
+            .filter(|artifact| artifact.status != ArtifactStatus::Valid)
+    }
+}
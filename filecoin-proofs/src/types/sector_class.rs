@@ -23,6 +23,8 @@ impl From<SectorClass> for PoRepConfig {
             partitions,
             porep_id,
             api_version,
+            non_interactive: false,
+            synthetic: false,
         }
     }
 }
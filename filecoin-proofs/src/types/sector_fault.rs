@@ -0,0 +1,55 @@
+use std::fmt::{self, Display, Formatter};
+
+use storage_proofs_core::sector::SectorId;
+
+use crate::types::VanillaProofBatchConfig;
+
+/// Why a sector could not be proven.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FaultReason {
+    /// The replica or its cached `tree-r-last` is missing.
+    MissingCache,
+    /// The cached `tree-r-last` does not lead to the sector's `comm_r_last`.
+    RootMismatch,
+    /// Reading the replica or its cache failed.
+    Io(String),
+    /// Proving the sector took longer than the configured timeout.
+    Timeout,
+    /// Proving the sector failed for any other reason.
+    Other(String),
+}
+
+impl Display for FaultReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultReason::MissingCache => write!(f, "missing cache"),
+            FaultReason::RootMismatch => write!(f, "root mismatch"),
+            FaultReason::Io(err) => write!(f, "i/o error: {}", err),
+            FaultReason::Timeout => write!(f, "timeout"),
+            FaultReason::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// A sector for which no vanilla proof could be generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectorFault {
+    pub sector_id: SectorId,
+    pub reason: FaultReason,
+}
+
+/// How `generate_window_post_with_options` handles sectors which cannot be proven.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultPolicy {
+    /// Fail with `Error::FaultySectors`, like `generate_window_post`.
+    Abort,
+    /// Leave the faulty sectors out of the proof, and report them alongside it. The vanilla
+    /// proofs are generated by `generate_vanilla_proofs_batch` with the given config.
+    Skip(VanillaProofBatchConfig),
+}
+
+impl Default for FaultPolicy {
+    fn default() -> Self {
+        FaultPolicy::Abort
+    }
+}
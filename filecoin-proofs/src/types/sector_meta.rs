@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+/// Metadata persisted next to `p_aux`, recording how the trees of a sector were stored.
+///
+/// Sectors sealed before it was introduced have no metadata, see
+/// `get_tree_r_last_rows_to_discard` for how the values are detected for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SectorMeta {
+    /// The number of rows which were discarded from each `tree-r-last` base tree.
+    pub tree_r_last_rows_to_discard: usize,
+}
@@ -1,8 +1,9 @@
 use fr32::to_unpadded_bytes;
+use serde::{Deserialize, Serialize};
 
 use crate::types::{PaddedBytesAmount, UnpaddedBytesAmount};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct SectorSize(pub u64);
 
 impl From<u64> for SectorSize {
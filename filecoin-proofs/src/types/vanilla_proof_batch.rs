@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use storage_proofs_core::merkle::MerkleTreeTrait;
+
+use crate::types::{FallbackPoStSectorProof, SectorFault};
+
+/// Scheduling limits for `generate_vanilla_proofs_batch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VanillaProofBatchConfig {
+    /// The maximum number of sectors proven at the same time whose cache directories and
+    /// replicas are on the same storage devices. The default of 1 suits rotational disks; solid
+    /// state storage benefits from higher values.
+    pub max_concurrent_per_path: usize,
+    /// If set, sectors which take longer to prove are reported as timed out. A timed out sector
+    /// is cancelled, and stops proving once its pending read returns.
+    pub sector_timeout: Option<Duration>,
+}
+
+impl Default for VanillaProofBatchConfig {
+    fn default() -> Self {
+        VanillaProofBatchConfig {
+            max_concurrent_per_path: 1,
+            sector_timeout: None,
+        }
+    }
+}
+
+/// The result of `generate_vanilla_proofs_batch`. Both proofs and faults are ordered by sector
+/// id.
+#[derive(Debug, Clone)]
+pub struct VanillaProofBatch<Tree: MerkleTreeTrait> {
+    pub proofs: Vec<FallbackPoStSectorProof<Tree>>,
+    pub faults: Vec<SectorFault>,
+}
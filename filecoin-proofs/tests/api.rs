@@ -1,34 +1,46 @@
 use std::collections::BTreeMap;
-use std::fs::{read_dir, remove_file};
+use std::fs::{read, read_dir, remove_file};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::sync::Once;
+use std::sync::{Arc, Once};
 
 use anyhow::{ensure, Result};
 use bellperson::bls::{Bls12, Fr};
 use bellperson::groth16;
-use bincode::serialize;
+use bincode::{deserialize, serialize};
 use ff::Field;
 use filecoin_hashers::Hasher;
 use filecoin_proofs::{
-    add_piece, aggregate_seal_commit_proofs, clear_cache, compute_comm_d, fauxrep_aux,
+    add_piece, aggregate_seal_commit_proofs, aggregate_seal_commit_proofs_heterogeneous,
+    aggregate_window_post_proofs, clear_cache, clear_layer_data, compute_comm_d, fauxrep_aux,
     generate_fallback_sector_challenges, generate_piece_commitment, generate_single_vanilla_proof,
-    generate_window_post, generate_window_post_with_vanilla, generate_winning_post,
+    generate_synthetic_proofs, generate_vanilla_proofs_batch, generate_window_post,
+    generate_window_post_with_options, generate_window_post_with_vanilla, generate_winning_post,
     generate_winning_post_sector_challenge, generate_winning_post_with_vanilla, get_seal_inputs,
-    seal_commit_phase1, seal_commit_phase2, seal_pre_commit_phase1, seal_pre_commit_phase2,
-    unseal_range, validate_cache_for_commit, validate_cache_for_precommit_phase2,
-    verify_aggregate_seal_commit_proofs, verify_seal, verify_window_post, verify_winning_post,
-    Commitment, DefaultTreeDomain, MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig,
-    PoRepProofPartitions, PoStConfig, PoStType, PrivateReplicaInfo, ProverId, PublicReplicaInfo,
-    SealCommitOutput, SealPreCommitOutput, SealPreCommitPhase1Output, SectorShape16KiB,
-    SectorShape2KiB, SectorShape32KiB, SectorShape4KiB, SectorSize, UnpaddedByteIndex,
-    UnpaddedBytesAmount, POREP_PARTITIONS, SECTOR_SIZE_16_KIB, SECTOR_SIZE_2_KIB,
-    SECTOR_SIZE_32_KIB, SECTOR_SIZE_4_KIB, WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT,
+    get_tree_r_last_rows_to_discard, get_unsealed_range, get_unsealed_range_partial,
+    persist_sector_meta, read_sector_meta, regenerate_missing_trees, regenerate_tree_d,
+    seal_aggregation_add_proof, seal_aggregation_finalize, seal_commit_phase1, seal_commit_phase2,
+    seal_commit_phase2_with_options, seal_pre_commit_phase1, seal_pre_commit_phase1_with_options,
+    seal_pre_commit_phase2, seal_pre_commit_phase2_with_options, unseal_range,
+    validate_cache_for_commit, validate_cache_for_precommit_phase2,
+    verify_aggregate_seal_commit_proofs, verify_aggregate_seal_commit_proofs_heterogeneous,
+    verify_aggregate_window_post_proofs, verify_seal, verify_sector_cache, verify_window_post,
+    verify_winning_post, with_config, Commitment, DefaultTreeDomain, FaultPolicy, FaultReason,
+    HeterogeneousAggregateProof, MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig,
+    PoStConfig, PoStType, PrivateReplicaInfo, ProofsConfig, ProverId, PublicReplicaInfo,
+    RunOptions, SealAggregationSession, SealCommitOutput, SealPreCommitOutput,
+    SealPreCommitPhase1Output, SectorMeta, SectorShape16KiB, SectorShape2KiB, SectorShape32KiB,
+    SectorShape4KiB, SectorSize, StoreConfig, UnpaddedByteIndex, UnpaddedBytesAmount,
+    VanillaProofBatchConfig, SECTOR_SIZE_16_KIB, SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_KIB,
+    SECTOR_SIZE_4_KIB, WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT,
     WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
 };
 use rand::{random, Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
-use storage_proofs_core::{api_version::ApiVersion, is_legacy_porep_id, sector::SectorId};
+use storage_proofs_core::{
+    api_version::ApiVersion, cache_key::CacheKey, is_legacy_porep_id, sector::SectorId,
+    settings::SETTINGS,
+};
 use tempfile::{tempdir, NamedTempFile, TempDir};
 
 // Use a fixed PoRep ID, so that the parents cache can be re-used between some tests.
@@ -116,6 +128,173 @@ fn test_seal_lifecycle_32kib_top_8_8_2() -> Result<()> {
     )
 }
 
+#[test]
+#[ignore]
+fn test_seal_lifecycle_non_interactive_2kib_base_8() -> Result<()> {
+    type Tree = SectorShape2KiB;
+
+    init_logger();
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let (mut piece_file, _piece_bytes) = generate_piece_file(SECTOR_SIZE_2_KIB)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+
+    let config = PoRepConfig::new_non_interactive(
+        SECTOR_SIZE_2_KIB,
+        ARBITRARY_POREP_ID_V1_1_0,
+        ApiVersion::V1_1_0,
+    );
+    let ticket = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    let (piece_infos, phase1_output) = run_seal_pre_commit_phase1::<Tree>(
+        config,
+        prover_id,
+        sector_id,
+        ticket,
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )?;
+
+    let pre_commit_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    // The challenges are derived from comm_r, so the proof does not depend on the seed.
+    let (commit_output, _commit_inputs, _seed, comm_r) = generate_proof::<Tree>(
+        config,
+        cache_dir.path(),
+        &sealed_sector_file,
+        prover_id,
+        sector_id,
+        ticket,
+        [0; 32],
+        &pre_commit_output,
+        &piece_infos,
+    )?;
+
+    let verified = verify_seal::<Tree>(
+        config,
+        comm_r,
+        pre_commit_output.comm_d,
+        prover_id,
+        sector_id,
+        ticket,
+        rng.gen(),
+        &commit_output.proof,
+    )?;
+    assert!(verified, "failed to verify valid non-interactive seal");
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_seal_lifecycle_synthetic_2kib_base_8() -> Result<()> {
+    type Tree = SectorShape2KiB;
+
+    init_logger();
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let (mut piece_file, _piece_bytes) = generate_piece_file(SECTOR_SIZE_2_KIB)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+
+    let config = PoRepConfig::new_synthetic(
+        SECTOR_SIZE_2_KIB,
+        ARBITRARY_POREP_ID_V1_1_0,
+        ApiVersion::V1_1_0,
+    );
+    let ticket = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    let (piece_infos, phase1_output) = run_seal_pre_commit_phase1::<Tree>(
+        config,
+        prover_id,
+        sector_id,
+        ticket,
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )?;
+
+    let pre_commit_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    generate_synthetic_proofs::<_, Tree>(
+        config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        pre_commit_output.clone(),
+        &piece_infos,
+    )?;
+
+    // The layers are not needed by the commit anymore.
+    clear_layer_data::<Tree>(cache_dir.path())?;
+    assert!(
+        !cache_dir
+            .path()
+            .join(format!("sc-02-data-{}.dat", CacheKey::label_layer(1)))
+            .exists(),
+        "layer data was not cleared"
+    );
+    validate_cache_for_commit::<_, _, Tree>(cache_dir.path(), sealed_sector_file.path())?;
+
+    let seed = rng.gen();
+    let (commit_output, _commit_inputs, _seed, comm_r) = generate_proof::<Tree>(
+        config,
+        cache_dir.path(),
+        &sealed_sector_file,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        &pre_commit_output,
+        &piece_infos,
+    )?;
+    assert!(
+        !cache_dir
+            .path()
+            .join(CacheKey::SyntheticPoRepProofs.to_string())
+            .exists(),
+        "synthetic proofs were not cleared"
+    );
+
+    let verified = verify_seal::<Tree>(
+        config,
+        comm_r,
+        pre_commit_output.comm_d,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        &commit_output.proof,
+    )?;
+    assert!(verified, "failed to verify valid synthetic seal");
+
+    Ok(())
+}
+
 // These tests are good to run, but take a long time.
 
 //#[test]
@@ -327,6 +506,62 @@ fn test_seal_proof_aggregation_818_32kib_porep_id_v1_1_base_8() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[ignore]
+fn test_seal_proof_aggregation_session_5_2kib_porep_id_v1_1_base_8() -> Result<()> {
+    let proofs_to_aggregate = 5; // Requires auto-padding
+
+    let porep_id = ARBITRARY_POREP_ID_V1_1_0;
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let (commit_output, commit_input, seed, comm_r) =
+        create_seal_for_aggregation::<_, SectorShape2KiB>(
+            rng,
+            SECTOR_SIZE_2_KIB,
+            prover_id,
+            &porep_id,
+            ApiVersion::V1_1_0,
+        )?;
+
+    let config = porep_config(SECTOR_SIZE_2_KIB, porep_id, ApiVersion::V1_1_0);
+    let mut session = SealAggregationSession::new();
+    let mut commit_inputs = Vec::with_capacity(proofs_to_aggregate);
+    for _ in 0..proofs_to_aggregate {
+        // Round-trip the session between additions, as a caller persisting it would.
+        let bytes = serialize(&session)?;
+        session = deserialize(&bytes)?;
+
+        seal_aggregation_add_proof(config, &mut session, comm_r, seed, &commit_output)?;
+        commit_inputs.extend(commit_input.clone());
+    }
+    assert_eq!(session.len(), proofs_to_aggregate);
+    assert_eq!(session.porep_config(), Some(config));
+    assert_eq!(session.padded_partition_proofs_len(), 8);
+
+    // Proofs of another porep config cannot be aggregated with the session's proofs.
+    let other_config = porep_config(SECTOR_SIZE_2_KIB, [1; 32], ApiVersion::V1_1_0);
+    assert!(
+        seal_aggregation_add_proof(other_config, &mut session, comm_r, seed, &commit_output)
+            .is_err()
+    );
+    assert_eq!(session.len(), proofs_to_aggregate);
+
+    let aggregate_proof = seal_aggregation_finalize::<SectorShape2KiB>(&session)?;
+    let verified = verify_aggregate_seal_commit_proofs::<SectorShape2KiB>(
+        config,
+        aggregate_proof,
+        session.comm_rs(),
+        session.seeds(),
+        commit_inputs,
+    )?;
+    assert!(verified);
+
+    Ok(())
+}
+
 //#[test]
 //#[ignore]
 //fn test_seal_proof_aggregation_818_32gib_porep_id_v1_1_base_8() -> Result<()> {
@@ -377,6 +612,145 @@ fn test_seal_proof_aggregation_818_32kib_porep_id_v1_1_base_8() -> Result<()> {
 //    inner_test_seal_proof_aggregation_2kib_porep_id_v1_1_base_8(proofs_to_aggregate)
 //}
 
+#[test]
+#[ignore]
+fn test_seal_proof_aggregation_heterogeneous_2kib_4kib_porep_id_v1_1() -> Result<()> {
+    let sector_sizes = [
+        SECTOR_SIZE_2_KIB,
+        SECTOR_SIZE_2_KIB,
+        SECTOR_SIZE_4_KIB,
+        SECTOR_SIZE_4_KIB,
+        SECTOR_SIZE_4_KIB,
+    ];
+    inner_test_seal_proof_aggregation_heterogeneous_2kib_4kib(
+        &sector_sizes,
+        &[vec![0, 1], vec![2, 3, 4]],
+    )
+}
+
+#[test]
+#[ignore]
+fn test_seal_proof_aggregation_heterogeneous_interleaved_2kib_4kib_porep_id_v1_1() -> Result<()> {
+    let sector_sizes = [
+        SECTOR_SIZE_4_KIB,
+        SECTOR_SIZE_2_KIB,
+        SECTOR_SIZE_4_KIB,
+        SECTOR_SIZE_2_KIB,
+        SECTOR_SIZE_4_KIB,
+    ];
+    inner_test_seal_proof_aggregation_heterogeneous_2kib_4kib(
+        &sector_sizes,
+        &[vec![0, 2, 4], vec![1, 3]],
+    )
+}
+
+fn inner_test_seal_proof_aggregation_heterogeneous_2kib_4kib(
+    sector_sizes: &[u64],
+    expected_groups: &[Vec<usize>],
+) -> Result<()> {
+    let porep_id = ARBITRARY_POREP_ID_V1_1_0;
+    let api_version = ApiVersion::V1_1_0;
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let (commit_output_2kib, commit_input_2kib, seed_2kib, comm_r_2kib) =
+        create_seal_for_aggregation::<_, SectorShape2KiB>(
+            rng,
+            SECTOR_SIZE_2_KIB,
+            prover_id,
+            &porep_id,
+            api_version,
+        )?;
+    let (commit_output_4kib, commit_input_4kib, seed_4kib, comm_r_4kib) =
+        create_seal_for_aggregation::<_, SectorShape4KiB>(
+            rng,
+            SECTOR_SIZE_4_KIB,
+            prover_id,
+            &porep_id,
+            api_version,
+        )?;
+
+    let config_2kib = porep_config(SECTOR_SIZE_2_KIB, porep_id, api_version);
+    let config_4kib = porep_config(SECTOR_SIZE_4_KIB, porep_id, api_version);
+    let is_2kib = |sector_size: &u64| *sector_size == SECTOR_SIZE_2_KIB;
+    let porep_configs: Vec<_> = sector_sizes
+        .iter()
+        .map(|s| if is_2kib(s) { config_2kib } else { config_4kib })
+        .collect();
+    let comm_rs: Vec<_> = sector_sizes
+        .iter()
+        .map(|s| if is_2kib(s) { comm_r_2kib } else { comm_r_4kib })
+        .collect();
+    let seeds: Vec<_> = sector_sizes
+        .iter()
+        .map(|s| if is_2kib(s) { seed_2kib } else { seed_4kib })
+        .collect();
+    let commit_outputs: Vec<_> = sector_sizes
+        .iter()
+        .map(|s| {
+            if is_2kib(s) {
+                commit_output_2kib.clone()
+            } else {
+                commit_output_4kib.clone()
+            }
+        })
+        .collect();
+    let commit_inputs: Vec<_> = sector_sizes
+        .iter()
+        .map(|s| {
+            if is_2kib(s) {
+                commit_input_2kib.clone()
+            } else {
+                commit_input_4kib.clone()
+            }
+        })
+        .collect();
+
+    let aggregate_proof = aggregate_seal_commit_proofs_heterogeneous(
+        &porep_configs,
+        &comm_rs,
+        &seeds,
+        &commit_outputs,
+    )?;
+    let groups: HeterogeneousAggregateProof = deserialize(&aggregate_proof)?;
+    let indices: Vec<_> = groups
+        .groups
+        .iter()
+        .map(|group| group.indices.clone())
+        .collect();
+    assert_eq!(indices, expected_groups);
+
+    let verified = verify_aggregate_seal_commit_proofs_heterogeneous(
+        &porep_configs,
+        aggregate_proof.clone(),
+        &comm_rs,
+        &seeds,
+        &commit_inputs,
+    )?;
+    assert!(verified);
+
+    // The group indices must match the porep configs.
+    let mut mismatched_configs = porep_configs.clone();
+    let last = mismatched_configs.len() - 1;
+    mismatched_configs[last] = if is_2kib(&sector_sizes[last]) {
+        config_4kib
+    } else {
+        config_2kib
+    };
+    assert!(verify_aggregate_seal_commit_proofs_heterogeneous(
+        &mismatched_configs,
+        aggregate_proof,
+        &comm_rs,
+        &seeds,
+        &commit_inputs,
+    )
+    .is_err());
+
+    Ok(())
+}
+
 fn aggregate_proofs<Tree: 'static + MerkleTreeTrait>(
     sector_size: u64,
     porep_id: &[u8; 32],
@@ -495,113 +869,696 @@ fn test_resumable_seal_v1_1() {
     run_resumable_seal::<SectorShape2KiB>(false, 1, &porep_id, ApiVersion::V1_1_0);
 }
 
-/// Create a seal, delete a layer and resume
-///
-/// The current code works on two layers only. The `layer_to_delete` specifies (zero-based) which
-/// layer should be deleted.
-fn run_resumable_seal<Tree: 'static + MerkleTreeTrait>(
-    skip_proofs: bool,
-    layer_to_delete: usize,
-    porep_id: &[u8; 32],
-    api_version: ApiVersion,
-) {
-    init_logger();
+/// Create a seal, delete a layer and resume
+///
+/// The current code works on two layers only. The `layer_to_delete` specifies (zero-based) which
+/// layer should be deleted.
+fn run_resumable_seal<Tree: 'static + MerkleTreeTrait>(
+    skip_proofs: bool,
+    layer_to_delete: usize,
+    porep_id: &[u8; 32],
+    api_version: ApiVersion,
+) {
+    init_logger();
+
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let (mut piece_file, piece_bytes) =
+        generate_piece_file(sector_size).expect("failed to generate piece file");
+    let sealed_sector_file = NamedTempFile::new().expect("failed to created sealed sector file");
+    let cache_dir = tempdir().expect("failed to create temp dir");
+
+    let config = porep_config(sector_size, *porep_id, api_version);
+    let ticket = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    // First create seals as expected
+    run_seal_pre_commit_phase1::<Tree>(
+        config,
+        prover_id,
+        sector_id,
+        ticket,
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )
+    .expect("failed to run seal pre commit phase1");
+    let layers = get_layer_file_paths(&cache_dir);
+    assert_eq!(layers.len(), 2, "not all expected layers were created");
+
+    // Delete one layer, keep the other
+    clear_cache_dir_keep_data_layer(&cache_dir);
+    remove_file(&layers[layer_to_delete]).expect("failed to remove layer");
+    let layers_remaining = get_layer_file_paths(&cache_dir);
+    assert_eq!(layers_remaining.len(), 1, "expected one layer only");
+    if layer_to_delete == 0 {
+        assert_eq!(layers_remaining[0], layers[1], "wrong layer was removed");
+    } else {
+        assert_eq!(layers_remaining[0], layers[0], "wrong layer was removed");
+    }
+
+    // Resume the seal
+    piece_file
+        .seek(SeekFrom::Start(0))
+        .expect("failed to seek piece file to start");
+    let (piece_infos, phase1_output) = run_seal_pre_commit_phase1::<Tree>(
+        config,
+        prover_id,
+        sector_id,
+        ticket,
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )
+    .expect("failed to run seal pre commit phase1");
+
+    // Running proofs clears the cache, hence we can only check for existence of files if we don't
+    // run them
+    if skip_proofs {
+        let layers_recreated = get_layer_file_paths(&cache_dir);
+        assert_eq!(
+            layers_recreated.len(),
+            2,
+            "not all expected layers were recreated"
+        );
+        assert_eq!(
+            layers_recreated, layers,
+            "recreated layers don't match original ones"
+        );
+    } else {
+        let pre_commit_output = seal_pre_commit_phase2(
+            config,
+            phase1_output,
+            cache_dir.path(),
+            sealed_sector_file.path(),
+        )
+        .expect("failed to run seal pre commit phase2");
+
+        validate_cache_for_commit::<_, _, Tree>(cache_dir.path(), sealed_sector_file.path())
+            .expect("failed to validate cache for commit");
+
+        let seed = rng.gen();
+        proof_and_unseal::<Tree>(
+            config,
+            cache_dir.path(),
+            &sealed_sector_file,
+            prover_id,
+            sector_id,
+            ticket,
+            seed,
+            pre_commit_output,
+            &piece_infos,
+            &piece_bytes,
+        )
+        .expect("failed to proof");
+    }
+}
+
+#[test]
+fn test_verify_sector_cache_2kib_base_8() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
+
+    let (mut piece_file, _) = generate_piece_file(sector_size)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+
+    let (_, phase1_output) = run_seal_pre_commit_phase1::<SectorShape2KiB>(
+        config,
+        rng.gen(),
+        rng.gen::<u64>().into(),
+        rng.gen(),
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )?;
+    seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    let report =
+        verify_sector_cache::<_, _, SectorShape2KiB>(cache_dir.path(), sealed_sector_file.path())?;
+    assert!(report.is_valid(), "{:?}", report);
+
+    // Flip a byte in the replica, which must invalidate tree-r-last.
+    let mut replica = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(sealed_sector_file.path())?;
+    let mut byte = [0u8; 1];
+    replica.seek(SeekFrom::Start(64))?;
+    replica.read_exact(&mut byte)?;
+    byte[0] ^= 0x01;
+    replica.seek(SeekFrom::Start(64))?;
+    replica.write_all(&byte)?;
+    replica.sync_all()?;
+
+    let report =
+        verify_sector_cache::<_, _, SectorShape2KiB>(cache_dir.path(), sealed_sector_file.path())?;
+    assert!(!report.is_valid());
+    let tree_r_last = CacheKey::CommRLastTree.to_string();
+    assert!(
+        report
+            .failures()
+            .any(|artifact| artifact.name.starts_with(&tree_r_last)),
+        "{:?}",
+        report
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_get_unsealed_range_partial_2kib_base_8() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
+
+    let (mut piece_file, piece_bytes) = generate_piece_file(sector_size)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+
+    let prover_id = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+    let ticket = rng.gen();
+    let (_, phase1_output) = run_seal_pre_commit_phase1::<SectorShape2KiB>(
+        config,
+        prover_id,
+        sector_id,
+        ticket,
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )?;
+    let pre_commit_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    let unpadded_sector_size = u64::from(UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size)));
+    for (offset, num_bytes) in &[
+        (0, 127),
+        (127, 254),
+        (508, 508),
+        (unpadded_sector_size - 508, 508),
+    ] {
+        let full_file = NamedTempFile::new()?;
+        get_unsealed_range::<_, SectorShape2KiB>(
+            config,
+            cache_dir.path().to_path_buf(),
+            sealed_sector_file.path().to_path_buf(),
+            full_file.path().to_path_buf(),
+            prover_id,
+            sector_id,
+            pre_commit_output.comm_d,
+            ticket,
+            UnpaddedByteIndex(*offset),
+            UnpaddedBytesAmount(*num_bytes),
+        )?;
+
+        let partial_file = NamedTempFile::new()?;
+        get_unsealed_range_partial::<_, SectorShape2KiB>(
+            config,
+            sealed_sector_file.path(),
+            partial_file.path(),
+            prover_id,
+            sector_id,
+            pre_commit_output.comm_d,
+            ticket,
+            UnpaddedByteIndex(*offset),
+            UnpaddedBytesAmount(*num_bytes),
+        )?;
+
+        let full = read(full_file.path())?;
+        let partial = read(partial_file.path())?;
+        assert_eq!(full, partial, "range {}+{}", offset, num_bytes);
+        assert_eq!(
+            &piece_bytes[*offset as usize..(*offset + *num_bytes) as usize],
+            &partial[..]
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sector_meta_2kib_base_8() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
+    let prover_id = rng.gen();
+
+    // By default, the single row between the base and the root of a 2KiB tree_r_last is
+    // discarded, so the sector is sealed with that row cached instead.
+    let rows_to_discard = 0;
+    let (sector_id, sealed_sector_file, cache_dir, pre_commit_output) =
+        seal_2kib_with_tree_r_last_rows_to_discard(rng, config, prover_id, rows_to_discard)?;
+
+    let meta = read_sector_meta(cache_dir.path())?.expect("missing sector meta");
+    assert_eq!(meta.tree_r_last_rows_to_discard, rows_to_discard);
+    assert_eq!(
+        get_tree_r_last_rows_to_discard::<SectorShape2KiB>(cache_dir.path(), config.sector_size)?,
+        rows_to_discard
+    );
+
+    // Sectors sealed without metadata are detected from their cached trees.
+    remove_file(cache_dir.path().join(CacheKey::SectorMeta.to_string()))?;
+    assert_eq!(read_sector_meta(cache_dir.path())?, None);
+    assert_eq!(
+        get_tree_r_last_rows_to_discard::<SectorShape2KiB>(cache_dir.path(), config.sector_size)?,
+        rows_to_discard
+    );
+
+    persist_sector_meta(cache_dir.path(), &meta)?;
+    assert_eq!(read_sector_meta(cache_dir.path())?, Some(meta));
+
+    let replica = PrivateReplicaInfo::<SectorShape2KiB>::new(
+        sealed_sector_file.path().to_path_buf(),
+        pre_commit_output.comm_r,
+        cache_dir.path().to_path_buf(),
+    )?;
+    let tree_r_last = replica.merkle_tree(config.sector_size)?;
+    assert_eq!(tree_r_last.root(), replica.safe_comm_r_last());
+
+    // The vanilla proofs are generated with the recorded rows_to_discard, and are checked
+    // against comm_r_last while being generated.
+    let post_config = PoStConfig {
+        sector_size: config.sector_size,
+        sector_count: WINNING_POST_SECTOR_COUNT,
+        challenge_count: WINNING_POST_CHALLENGE_COUNT,
+        typ: PoStType::Winning,
+        priority: false,
+        api_version: ApiVersion::V1_1_0,
+    };
+    let random_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut randomness = [0u8; 32];
+    randomness.copy_from_slice(AsRef::<[u8]>::as_ref(&random_fr));
+    let challenges = generate_fallback_sector_challenges::<SectorShape2KiB>(
+        &post_config,
+        &randomness,
+        &[sector_id],
+        prover_id,
+    )?;
+    generate_single_vanilla_proof::<SectorShape2KiB>(
+        &post_config,
+        sector_id,
+        &replica,
+        &challenges[&sector_id],
+    )?;
+
+    let mut priv_replicas = BTreeMap::new();
+    priv_replicas.insert(sector_id, replica);
+    let batch = generate_vanilla_proofs_batch::<SectorShape2KiB>(
+        &post_config,
+        &priv_replicas,
+        &challenges,
+        &VanillaProofBatchConfig::default(),
+    )?;
+    assert!(batch.faults.is_empty());
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_post_non_default_rows_to_discard_2kib_base_8() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
+
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let (sector_id, sealed_sector_file, cache_dir, pre_commit_output) =
+        seal_2kib_with_tree_r_last_rows_to_discard(rng, config, prover_id, 0)?;
+    let comm_r = pre_commit_output.comm_r;
+    let replica = PrivateReplicaInfo::<SectorShape2KiB>::new(
+        sealed_sector_file.path().to_path_buf(),
+        comm_r,
+        cache_dir.path().to_path_buf(),
+    )?;
+
+    let random_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut randomness = [0u8; 32];
+    randomness.copy_from_slice(AsRef::<[u8]>::as_ref(&random_fr));
+
+    let winning_config = PoStConfig {
+        sector_size: config.sector_size,
+        sector_count: WINNING_POST_SECTOR_COUNT,
+        challenge_count: WINNING_POST_CHALLENGE_COUNT,
+        typ: PoStType::Winning,
+        priority: false,
+        api_version: ApiVersion::V1_1_0,
+    };
+    let priv_replicas = vec![(sector_id, replica.clone())];
+    let pub_replicas = vec![(sector_id, PublicReplicaInfo::new(comm_r)?)];
+    let proof = generate_winning_post::<SectorShape2KiB>(
+        &winning_config,
+        &randomness,
+        &priv_replicas[..],
+        prover_id,
+    )?;
+    let valid = verify_winning_post::<SectorShape2KiB>(
+        &winning_config,
+        &randomness,
+        &pub_replicas[..],
+        prover_id,
+        &proof,
+    )?;
+    assert!(valid, "winning proof did not verify");
+
+    let window_config = PoStConfig {
+        sector_size: config.sector_size,
+        sector_count: *WINDOW_POST_SECTOR_COUNT
+            .read()
+            .expect("WINDOW_POST_SECTOR_COUNT poisoned")
+            .get(&sector_size)
+            .expect("unknown sector size"),
+        challenge_count: WINDOW_POST_CHALLENGE_COUNT,
+        typ: PoStType::Window,
+        priority: false,
+        api_version: ApiVersion::V1_1_0,
+    };
+    let mut priv_replicas = BTreeMap::new();
+    priv_replicas.insert(sector_id, replica);
+    let mut pub_replicas = BTreeMap::new();
+    pub_replicas.insert(sector_id, PublicReplicaInfo::new(comm_r)?);
+    let proof = generate_window_post::<SectorShape2KiB>(
+        &window_config,
+        &randomness,
+        &priv_replicas,
+        prover_id,
+    )?;
+    let valid = verify_window_post::<SectorShape2KiB>(
+        &window_config,
+        &randomness,
+        &pub_replicas,
+        prover_id,
+        &proof,
+    )?;
+    assert!(valid, "window proof did not verify");
+
+    Ok(())
+}
+
+/// Seals a 2KiB sector, and then rebuilds its cached tree_r_last with `rows_to_discard`, which is
+/// recorded in the sector metadata as if the sector had been sealed with it.
+fn seal_2kib_with_tree_r_last_rows_to_discard(
+    rng: &mut XorShiftRng,
+    config: PoRepConfig,
+    prover_id: ProverId,
+    rows_to_discard: usize,
+) -> Result<(SectorId, NamedTempFile, TempDir, SealPreCommitOutput)> {
+    let (mut piece_file, _) = generate_piece_file(SECTOR_SIZE_2_KIB)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+    let sector_id = rng.gen::<u64>().into();
+
+    let (_, phase1_output) = run_seal_pre_commit_phase1::<SectorShape2KiB>(
+        config,
+        prover_id,
+        sector_id,
+        rng.gen(),
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )?;
+    let pre_commit_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    let tree_r_last_config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommRLastTree.to_string(),
+        rows_to_discard,
+    );
+    remove_file(StoreConfig::data_path(
+        &tree_r_last_config.path,
+        &tree_r_last_config.id,
+    ))?;
+    let replica = read(sealed_sector_file.path())?;
+    SectorShape2KiB::from_byte_slice_with_config(&replica, tree_r_last_config)?;
+
+    persist_sector_meta(
+        cache_dir.path(),
+        &SectorMeta {
+            tree_r_last_rows_to_discard: rows_to_discard,
+        },
+    )?;
+
+    Ok((sector_id, sealed_sector_file, cache_dir, pre_commit_output))
+}
+
+#[test]
+#[ignore]
+fn test_seal_and_winning_post_with_config_2kib_base_8() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
+
+    // The parent cache is created in the directory of the given config, not the global one.
+    // The config is passed in the options of the calls which take any, and put in effect with
+    // `with_config` around the others.
+    let parent_cache_dir = tempdir().expect("failed to create temp dir");
+    let proofs_config = Arc::new(ProofsConfig {
+        parent_cache: parent_cache_dir.path().to_string_lossy().into_owned(),
+        ..(**SETTINGS).clone()
+    });
+    let options = RunOptions {
+        config: Some(proofs_config.clone()),
+        ..Default::default()
+    };
+
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+    let sector_id: SectorId = rng.gen::<u64>().into();
+    let ticket = rng.gen();
+    let seed = rng.gen();
+
+    let number_of_bytes_in_piece =
+        UnpaddedBytesAmount::from(PaddedBytesAmount(config.sector_size.into()));
+    let (mut piece_file, _) = generate_piece_file(sector_size)?;
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(
+        &mut piece_file,
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+    let piece_infos = vec![piece_info];
+
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+
+    let phase1_output = seal_pre_commit_phase1_with_options::<_, _, _, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+        &options,
+    )?;
+    assert!(
+        read_dir(parent_cache_dir.path())?.next().is_some(),
+        "parent cache was not created in the configured directory"
+    );
+
+    let pre_commit_output = seal_pre_commit_phase2_with_options(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        &options,
+    )?;
+    let phase1_output = with_config(Some(&proofs_config), || {
+        seal_commit_phase1::<_, SectorShape2KiB>(
+            config,
+            cache_dir.path(),
+            sealed_sector_file.path(),
+            prover_id,
+            sector_id,
+            ticket,
+            seed,
+            pre_commit_output.clone(),
+            &piece_infos,
+        )
+    })?;
+    let commit_output =
+        seal_commit_phase2_with_options(config, phase1_output, prover_id, sector_id, &options)?;
+
+    let verified = verify_seal::<SectorShape2KiB>(
+        config,
+        pre_commit_output.comm_r,
+        pre_commit_output.comm_d,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        &commit_output.proof,
+    )?;
+    assert!(verified, "failed to verify valid seal");
+
+    let random_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut randomness = [0u8; 32];
+    randomness.copy_from_slice(AsRef::<[u8]>::as_ref(&random_fr));
+    let post_config = PoStConfig {
+        sector_size: sector_size.into(),
+        sector_count: WINNING_POST_SECTOR_COUNT,
+        challenge_count: WINNING_POST_CHALLENGE_COUNT,
+        typ: PoStType::Winning,
+        priority: false,
+        api_version: ApiVersion::V1_1_0,
+    };
+    let priv_replicas = vec![(
+        sector_id,
+        PrivateReplicaInfo::new(
+            sealed_sector_file.path().to_path_buf(),
+            pre_commit_output.comm_r,
+            cache_dir.path().to_path_buf(),
+        )?,
+    )];
+    let pub_replicas = vec![(sector_id, PublicReplicaInfo::new(pre_commit_output.comm_r)?)];
+
+    let proof = with_config(Some(&proofs_config), || {
+        generate_winning_post::<SectorShape2KiB>(
+            &post_config,
+            &randomness,
+            &priv_replicas[..],
+            prover_id,
+        )
+    })?;
+    let valid = verify_winning_post::<SectorShape2KiB>(
+        &post_config,
+        &randomness,
+        &pub_replicas[..],
+        prover_id,
+        &proof,
+    )?;
+    assert!(valid, "proof did not verify");
+
+    Ok(())
+}
+
+#[test]
+fn test_regenerate_missing_trees_2kib_base_8() -> Result<()> {
+    regenerate_missing_trees_lifecycle::<SectorShape2KiB>(SECTOR_SIZE_2_KIB)
+}
 
-    let sector_size = SECTOR_SIZE_2_KIB;
+#[test]
+fn test_regenerate_missing_trees_4kib_sub_8_2() -> Result<()> {
+    regenerate_missing_trees_lifecycle::<SectorShape4KiB>(SECTOR_SIZE_4_KIB)
+}
+
+fn regenerate_missing_trees_lifecycle<Tree: 'static + MerkleTreeTrait>(
+    sector_size: u64,
+) -> Result<()> {
     let rng = &mut XorShiftRng::from_seed(TEST_SEED);
-    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
-    let mut prover_id = [0u8; 32];
-    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
 
-    let (mut piece_file, piece_bytes) =
-        generate_piece_file(sector_size).expect("failed to generate piece file");
-    let sealed_sector_file = NamedTempFile::new().expect("failed to created sealed sector file");
+    let (mut piece_file, _) = generate_piece_file(sector_size)?;
+    let sealed_sector_file = NamedTempFile::new()?;
     let cache_dir = tempdir().expect("failed to create temp dir");
 
-    let config = porep_config(sector_size, *porep_id, api_version);
-    let ticket = rng.gen();
-    let sector_id = rng.gen::<u64>().into();
-
-    // First create seals as expected
-    run_seal_pre_commit_phase1::<Tree>(
+    let (_, phase1_output) = run_seal_pre_commit_phase1::<Tree>(
         config,
-        prover_id,
-        sector_id,
-        ticket,
+        rng.gen(),
+        rng.gen::<u64>().into(),
+        rng.gen(),
         &cache_dir,
         &mut piece_file,
         &sealed_sector_file,
-    )
-    .expect("failed to run seal pre commit phase1");
-    let layers = get_layer_file_paths(&cache_dir);
-    assert_eq!(layers.len(), 2, "not all expected layers were created");
+    )?;
+    let pre_commit_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
 
-    // Delete one layer, keep the other
-    clear_cache_dir_keep_data_layer(&cache_dir);
-    remove_file(&layers[layer_to_delete]).expect("failed to remove layer");
-    let layers_remaining = get_layer_file_paths(&cache_dir);
-    assert_eq!(layers_remaining.len(), 1, "expected one layer only");
-    if layer_to_delete == 0 {
-        assert_eq!(layers_remaining[0], layers[1], "wrong layer was removed");
-    } else {
-        assert_eq!(layers_remaining[0], layers[0], "wrong layer was removed");
+    // Nothing is missing yet.
+    let regenerated = regenerate_missing_trees::<_, _, Tree>(
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        pre_commit_output.comm_d,
+    )?;
+    assert!(regenerated.is_empty());
+
+    for entry in read_dir(cache_dir.path())? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .expect("invalid cache file name");
+        if name.starts_with("sc-02-data-tree-c") || name.starts_with("sc-02-data-tree-d") {
+            remove_file(&path)?;
+        }
     }
+    assert!(
+        validate_cache_for_commit::<_, _, Tree>(cache_dir.path(), sealed_sector_file.path())
+            .is_err()
+    );
 
-    // Resume the seal
-    piece_file
-        .seek(SeekFrom::Start(0))
-        .expect("failed to seek piece file to start");
-    let (piece_infos, phase1_output) = run_seal_pre_commit_phase1::<Tree>(
-        config,
-        prover_id,
-        sector_id,
-        ticket,
-        &cache_dir,
-        &mut piece_file,
-        &sealed_sector_file,
+    let regenerated = regenerate_missing_trees::<_, _, Tree>(
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        pre_commit_output.comm_d,
+    )?;
+    assert_eq!(regenerated.len(), 2);
+    validate_cache_for_commit::<_, _, Tree>(cache_dir.path(), sealed_sector_file.path())?;
+
+    // A tree_d that does not match comm_d is rejected, and does not replace the existing one.
+    let tree_d_path = cache_dir.path().join("sc-02-data-tree-d.dat");
+    let tree_d = read(&tree_d_path)?;
+    let mut wrong_comm_d = pre_commit_output.comm_d;
+    wrong_comm_d[0] ^= 0x01;
+    assert!(regenerate_tree_d::<_, _, Tree>(
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        wrong_comm_d,
     )
-    .expect("failed to run seal pre commit phase1");
+    .is_err());
+    assert_eq!(read(&tree_d_path)?, tree_d);
 
-    // Running proofs clears the cache, hence we can only check for existence of files if we don't
-    // run them
-    if skip_proofs {
-        let layers_recreated = get_layer_file_paths(&cache_dir);
-        assert_eq!(
-            layers_recreated.len(),
-            2,
-            "not all expected layers were recreated"
-        );
-        assert_eq!(
-            layers_recreated, layers,
-            "recreated layers don't match original ones"
+    remove_file(&tree_d_path)?;
+    assert!(regenerate_missing_trees::<_, _, Tree>(
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        wrong_comm_d,
+    )
+    .is_err());
+    assert!(!tree_d_path.exists());
+
+    // The rejected trees are not left behind.
+    for entry in read_dir(cache_dir.path())? {
+        let name = entry?.file_name();
+        assert!(
+            !name.to_string_lossy().contains("-regenerate"),
+            "leftover {:?}",
+            name
         );
-    } else {
-        let pre_commit_output = seal_pre_commit_phase2(
-            config,
-            phase1_output,
-            cache_dir.path(),
-            sealed_sector_file.path(),
-        )
-        .expect("failed to run seal pre commit phase2");
-
-        validate_cache_for_commit::<_, _, Tree>(cache_dir.path(), sealed_sector_file.path())
-            .expect("failed to validate cache for commit");
-
-        let seed = rng.gen();
-        proof_and_unseal::<Tree>(
-            config,
-            cache_dir.path(),
-            &sealed_sector_file,
-            prover_id,
-            sector_id,
-            ticket,
-            seed,
-            pre_commit_output,
-            &piece_infos,
-            &piece_bytes,
-        )
-        .expect("failed to proof");
     }
+
+    Ok(())
 }
 
 #[test]
@@ -1140,6 +2097,21 @@ fn window_post<Tree: 'static + MerkleTreeTrait>(
 
     let proof =
         generate_window_post_with_vanilla::<Tree>(&config, &randomness, prover_id, vanilla_proofs)?;
+
+    let valid = verify_window_post::<Tree>(&config, &randomness, &pub_replicas, prover_id, &proof)?;
+    assert!(valid, "proof did not verify");
+
+    // 3)
+    let batch = generate_vanilla_proofs_batch::<Tree>(
+        &config,
+        &priv_replicas,
+        &challenges,
+        &VanillaProofBatchConfig::default(),
+    )?;
+    assert!(batch.faults.is_empty());
+
+    let proof =
+        generate_window_post_with_vanilla::<Tree>(&config, &randomness, prover_id, batch.proofs)?;
     /////////////////////////////////////////////
 
     let valid = verify_window_post::<Tree>(&config, &randomness, &pub_replicas, prover_id, &proof)?;
@@ -1148,6 +2120,245 @@ fn window_post<Tree: 'static + MerkleTreeTrait>(
     Ok(())
 }
 
+#[test]
+#[ignore]
+fn test_window_post_proof_aggregation_2kib_base_8() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let api_version = ApiVersion::V1_1_0;
+
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let config = PoStConfig {
+        sector_size: sector_size.into(),
+        sector_count: 2,
+        challenge_count: WINDOW_POST_CHALLENGE_COUNT,
+        typ: PoStType::Window,
+        priority: false,
+        api_version,
+    };
+
+    // Two proofs, one over two partitions and one over a single partition.
+    let mut sectors = Vec::new();
+    let mut randomnesses = Vec::new();
+    let mut proofs = Vec::new();
+    let mut all_pub_replicas = Vec::new();
+    for total_sector_count in &[3, 1] {
+        let mut pub_replicas = BTreeMap::new();
+        let mut priv_replicas = BTreeMap::new();
+        for _ in 0..*total_sector_count {
+            let (sector_id, replica, comm_r, cache_dir) = create_fake_seal::<_, SectorShape2KiB>(
+                rng,
+                sector_size,
+                &ARBITRARY_POREP_ID_V1_1_0,
+                api_version,
+            )?;
+            priv_replicas.insert(
+                sector_id,
+                PrivateReplicaInfo::new(replica.path().into(), comm_r, cache_dir.path().into())?,
+            );
+            pub_replicas.insert(sector_id, PublicReplicaInfo::new(comm_r)?);
+            sectors.push((replica, cache_dir));
+        }
+
+        let random_fr: DefaultTreeDomain = Fr::random(rng).into();
+        let mut randomness = [0u8; 32];
+        randomness.copy_from_slice(AsRef::<[u8]>::as_ref(&random_fr));
+
+        let proof = generate_window_post::<SectorShape2KiB>(
+            &config,
+            &randomness,
+            &priv_replicas,
+            prover_id,
+        )?;
+        randomnesses.push(randomness);
+        proofs.push(proof);
+        all_pub_replicas.push(pub_replicas);
+    }
+
+    let aggregate_proof =
+        aggregate_window_post_proofs::<SectorShape2KiB>(&config, &randomnesses, &proofs)?;
+
+    let verified = verify_aggregate_window_post_proofs::<SectorShape2KiB>(
+        &config,
+        prover_id,
+        aggregate_proof.clone(),
+        &randomnesses,
+        &all_pub_replicas,
+    )?;
+    assert!(verified, "failed to verify aggregate window post proof");
+
+    // The aggregate must not verify for the wrong randomness.
+    randomnesses.reverse();
+    let verified = verify_aggregate_window_post_proofs::<SectorShape2KiB>(
+        &config,
+        prover_id,
+        aggregate_proof,
+        &randomnesses,
+        &all_pub_replicas,
+    )?;
+    assert!(
+        !verified,
+        "verified aggregate window post proof with the wrong randomness"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_vanilla_proofs_batch_reports_faults_2kib_base_8() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let api_version = ApiVersion::V1_1_0;
+
+    let mut sectors = Vec::new();
+    let mut priv_replicas = BTreeMap::new();
+    for _ in 0..3 {
+        let (sector_id, replica, comm_r, cache_dir) = create_fake_seal::<_, SectorShape2KiB>(
+            rng,
+            sector_size,
+            &ARBITRARY_POREP_ID_V1_1_0,
+            api_version,
+        )?;
+        priv_replicas.insert(
+            sector_id,
+            PrivateReplicaInfo::new(replica.path().into(), comm_r, cache_dir.path().into())?,
+        );
+        sectors.push((sector_id, replica, cache_dir));
+    }
+
+    // Break the tree of the first sector.
+    let (faulty_sector, _, faulty_cache_dir) = &sectors[0];
+    for entry in read_dir(faulty_cache_dir.path())? {
+        let path = entry?.path();
+        if path.to_string_lossy().contains("tree-r-last") {
+            remove_file(path)?;
+        }
+    }
+
+    let config = PoStConfig {
+        sector_size: sector_size.into(),
+        sector_count: 3,
+        challenge_count: WINDOW_POST_CHALLENGE_COUNT,
+        typ: PoStType::Window,
+        priority: false,
+        api_version,
+    };
+    let randomness = [7u8; 32];
+    let replica_sectors: Vec<SectorId> = priv_replicas.keys().copied().collect();
+    let challenges = generate_fallback_sector_challenges::<SectorShape2KiB>(
+        &config,
+        &randomness,
+        &replica_sectors,
+        [0u8; 32],
+    )?;
+
+    let batch = generate_vanilla_proofs_batch::<SectorShape2KiB>(
+        &config,
+        &priv_replicas,
+        &challenges,
+        &VanillaProofBatchConfig {
+            max_concurrent_per_path: 2,
+            ..Default::default()
+        },
+    )?;
+
+    assert_eq!(batch.proofs.len(), 2);
+    assert_eq!(batch.faults.len(), 1);
+    assert_eq!(batch.faults[0].sector_id, *faulty_sector);
+    assert_eq!(batch.faults[0].reason, FaultReason::MissingCache);
+
+    // The batched proofs match the ones generated one sector at a time.
+    for proof in &batch.proofs {
+        let single_proof = generate_single_vanilla_proof::<SectorShape2KiB>(
+            &config,
+            proof.sector_id,
+            &priv_replicas[&proof.sector_id],
+            &challenges[&proof.sector_id],
+        )?;
+        assert_eq!(serialize(proof)?, serialize(&single_proof)?);
+    }
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_window_post_skips_faulty_sectors_2kib_base_8() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let api_version = ApiVersion::V1_1_0;
+
+    let mut sectors = Vec::new();
+    let mut priv_replicas = BTreeMap::new();
+    let mut pub_replicas = BTreeMap::new();
+    for _ in 0..3 {
+        let (sector_id, replica, comm_r, cache_dir) = create_fake_seal::<_, SectorShape2KiB>(
+            rng,
+            sector_size,
+            &ARBITRARY_POREP_ID_V1_1_0,
+            api_version,
+        )?;
+        priv_replicas.insert(
+            sector_id,
+            PrivateReplicaInfo::new(replica.path().into(), comm_r, cache_dir.path().into())?,
+        );
+        pub_replicas.insert(sector_id, PublicReplicaInfo::new(comm_r)?);
+        sectors.push((sector_id, replica, cache_dir));
+    }
+
+    // Make the last sector unreadable.
+    let (faulty_sector, faulty_replica, _) = &sectors[2];
+    remove_file(faulty_replica.path())?;
+
+    let config = PoStConfig {
+        sector_size: sector_size.into(),
+        sector_count: 3,
+        challenge_count: WINDOW_POST_CHALLENGE_COUNT,
+        typ: PoStType::Window,
+        priority: false,
+        api_version,
+    };
+    let randomness = [7u8; 32];
+    let prover_id = [0u8; 32];
+
+    assert!(generate_window_post_with_options::<SectorShape2KiB>(
+        &config,
+        &randomness,
+        &priv_replicas,
+        prover_id,
+        FaultPolicy::Abort,
+        &RunOptions::default(),
+    )
+    .is_err());
+
+    let (proof, faults) = generate_window_post_with_options::<SectorShape2KiB>(
+        &config,
+        &randomness,
+        &priv_replicas,
+        prover_id,
+        FaultPolicy::Skip(VanillaProofBatchConfig::default()),
+        &RunOptions::default(),
+    )?;
+    assert_eq!(faults.len(), 1);
+    assert_eq!(faults[0].sector_id, *faulty_sector);
+    assert_eq!(faults[0].reason, FaultReason::MissingCache);
+
+    pub_replicas.remove(faulty_sector);
+    let valid = verify_window_post::<SectorShape2KiB>(
+        &config,
+        &randomness,
+        &pub_replicas,
+        prover_id,
+        &proof,
+    )?;
+    assert!(valid, "proof did not verify");
+
+    Ok(())
+}
+
 fn generate_piece_file(sector_size: u64) -> Result<(NamedTempFile, Vec<u8>)> {
     let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
 
@@ -1164,18 +2375,7 @@ fn generate_piece_file(sector_size: u64) -> Result<(NamedTempFile, Vec<u8>)> {
 }
 
 fn porep_config(sector_size: u64, porep_id: [u8; 32], api_version: ApiVersion) -> PoRepConfig {
-    PoRepConfig {
-        sector_size: SectorSize(sector_size),
-        partitions: PoRepProofPartitions(
-            *POREP_PARTITIONS
-                .read()
-                .expect("POREP_PARTITIONS poisoned")
-                .get(&sector_size)
-                .expect("unknown sector size"),
-        ),
-        porep_id,
-        api_version,
-    }
+    PoRepConfig::new_groth16(sector_size, porep_id, api_version)
 }
 
 fn run_seal_pre_commit_phase1<Tree: 'static + MerkleTreeTrait>(
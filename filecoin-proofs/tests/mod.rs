@@ -35,6 +35,8 @@ fn test_verify_seal_fr32_validation() {
                 ),
                 porep_id: arbitrary_porep_id,
                 api_version: ApiVersion::V1_1_0,
+                non_interactive: false,
+                synthetic: false,
             },
             not_convertible_to_fr_bytes,
             convertible_to_fr_bytes,
@@ -73,6 +75,8 @@ fn test_verify_seal_fr32_validation() {
                 ),
                 porep_id: arbitrary_porep_id,
                 api_version: ApiVersion::V1_1_0,
+                non_interactive: false,
+                synthetic: false,
             },
             convertible_to_fr_bytes,
             not_convertible_to_fr_bytes,
@@ -1,23 +1,31 @@
 use std::io::{Cursor, Read};
 use std::iter::Iterator;
+use std::path::Path;
 
 use anyhow::Result;
 use bellperson::bls::Fr;
 use filecoin_proofs::{
-    add_piece, commitment_from_fr,
+    add_piece, commitment_from_fr, generate_piece_inclusion_proof,
     pieces::{
         compute_comm_d, get_piece_alignment, get_piece_start_byte, piece_hash, verify_pieces,
         zero_padding, EmptySource, PieceAlignment,
     },
-    Commitment, DataTree, DefaultPieceHasher, PaddedBytesAmount, PieceInfo, SectorSize,
-    UnpaddedByteIndex, UnpaddedBytesAmount, DRG_DEGREE, EXP_DEGREE, TEST_SEED,
+    verify_piece_inclusion_proof, Commitment, DataTree, DefaultPieceHasher, PaddedBytesAmount,
+    PieceInclusionProof, PieceInfo, SectorSize, UnpaddedByteIndex, UnpaddedBytesAmount,
+    BINARY_ARITY, DRG_DEGREE, EXP_DEGREE, SECTOR_SIZE_2_KIB, TEST_SEED,
 };
+use merkletree::store::StoreConfig;
 use rand::{Rng, RngCore, SeedableRng};
 use rand_xorshift::XorShiftRng;
 use storage_proofs_core::{
-    api_version::ApiVersion, drgraph::Graph, merkle::create_base_merkle_tree, util::NODE_SIZE,
+    api_version::ApiVersion,
+    cache_key::CacheKey,
+    drgraph::Graph,
+    merkle::create_base_merkle_tree,
+    util::{default_rows_to_discard, NODE_SIZE},
 };
 use storage_proofs_porep::stacked::StackedBucketGraph;
+use tempfile::tempdir;
 
 #[test]
 fn test_empty_source() {
@@ -358,7 +366,7 @@ fn test_verify_random_pieces() -> Result<()> {
             assert!(sum_piece_bytes_with_alignment(&piece_sizes) <= unpadded_sector_size);
             assert!(!piece_sizes.is_empty());
 
-            let (comm_d, piece_infos) = build_sector(&piece_sizes, sector_size)?;
+            let (comm_d, piece_infos) = build_sector(&piece_sizes, sector_size, None)?;
 
             assert!(
                 verify_pieces(&comm_d, &piece_infos, sector_size)?,
@@ -370,9 +378,128 @@ fn test_verify_random_pieces() -> Result<()> {
     Ok(())
 }
 
+/// Polls `future` to completion on the current thread. This only suits futures which never wait
+/// on a runtime, like the ones reading and writing in memory below.
+#[cfg(feature = "async-io")]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    fn noop_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            noop_raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+#[cfg(feature = "async-io")]
+#[test]
+fn test_add_piece_async_matches_add_piece() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let piece_lengths = [UnpaddedBytesAmount(127)];
+    let piece_size = UnpaddedBytesAmount(127 * 1024 * 32);
+    let mut source = vec![0u8; u64::from(piece_size) as usize];
+    rng.fill_bytes(&mut source);
+
+    let mut expected_target = Vec::new();
+    let expected = add_piece(
+        Cursor::new(&source),
+        &mut expected_target,
+        piece_size,
+        &piece_lengths,
+    )?;
+
+    let mut target = Vec::new();
+    let actual = block_on(filecoin_proofs::add_piece_async(
+        Cursor::new(&source),
+        &mut target,
+        piece_size,
+        &piece_lengths,
+    ))?;
+
+    assert_eq!(expected, actual);
+    assert_eq!(expected_target, target);
+
+    Ok(())
+}
+
+#[test]
+fn test_piece_inclusion_proofs() -> Result<()> {
+    let sector_size = SectorSize(SECTOR_SIZE_2_KIB);
+    let piece_sizes: Vec<_> = [127, 127, 254, 508, 1016]
+        .iter()
+        .copied()
+        .map(UnpaddedBytesAmount)
+        .collect();
+
+    let cache_dir = tempdir()?;
+    let (comm_d, piece_infos) = build_sector(&piece_sizes, sector_size, Some(cache_dir.path()))?;
+
+    for (i, piece_info) in piece_infos.iter().enumerate() {
+        let offset = get_piece_start_byte(&piece_sizes[..i], piece_info.size);
+        let proof =
+            generate_piece_inclusion_proof(cache_dir.path(), sector_size, piece_info, offset)?;
+
+        let bytes = serde_json::to_vec(&proof)?;
+        let proof: PieceInclusionProof = serde_json::from_slice(&bytes)?;
+
+        assert!(verify_piece_inclusion_proof(
+            sector_size,
+            &comm_d,
+            piece_info,
+            offset,
+            &proof
+        )?);
+
+        // The same proof must not verify for another piece or another offset.
+        let other = &piece_infos[(i + 1) % piece_infos.len()];
+        assert!(!verify_piece_inclusion_proof(
+            sector_size,
+            &comm_d,
+            &PieceInfo::new(other.commitment, piece_info.size)?,
+            offset,
+            &proof
+        )?);
+        if offset != UnpaddedByteIndex(0) {
+            assert!(!verify_piece_inclusion_proof(
+                sector_size,
+                &comm_d,
+                piece_info,
+                UnpaddedByteIndex(0),
+                &proof
+            )?);
+        }
+    }
+
+    // Generating a proof for a piece that is not in the sector fails.
+    assert!(generate_piece_inclusion_proof(
+        cache_dir.path(),
+        sector_size,
+        &piece_infos[1],
+        get_piece_start_byte(&[], piece_infos[1].size),
+    )
+    .is_err());
+
+    Ok(())
+}
+
 fn build_sector(
     piece_sizes: &[UnpaddedBytesAmount],
     sector_size: SectorSize,
+    cache_path: Option<&Path>,
 ) -> Result<(Commitment, Vec<PieceInfo>)> {
     let rng = &mut XorShiftRng::from_seed(TEST_SEED);
     let porep_id = [32; 32];
@@ -406,8 +533,15 @@ fn build_sector(
     }
     assert_eq!(staged_sector.len(), u64::from(sector_size) as usize);
 
+    let config = cache_path.map(|cache_path| {
+        StoreConfig::new(
+            cache_path,
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(graph.size(), BINARY_ARITY),
+        )
+    });
     let data_tree: DataTree =
-        create_base_merkle_tree::<DataTree>(None, graph.size(), &staged_sector)
+        create_base_merkle_tree::<DataTree>(config, graph.size(), &staged_sector)
             .expect("failed to create data tree");
     let comm_d_root: Fr = data_tree.root().into();
     let comm_d = commitment_from_fr(comm_d_root);
@@ -0,0 +1,255 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+use filecoin_proofs::{
+    add_piece, generate_piece_commitment, seal_pre_commit_phase1, seal_pre_commit_phase2,
+    validate_cache_for_commit, with_local_artifacts, with_local_sector, FsSectorStore,
+    MemorySectorStore, PaddedBytesAmount, PoRepConfig, PoRepProofPartitions, SectorArtifact,
+    SectorShape2KiB, SectorSize, SectorStore, UnpaddedBytesAmount, POREP_PARTITIONS,
+    SECTOR_SIZE_2_KIB, TEST_SEED,
+};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use storage_proofs_core::{api_version::ApiVersion, cache_key::CacheKey};
+use tempfile::{tempdir, NamedTempFile};
+
+#[test]
+fn test_sector_artifact_file_names() {
+    let artifacts = vec![
+        SectorArtifact::Aux(CacheKey::PAux),
+        SectorArtifact::Aux(CacheKey::TAux),
+        SectorArtifact::Aux(CacheKey::LabelManifest),
+        SectorArtifact::Layer(1),
+        SectorArtifact::Layer(11),
+        SectorArtifact::Tree {
+            key: CacheKey::CommDTree,
+            index: None,
+        },
+        SectorArtifact::Tree {
+            key: CacheKey::CommCTree,
+            index: Some(7),
+        },
+        SectorArtifact::Tree {
+            key: CacheKey::CommRLastTree,
+            index: Some(0),
+        },
+    ];
+
+    for artifact in artifacts {
+        let name = artifact.file_name().expect("missing file name");
+        assert_eq!(SectorArtifact::from_file_name(&name), Some(artifact));
+    }
+
+    assert_eq!(SectorArtifact::Replica.file_name(), None);
+    assert_eq!(SectorArtifact::from_file_name("unrelated.tmp"), None);
+}
+
+#[test]
+fn test_seal_with_memory_sector_store() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let store = MemorySectorStore::new();
+
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let config = PoRepConfig {
+        sector_size: SectorSize(sector_size),
+        partitions: PoRepProofPartitions(
+            *POREP_PARTITIONS
+                .read()
+                .expect("POREP_PARTITIONS poisoned")
+                .get(&sector_size)
+                .expect("unknown sector size"),
+        ),
+        porep_id: [5; 32],
+        api_version: ApiVersion::V1_1_0,
+        non_interactive: false,
+        synthetic: false,
+    };
+
+    let piece_size = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    let piece_bytes: Vec<u8> = (0..piece_size.0).map(|_| rng.gen::<u8>()).collect();
+    let mut piece_file = NamedTempFile::new()?;
+    piece_file.write_all(&piece_bytes)?;
+    piece_file.seek(SeekFrom::Start(0))?;
+
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), piece_size)?;
+    piece_file.seek(SeekFrom::Start(0))?;
+
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(&mut piece_file, &mut staged_sector_file, piece_size, &[])?;
+
+    let prover_id = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+    let ticket = rng.gen();
+
+    let seal_dir = tempdir()?;
+    with_local_sector(&store, seal_dir.path(), |cache_path, replica_path| {
+        File::create(replica_path)?;
+        let phase1_output = seal_pre_commit_phase1::<_, _, _, SectorShape2KiB>(
+            config,
+            cache_path,
+            staged_sector_file.path(),
+            replica_path,
+            prover_id,
+            sector_id,
+            ticket,
+            &[piece_info.clone()],
+        )?;
+        seal_pre_commit_phase2(config, phase1_output, cache_path, replica_path)
+    })?;
+
+    for artifact in &[
+        SectorArtifact::Replica,
+        SectorArtifact::Aux(CacheKey::PAux),
+        SectorArtifact::Aux(CacheKey::TAux),
+        SectorArtifact::Layer(1),
+        SectorArtifact::Tree {
+            key: CacheKey::CommRLastTree,
+            index: None,
+        },
+    ] {
+        assert!(store.exists(artifact)?, "missing {:?}", artifact);
+    }
+    assert_eq!(store.size(&SectorArtifact::Replica)?, sector_size);
+
+    // A fresh local copy of the stored sector is a valid cache for commit.
+    let commit_dir = tempdir()?;
+    with_local_sector(&store, commit_dir.path(), |cache_path, replica_path| {
+        validate_cache_for_commit::<_, _, SectorShape2KiB>(cache_path, replica_path)
+    })?;
+
+    Ok(())
+}
+
+/// Records the artifacts written to the wrapped store.
+#[derive(Default)]
+struct RecordingStore {
+    inner: MemorySectorStore,
+    written: Mutex<Vec<SectorArtifact>>,
+}
+
+impl SectorStore for RecordingStore {
+    fn exists(&self, artifact: &SectorArtifact) -> Result<bool> {
+        self.inner.exists(artifact)
+    }
+
+    fn size(&self, artifact: &SectorArtifact) -> Result<u64> {
+        self.inner.size(artifact)
+    }
+
+    fn reader<'a>(&'a self, artifact: &SectorArtifact) -> Result<Box<dyn Read + Send + 'a>> {
+        self.inner.reader(artifact)
+    }
+
+    fn write(&self, artifact: &SectorArtifact, data: &mut dyn Read) -> Result<u64> {
+        self.written
+            .lock()
+            .expect("written lock poisoned")
+            .push(artifact.clone());
+        self.inner.write(artifact, data)
+    }
+
+    fn remove(&self, artifact: &SectorArtifact) -> Result<()> {
+        self.inner.remove(artifact)
+    }
+
+    fn list(&self) -> Result<Vec<SectorArtifact>> {
+        self.inner.list()
+    }
+}
+
+#[test]
+fn test_with_local_sector_writes_back_changes() -> Result<()> {
+    let p_aux = SectorArtifact::Aux(CacheKey::PAux);
+    let t_aux = SectorArtifact::Aux(CacheKey::TAux);
+    let layer = SectorArtifact::Layer(1);
+
+    let store = RecordingStore::default();
+    store.inner.insert(SectorArtifact::Replica, vec![1; 64]);
+    store.inner.insert(p_aux.clone(), vec![2; 64]);
+    store.inner.insert(t_aux.clone(), vec![3; 64]);
+
+    // A failing call leaves the store untouched, and still removes its copy, but nothing else,
+    // from the work dir.
+    let dir = tempdir()?;
+    let work_dir = dir.path();
+    let unrelated = work_dir.join("unrelated");
+    fs::write(&unrelated, [0; 8])?;
+    let result: Result<()> = with_local_sector(&store, work_dir, |cache_path, _| {
+        fs::write(
+            cache_path.join(p_aux.file_name().expect("file name")),
+            [4; 32],
+        )?;
+        bail!("interrupted");
+    });
+    assert!(result.is_err());
+    assert_eq!(fs::read_dir(work_dir)?.count(), 1);
+    assert!(unrelated.exists());
+    assert_eq!(store.inner.get(&p_aux), Some(vec![2; 64]));
+
+    // Only the modified, created and removed artifacts are written back, including the ones
+    // rewritten with the same length.
+    store.written.lock().expect("written lock poisoned").clear();
+    with_local_sector(&store, work_dir, |cache_path, _| {
+        fs::write(
+            cache_path.join(p_aux.file_name().expect("file name")),
+            [4; 64],
+        )?;
+        fs::write(
+            cache_path.join(layer.file_name().expect("file name")),
+            [5; 32],
+        )?;
+        fs::remove_file(cache_path.join(t_aux.file_name().expect("file name")))?;
+        Ok(())
+    })?;
+    assert_eq!(fs::read_dir(work_dir)?.count(), 1);
+
+    let mut written = store.written.lock().expect("written lock poisoned").clone();
+    written.sort_by_key(|artifact| format!("{:?}", artifact));
+    assert_eq!(written, vec![p_aux.clone(), layer.clone()]);
+    assert_eq!(store.inner.get(&p_aux), Some(vec![4; 64]));
+    assert_eq!(store.inner.get(&layer), Some(vec![5; 32]));
+    assert!(!store.exists(&t_aux)?);
+    assert_eq!(store.inner.get(&SectorArtifact::Replica), Some(vec![1; 64]));
+
+    Ok(())
+}
+
+#[test]
+fn test_with_local_artifacts() -> Result<()> {
+    let p_aux = SectorArtifact::Aux(CacheKey::PAux);
+
+    let store = MemorySectorStore::new();
+    store.insert(SectorArtifact::Replica, vec![1; 64]);
+    store.insert(p_aux.clone(), vec![2; 64]);
+
+    // Only the needed artifacts are copied.
+    let work_dir = tempdir()?;
+    with_local_artifacts(
+        &store,
+        work_dir.path(),
+        |artifact| *artifact != SectorArtifact::Replica,
+        |cache_path, replica_path| {
+            assert!(!replica_path.exists());
+            assert!(cache_path
+                .join(p_aux.file_name().expect("file name"))
+                .exists());
+            Ok(())
+        },
+    )?;
+    assert_eq!(store.get(&SectorArtifact::Replica), Some(vec![1; 64]));
+
+    // A local store is used in place.
+    let cache_dir = tempdir()?;
+    let replica_path = cache_dir.path().join("replica");
+    let store = FsSectorStore::new(cache_dir.path(), &replica_path);
+    with_local_sector(&store, work_dir.path(), |cache_path, path| {
+        assert_eq!(cache_path, cache_dir.path());
+        assert_eq!(path, replica_path);
+        Ok(())
+    })?;
+    assert_eq!(fs::read_dir(work_dir.path())?.count(), 0);
+
+    Ok(())
+}
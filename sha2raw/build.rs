@@ -0,0 +1,14 @@
+use std::env;
+
+fn main() {
+    let target_arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+
+    // The SHA-2 intrinsics are not available on the stable toolchain for aarch64.
+    if target_arch == "aarch64" {
+        println!("cargo:rerun-if-changed=src/sha256_armv8.c");
+        cc::Build::new()
+            .flag("-march=armv8-a+crypto")
+            .file("src/sha256_armv8.c")
+            .compile("sha256_armv8");
+    }
+}
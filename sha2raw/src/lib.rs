@@ -10,8 +10,14 @@ pub use digest::Digest;
 mod consts;
 mod platform;
 mod sha256;
+#[cfg(target_arch = "aarch64")]
+mod sha256_armv8;
+#[cfg(target_arch = "x86_64")]
+mod sha256_avx2;
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 mod sha256_intrinsics;
+mod sha256_multi;
 mod sha256_utils;
 
 pub use sha256::Sha256;
+pub use sha256_multi::{MultiSha256, Sha256x16, Sha256x4, Sha256x8};
@@ -1,3 +1,7 @@
+#[cfg(target_arch = "aarch64")]
+use crate::sha256_armv8;
+#[cfg(target_arch = "x86_64")]
+use crate::sha256_avx2;
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 use crate::sha256_intrinsics;
 use crate::sha256_utils;
@@ -10,9 +14,11 @@ enum Platform {
     Asm,
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     Sha,
+    #[cfg(target_arch = "aarch64")]
+    Armv8,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Implementation(Platform);
 
 impl Implementation {
@@ -24,6 +30,12 @@ impl Implementation {
                 return sha_impl;
             }
         }
+        #[cfg(target_arch = "aarch64")]
+        {
+            if let Some(armv8_impl) = Self::armv8_if_supported() {
+                return armv8_impl;
+            }
+        }
         #[cfg(feature = "asm")]
         {
             if let Some(asm_impl) = Self::asm_if_supported() {
@@ -60,8 +72,25 @@ impl Implementation {
         None
     }
 
+    #[cfg(target_arch = "aarch64")]
+    pub fn armv8_if_supported() -> Option<Self> {
+        if sha256_armv8::is_supported() {
+            return Some(Implementation(Platform::Armv8));
+        }
+
+        None
+    }
+
     #[cfg(feature = "asm")]
     pub fn asm_if_supported() -> Option<Self> {
+        // The aarch64 assembly uses the same SHA-2 instructions.
+        #[cfg(target_arch = "aarch64")]
+        {
+            if !sha256_armv8::is_supported() {
+                return None;
+            }
+        }
+
         Some(Implementation(Platform::Asm))
     }
 
@@ -75,6 +104,10 @@ impl Implementation {
             Platform::Sha => {
                 unsafe { sha256_intrinsics::compress256(state, blocks) };
             }
+            #[cfg(target_arch = "aarch64")]
+            Platform::Armv8 => {
+                unsafe { sha256_armv8::compress256(state, blocks) };
+            }
             #[cfg(feature = "asm")]
             Platform::Asm => {
                 let mut buffer = [0u8; 64];
@@ -87,3 +120,88 @@ impl Implementation {
         }
     }
 }
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum MultiPlatform {
+    /// Hashes the lanes one after another with a single lane implementation.
+    Lanes(Implementation),
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+}
+
+/// Implementation of hashing several independent messages of the same length at once.
+#[derive(Clone, Copy, Debug)]
+pub struct MultiImplementation(MultiPlatform);
+
+impl MultiImplementation {
+    pub fn detect() -> Self {
+        // SHA-NI hashes a single message faster than AVX2 hashes eight, so prefer it.
+        #[cfg(target_arch = "x86_64")]
+        {
+            if let Some(sha_impl) = Implementation::sha_if_supported() {
+                return Self::lanes(sha_impl);
+            }
+            if let Some(avx2_impl) = Self::avx2_if_supported() {
+                return avx2_impl;
+            }
+        }
+
+        Self::lanes(Implementation::detect())
+    }
+
+    pub fn lanes(implementation: Implementation) -> Self {
+        MultiImplementation(MultiPlatform::Lanes(implementation))
+    }
+
+    /// Hashes eight lanes at a time, wider batches in chunks of eight. AVX-512 is out of scope,
+    /// as its intrinsics are not available on the stable toolchain.
+    #[cfg(target_arch = "x86_64")]
+    pub fn avx2_if_supported() -> Option<Self> {
+        // Use raw_cpuid instead of is_x86_feature_detected, to ensure the check
+        // never happens at compile time.
+        if cpuid_bool::cpuid_bool!("avx2") {
+            return Some(MultiImplementation(MultiPlatform::Avx2));
+        }
+
+        None
+    }
+
+    /// Compresses the blocks of each lane into the state of the same index.
+    ///
+    /// Panics unless all lanes consist of the same number of blocks.
+    #[inline]
+    pub fn compress256(self, states: &mut [[u32; 8]], lanes: &[&[&[u8]]]) {
+        assert_eq!(states.len(), lanes.len(), "invalid number of lanes");
+        assert!(
+            lanes.windows(2).all(|pair| pair[0].len() == pair[1].len()),
+            "lanes of different lengths"
+        );
+
+        match self.0 {
+            MultiPlatform::Lanes(implementation) => {
+                for (state, blocks) in states.iter_mut().zip(lanes) {
+                    implementation.compress256(state, blocks);
+                }
+            }
+            #[cfg(target_arch = "x86_64")]
+            MultiPlatform::Avx2 => {
+                for (states, lanes) in states
+                    .chunks_mut(sha256_avx2::LANES)
+                    .zip(lanes.chunks(sha256_avx2::LANES))
+                {
+                    // Fill up a partial chunk with copies of its first lane, whose results are
+                    // discarded.
+                    let mut chunk_states = [states[0]; sha256_avx2::LANES];
+                    let mut chunk_lanes = [lanes[0]; sha256_avx2::LANES];
+                    chunk_states[..states.len()].copy_from_slice(states);
+                    chunk_lanes[..lanes.len()].copy_from_slice(lanes);
+
+                    unsafe { sha256_avx2::compress256(&mut chunk_states, &chunk_lanes) };
+
+                    states.copy_from_slice(&chunk_states[..states.len()]);
+                }
+            }
+        }
+    }
+}
@@ -100,6 +100,36 @@ mod tests {
         fuzz(1_000);
     }
 
+    #[test]
+    fn test_fuzz_implementations() {
+        let mut implementations = Vec::new();
+        #[cfg(target_arch = "x86_64")]
+        implementations.extend(Implementation::sha_if_supported());
+        #[cfg(target_arch = "aarch64")]
+        implementations.extend(Implementation::armv8_if_supported());
+        #[cfg(feature = "asm")]
+        implementations.extend(Implementation::asm_if_supported());
+
+        let rng = &mut XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+        for implementation in implementations {
+            for k in 1..100 {
+                let mut input = vec![0u8; 64 * k];
+                rng.fill_bytes(&mut input);
+                let chunked = input.chunks(32).collect::<Vec<_>>();
+
+                let mut state = H256;
+                implementation.compress256(&mut state, &chunked);
+                let mut expected = H256;
+                Implementation::portable().compress256(&mut expected, &chunked);
+
+                assert_eq!(state, expected, "{:?}", implementation);
+            }
+        }
+    }
+
     fn fuzz(n: usize) {
         let rng = &mut XorShiftRng::from_seed([
             0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
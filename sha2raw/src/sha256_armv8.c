@@ -0,0 +1,54 @@
+/*
+ * SHA-256 compression with the ARMv8 cryptography extensions.
+ *
+ * The SHA-2 intrinsics are not available on the stable Rust toolchain, so this is compiled
+ * by the build script on aarch64 targets and called from `sha256_armv8.rs`.
+ * Based on https://github.com/noloader/SHA-Intrinsics/blob/master/sha256-arm.c
+ */
+
+#include <arm_neon.h>
+#include <stdint.h>
+
+static const uint32_t K[64] = {
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+};
+
+/* Processes the 64 byte block made up of the 32 byte halves `block0` and `block1`. */
+void sha2raw_compress256_armv8(uint32_t state[8], const uint8_t *block0, const uint8_t *block1)
+{
+    uint32x4_t state0 = vld1q_u32(&state[0]); /* ABCD */
+    uint32x4_t state1 = vld1q_u32(&state[4]); /* EFGH */
+    const uint32x4_t abcd_save = state0;
+    const uint32x4_t efgh_save = state1;
+
+    /* Load the message in big endian order, the loads do not require alignment. */
+    uint32x4_t msg[4];
+    msg[0] = vreinterpretq_u32_u8(vrev32q_u8(vld1q_u8(block0)));
+    msg[1] = vreinterpretq_u32_u8(vrev32q_u8(vld1q_u8(block0 + 16)));
+    msg[2] = vreinterpretq_u32_u8(vrev32q_u8(vld1q_u8(block1)));
+    msg[3] = vreinterpretq_u32_u8(vrev32q_u8(vld1q_u8(block1 + 16)));
+
+    /* Four rounds per iteration, the message schedule for the next 16 rounds is computed
+     * in place of the words being consumed. */
+    for (int i = 0; i < 16; i++) {
+        const uint32x4_t wk = vaddq_u32(msg[i % 4], vld1q_u32(&K[4 * i]));
+        if (i < 12) {
+            msg[i % 4] = vsha256su1q_u32(vsha256su0q_u32(msg[i % 4], msg[(i + 1) % 4]),
+                                         msg[(i + 2) % 4], msg[(i + 3) % 4]);
+        }
+
+        const uint32x4_t tmp = state0;
+        state0 = vsha256hq_u32(state0, state1, wk);
+        state1 = vsha256h2q_u32(state1, tmp, wk);
+    }
+
+    vst1q_u32(&state[0], vaddq_u32(state0, abcd_save));
+    vst1q_u32(&state[4], vaddq_u32(state1, efgh_save));
+}
@@ -0,0 +1,38 @@
+extern "C" {
+    fn sha2raw_compress256_armv8(state: *mut u32, block0: *const u8, block1: *const u8);
+}
+
+/// Returns true if the CPU supports the ARMv8 SHA-2 instructions.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn is_supported() -> bool {
+    // Not exported by libc for all targets.
+    const HWCAP_SHA2: libc::c_ulong = 1 << 6;
+
+    unsafe { libc::getauxval(libc::AT_HWCAP) & HWCAP_SHA2 != 0 }
+}
+
+/// Returns true if the CPU supports the ARMv8 SHA-2 instructions.
+#[cfg(target_os = "macos")]
+pub fn is_supported() -> bool {
+    // All Apple aarch64 CPUs support them.
+    true
+}
+
+/// Returns true if the CPU supports the ARMv8 SHA-2 instructions.
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+pub fn is_supported() -> bool {
+    false
+}
+
+/// Process a block with the SHA-256 algorithm, using the ARMv8 cryptography extensions.
+/// Must only be called if `is_supported` returns true.
+#[inline(always)]
+pub unsafe fn compress256(state: &mut [u32; 8], blocks: &[&[u8]]) {
+    assert_eq!(blocks.len() % 2, 0);
+
+    for block in blocks.chunks(2) {
+        assert_eq!(block[0].len(), 32);
+        assert_eq!(block[1].len(), 32);
+        sha2raw_compress256_armv8(state.as_mut_ptr(), block[0].as_ptr(), block[1].as_ptr());
+    }
+}
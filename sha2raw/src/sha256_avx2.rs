@@ -0,0 +1,159 @@
+#![allow(clippy::many_single_char_names)]
+#![allow(clippy::cast_ptr_alignment)] // Safe to cast without alignment checks as the loads and stores do not require alignment.
+
+use std::arch::x86_64::{
+    __m256i, _mm256_add_epi32, _mm256_and_si256, _mm256_andnot_si256, _mm256_loadu_si256,
+    _mm256_or_si256, _mm256_permute2x128_si256, _mm256_set1_epi32, _mm256_setr_epi8,
+    _mm256_shuffle_epi8, _mm256_slli_epi32, _mm256_srli_epi32, _mm256_storeu_si256,
+    _mm256_unpackhi_epi32, _mm256_unpackhi_epi64, _mm256_unpacklo_epi32, _mm256_unpacklo_epi64,
+    _mm256_xor_si256,
+};
+
+use crate::consts::K32;
+
+/// The number of messages hashed in parallel, one per 32 bit lane of a 256 bit register.
+pub const LANES: usize = 8;
+
+macro_rules! rotr {
+    ($x:expr, $n:literal, $m:literal) => {
+        _mm256_or_si256(_mm256_srli_epi32($x, $n), _mm256_slli_epi32($x, $m))
+    };
+}
+
+#[inline(always)]
+unsafe fn xor3(a: __m256i, b: __m256i, c: __m256i) -> __m256i {
+    _mm256_xor_si256(_mm256_xor_si256(a, b), c)
+}
+
+#[inline(always)]
+unsafe fn add3(a: __m256i, b: __m256i, c: __m256i) -> __m256i {
+    _mm256_add_epi32(_mm256_add_epi32(a, b), c)
+}
+
+/// Transposes the 8x8 matrix of 32 bit words held in `rows`, turning the eight words of each
+/// lane into the same word of all lanes, and back.
+#[inline(always)]
+unsafe fn transpose(rows: [__m256i; LANES]) -> [__m256i; LANES] {
+    let [r0, r1, r2, r3, r4, r5, r6, r7] = rows;
+
+    let t0 = _mm256_unpacklo_epi32(r0, r1);
+    let t1 = _mm256_unpackhi_epi32(r0, r1);
+    let t2 = _mm256_unpacklo_epi32(r2, r3);
+    let t3 = _mm256_unpackhi_epi32(r2, r3);
+    let t4 = _mm256_unpacklo_epi32(r4, r5);
+    let t5 = _mm256_unpackhi_epi32(r4, r5);
+    let t6 = _mm256_unpacklo_epi32(r6, r7);
+    let t7 = _mm256_unpackhi_epi32(r6, r7);
+
+    let u0 = _mm256_unpacklo_epi64(t0, t2);
+    let u1 = _mm256_unpackhi_epi64(t0, t2);
+    let u2 = _mm256_unpacklo_epi64(t1, t3);
+    let u3 = _mm256_unpackhi_epi64(t1, t3);
+    let u4 = _mm256_unpacklo_epi64(t4, t6);
+    let u5 = _mm256_unpackhi_epi64(t4, t6);
+    let u6 = _mm256_unpacklo_epi64(t5, t7);
+    let u7 = _mm256_unpackhi_epi64(t5, t7);
+
+    [
+        _mm256_permute2x128_si256(u0, u4, 0x20),
+        _mm256_permute2x128_si256(u1, u5, 0x20),
+        _mm256_permute2x128_si256(u2, u6, 0x20),
+        _mm256_permute2x128_si256(u3, u7, 0x20),
+        _mm256_permute2x128_si256(u0, u4, 0x31),
+        _mm256_permute2x128_si256(u1, u5, 0x31),
+        _mm256_permute2x128_si256(u2, u6, 0x31),
+        _mm256_permute2x128_si256(u3, u7, 0x31),
+    ]
+}
+
+/// Loads the 32 byte block `index` of all lanes, as the eight big endian message words of all
+/// lanes, one word per register.
+#[inline(always)]
+unsafe fn load_blocks(lanes: &[&[&[u8]]; LANES], index: usize) -> [__m256i; LANES] {
+    let swap_bytes = _mm256_setr_epi8(
+        3, 2, 1, 0, 7, 6, 5, 4, 11, 10, 9, 8, 15, 14, 13, 12, 3, 2, 1, 0, 7, 6, 5, 4, 11, 10, 9, 8,
+        15, 14, 13, 12,
+    );
+
+    let mut rows = [_mm256_set1_epi32(0); LANES];
+    for (row, blocks) in rows.iter_mut().zip(lanes.iter()) {
+        let block = blocks[index];
+        assert_eq!(block.len(), 32);
+        *row = _mm256_shuffle_epi8(
+            _mm256_loadu_si256(block.as_ptr() as *const __m256i),
+            swap_bytes,
+        );
+    }
+
+    transpose(rows)
+}
+
+/// Process the blocks of eight independent messages with the SHA-256 algorithm, each message
+/// in one 32 bit lane. All messages must consist of the same number of blocks.
+#[target_feature(enable = "avx2")]
+pub unsafe fn compress256(states: &mut [[u32; 8]; LANES], lanes: &[&[&[u8]]; LANES]) {
+    let blocks_len = lanes[0].len();
+    assert_eq!(blocks_len % 2, 0);
+    assert!(lanes.iter().all(|blocks| blocks.len() == blocks_len));
+
+    // Transpose the states, so that each register holds the same state word of all lanes.
+    let mut rows = [_mm256_set1_epi32(0); LANES];
+    for (row, lane_state) in rows.iter_mut().zip(states.iter()) {
+        *row = _mm256_loadu_si256(lane_state.as_ptr() as *const __m256i);
+    }
+    let mut state = transpose(rows);
+
+    let mut w = [_mm256_set1_epi32(0); 16];
+    for i in (0..blocks_len).step_by(2) {
+        w[..8].copy_from_slice(&load_blocks(lanes, i));
+        w[8..].copy_from_slice(&load_blocks(lanes, i + 1));
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+
+        for (t, k) in K32.iter().enumerate() {
+            if t >= 16 {
+                let w15 = w[(t - 15) % 16];
+                let w2 = w[(t - 2) % 16];
+                let s0 = xor3(
+                    rotr!(w15, 7, 25),
+                    rotr!(w15, 18, 14),
+                    _mm256_srli_epi32(w15, 3),
+                );
+                let s1 = xor3(
+                    rotr!(w2, 17, 15),
+                    rotr!(w2, 19, 13),
+                    _mm256_srli_epi32(w2, 10),
+                );
+                w[t % 16] = _mm256_add_epi32(add3(w[t % 16], s0, w[(t - 7) % 16]), s1);
+            }
+
+            let s1 = xor3(rotr!(e, 6, 26), rotr!(e, 11, 21), rotr!(e, 25, 7));
+            let ch = _mm256_xor_si256(_mm256_and_si256(e, f), _mm256_andnot_si256(e, g));
+            let t1 = add3(add3(h, s1, ch), _mm256_set1_epi32(*k as i32), w[t % 16]);
+            let s0 = xor3(rotr!(a, 2, 30), rotr!(a, 13, 19), rotr!(a, 22, 10));
+            let maj = xor3(
+                _mm256_and_si256(a, b),
+                _mm256_and_si256(a, c),
+                _mm256_and_si256(b, c),
+            );
+            let t2 = _mm256_add_epi32(s0, maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = _mm256_add_epi32(d, t1);
+            d = c;
+            c = b;
+            b = a;
+            a = _mm256_add_epi32(t1, t2);
+        }
+
+        for (word, x) in state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+            *word = _mm256_add_epi32(*word, *x);
+        }
+    }
+
+    for (lane_state, row) in states.iter_mut().zip(&transpose(state)) {
+        _mm256_storeu_si256(lane_state.as_mut_ptr() as *mut __m256i, *row);
+    }
+}
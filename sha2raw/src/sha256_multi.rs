@@ -0,0 +1,268 @@
+use std::fmt;
+
+use byteorder::{ByteOrder, BE};
+use lazy_static::lazy_static;
+
+use crate::{consts::H256, platform::MultiImplementation};
+
+lazy_static! {
+    static ref MULTI_IMPL: MultiImplementation = MultiImplementation::detect();
+}
+
+/// Hashes `LANES` independent messages at once, see `Sha256` for the single message version.
+///
+/// All messages must be of the same length, as for example the labels of different nodes.
+///
+/// On x86_64 the lanes are hashed with AVX2, eight at a time, unless SHA-NI is available, which
+/// hashes them one after another faster. AVX-512 would hash sixteen lanes at a time, but its
+/// intrinsics are not available on the stable toolchain this crate builds with, so it is not
+/// supported.
+#[derive(Clone)]
+pub struct MultiSha256<const LANES: usize> {
+    len: u64,
+    states: [[u32; 8]; LANES],
+}
+
+pub type Sha256x4 = MultiSha256<4>;
+pub type Sha256x8 = MultiSha256<8>;
+pub type Sha256x16 = MultiSha256<16>;
+
+impl<const LANES: usize> Default for MultiSha256<LANES> {
+    fn default() -> Self {
+        MultiSha256 {
+            len: 0,
+            states: [H256; LANES],
+        }
+    }
+}
+
+impl<const LANES: usize> MultiSha256<LANES> {
+    pub fn new() -> Self {
+        MultiSha256::default()
+    }
+
+    pub fn digest(lanes: &[&[&[u8]]; LANES]) -> [[u8; 32]; LANES] {
+        let mut sha = MultiSha256::new();
+        sha.input(lanes);
+        sha.finish()
+    }
+
+    /// Hashes the 32 byte blocks of each lane, an even number of them in every lane.
+    ///
+    /// Panics if the lanes do not have the same number of blocks, as the lanes are processed in
+    /// lockstep.
+    pub fn input(&mut self, lanes: &[&[&[u8]]; LANES]) {
+        let blocks_len = lanes[0].len();
+        assert_eq!(blocks_len % 2, 0, "invalid block length");
+
+        self.len += (blocks_len as u64) << 8;
+
+        MULTI_IMPL.compress256(&mut self.states, lanes);
+    }
+
+    pub fn finish(mut self) -> [[u8; 32]; LANES] {
+        let mut block0 = [0u8; 32];
+        let mut block1 = [0u8; 32];
+
+        // Append single 1 bit
+        block0[0] = 0b1000_0000;
+
+        // Write L as 64 big endian integer
+        let l = self.len;
+        block1[32 - 8..].copy_from_slice(&l.to_be_bytes()[..]);
+
+        let padding = [&block0[..], &block1[..]];
+        MULTI_IMPL.compress256(&mut self.states, &[&padding[..]; LANES]);
+
+        self.output()
+    }
+
+    pub fn finish_with(mut self, block0: &[&[u8]; LANES]) -> [[u8; 32]; LANES] {
+        assert!(
+            block0.iter().all(|block| block.len() == 32),
+            "invalid block length"
+        );
+
+        let mut block1 = [0u8; 32];
+
+        // Append single 1 bit
+        block1[0] = 0b1000_0000;
+
+        // Write L as 64 big endian integer
+        let l = self.len + 256;
+        block1[32 - 8..].copy_from_slice(&l.to_be_bytes()[..]);
+
+        let mut padding = [[&block1[..]; 2]; LANES];
+        for (blocks, block) in padding.iter_mut().zip(block0.iter()) {
+            blocks[0] = *block;
+        }
+        let mut lanes = [&padding[0][..]; LANES];
+        for (lane, blocks) in lanes.iter_mut().zip(padding.iter()) {
+            *lane = &blocks[..];
+        }
+        MULTI_IMPL.compress256(&mut self.states, &lanes);
+
+        self.output()
+    }
+
+    fn output(&self) -> [[u8; 32]; LANES] {
+        let mut out = [[0u8; 32]; LANES];
+        for (out, state) in out.iter_mut().zip(self.states.iter()) {
+            BE::write_u32_into(state, out);
+        }
+        out
+    }
+}
+
+impl<const LANES: usize> fmt::Debug for MultiSha256<LANES> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MultiSha256 { ... }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::{platform::Implementation, Sha256};
+
+    #[test]
+    fn test_fuzz_simple() {
+        fuzz::<4>(10);
+        fuzz::<8>(10);
+        fuzz::<16>(10);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_fuzz_long() {
+        fuzz::<4>(1_000);
+        fuzz::<8>(1_000);
+        fuzz::<16>(1_000);
+    }
+
+    #[test]
+    fn test_fuzz_implementations() {
+        let mut implementations = vec![MultiImplementation::lanes(Implementation::portable())];
+        #[cfg(target_arch = "x86_64")]
+        {
+            implementations.extend(MultiImplementation::avx2_if_supported());
+            implementations
+                .extend(Implementation::sha_if_supported().map(MultiImplementation::lanes));
+        }
+
+        let rng = &mut XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+        for implementation in implementations {
+            // Include lane counts which do not fill up the wider backends.
+            for lanes_len in &[1, 3, 8, 11, 16] {
+                for k in 1..10 {
+                    let inputs = random_inputs(rng, *lanes_len, 64 * k);
+                    let chunked = inputs
+                        .iter()
+                        .map(|input| input.chunks(32).collect::<Vec<_>>())
+                        .collect::<Vec<_>>();
+                    let lanes = chunked.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+                    let mut states = vec![H256; *lanes_len];
+                    implementation.compress256(&mut states, &lanes);
+
+                    for (state, blocks) in states.iter().zip(&lanes) {
+                        let mut expected = H256;
+                        Implementation::portable().compress256(&mut expected, blocks);
+                        assert_eq!(state, &expected, "{:?}", implementation);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "lanes of different lengths")]
+    fn test_lanes_of_different_lengths() {
+        let block = [0u8; 32];
+        let short = [&block[..]; 2];
+        let long = [&block[..]; 4];
+        MultiSha256::<2>::digest(&[&short[..], &long[..]]);
+    }
+
+    fn random_inputs(rng: &mut XorShiftRng, lanes_len: usize, len: usize) -> Vec<Vec<u8>> {
+        (0..lanes_len)
+            .map(|_| {
+                let mut input = vec![0u8; len];
+                rng.fill_bytes(&mut input);
+                input
+            })
+            .collect()
+    }
+
+    fn fuzz<const LANES: usize>(n: usize) {
+        let rng = &mut XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+        for k in 1..n {
+            for _ in 0..10 {
+                let inputs = random_inputs(rng, LANES, 64 * k);
+                let chunked = inputs
+                    .iter()
+                    .map(|input| input.chunks(32).collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+                let mut lanes = [&chunked[0][..]; LANES];
+                for (lane, blocks) in lanes.iter_mut().zip(&chunked) {
+                    *lane = &blocks[..];
+                }
+
+                let hashes = MultiSha256::<LANES>::digest(&lanes);
+                for (hash, blocks) in hashes.iter().zip(&chunked) {
+                    assert_eq!(hash, &Sha256::digest(blocks));
+                }
+            }
+        }
+
+        for k in (1..n).step_by(2) {
+            for _ in 0..10 {
+                let inputs = random_inputs(rng, LANES, 32 * k);
+                let mut hasher = MultiSha256::<LANES>::new();
+                let full_blocks = inputs[0].len() / 64 * 64;
+                for offset in (0..full_blocks).step_by(64) {
+                    let chunks = inputs
+                        .iter()
+                        .map(|input| {
+                            [
+                                &input[offset..offset + 32],
+                                &input[offset + 32..offset + 64],
+                            ]
+                        })
+                        .collect::<Vec<_>>();
+                    let mut lanes = [&chunks[0][..]; LANES];
+                    for (lane, blocks) in lanes.iter_mut().zip(&chunks) {
+                        *lane = &blocks[..];
+                    }
+                    hasher.input(&lanes);
+                }
+                assert_eq!(inputs[0].len() % 64, 32);
+                let mut last = [&inputs[0][..]; LANES];
+                for (last, input) in last.iter_mut().zip(&inputs) {
+                    *last = &input[input.len() - 32..];
+                }
+                let hashes = hasher.finish_with(&last);
+
+                for (hash, input) in hashes.iter().zip(&inputs) {
+                    let mut single = Sha256::new();
+                    for chunk in input.chunks(64) {
+                        if chunk.len() == 64 {
+                            single.input(&[&chunk[..32], &chunk[32..]]);
+                        }
+                    }
+                    assert_eq!(hash, &single.finish_with(&input[input.len() - 32..]));
+                }
+            }
+        }
+    }
+}
@@ -74,6 +74,50 @@ fn sha256_raw_benchmark(c: &mut Criterion) {
     group.finish();
 }
 
+fn sha256_raw_multi_benchmark(c: &mut Criterion) {
+    fn bench_lanes<const LANES: usize>(c: &mut Criterion, bytes: usize) {
+        let mut group = c.benchmark_group(format!("hash-sha256-raw-x{}", LANES));
+
+        let mut rng = thread_rng();
+        let data: Vec<Vec<u8>> = (0..LANES)
+            .map(|_| (0..bytes).map(|_| rng.gen()).collect())
+            .collect();
+        let chunks = data
+            .iter()
+            .map(|data| data.chunks(32).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let mut lanes = [&chunks[0][..]; LANES];
+        for (lane, chunks) in lanes.iter_mut().zip(&chunks) {
+            *lane = &chunks[..];
+        }
+
+        group
+            .bench_function(format!("sequential-{}", bytes), |b| {
+                b.iter(|| {
+                    black_box(
+                        lanes
+                            .iter()
+                            .map(|lane| sha2raw::Sha256::digest(lane))
+                            .collect::<Vec<_>>(),
+                    )
+                })
+            })
+            .throughput(Throughput::Bytes((bytes * LANES) as u64));
+        group
+            .bench_function(format!("multi-{}", bytes), |b| {
+                b.iter(|| black_box(sha2raw::MultiSha256::<LANES>::digest(&lanes)))
+            })
+            .throughput(Throughput::Bytes((bytes * LANES) as u64));
+
+        group.finish();
+    }
+
+    // The size of a label preimage hashed during labeling.
+    let bytes = 38 * 32;
+    bench_lanes::<8>(c, bytes);
+    bench_lanes::<16>(c, bytes);
+}
+
 fn sha256_circuit_benchmark(c: &mut Criterion) {
     let mut rng1 = thread_rng();
 
@@ -131,6 +175,7 @@ criterion_group!(
     benches,
     sha256_benchmark,
     sha256_raw_benchmark,
+    sha256_raw_multi_benchmark,
     sha256_circuit_benchmark
 );
 criterion_main!(benches);
@@ -3,6 +3,7 @@ use std::str::FromStr;
 
 use anyhow::{format_err, Error, Result};
 use semver::Version;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum ApiVersion {
@@ -53,6 +54,20 @@ impl FromStr for ApiVersion {
     }
 }
 
+/// Serialized as its semver string.
+impl Serialize for ApiVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ApiVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let api_version_str = String::deserialize(deserializer)?;
+        api_version_str.parse().map_err(de::Error::custom)
+    }
+}
+
 #[test]
 fn test_fmt() {
     assert_eq!(format!("{}", ApiVersion::V1_0_0), "1.0.0");
@@ -64,3 +79,12 @@ fn test_as_semver() {
     assert_eq!(ApiVersion::V1_0_0.as_semver().major, 1);
     assert_eq!(ApiVersion::V1_1_0.as_semver().major, 1);
 }
+
+#[test]
+fn test_serde() {
+    let json = serde_json::to_string(&ApiVersion::V1_1_0).expect("serialize failure");
+    assert_eq!(json, "\"1.1.0\"");
+    let api_version: ApiVersion = serde_json::from_str(&json).expect("deserialize failure");
+    assert_eq!(api_version, ApiVersion::V1_1_0);
+    assert!(serde_json::from_str::<ApiVersion>("\"1.2.0\"").is_err());
+}
@@ -1,12 +1,15 @@
 use std::fmt::{self, Display, Formatter};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum CacheKey {
     PAux,
     TAux,
     CommDTree,
     CommCTree,
     CommRLastTree,
+    LabelManifest,
+    SectorMeta,
+    SyntheticPoRepProofs,
 }
 
 impl Display for CacheKey {
@@ -17,6 +20,9 @@ impl Display for CacheKey {
             CacheKey::CommDTree => write!(f, "tree-d"),
             CacheKey::CommCTree => write!(f, "tree-c"),
             CacheKey::CommRLastTree => write!(f, "tree-r-last"),
+            CacheKey::LabelManifest => write!(f, "label-manifest"),
+            CacheKey::SectorMeta => write!(f, "sector-meta"),
+            CacheKey::SyntheticPoRepProofs => write!(f, "syn-porep-vanilla-proofs.dat"),
         }
     }
 }
@@ -0,0 +1,75 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+
+use bellperson::{bls::Bls12, Circuit, ConstraintSystem, SynthesisError};
+
+use crate::error::{Error, Result};
+
+/// A handle to abort a long running proving call, either explicitly through `cancel` or once a
+/// deadline has passed. Clones share the same cancellation state.
+///
+/// The default token is never cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a token which is cancelled once `deadline` has passed.
+    pub fn with_deadline(deadline: Instant) -> Self {
+        CancellationToken {
+            cancelled: Default::default(),
+            deadline: Some(deadline),
+        }
+    }
+
+    /// Creates a token which is cancelled once `timeout` has elapsed from now.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self::with_deadline(Instant::now() + timeout)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+            || self
+                .deadline
+                .map(|deadline| Instant::now() >= deadline)
+                .unwrap_or(false)
+    }
+
+    /// Returns `Error::Cancelled` if the token has been cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Wraps a circuit so that its synthesis is skipped once `cancel` has been cancelled.
+pub struct CancellableCircuit<C> {
+    pub circuit: C,
+    pub cancel: CancellationToken,
+}
+
+impl<C: Circuit<Bls12>> Circuit<Bls12> for CancellableCircuit<C> {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        if self.cancel.is_cancelled() {
+            return Err(SynthesisError::AssignmentMissing);
+        }
+
+        self.circuit.synthesize(cs)
+    }
+}
@@ -18,11 +18,16 @@ use rayon::prelude::{
 };
 
 use crate::{
+    cancel::CancellableCircuit,
     error::Result,
+    measurements::{measure_op, MeasuredCircuit, Operation},
     multi_proof::MultiProof,
     parameter_cache::{CacheableParameters, ParameterSetMetadata},
     partitions::partition_count,
+    progress::ProgressEvent,
     proof::ProofScheme,
+    run_options::RunOptions,
+    settings::{self, with_config, ConfiguredCircuit},
 };
 
 #[derive(Clone)]
@@ -84,18 +89,41 @@ where
         priv_in: &S::PrivateInputs,
         groth_params: &'b groth16::MappedParameters<Bls12>,
     ) -> Result<MultiProof<'b>> {
+        Self::prove_with_options(
+            pub_params,
+            pub_in,
+            priv_in,
+            groth_params,
+            &RunOptions::default(),
+        )
+    }
+
+    /// prove_with_options is equivalent to prove. It returns `Error::Cancelled` once
+    /// `options.cancel` is observed, and reports every proved partition to `options.progress`.
+    fn prove_with_options<'b>(
+        pub_params: &PublicParams<'a, S>,
+        pub_in: &S::PublicInputs,
+        priv_in: &S::PrivateInputs,
+        groth_params: &'b groth16::MappedParameters<Bls12>,
+        options: &RunOptions<'_>,
+    ) -> Result<MultiProof<'b>> {
+        let _config = options.enter_config();
+        options.cancel.check()?;
+
         let partition_count = Self::partition_count(pub_params);
 
         // This will always run at least once, since there cannot be zero partitions.
         ensure!(partition_count > 0, "There must be partitions");
 
         info!("vanilla_proofs:start");
-        let vanilla_proofs = S::prove_all_partitions(
-            &pub_params.vanilla_params,
-            &pub_in,
-            priv_in,
-            partition_count,
-        )?;
+        let vanilla_proofs = measure_op(Operation::VanillaProofs, || {
+            S::prove_all_partitions(
+                &pub_params.vanilla_params,
+                &pub_in,
+                priv_in,
+                partition_count,
+            )
+        })?;
 
         info!("vanilla_proofs:finish");
 
@@ -104,12 +132,13 @@ where
         ensure!(sanity_check, "sanity check failed");
 
         info!("snark_proof:start");
-        let groth_proofs = Self::circuit_proofs(
+        let groth_proofs = Self::circuit_proofs_with_options(
             pub_in,
             vanilla_proofs,
             &pub_params.vanilla_params,
             groth_params,
             pub_params.priority,
+            options,
         )?;
         info!("snark_proof:finish");
 
@@ -122,18 +151,40 @@ where
         vanilla_proofs: Vec<S::Proof>,
         groth_params: &'b groth16::MappedParameters<Bls12>,
     ) -> Result<MultiProof<'b>> {
+        Self::prove_with_vanilla_and_options(
+            pub_params,
+            pub_in,
+            vanilla_proofs,
+            groth_params,
+            &RunOptions::default(),
+        )
+    }
+
+    /// prove_with_vanilla_and_options is equivalent to prove_with_vanilla, with the cancellation
+    /// and progress reporting of prove_with_options.
+    fn prove_with_vanilla_and_options<'b>(
+        pub_params: &PublicParams<'a, S>,
+        pub_in: &S::PublicInputs,
+        vanilla_proofs: Vec<S::Proof>,
+        groth_params: &'b groth16::MappedParameters<Bls12>,
+        options: &RunOptions<'_>,
+    ) -> Result<MultiProof<'b>> {
+        let _config = options.enter_config();
+        options.cancel.check()?;
+
         let partition_count = Self::partition_count(pub_params);
 
         // This will always run at least once, since there cannot be zero partitions.
         ensure!(partition_count > 0, "There must be partitions");
 
         info!("snark_proof:start");
-        let groth_proofs = Self::circuit_proofs(
+        let groth_proofs = Self::circuit_proofs_with_options(
             pub_in,
             vanilla_proofs,
             &pub_params.vanilla_params,
             groth_params,
             pub_params.priority,
+            options,
         )?;
         info!("snark_proof:finish");
 
@@ -240,31 +291,99 @@ where
         groth_params: &groth16::MappedParameters<Bls12>,
         priority: bool,
     ) -> Result<Vec<groth16::Proof<Bls12>>> {
+        Self::circuit_proofs_with_options(
+            pub_in,
+            vanilla_proofs,
+            pub_params,
+            groth_params,
+            priority,
+            &RunOptions::default(),
+        )
+    }
+
+    /// circuit_proofs_with_options is equivalent to circuit_proofs. The partitions are proved in
+    /// batches of `options.partition_batch_size`, and each partition is reported to
+    /// `options.progress` as soon as its batch is proved. Each circuit checks `options.cancel`
+    /// before it is synthesized, and `Error::Cancelled` is returned if it was observed.
+    fn circuit_proofs_with_options(
+        pub_in: &S::PublicInputs,
+        vanilla_proofs: Vec<S::Proof>,
+        pub_params: &S::PublicParams,
+        groth_params: &groth16::MappedParameters<Bls12>,
+        priority: bool,
+        options: &RunOptions<'_>,
+    ) -> Result<Vec<groth16::Proof<Bls12>>> {
+        let _config = options.enter_config();
+        let cancel = &options.cancel;
+        cancel.check()?;
+
         let mut rng = OsRng;
         ensure!(
             !vanilla_proofs.is_empty(),
             "cannot create a circuit proof over missing vanilla proofs"
         );
 
-        let circuits = vanilla_proofs
-            .into_par_iter()
-            .enumerate()
-            .map(|(k, vanilla_proof)| {
-                Self::circuit(
-                    &pub_in,
-                    C::ComponentPrivateInputs::default(),
-                    &vanilla_proof,
-                    &pub_params,
-                    Some(k),
-                )
-            })
-            .collect::<Result<Vec<_>>>()?;
-
-        let groth_proofs = if priority {
-            create_random_proof_batch_in_priority(circuits, groth_params, &mut rng)?
-        } else {
-            create_random_proof_batch(circuits, groth_params, &mut rng)?
-        };
+        // Circuits are built and synthesized on other threads, which need the caller's config.
+        let config = settings::current();
+
+        let partitions = vanilla_proofs.len();
+        let batch_size = options
+            .partition_batch_size
+            .unwrap_or(partitions)
+            .max(1)
+            .min(partitions);
+
+        let mut groth_proofs = Vec::with_capacity(partitions);
+        for (batch_index, batch) in vanilla_proofs.chunks(batch_size).enumerate() {
+            let offset = batch_index * batch_size;
+
+            let circuits = batch
+                .par_iter()
+                .enumerate()
+                .map(|(i, vanilla_proof)| {
+                    let k = offset + i;
+                    let circuit = with_config(Some(&config), || {
+                        Self::circuit(
+                            &pub_in,
+                            C::ComponentPrivateInputs::default(),
+                            vanilla_proof,
+                            &pub_params,
+                            Some(k),
+                        )
+                    })?;
+                    let circuit = ConfiguredCircuit {
+                        circuit: CancellableCircuit {
+                            circuit,
+                            cancel: cancel.clone(),
+                        },
+                        config: config.clone(),
+                    };
+
+                    Ok(MeasuredCircuit::new(circuit, k))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            cancel.check()?;
+
+            let batch_proofs = measure_op(Operation::SnarkProve, || {
+                if priority {
+                    create_random_proof_batch_in_priority(circuits, groth_params, &mut rng)
+                } else {
+                    create_random_proof_batch(circuits, groth_params, &mut rng)
+                }
+            });
+            // A circuit refusing to synthesize after cancellation is reported as such.
+            cancel.check()?;
+            let batch_proofs = batch_proofs?;
+
+            for partition in offset..offset + batch_proofs.len() {
+                options.progress.on_event(ProgressEvent::PartitionProved {
+                    partition,
+                    partitions,
+                });
+            }
+            groth_proofs.extend(batch_proofs);
+        }
 
         groth_proofs
             .into_iter()
@@ -285,11 +404,13 @@ where
         hashed_seeds_and_comm_rs: &[u8],
         proofs: &[groth16::Proof<Bls12>],
     ) -> Result<AggregateProof<Bls12>> {
-        Ok(aggregate_proofs::<Bls12>(
-            prover_srs,
-            hashed_seeds_and_comm_rs,
-            proofs,
-        )?)
+        measure_op(Operation::AggregateProofs, || {
+            Ok(aggregate_proofs::<Bls12>(
+                prover_srs,
+                hashed_seeds_and_comm_rs,
+                proofs,
+            )?)
+        })
     }
 
     /// Verifies the aggregate proof, with respect to the flattened input list.
@@ -308,14 +429,16 @@ where
     ) -> Result<bool> {
         let mut rng = OsRng;
 
-        Ok(verify_aggregate_proof(
-            ip_verifier_srs,
-            pvk,
-            &mut rng,
-            public_inputs,
-            aggregate_proof,
-            hashed_seeds_and_comm_rs,
-        )?)
+        measure_op(Operation::VerifyAggregateProofs, || {
+            Ok(verify_aggregate_proof(
+                ip_verifier_srs,
+                pvk,
+                &mut rng,
+                public_inputs,
+                aggregate_proof,
+                hashed_seeds_and_comm_rs,
+            )?)
+        })
     }
 
     /// generate_public_inputs generates public inputs suitable for use as input during verification
@@ -13,6 +13,7 @@ use std::convert::TryInto;
 
 pub mod api_version;
 pub mod cache_key;
+pub mod cancel;
 pub mod compound_proof;
 pub mod crypto;
 pub mod data;
@@ -26,7 +27,9 @@ pub mod parameter_cache;
 pub mod partitions;
 pub mod pieces;
 pub mod por;
+pub mod progress;
 pub mod proof;
+pub mod run_options;
 pub mod sector;
 pub mod settings;
 pub mod test_helper;
@@ -0,0 +1,282 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(feature = "measurements")]
+use std::sync::{
+    mpsc::{channel, Receiver, Sender},
+    Mutex,
+};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use bellperson::{bls::Bls12, Circuit, ConstraintSystem, SynthesisError};
+use lazy_static::lazy_static;
+use log::warn;
+use serde::Serialize;
+
+mod sink;
+
+pub use self::sink::{CollectorSink, JsonLinesSink, MeasurementSink, PrometheusSink};
+
+/// Key-value pairs attached to a measurement, such as the sector id or layer.
+pub type Tags = BTreeMap<String, String>;
+
+#[cfg(feature = "measurements")]
+lazy_static! {
+    pub static ref OP_MEASUREMENTS: (
+        Mutex<Option<Sender<OpMeasurement>>>,
+        Mutex<Receiver<OpMeasurement>>
+    ) = {
+        // create asynchronous channel with unlimited buffer
+        let (tx, rx) = channel();
+        (Mutex::new(Some(tx)), Mutex::new(rx))
+    };
+}
+
+lazy_static! {
+    static ref MEASUREMENT_SINK: RwLock<Option<Arc<dyn MeasurementSink>>> = RwLock::new(None);
+}
+
+thread_local! {
+    static TAGS: RefCell<Tags> = RefCell::new(Tags::new());
+}
+
+/// A single timed operation.
+///
+/// `cpu_time` is the process CPU time spent while the operation ran, and is only measured with
+/// the `measurements` feature. Without it, `cpu_time` is always zero.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OpMeasurement {
+    pub op: Operation,
+    pub cpu_time: Duration,
+    pub wall_time: Duration,
+    pub tags: Tags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    AddPiece,
+    GeneratePieceCommitment,
+    GenerateTreeC,
+    GenerateTreeRLast,
+    CommD,
+    EncodeWindowTimeAll,
+    WindowCommLeavesTime,
+    PorepCommitTime,
+    PostInclusionProofs,
+    PostFinalizeTicket,
+    PostReadChallengedRange,
+    PostPartialTicketHash,
+    /// Labeling of a single layer, tagged with `layer`.
+    LabelLayer,
+    /// Hashing the columns of a base tree_c on the CPU, tagged with `tree`.
+    ColumnHashing,
+    /// Generating the vanilla proofs of all partitions.
+    VanillaProofs,
+    /// Synthesizing the circuit of a single partition, tagged with `partition`.
+    CircuitSynthesis,
+    /// Generating the groth16 proofs of all partitions, including circuit synthesis.
+    SnarkProve,
+    /// Aggregating groth16 proofs.
+    AggregateProofs,
+    /// Verifying an aggregated proof.
+    VerifyAggregateProofs,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(name)) => write!(f, "{}", name),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// Sets the sink receiving every measurement, replacing the previous one. `None` removes it.
+///
+/// The sink works independently of the `measurements` feature, which additionally measures the
+/// CPU time and sends all measurements to `OP_MEASUREMENTS`.
+pub fn set_measurement_sink(sink: Option<Arc<dyn MeasurementSink>>) {
+    *MEASUREMENT_SINK
+        .write()
+        .expect("measurement sink lock poisoned") = sink;
+}
+
+fn measurement_sink() -> Option<Arc<dyn MeasurementSink>> {
+    MEASUREMENT_SINK
+        .read()
+        .expect("measurement sink lock poisoned")
+        .clone()
+}
+
+/// Runs `f` with `tags` added to every measurement taken on the current thread.
+///
+/// Tags nest, with inner values taking precedence. Work spawned onto other threads does not
+/// inherit them, see `current_tags` and `with_tag_map`.
+pub fn with_tags<T, F>(tags: &[(&str, String)], f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let mut tag_map = current_tags();
+    for (key, value) in tags {
+        tag_map.insert(key.to_string(), value.clone());
+    }
+
+    with_tag_map(tag_map, f)
+}
+
+/// Runs `f` with exactly `tags` attached to the measurements taken on the current thread.
+pub fn with_tag_map<T, F>(tags: Tags, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    struct Restore(Option<Tags>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(tags) = self.0.take() {
+                TAGS.with(|current| *current.borrow_mut() = tags);
+            }
+        }
+    }
+
+    let previous = TAGS.with(|current| current.replace(tags));
+    let _restore = Restore(Some(previous));
+
+    f()
+}
+
+/// Returns the tags attached to the measurements of the current thread.
+pub fn current_tags() -> Tags {
+    TAGS.with(|current| current.borrow().clone())
+}
+
+/// Measures `op` while running `f`, with `tags` added to the current ones.
+pub fn measure_op_with_tags<T, F>(op: Operation, tags: &[(&str, String)], f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    with_tags(tags, || measure_op(op, f))
+}
+
+#[cfg(feature = "measurements")]
+pub fn measure_op<T, F>(op: Operation, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let cpu_time_start = cpu_time::ProcessTime::now();
+    let wall_start_time = Instant::now();
+
+    #[cfg(feature = "profile")]
+    gperftools::profiler::PROFILER
+        .lock()
+        .unwrap()
+        .start(format!("./{:?}.profile", op))
+        .unwrap();
+    let x = f();
+    #[cfg(feature = "profile")]
+    gperftools::profiler::PROFILER
+        .lock()
+        .unwrap()
+        .stop()
+        .unwrap();
+
+    let measurement = OpMeasurement {
+        op,
+        cpu_time: cpu_time_start.elapsed(),
+        wall_time: wall_start_time.elapsed(),
+        tags: current_tags(),
+    };
+
+    if let Some(sink) = measurement_sink() {
+        record(&*sink, &measurement);
+    }
+
+    let opt_tx = OP_MEASUREMENTS
+        .0
+        .lock()
+        .expect("acquire lock on tx side of perf channel");
+
+    if let Some(tx) = opt_tx.as_ref() {
+        tx.clone()
+            .send(measurement)
+            .expect("failed to send to perf channel");
+    }
+
+    x
+}
+
+#[cfg(not(feature = "measurements"))]
+pub fn measure_op<T, F>(op: Operation, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let sink = match measurement_sink() {
+        Some(sink) => sink,
+        None => return f(),
+    };
+
+    let wall_start_time = Instant::now();
+    let x = f();
+
+    record(
+        &*sink,
+        &OpMeasurement {
+            op,
+            cpu_time: Duration::default(),
+            wall_time: wall_start_time.elapsed(),
+            tags: current_tags(),
+        },
+    );
+
+    x
+}
+
+/// A failing sink must not fail the measured operation, so errors are only logged.
+fn record(sink: &dyn MeasurementSink, measurement: &OpMeasurement) {
+    if let Err(err) = sink.record(measurement) {
+        warn!(
+            "failed to record measurement of {}: {:#}",
+            measurement.op, err
+        );
+    }
+}
+
+/// Wraps the circuit of a partition, so that its synthesis is measured as
+/// `Operation::CircuitSynthesis` with the tags of the thread which created it.
+pub struct MeasuredCircuit<C> {
+    pub circuit: C,
+    pub partition: usize,
+    pub tags: Tags,
+}
+
+impl<C> MeasuredCircuit<C> {
+    /// Wraps `circuit`, capturing the tags of the current thread.
+    pub fn new(circuit: C, partition: usize) -> Self {
+        MeasuredCircuit {
+            circuit,
+            partition,
+            tags: current_tags(),
+        }
+    }
+}
+
+impl<C: Circuit<Bls12>> Circuit<Bls12> for MeasuredCircuit<C> {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let MeasuredCircuit {
+            circuit,
+            partition,
+            tags,
+        } = self;
+
+        with_tag_map(tags, || {
+            measure_op_with_tags(
+                Operation::CircuitSynthesis,
+                &[("partition", partition.to_string())],
+                || circuit.synthesize(cs),
+            )
+        })
+    }
+}
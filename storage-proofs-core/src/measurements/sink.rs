@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use log::warn;
+
+use crate::error::Result;
+use crate::measurements::{OpMeasurement, Operation, Tags};
+
+/// Receives every measurement taken by `measure_op`, see `set_measurement_sink`.
+///
+/// Measurements are recorded from whichever thread ran the operation.
+pub trait MeasurementSink: Send + Sync {
+    fn record(&self, measurement: &OpMeasurement) -> Result<()>;
+}
+
+/// Keeps all measurements in memory.
+#[derive(Debug, Default)]
+pub struct CollectorSink {
+    measurements: Mutex<Vec<OpMeasurement>>,
+}
+
+impl CollectorSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of the measurements collected so far.
+    pub fn measurements(&self) -> Vec<OpMeasurement> {
+        self.measurements
+            .lock()
+            .expect("collector lock poisoned")
+            .clone()
+    }
+
+    /// Returns the measurements collected so far, and clears them.
+    pub fn take(&self) -> Vec<OpMeasurement> {
+        mem::take(&mut *self.measurements.lock().expect("collector lock poisoned"))
+    }
+}
+
+impl MeasurementSink for CollectorSink {
+    fn record(&self, measurement: &OpMeasurement) -> Result<()> {
+        self.measurements
+            .lock()
+            .expect("collector lock poisoned")
+            .push(measurement.clone());
+
+        Ok(())
+    }
+}
+
+/// Appends every measurement to a file, as one JSON object per line.
+#[derive(Debug)]
+pub struct JsonLinesSink {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl JsonLinesSink {
+    /// Opens `path` for appending, creating it if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("could not open measurements file {:?}", path))?;
+
+        Ok(JsonLinesSink {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+}
+
+impl MeasurementSink for JsonLinesSink {
+    fn record(&self, measurement: &OpMeasurement) -> Result<()> {
+        let mut line = serde_json::to_vec(measurement)?;
+        line.push(b'\n');
+
+        let mut writer = self.writer.lock().expect("json lines lock poisoned");
+        writer.write_all(&line)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Totals {
+    count: u64,
+    cpu_seconds: f64,
+    wall_seconds: f64,
+}
+
+/// The tags left out of the Prometheus labels, as they take a new value for every sector and
+/// would create an unbounded number of series.
+const UNBOUNDED_TAGS: &[&str] = &["sector_id"];
+
+#[derive(Debug)]
+struct PrometheusState {
+    totals: BTreeMap<(Operation, Tags), Totals>,
+    last_flush: Instant,
+    dirty: bool,
+}
+
+/// Accumulates the measurements per operation and tags in memory, and periodically writes the
+/// totals to a file in the Prometheus text exposition format, e.g. for the node exporter's
+/// textfile collector.
+///
+/// The file is rewritten at most once per flush interval, when a measurement is recorded, and
+/// on `flush` or drop. A sink installed with `set_measurement_sink` is only dropped when it is
+/// replaced, so call `flush` before the process exits to export the last measurements.
+///
+/// Tags which identify a sector are not exported as labels, see `UNBOUNDED_TAGS`. The CPU time
+/// series is only exported with the `measurements` feature, which measures it.
+#[derive(Debug)]
+pub struct PrometheusSink {
+    path: PathBuf,
+    flush_interval: Duration,
+    state: Mutex<PrometheusState>,
+}
+
+impl PrometheusSink {
+    /// The default interval between two writes of the metrics file.
+    pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(15);
+
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self::with_flush_interval(path, Self::DEFAULT_FLUSH_INTERVAL)
+    }
+
+    /// Creates a sink writing the metrics file at most once per `flush_interval`.
+    pub fn with_flush_interval<P: AsRef<Path>>(path: P, flush_interval: Duration) -> Self {
+        PrometheusSink {
+            path: path.as_ref().to_path_buf(),
+            flush_interval,
+            state: Mutex::new(PrometheusState {
+                totals: Default::default(),
+                last_flush: Instant::now(),
+                dirty: false,
+            }),
+        }
+    }
+
+    /// Writes the totals recorded so far to the metrics file, if they changed since the last
+    /// write.
+    pub fn flush(&self) -> Result<()> {
+        let mut state = self.state.lock().expect("prometheus lock poisoned");
+        self.write(&mut state)
+    }
+
+    fn write(&self, state: &mut PrometheusState) -> Result<()> {
+        state.last_flush = Instant::now();
+        if !state.dirty {
+            return Ok(());
+        }
+
+        // Replace the file atomically, so that readers never see a partial update.
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, Self::render(&state.totals))
+            .with_context(|| format!("could not write {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("could not rename {:?}", tmp_path))?;
+        state.dirty = false;
+
+        Ok(())
+    }
+
+    fn render(totals: &BTreeMap<(Operation, Tags), Totals>) -> String {
+        let mut metrics: Vec<(&str, &str, &str, fn(&Totals) -> String)> = vec![
+            (
+                "fil_proofs_operations_total",
+                "Number of completed operations.",
+                "counter",
+                |totals| totals.count.to_string(),
+            ),
+            (
+                "fil_proofs_operation_wall_seconds_total",
+                "Wall clock time spent in operations.",
+                "counter",
+                |totals| totals.wall_seconds.to_string(),
+            ),
+        ];
+        // Without the `measurements` feature the CPU time is not measured, and always zero.
+        if cfg!(feature = "measurements") {
+            metrics.push((
+                "fil_proofs_operation_cpu_seconds_total",
+                "Process CPU time spent in operations.",
+                "counter",
+                |totals| totals.cpu_seconds.to_string(),
+            ));
+        }
+
+        let mut out = String::new();
+        for (name, help, typ, value) in &metrics {
+            // Writing into a String cannot fail.
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, typ);
+            for ((op, tags), op_totals) in totals {
+                let _ = writeln!(
+                    out,
+                    "{}{{{}}} {}",
+                    name,
+                    labels(*op, tags),
+                    value(op_totals)
+                );
+            }
+        }
+
+        out
+    }
+}
+
+impl MeasurementSink for PrometheusSink {
+    fn record(&self, measurement: &OpMeasurement) -> Result<()> {
+        let mut state = self.state.lock().expect("prometheus lock poisoned");
+        let tags = measurement
+            .tags
+            .iter()
+            .filter(|(key, _)| !UNBOUNDED_TAGS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        let entry = state.totals.entry((measurement.op, tags)).or_default();
+        entry.count += 1;
+        entry.cpu_seconds += measurement.cpu_time.as_secs_f64();
+        entry.wall_seconds += measurement.wall_time.as_secs_f64();
+        state.dirty = true;
+
+        if state.last_flush.elapsed() >= self.flush_interval {
+            self.write(&mut state)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for PrometheusSink {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            warn!("failed to flush prometheus metrics: {:?}", err);
+        }
+    }
+}
+
+fn labels(op: Operation, tags: &Tags) -> String {
+    let mut labels = format!("op=\"{}\"", op);
+    for (key, value) in tags {
+        let key: String = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let value = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        labels.push_str(&format!(",{}=\"{}\"", key, value));
+    }
+
+    labels
+}
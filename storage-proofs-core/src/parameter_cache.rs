@@ -19,7 +19,7 @@ use sha2::{Digest, Sha256};
 
 use crate::{
     error::{Error, Result},
-    settings::SETTINGS,
+    settings,
 };
 
 /// Bump this when circuits change to invalidate the cache.
@@ -155,7 +155,7 @@ impl Drop for LockedFile {
 }
 
 pub fn parameter_cache_dir_name() -> String {
-    SETTINGS.parameter_cache.clone()
+    settings::current().parameter_cache.clone()
 }
 
 pub fn parameter_cache_dir() -> PathBuf {
@@ -398,7 +398,7 @@ fn ensure_parent(path: &Path) -> io::Result<()> {
 pub fn read_cached_params(cache_entry_path: &Path) -> Result<groth16::MappedParameters<Bls12>> {
     info!("checking cache_path: {:?} for parameters", cache_entry_path);
 
-    let verify_production_params = SETTINGS.verify_production_params;
+    let verify_production_params = settings::current().verify_production_params;
 
     // If the verify production params is set, we make sure that the path being accessed matches a
     // production cache key, found in the 'srs-inner-product.json' file. The parameter data file is
@@ -481,7 +481,7 @@ fn read_cached_verifying_key(cache_entry_path: &Path) -> io::Result<groth16::Ver
 fn read_cached_srs_key(cache_entry_path: &Path) -> Result<groth16::aggregate::GenericSRS<Bls12>> {
     info!("checking cache_path: {:?} for srs", cache_entry_path);
 
-    let verify_production_params = SETTINGS.verify_production_params;
+    let verify_production_params = settings::current().verify_production_params;
 
     // If the verify production params is set, we make sure that the path being accessed matches a
     // production cache key, found in the 'parameters.json' file. The parameter data file is also
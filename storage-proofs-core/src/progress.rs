@@ -0,0 +1,44 @@
+use crate::cache_key::CacheKey;
+
+/// A structured progress report emitted while sealing or proving.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// Labeling of `layer` (starting at 1) out of `layers` started.
+    LayerStarted { layer: usize, layers: usize },
+    /// `nodes` out of `total` nodes of `layer` have been labeled so far.
+    NodesLabeled {
+        layer: usize,
+        nodes: u64,
+        total: u64,
+    },
+    /// Base tree `batch` (starting at 0) out of `batches` of `tree` has been written.
+    TreeBatchFinished {
+        tree: CacheKey,
+        batch: usize,
+        batches: usize,
+    },
+    /// The circuit proof of `partition` (starting at 0) out of `partitions` is done.
+    PartitionProved { partition: usize, partitions: usize },
+}
+
+/// Receives progress events from the sealing and proving pipelines.
+///
+/// Events may be emitted from worker threads, so implementations should return quickly and
+/// must not block on the pipeline itself.
+pub trait ProgressObserver: Send + Sync {
+    fn on_event(&self, event: ProgressEvent);
+}
+
+/// A `ProgressObserver` ignoring all events.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {
+    fn on_event(&self, _event: ProgressEvent) {}
+}
+
+impl<F: Fn(ProgressEvent) + Send + Sync> ProgressObserver for F {
+    fn on_event(&self, event: ProgressEvent) {
+        self(event)
+    }
+}
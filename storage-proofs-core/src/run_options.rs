@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use crate::{
+    cancel::CancellationToken,
+    progress::{NoProgress, ProgressObserver},
+    settings::{enter_config, ConfigGuard, ProofsConfig},
+};
+
+/// Options of a long running sealing or proving call.
+///
+/// The default options never cancel, ignore all progress events, prove all partitions in a
+/// single batch and use the settings currently in effect.
+#[derive(Clone)]
+pub struct RunOptions<'a> {
+    /// Once cancelled, the call returns `Error::Cancelled` at its next check.
+    pub cancel: CancellationToken,
+    /// Receives the progress events of the call.
+    pub progress: &'a dyn ProgressObserver,
+    /// The number of partitions whose circuit proofs are generated at once. The
+    /// `PartitionProved` events of a batch are emitted as soon as it is done, so smaller batches
+    /// report progress sooner, at the cost of synthesizing fewer circuits in parallel. `None`
+    /// proves all partitions in one batch.
+    pub partition_batch_size: Option<usize>,
+    /// The settings used for the call instead of the ones currently in effect, see
+    /// `settings::with_config`. `None` keeps the current settings.
+    pub config: Option<Arc<ProofsConfig>>,
+}
+
+impl Default for RunOptions<'_> {
+    fn default() -> Self {
+        RunOptions {
+            cancel: CancellationToken::default(),
+            progress: &NoProgress,
+            partition_batch_size: None,
+            config: None,
+        }
+    }
+}
+
+impl RunOptions<'_> {
+    /// Puts the `config` of these options in effect on the current thread until the returned
+    /// guard is dropped.
+    pub fn enter_config(&self) -> ConfigGuard {
+        enter_config(self.config.as_ref())
+    }
+}
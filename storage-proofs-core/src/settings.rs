@@ -1,17 +1,28 @@
+use std::cell::RefCell;
 use std::env;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Arc;
 
+use bellperson::{bls::Bls12, Circuit, ConstraintSystem, SynthesisError};
 use config::{Config, ConfigError, Environment, File};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 
 lazy_static! {
-    pub static ref SETTINGS: Settings = Settings::new().expect("invalid configuration");
+    /// The process wide settings, loaded from the config file and the environment.
+    pub static ref SETTINGS: Arc<Settings> =
+        Arc::new(Settings::new().expect("invalid configuration"));
+}
+
+thread_local! {
+    static CONFIG: RefCell<Option<Arc<ProofsConfig>>> = RefCell::new(None);
 }
 
 const SETTINGS_PATH: &str = "./rust-fil-proofs.config.toml";
 const PREFIX: &str = "FIL_PROOFS";
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Settings {
     pub verify_cache: bool,
@@ -30,6 +41,7 @@ pub struct Settings {
     pub multicore_sdr_producers: usize,
     pub multicore_sdr_producer_stride: u64,
     pub multicore_sdr_lookahead: usize,
+    pub sdr_parents_on_the_fly: bool,
 }
 
 impl Default for Settings {
@@ -54,6 +66,7 @@ impl Default for Settings {
             multicore_sdr_producers: 3,
             multicore_sdr_producer_stride: 128,
             multicore_sdr_lookahead: 800,
+            sdr_parents_on_the_fly: false,
         }
     }
 }
@@ -77,4 +90,97 @@ impl Settings {
 
         s.try_into()
     }
+
+    /// Loads the settings from the given toml file only, using the defaults for all missing
+    /// values. Neither `rust-fil-proofs.config.toml` nor the environment are consulted.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Settings, ConfigError> {
+        let mut s = Config::new();
+
+        s.merge(File::from(path.as_ref()).required(true))?;
+
+        s.try_into()
+    }
+}
+
+/// Settings which are scoped to a call, see `with_config`.
+///
+/// This allows a single process to work with different settings at the same time, e.g. to seal
+/// sectors with different `rows_to_discard` or parent cache locations. The core groups used by
+/// multicore SDR are sized once per process, using the global `multicore_sdr_producers`, so
+/// multicore SDR rejects a config setting a different value.
+pub type ProofsConfig = Settings;
+
+/// Returns the settings in effect on the current thread: the config set by `with_config`, or
+/// the global `SETTINGS` if there is none.
+pub fn current() -> Arc<ProofsConfig> {
+    CONFIG
+        .with(|config| config.borrow().clone())
+        .unwrap_or_else(|| SETTINGS.clone())
+}
+
+/// Runs `f` with `config` in effect on the current thread. `None` keeps the settings which are
+/// currently in effect.
+///
+/// Work spawned onto other threads does not inherit the config, so code spawning threads must
+/// pass `current()` on explicitly.
+pub fn with_config<T, F>(config: Option<&Arc<ProofsConfig>>, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let _config = enter_config(config);
+
+    f()
+}
+
+/// Puts `config` in effect on the current thread until the returned guard is dropped, like
+/// `with_config` does for the duration of a closure. `None` keeps the settings which are
+/// currently in effect.
+pub fn enter_config(config: Option<&Arc<ProofsConfig>>) -> ConfigGuard {
+    let previous =
+        config.map(|config| CONFIG.with(|current| current.replace(Some(config.clone()))));
+
+    ConfigGuard {
+        previous,
+        _not_send: PhantomData,
+    }
+}
+
+/// Restores the settings which were in effect before `enter_config` when dropped.
+#[must_use]
+pub struct ConfigGuard {
+    previous: Option<Option<Arc<ProofsConfig>>>,
+    // The config is thread local, so the guard must be dropped on the thread it was created on.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ConfigGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            CONFIG.with(|config| *config.borrow_mut() = previous);
+        }
+    }
+}
+
+/// Wraps a circuit, so that it is synthesized with the config which was in effect when it was
+/// created, independent of the thread doing the synthesis.
+pub struct ConfiguredCircuit<C> {
+    pub circuit: C,
+    pub config: Arc<ProofsConfig>,
+}
+
+impl<C> ConfiguredCircuit<C> {
+    pub fn new(circuit: C) -> Self {
+        ConfiguredCircuit {
+            circuit,
+            config: current(),
+        }
+    }
+}
+
+impl<C: Circuit<Bls12>> Circuit<Bls12> for ConfiguredCircuit<C> {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let ConfiguredCircuit { circuit, config } = self;
+
+        with_config(Some(&config), || circuit.synthesize(cs))
+    }
 }
@@ -0,0 +1,163 @@
+use std::fs;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use storage_proofs_core::measurements::{
+    current_tags, measure_op, measure_op_with_tags, set_measurement_sink, with_tags, CollectorSink,
+    JsonLinesSink, MeasurementSink, OpMeasurement, Operation, PrometheusSink,
+};
+use tempfile::tempdir;
+
+lazy_static! {
+    /// Serializes the tests installing the process global measurement sink.
+    static ref GLOBAL_SINK: Mutex<()> = Mutex::new(());
+}
+
+/// Installs `sink` as the global measurement sink until the returned guard is dropped.
+struct SinkGuard(MutexGuard<'static, ()>);
+
+impl SinkGuard {
+    fn install(sink: Arc<dyn MeasurementSink>) -> Self {
+        let lock = GLOBAL_SINK.lock().unwrap_or_else(|err| err.into_inner());
+        set_measurement_sink(Some(sink));
+        SinkGuard(lock)
+    }
+}
+
+impl Drop for SinkGuard {
+    fn drop(&mut self) {
+        set_measurement_sink(None);
+    }
+}
+
+fn measurement(op: Operation, tags: &[(&str, &str)]) -> OpMeasurement {
+    OpMeasurement {
+        op,
+        cpu_time: Duration::from_millis(500),
+        wall_time: Duration::from_secs(2),
+        tags: tags
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+    }
+}
+
+#[test]
+fn test_measurements_collector_sink_tags() {
+    let sink = Arc::new(CollectorSink::new());
+    let guard = SinkGuard::install(sink.clone());
+
+    let value = with_tags(&[("sector_id", "7".to_string())], || {
+        measure_op_with_tags(Operation::LabelLayer, &[("layer", "2".to_string())], || {
+            measure_op(Operation::VanillaProofs, || 42)
+        })
+    });
+    assert_eq!(value, 42);
+    assert!(current_tags().is_empty(), "tags must not leak");
+
+    drop(guard);
+    measure_op(Operation::SnarkProve, || ());
+
+    let measurements = sink.take();
+    assert_eq!(measurements.len(), 2);
+
+    // The inner operation finishes first, and inherits the tags of the outer ones.
+    let inner = &measurements[0];
+    assert_eq!(inner.op, Operation::VanillaProofs);
+    assert_eq!(inner.tags.get("sector_id").map(String::as_str), Some("7"));
+    assert_eq!(inner.tags.get("layer").map(String::as_str), Some("2"));
+
+    let outer = &measurements[1];
+    assert_eq!(outer.op, Operation::LabelLayer);
+    assert_eq!(outer.tags.len(), 2);
+    assert!(outer.wall_time >= inner.wall_time);
+
+    assert!(sink.measurements().is_empty());
+}
+
+#[test]
+fn test_measurements_json_lines_sink() {
+    let dir = tempdir().expect("tempdir failure");
+    let path = dir.path().join("measurements.jsonl");
+
+    let sink = JsonLinesSink::open(&path).expect("failed to open sink");
+    sink.record(&measurement(
+        Operation::GenerateTreeC,
+        &[("sector_id", "1")],
+    ))
+    .expect("failed to record");
+    sink.record(&measurement(Operation::ColumnHashing, &[("tree", "0")]))
+        .expect("failed to record");
+
+    let contents = fs::read_to_string(&path).expect("failed to read measurements");
+    let lines: Vec<serde_json::Value> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("invalid json line"))
+        .collect();
+    assert_eq!(lines.len(), 2);
+
+    assert_eq!(lines[0]["op"], "generate-tree-c");
+    assert_eq!(lines[0]["tags"]["sector_id"], "1");
+    assert_eq!(lines[0]["wall-time"]["secs"], 2);
+    assert_eq!(lines[1]["op"], "column-hashing");
+    assert_eq!(lines[1]["tags"]["tree"], "0");
+}
+
+#[test]
+fn test_measurements_prometheus_sink() {
+    let dir = tempdir().expect("tempdir failure");
+    let path = dir.path().join("proofs.prom");
+
+    let sink = PrometheusSink::with_flush_interval(&path, Duration::from_secs(3600));
+    let tags = [("sector_id", "3"), ("note", "a \"quoted\" value")];
+    sink.record(&measurement(Operation::SnarkProve, &tags))
+        .expect("failed to record");
+    sink.record(&measurement(
+        Operation::SnarkProve,
+        &[("sector_id", "4"), ("note", "a \"quoted\" value")],
+    ))
+    .expect("failed to record");
+    sink.record(&measurement(Operation::LabelLayer, &[]))
+        .expect("failed to record");
+
+    // Nothing is written before the flush interval elapses.
+    assert!(!path.exists());
+    sink.flush().expect("failed to flush");
+
+    let contents = fs::read_to_string(&path).expect("failed to read metrics");
+    // The sector ids are aggregated over, rather than exported as labels.
+    let labels = r#"op="snark-prove",note="a \"quoted\" value""#;
+
+    assert!(!contents.contains("sector_id"));
+    assert!(contents.contains("# TYPE fil_proofs_operations_total counter"));
+    assert!(contents.contains(&format!("fil_proofs_operations_total{{{}}} 2", labels)));
+    assert!(contents.contains(&format!(
+        "fil_proofs_operation_wall_seconds_total{{{}}} 4",
+        labels
+    )));
+    // The CPU time is only measured with the `measurements` feature.
+    if cfg!(feature = "measurements") {
+        assert!(contents.contains(&format!(
+            "fil_proofs_operation_cpu_seconds_total{{{}}} 1",
+            labels
+        )));
+    } else {
+        assert!(!contents.contains("fil_proofs_operation_cpu_seconds_total"));
+    }
+    assert!(contents.contains(r#"fil_proofs_operations_total{op="label-layer"} 1"#));
+
+    // Dropping the sink writes the pending totals.
+    sink.record(&measurement(Operation::LabelLayer, &[]))
+        .expect("failed to record");
+    drop(sink);
+    let contents = fs::read_to_string(&path).expect("failed to read metrics");
+    assert!(contents.contains(r#"fil_proofs_operations_total{op="label-layer"} 2"#));
+
+    // A zero interval writes on every measurement.
+    let sink = PrometheusSink::with_flush_interval(&path, Duration::from_secs(0));
+    sink.record(&measurement(Operation::LabelLayer, &[]))
+        .expect("failed to record");
+    let contents = fs::read_to_string(&path).expect("failed to read metrics");
+    assert!(contents.contains(r#"fil_proofs_operations_total{op="label-layer"} 1"#));
+}
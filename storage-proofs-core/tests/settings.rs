@@ -0,0 +1,89 @@
+use std::fs;
+use std::sync::Arc;
+use std::thread;
+
+use storage_proofs_core::{
+    run_options::RunOptions,
+    settings::{self, with_config, ProofsConfig, SETTINGS},
+    util::default_rows_to_discard,
+};
+use tempfile::tempdir;
+
+// An oct tree with 8^5 leafs has 6 rows, so up to 4 of them can be discarded.
+const LEAFS: usize = 32_768;
+const ARITY: usize = 8;
+
+fn config(rows_to_discard: u32) -> Arc<ProofsConfig> {
+    Arc::new(ProofsConfig {
+        rows_to_discard,
+        ..(**SETTINGS).clone()
+    })
+}
+
+#[test]
+fn test_settings_with_config() {
+    let global = default_rows_to_discard(LEAFS, ARITY);
+    assert_eq!(global, (SETTINGS.rows_to_discard as usize).min(4));
+
+    let three = config(3);
+    let one = config(1);
+    with_config(Some(&three), || {
+        assert_eq!(settings::current().rows_to_discard, 3);
+        assert_eq!(default_rows_to_discard(LEAFS, ARITY), 3);
+
+        with_config(None, || {
+            assert_eq!(default_rows_to_discard(LEAFS, ARITY), 3)
+        });
+        with_config(Some(&one), || {
+            assert_eq!(default_rows_to_discard(LEAFS, ARITY), 1)
+        });
+        assert_eq!(default_rows_to_discard(LEAFS, ARITY), 3);
+
+        // Other threads are not affected.
+        let other = thread::spawn(|| default_rows_to_discard(LEAFS, ARITY))
+            .join()
+            .expect("thread failed");
+        assert_eq!(other, global);
+    });
+
+    assert_eq!(default_rows_to_discard(LEAFS, ARITY), global);
+    assert_eq!(settings::current(), *SETTINGS);
+}
+
+#[test]
+fn test_run_options_config() {
+    let options = RunOptions {
+        config: Some(config(3)),
+        ..Default::default()
+    };
+    {
+        let _config = options.enter_config();
+        assert_eq!(settings::current().rows_to_discard, 3);
+
+        let _none = RunOptions::default().enter_config();
+        assert_eq!(settings::current().rows_to_discard, 3);
+    }
+
+    assert_eq!(settings::current(), *SETTINGS);
+}
+
+#[test]
+fn test_settings_from_file() {
+    let dir = tempdir().expect("tempdir failure");
+    let path = dir.path().join("tenant.config.toml");
+    fs::write(
+        &path,
+        "rows_to_discard = 4\nparent_cache = \"/tmp/tenant-parents\"\n",
+    )
+    .expect("failed to write config");
+
+    let config = ProofsConfig::from_file(&path).expect("failed to load config");
+    assert_eq!(config.rows_to_discard, 4);
+    assert_eq!(config.parent_cache, "/tmp/tenant-parents");
+    assert_eq!(
+        config.multicore_sdr_lookahead,
+        ProofsConfig::default().multicore_sdr_lookahead
+    );
+
+    assert!(ProofsConfig::from_file(dir.path().join("missing.toml")).is_err());
+}
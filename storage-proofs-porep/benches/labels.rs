@@ -0,0 +1,65 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use filecoin_hashers::poseidon::PoseidonHasher;
+use generic_array::typenum::{U0, U8};
+use merkletree::store::StoreConfig;
+use storage_proofs_core::{
+    api_version::ApiVersion, cache_key::CacheKey, drgraph::BASE_DEGREE, merkle::LCTree,
+    util::NODE_SIZE,
+};
+use storage_proofs_porep::stacked::{
+    create_label::multi::create_labels_for_decoding, StackedBucketGraph, EXP_DEGREE,
+};
+use tempfile::tempdir;
+
+type Tree = LCTree<PoseidonHasher, U8, U0, U0>;
+
+/// Compares multicore labeling with the parents read from the mmapped parent cache against
+/// the parents computed on the fly by the producer threads.
+fn labels_benchmark(c: &mut Criterion) {
+    let nodes = 1 << 15;
+    let layers = 2;
+    let replica_id = [9u8; 32];
+
+    let graph = StackedBucketGraph::<PoseidonHasher>::new_stacked(
+        nodes,
+        BASE_DEGREE,
+        EXP_DEGREE,
+        [32; 32],
+        ApiVersion::V1_1_0,
+    )
+    .unwrap();
+    let parent_cache = graph.parent_cache().unwrap();
+
+    let mut group = c.benchmark_group("multicore labels");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes((nodes * NODE_SIZE * layers) as u64));
+
+    for (name, parent_cache) in &[("cache", Some(&parent_cache)), ("on-the-fly", None)] {
+        group.bench_function(*name, |b| {
+            let cache_dir = tempdir().unwrap();
+            let config = StoreConfig::new(
+                cache_dir.path(),
+                CacheKey::CommDTree.to_string(),
+                nodes.trailing_zeros() as usize,
+            );
+
+            b.iter(|| {
+                black_box(
+                    create_labels_for_decoding::<Tree, _>(
+                        &graph,
+                        *parent_cache,
+                        layers,
+                        replica_id,
+                        config.clone(),
+                    )
+                    .unwrap(),
+                )
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, labels_benchmark);
+criterion_main!(benches);
@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
-use std::fs::{remove_file, File};
+use std::fmt;
+use std::fs::{self, remove_file, File};
 use std::io;
 use std::path::{Path, PathBuf};
 
@@ -16,7 +17,7 @@ use storage_proofs_core::{
     drgraph::{Graph, BASE_DEGREE},
     error::Result,
     parameter_cache::{with_exclusive_lock, LockedFile, ParameterSetMetadata, VERSION},
-    settings::SETTINGS,
+    settings,
     util::NODE_SIZE,
 };
 
@@ -40,6 +41,62 @@ lazy_static! {
         serde_json::from_str(PARENT_CACHE_DATA).expect("Invalid parent_cache.json");
 }
 
+/// A parent cache file found in the parent cache directory, see `list_parent_caches`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParentCacheInfo {
+    pub path: PathBuf,
+    /// The file stem, which is the key of the cache in the parent cache manifest.
+    pub id: String,
+    /// The size of the file in bytes.
+    pub len: u64,
+    /// The sector size of the cache, if it is part of the parent cache manifest.
+    pub sector_size: Option<u64>,
+}
+
+/// The outcome of checking a parent cache file, see `verify_parent_cache`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParentCacheStatus {
+    /// The digest of the cache matches the parent cache manifest.
+    Valid,
+    /// The cache is not part of the parent cache manifest, e.g. it belongs to a test sector
+    /// size, so only its digest can be reported.
+    Unknown {
+        digest: String,
+    },
+    Missing,
+    /// The cache could not be read or has an invalid size.
+    Corrupt(String),
+    /// The digest of the cache does not match the parent cache manifest.
+    Mismatch {
+        expected: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for ParentCacheStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParentCacheStatus::Valid => write!(f, "valid"),
+            ParentCacheStatus::Unknown { digest } => write!(f, "unknown (digest {})", digest),
+            ParentCacheStatus::Missing => write!(f, "missing"),
+            ParentCacheStatus::Corrupt(reason) => write!(f, "corrupt: {}", reason),
+            ParentCacheStatus::Mismatch { expected, actual } => {
+                write!(f, "mismatch: expected {}, got {}", expected, actual)
+            }
+        }
+    }
+}
+
+impl ParentCacheStatus {
+    /// Returns false if the cache is known to be missing or invalid.
+    pub fn is_usable(&self) -> bool {
+        matches!(
+            self,
+            ParentCacheStatus::Valid | ParentCacheStatus::Unknown { .. }
+        )
+    }
+}
+
 // StackedGraph will hold two different (but related) `ParentCache`,
 #[derive(Debug)]
 pub struct ParentCache {
@@ -200,14 +257,14 @@ impl ParentCache {
 
                     (
                         None,
-                        SETTINGS.verify_cache,
+                        settings::current().verify_cache,
                         false, // not production since not in manifest
                         "".to_string(),
                     )
                 }
                 Some(pcd) => (
                     Some(pcd),
-                    SETTINGS.verify_cache,
+                    settings::current().verify_cache,
                     true, // is_production since it exists in the manifest
                     pcd.digest.clone(),
                 ),
@@ -219,22 +276,30 @@ impl ParentCache {
             verify_cache
         );
 
+        // A truncated or overlong cache can be detected without reading it, so it is always
+        // replaced rather than being used for labeling. The shared lock waits for a concurrent
+        // generation of the cache to finish.
+        let expected_len = cache_len(cache_entries);
+        let actual_len = LockedFile::open_shared_read(path)
+            .with_context(|| format!("could not open path={}", path.display()))?
+            .as_ref()
+            .metadata()?
+            .len();
+        if actual_len != expected_len {
+            info!(
+                "[!!!] Parent cache size mismatch detected, expected {}, got {} bytes.  Regenerating {}",
+                expected_len,
+                actual_len,
+                path.display()
+            );
+            return Self::regenerate(len, cache_entries, graph, path);
+        }
+
         if verify_cache {
             // Always check all of the data for integrity checks, even
             // if we're only opening a portion of it.
-            let mut hasher = Sha256::new();
             info!("[open] parent cache: calculating consistency digest");
-            let file = File::open(&path)?;
-            let data = unsafe {
-                MmapOptions::new()
-                    .map(&file)
-                    .with_context(|| format!("could not mmap path={}", path.display()))?
-            };
-            hasher.update(&data);
-            drop(data);
-
-            let hash = hasher.finalize();
-            digest_hex = hash.iter().map(|x| format!("{:01$x}", x, 2)).collect();
+            digest_hex = file_digest(path)?;
 
             info!(
                 "[open] parent cache: calculated consistency digest: {:?}",
@@ -258,10 +323,8 @@ impl ParentCache {
                         "[!!!] Parent cache digest mismatch detected.  Regenerating {}",
                         path.display()
                     );
-                    // delete invalid cache
-                    remove_file(path)?;
                     ensure!(
-                        Self::generate(len, graph.size() as u32, graph, path).is_ok(),
+                        Self::regenerate(len, graph.size() as u32, graph, path).is_ok(),
                         "Failed to generate parent cache"
                     );
 
@@ -296,9 +359,9 @@ impl ParentCache {
         let sector_size = graph.size() * NODE_SIZE;
 
         with_exclusive_lock(&path.to_path_buf(), |file| {
-            let cache_size = cache_entries as usize * NODE_BYTES * DEGREE;
+            let cache_size = cache_len(cache_entries);
             file.as_ref()
-                .set_len(cache_size as u64)
+                .set_len(cache_size)
                 .with_context(|| format!("failed to set length: {}", cache_size))?;
 
             let mut data = unsafe {
@@ -324,10 +387,7 @@ impl ParentCache {
             data.flush().context("failed to flush parent cache")?;
 
             info!("[generate] parent cache: generating consistency digest");
-            let mut hasher = Sha256::new();
-            hasher.update(&data);
-            let hash = hasher.finalize();
-            digest_hex = hash.iter().map(|x| format!("{:01$x}", x, 2)).collect();
+            digest_hex = digest(&data);
             info!(
                 "[generate] parent cache: generated consistency digest: {:?}",
                 digest_hex
@@ -363,6 +423,37 @@ impl ParentCache {
         })
     }
 
+    /// Removes the cache at `path`, if present, and generates it again.
+    ///
+    /// The existing cache is only removed once an exclusive lock on it is held, so a concurrent
+    /// generation or check of it finishes first.
+    pub fn regenerate<H, G>(
+        len: u32,
+        cache_entries: u32,
+        graph: &StackedGraph<H, G>,
+        path: &Path,
+    ) -> Result<Self>
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Send + Sync,
+    {
+        // The lock on the removed cache is held until the new one is generated.
+        let _lock = match LockedFile::open_exclusive_read(path) {
+            Ok(file) => {
+                info!("parent cache: removing {}", path.display());
+                remove_file(path)
+                    .with_context(|| format!("could not remove path={}", path.display()))?;
+                Some(file)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => {
+                return Err(err).with_context(|| format!("could not lock path={}", path.display()))
+            }
+        };
+
+        Self::generate(len, cache_entries, graph, path)
+    }
+
     /// Read a single cache element at position `node`.
     pub fn read(&mut self, node: u32) -> Result<[u32; DEGREE]> {
         if self.cache.contains(node) {
@@ -393,7 +484,125 @@ impl ParentCache {
 }
 
 fn parent_cache_dir_name() -> String {
-    SETTINGS.parent_cache.clone()
+    settings::current().parent_cache.clone()
+}
+
+/// The directory holding the parent caches, which can be set with FIL_PROOFS_PARENT_CACHE.
+pub fn parent_cache_dir() -> PathBuf {
+    PathBuf::from(parent_cache_dir_name())
+}
+
+/// Lists the parent cache files in `parent_cache_dir`, of all versions, sorted by path.
+pub fn list_parent_caches() -> Result<Vec<ParentCacheInfo>> {
+    let dir = parent_cache_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut caches = Vec::new();
+    for entry in fs::read_dir(&dir).with_context(|| format!("could not read {}", dir.display()))? {
+        let path = entry?.path();
+        if !is_parent_cache_file(&path) {
+            continue;
+        }
+
+        caches.push(ParentCacheInfo {
+            id: parent_cache_id(&path),
+            len: fs::metadata(&path)?.len(),
+            sector_size: get_parent_cache_data(&path).map(|pcd| pcd.sector_size),
+            path,
+        });
+    }
+    caches.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(caches)
+}
+
+/// Checks the parent cache at `path` against the parent cache manifest.
+///
+/// Caches which are part of the manifest must have the size of their sector size and the
+/// digest recorded in the manifest. The digest of other caches is computed and returned, as
+/// there is nothing to compare it against. Note that computing the digest reads the whole cache.
+pub fn verify_parent_cache(path: &Path) -> Result<ParentCacheStatus> {
+    if !path.exists() {
+        return Ok(ParentCacheStatus::Missing);
+    }
+
+    // Wait for a concurrent generation of the cache to finish, before reading it.
+    let file = match LockedFile::open_shared_read(path) {
+        Ok(file) => file,
+        Err(err) => return Ok(ParentCacheStatus::Corrupt(err.to_string())),
+    };
+
+    let parent_cache_data = get_parent_cache_data(path);
+    let actual_len = file.as_ref().metadata()?.len();
+    if actual_len == 0 {
+        return Ok(ParentCacheStatus::Corrupt("empty file".to_string()));
+    }
+    if let Some(pcd) = parent_cache_data {
+        let expected_len = cache_len((pcd.sector_size / NODE_SIZE as u64) as u32);
+        if actual_len != expected_len {
+            return Ok(ParentCacheStatus::Corrupt(format!(
+                "expected {} bytes, got {}",
+                expected_len, actual_len
+            )));
+        }
+    }
+
+    let data = unsafe {
+        MmapOptions::new()
+            .map(file.as_ref())
+            .with_context(|| format!("could not mmap path={}", path.display()))?
+    };
+    let digest_hex = digest(&data);
+
+    let status = match parent_cache_data {
+        None => ParentCacheStatus::Unknown { digest: digest_hex },
+        Some(pcd) if pcd.digest == digest_hex => ParentCacheStatus::Valid,
+        Some(pcd) => ParentCacheStatus::Mismatch {
+            expected: pcd.digest.clone(),
+            actual: digest_hex,
+        },
+    };
+
+    Ok(status)
+}
+
+/// Returns true if `path` is named like a parent cache, e.g. `v28-sdr-parent-<id>.cache`.
+fn is_parent_cache_file(path: &Path) -> bool {
+    path.is_file()
+        && path.extension().and_then(|ext| ext.to_str()) == Some("cache")
+        && path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .map(|stem| stem.contains("-sdr-parent-"))
+            .unwrap_or(false)
+}
+
+/// The size in bytes of a parent cache with `cache_entries` nodes.
+fn cache_len(cache_entries: u32) -> u64 {
+    cache_entries as u64 * (DEGREE * NODE_BYTES) as u64
+}
+
+/// The hex encoded consistency digest of the parent cache `data`.
+fn digest(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    let hash = hasher.finalize();
+
+    hash.iter().map(|x| format!("{:01$x}", x, 2)).collect()
+}
+
+/// The consistency digest of the parent cache file at `path`.
+fn file_digest(path: &Path) -> Result<String> {
+    let file = File::open(&path)?;
+    let data = unsafe {
+        MmapOptions::new()
+            .map(&file)
+            .with_context(|| format!("could not mmap path={}", path.display()))?
+    };
+
+    Ok(digest(&data))
 }
 
 fn parent_cache_id(path: &Path) -> String {
@@ -410,7 +619,7 @@ fn get_parent_cache_data(path: &Path) -> Option<&ParentCacheData> {
     PARENT_CACHE.get(&parent_cache_id(path))
 }
 
-fn cache_path<H, G>(cache_entries: u32, graph: &StackedGraph<H, G>) -> PathBuf
+pub(crate) fn cache_path<H, G>(cache_entries: u32, graph: &StackedGraph<H, G>) -> PathBuf
 where
     H: Hasher,
     G: Graph<H> + ParameterSetMetadata + Send + Sync,
@@ -525,4 +734,70 @@ mod tests {
             assert_eq!(expected_parents, parents);
         }
     }
+
+    #[test]
+    fn test_verify_and_repair() {
+        let nodes = 32u32;
+        // Use a porep_id not used by the other tests, as the cache is corrupted below.
+        let graph = StackedBucketGraph::<PoseidonHasher>::new_stacked(
+            nodes as usize,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            [7u8; 32],
+            ApiVersion::V1_1_0,
+        )
+        .expect("new_stacked failure");
+        let path = graph.parent_cache_path();
+
+        graph.parent_cache().expect("parent cache failure");
+        assert!(list_parent_caches()
+            .expect("list parent caches failure")
+            .iter()
+            .any(|cache| cache.path == path && cache.len == cache_len(nodes)));
+
+        // Not part of the manifest, so only the digest is known.
+        let status = verify_parent_cache(&path).expect("verify parent cache failure");
+        assert!(status.is_usable());
+        assert!(matches!(status, ParentCacheStatus::Unknown { .. }));
+
+        // A truncated cache is regenerated when opened.
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .expect("open failure")
+            .set_len(cache_len(nodes / 2))
+            .expect("set_len failure");
+        assert_ne!(
+            verify_parent_cache(&path).expect("verify parent cache failure"),
+            status
+        );
+
+        let mut cache = graph.parent_cache().expect("parent cache failure");
+        for node in 0..nodes {
+            let mut expected_parents = [0; DEGREE];
+            graph
+                .parents(node as usize, &mut expected_parents)
+                .expect("graph parents failure");
+            let parents = cache.read(node).expect("cache read failure");
+
+            assert_eq!(expected_parents, parents);
+        }
+        assert_eq!(
+            verify_parent_cache(&path).expect("verify parent cache failure"),
+            status
+        );
+
+        fs::remove_file(&path).expect("remove failure");
+        assert_eq!(
+            verify_parent_cache(&path).expect("verify parent cache failure"),
+            ParentCacheStatus::Missing
+        );
+        graph
+            .regenerate_parent_cache()
+            .expect("regenerate parent cache failure");
+        assert_eq!(
+            verify_parent_cache(&path).expect("verify parent cache failure"),
+            status
+        );
+    }
 }
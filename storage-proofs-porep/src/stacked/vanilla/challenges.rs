@@ -1,26 +1,75 @@
+use std::fmt;
+
 use filecoin_hashers::Domain;
 use num_bigint::BigUint;
 use num_traits::cast::ToPrimitive;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LayerChallenges {
     /// How many layers we are generating challenges for.
     layers: usize,
     /// The maximum count of challenges
     max_count: usize,
+    /// Whether the challenges are derived from CommR instead of the interactive seed.
+    #[serde(default)]
+    non_interactive: bool,
+    /// The number of precomputed synthetic challenges the challenges are selected from, zero
+    /// if the challenges are not synthetic.
+    #[serde(default)]
+    synthetic_count: usize,
 }
 
 impl LayerChallenges {
     pub const fn new(layers: usize, max_count: usize) -> Self {
-        LayerChallenges { layers, max_count }
+        LayerChallenges {
+            layers,
+            max_count,
+            non_interactive: false,
+            synthetic_count: 0,
+        }
+    }
+
+    /// Challenges for the non-interactive PoRep, which are derived from CommR, so that the
+    /// commit proof can be generated right after sealing without waiting for a seed.
+    pub const fn new_non_interactive(layers: usize, max_count: usize) -> Self {
+        LayerChallenges {
+            layers,
+            max_count,
+            non_interactive: true,
+            synthetic_count: 0,
+        }
+    }
+
+    /// Challenges for the synthetic PoRep. `synthetic_count` challenges are derived from CommR
+    /// and proven ahead of time, so that the layers can be discarded before the seed arrives.
+    /// The seed then only selects which of the synthetic challenges are proven.
+    pub const fn new_synthetic(layers: usize, max_count: usize, synthetic_count: usize) -> Self {
+        LayerChallenges {
+            layers,
+            max_count,
+            non_interactive: false,
+            synthetic_count,
+        }
     }
 
     pub fn layers(&self) -> usize {
         self.layers
     }
 
+    pub fn is_non_interactive(&self) -> bool {
+        self.non_interactive
+    }
+
+    pub fn is_synthetic(&self) -> bool {
+        self.synthetic_count > 0
+    }
+
+    pub fn synthetic_count(&self) -> usize {
+        self.synthetic_count
+    }
+
     pub fn challenges_count_all(&self) -> usize {
         self.max_count
     }
@@ -56,20 +105,83 @@ impl LayerChallenges {
                     .chain(&j.to_le_bytes())
                     .finalize();
 
-                let big_challenge = BigUint::from_bytes_le(hash.as_ref());
-
                 // We cannot try to prove the first node, so make sure the challenge
                 // can never be 0.
-                let big_mod_challenge = big_challenge % (leaves - 1);
-                let big_mod_challenge = big_mod_challenge
-                    .to_usize()
-                    .expect("`big_mod_challenge` exceeds size of `usize`");
-                big_mod_challenge + 1
+                hash_mod(hash.as_ref(), leaves - 1) + 1
+            })
+            .collect()
+    }
+
+    /// Derives the synthetic challenge with index `index`, which only depends on the replica
+    /// and CommR.
+    pub fn derive_synthetic<D: Domain>(
+        &self,
+        leaves: usize,
+        replica_id: &D,
+        comm_r: &D,
+        index: usize,
+    ) -> usize {
+        assert!(leaves > 2, "Too few leaves: {}", leaves);
+        assert!(index < self.synthetic_count, "Invalid synthetic index");
+
+        let hash = Sha256::new()
+            .chain(replica_id.into_bytes())
+            .chain(comm_r.into_bytes())
+            .chain(&(index as u64).to_le_bytes())
+            .finalize();
+
+        hash_mod(hash.as_ref(), leaves - 1) + 1
+    }
+
+    /// Derives the indexes of the synthetic challenges proven in partition `k`.
+    pub fn derive_synthetic_indexes<D: Domain>(
+        &self,
+        replica_id: &D,
+        seed: &[u8; 32],
+        k: u8,
+    ) -> Vec<usize> {
+        assert!(self.is_synthetic(), "challenges are not synthetic");
+
+        let challenges_count = self.challenges_count_all();
+        (0..challenges_count)
+            .map(|i| {
+                let j: u32 = ((challenges_count * k as usize) + i) as u32;
+
+                let hash = Sha256::new()
+                    .chain(replica_id.into_bytes())
+                    .chain(seed)
+                    .chain(&j.to_le_bytes())
+                    .finalize();
+
+                hash_mod(hash.as_ref(), self.synthetic_count)
             })
             .collect()
     }
 }
 
+fn hash_mod(hash: &[u8], modulus: usize) -> usize {
+    let big_mod = BigUint::from_bytes_le(hash) % modulus;
+    big_mod
+        .to_usize()
+        .expect("`big_mod` exceeds size of `usize`")
+}
+
+// The debug output is part of the parameter identifiers, so it must not change for the
+// interactive challenges. Synthetic challenges are proven by the interactive circuit and share
+// its parameters, so they are not part of the output either.
+impl fmt::Debug for LayerChallenges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("LayerChallenges");
+        debug
+            .field("layers", &self.layers)
+            .field("max_count", &self.max_count);
+        if self.non_interactive {
+            debug.field("non_interactive", &self.non_interactive);
+        }
+        debug.finish()
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ChallengeRequirements {
     pub minimum_challenges: usize,
@@ -93,6 +205,23 @@ mod test {
         assert_eq!(expected as usize, calculated_count);
     }
 
+    #[test]
+    fn test_layer_challenges_debug() {
+        // Changing the output for interactive challenges would change the parameter identifiers.
+        assert_eq!(
+            format!("{:?}", LayerChallenges::new(11, 18)),
+            "LayerChallenges { layers: 11, max_count: 18 }"
+        );
+        assert_eq!(
+            format!("{:?}", LayerChallenges::new_non_interactive(11, 18)),
+            "LayerChallenges { layers: 11, max_count: 18, non_interactive: true }"
+        );
+        assert_eq!(
+            format!("{:?}", LayerChallenges::new_synthetic(11, 18, 1 << 18)),
+            "LayerChallenges { layers: 11, max_count: 18 }"
+        );
+    }
+
     #[test]
     fn challenge_derivation() {
         let n = 200;
@@ -131,6 +260,32 @@ mod test {
         assert!(layers_with_duplicates < 3);
     }
 
+    #[test]
+    fn synthetic_challenge_derivation() {
+        let synthetic_count = 64;
+        let challenges = LayerChallenges::new_synthetic(2, 10, synthetic_count);
+        let leaves = 1 << 30;
+        let rng = &mut thread_rng();
+        let replica_id: Sha256Domain = Sha256Domain::random(rng);
+        let comm_r: Sha256Domain = Sha256Domain::random(rng);
+        let seed: [u8; 32] = rng.gen();
+
+        for k in 0..3 {
+            let indexes = challenges.derive_synthetic_indexes(&replica_id, &seed, k);
+            assert_eq!(indexes.len(), 10);
+            assert!(indexes.iter().all(|&index| index < synthetic_count));
+        }
+
+        for index in 0..synthetic_count {
+            let challenge = challenges.derive_synthetic(leaves, &replica_id, &comm_r, index);
+            assert!(challenge > 0 && challenge < leaves);
+            assert_eq!(
+                challenge,
+                challenges.derive_synthetic(leaves, &replica_id, &comm_r, index)
+            );
+        }
+    }
+
     #[test]
     // This test shows that partitioning (k = 0..partitions) generates the same challenges as
     // generating the same number of challenges with only one partition (k = 0).
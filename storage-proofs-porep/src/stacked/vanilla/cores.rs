@@ -1,10 +1,10 @@
 use std::sync::{Mutex, MutexGuard};
 
-use anyhow::{format_err, Result};
+use anyhow::{ensure, format_err, Result};
 use hwloc::{Bitmap, ObjectType, Topology, TopologyObject, CPUBIND_THREAD};
 use lazy_static::lazy_static;
 use log::{debug, info, warn};
-use storage_proofs_core::settings::SETTINGS;
+use storage_proofs_core::settings::{self, SETTINGS};
 
 type CoreGroup = Vec<CoreIndex>;
 lazy_static! {
@@ -22,14 +22,26 @@ lazy_static! {
 /// created with a value known to be less than the number of visible cores.
 pub struct CoreIndex(usize);
 
-pub fn checkout_core_group() -> Option<MutexGuard<'static, CoreGroup>> {
-    match &*CORE_GROUPS {
+/// Checks out a free core group for multicore SDR, if any.
+///
+/// The core groups are sized once per process for the global `multicore_sdr_producers`, so a
+/// config in effect which sets a different number of producers is rejected rather than
+/// labeling with core groups which don't fit it.
+pub fn checkout_core_group() -> Result<Option<MutexGuard<'static, CoreGroup>>> {
+    let num_producers = settings::current().multicore_sdr_producers;
+    ensure!(
+        num_producers == SETTINGS.multicore_sdr_producers,
+        "multicore_sdr_producers must be {}, the core groups are sized once per process",
+        SETTINGS.multicore_sdr_producers
+    );
+
+    Ok(match &*CORE_GROUPS {
         Some(groups) => {
             for (i, group) in groups.iter().enumerate() {
                 match group.try_lock() {
                     Ok(guard) => {
                         debug!("checked out core group {}", i);
-                        return Some(guard);
+                        return Ok(Some(guard));
                     }
                     Err(_) => debug!("core group {} locked, could not checkout", i),
                 }
@@ -37,7 +49,7 @@ pub fn checkout_core_group() -> Option<MutexGuard<'static, CoreGroup>> {
             None
         }
         None => None,
-    }
+    })
 }
 
 #[cfg(not(target_os = "windows"))]
@@ -202,9 +214,9 @@ mod tests {
     #[test]
     #[cfg(feature = "single-threaded")]
     fn test_checkout_cores() {
-        let checkout1 = checkout_core_group();
+        let checkout1 = checkout_core_group().expect("checkout_core_group failed");
         dbg!(&checkout1);
-        let checkout2 = checkout_core_group();
+        let checkout2 = checkout_core_group().expect("checkout_core_group failed");
         dbg!(&checkout2);
 
         // This test might fail if run on a machine with fewer than four cores.
@@ -1,10 +1,17 @@
+use std::collections::BTreeMap;
 use std::fs::{self, create_dir_all, remove_file, rename, File};
 use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
+use bincode::{deserialize, serialize};
 use filecoin_hashers::Hasher;
 use log::{info, warn};
+use mapr::MmapOptions;
 use merkletree::{merkle::Element, store::StoreConfig};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use storage_proofs_core::{
     cache_key::CacheKey, drgraph::Graph, error::Result, merkle::MerkleTreeTrait,
 };
@@ -15,27 +22,157 @@ use crate::stacked::vanilla::{proof::LayerState, StackedBucketGraph};
 pub mod multi;
 pub mod single;
 
+/// Size of the chunks that are hashed in parallel when computing a layer digest.
+const LAYER_DIGEST_CHUNK_SIZE: usize = 1 << 26;
+
+/// Records the replica id and the digest of every completed layer, so that an interrupted
+/// labeling run can resume from the last layer that is known to be valid.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerManifest {
+    pub replica_id: Vec<u8>,
+    pub layers: BTreeMap<usize, [u8; 32]>,
+}
+
+impl LayerManifest {
+    pub fn new<T: AsRef<[u8]>>(replica_id: T) -> Self {
+        LayerManifest {
+            replica_id: replica_id.as_ref().to_vec(),
+            layers: BTreeMap::new(),
+        }
+    }
+
+    /// The location of the manifest inside the cache directory `path`.
+    pub fn path<P: AsRef<Path>>(path: P) -> PathBuf {
+        path.as_ref().join(CacheKey::LabelManifest.to_string())
+    }
+
+    /// Loads the manifest from the cache directory `path`, if one exists.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
+        let manifest_path = Self::path(path);
+        if !manifest_path.exists() {
+            return Ok(None);
+        }
+
+        let bytes = fs::read(&manifest_path)
+            .with_context(|| format!("could not read from path={:?}", manifest_path))?;
+        let manifest = deserialize(&bytes).context("failed to deserialize layer manifest")?;
+
+        Ok(Some(manifest))
+    }
+
+    /// Stores the manifest atomically in the cache directory `path`.
+    pub fn persist<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let manifest_path = Self::path(path);
+        let tmp_manifest_path = manifest_path.with_extension("tmp");
+
+        fs::write(&tmp_manifest_path, serialize(self)?)
+            .context("failed to write layer manifest")?;
+        rename(tmp_manifest_path, manifest_path).context("failed to rename tmp manifest")?;
+
+        Ok(())
+    }
+
+    /// Records the digest of a freshly written layer and persists the manifest.
+    pub fn record(&mut self, layer: usize, data: &[u8], config: &StoreConfig) -> Result<()> {
+        self.layers.insert(layer, layer_digest(data));
+        self.persist(&config.path)
+    }
+}
+
+/// Computes the digest of a layer, by hashing fixed size chunks in parallel and hashing the
+/// resulting chunk digests in order.
+pub fn layer_digest(data: &[u8]) -> [u8; 32] {
+    let chunk_digests: Vec<_> = data
+        .par_chunks(LAYER_DIGEST_CHUNK_SIZE)
+        .map(Sha256::digest)
+        .collect();
+
+    let mut hasher = Sha256::new();
+    for chunk_digest in &chunk_digests {
+        hasher.update(chunk_digest);
+    }
+
+    hasher.finalize().into()
+}
+
+/// Computes the digest of a layer stored on disk.
+pub fn layer_file_digest(config: &StoreConfig) -> Result<[u8; 32]> {
+    let data_path = StoreConfig::data_path(&config.path, &config.id);
+    let file = File::open(&data_path).context("failed to open layer")?;
+    let data = unsafe {
+        MmapOptions::new()
+            .map(&file)
+            .with_context(|| format!("could not mmap layer={:?}", data_path))?
+    };
+
+    Ok(layer_digest(&data))
+}
+
 /// Prepares the necessary `StoreConfig`s with which the layers are stored.
 /// Also checks for already existing layers and marks them as such.
-pub fn prepare_layers<Tree: 'static + MerkleTreeTrait>(
+///
+/// Existing layers are only reused as a contiguous run starting at layer 1. If a manifest for
+/// the same replica id is present, each layer must also match its recorded digest, and a
+/// manifest for another replica id disables reuse. Caches written before manifests existed
+/// have their layers checked by size only, and are recorded in the returned manifest.
+///
+/// Checking or recording a digest reads and hashes the whole layer, i.e. 32 or 64 GiB for each
+/// layer of a 32 or 64 GiB sector, which takes in the order of a minute per layer at typical
+/// disk throughput. This is still far less than labeling the layer again.
+pub fn prepare_layers<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]>>(
     graph: &StackedBucketGraph<Tree::Hasher>,
     config: &StoreConfig,
     layers: usize,
-) -> Vec<LayerState> {
+    replica_id: T,
+) -> Result<(Vec<LayerState>, LayerManifest)> {
     let label_configs = (1..=layers).map(|layer| {
         StoreConfig::from_config(&config, CacheKey::label_layer(layer), Some(graph.size()))
     });
 
+    // Without a manifest, `previous` is `None` and the layers are only checked by size.
+    let (previous, mut resumable) = match LayerManifest::load(&config.path) {
+        Ok(Some(manifest)) if manifest.replica_id == replica_id.as_ref() => (Some(manifest), true),
+        Ok(Some(_)) => {
+            info!("ignoring layers of a different replica");
+            (None, false)
+        }
+        Ok(None) => (None, true),
+        Err(err) => {
+            warn!("failed to load layer manifest: {}", err);
+            (None, false)
+        }
+    };
+    let mut manifest = LayerManifest::new(replica_id);
+
     let mut states = Vec::with_capacity(layers);
     for (layer, label_config) in (1..=layers).zip(label_configs) {
         // Clear possible left over tmp files
         remove_tmp_layer(&label_config);
 
-        // Check if this layer is already on disk
-        let generated = is_layer_written::<Tree>(graph, &label_config).unwrap_or_default();
+        // Check if this layer is already on disk, and that all previous layers are as well
+        let generated = resumable
+            && is_layer_written::<Tree>(graph, &label_config).unwrap_or_default()
+            && match layer_file_digest(&label_config) {
+                Ok(digest) => {
+                    let valid = previous.as_ref().map_or(true, |previous| {
+                        previous.layers.get(&layer) == Some(&digest)
+                    });
+                    if valid {
+                        manifest.layers.insert(layer, digest);
+                    }
+                    valid
+                }
+                Err(err) => {
+                    warn!("failed to compute digest of layer {}: {}", layer, err);
+                    false
+                }
+            };
+
         if generated {
             // succesful load
             info!("found valid labels for layer {}", layer);
+        } else {
+            resumable = false;
         }
 
         states.push(LayerState {
@@ -44,7 +181,11 @@ pub fn prepare_layers<Tree: 'static + MerkleTreeTrait>(
         });
     }
 
-    states
+    manifest
+        .persist(&config.path)
+        .context("failed to store layer manifest")?;
+
+    Ok((states, manifest))
 }
 
 /// Stores a layer atomically on disk, by writing first to `.tmp` and then renaming.
@@ -20,9 +20,12 @@ use mapr::MmapMut;
 use merkletree::store::{DiskStore, Store, StoreConfig};
 use storage_proofs_core::{
     cache_key::CacheKey,
+    cancel::CancellationToken,
     drgraph::{Graph, BASE_DEGREE},
+    measurements::{measure_op_with_tags, Operation},
     merkle::MerkleTreeTrait,
-    settings::SETTINGS,
+    progress::{NoProgress, ProgressEvent, ProgressObserver},
+    settings,
     util::NODE_SIZE,
 };
 
@@ -30,7 +33,7 @@ use crate::stacked::vanilla::{
     cache::ParentCache,
     cores::{bind_core, checkout_core_group, CoreIndex},
     create_label::{prepare_layers, read_layer, write_layer},
-    graph::{StackedBucketGraph, DEGREE, EXP_DEGREE},
+    graph::{StackedBucketGraph, DEGREE},
     memory_handling::{setup_create_label_memory, CacheReader},
     params::{Labels, LabelsCache},
     proof::LayerState,
@@ -39,6 +42,9 @@ use crate::stacked::vanilla::{
 
 const MIN_BASE_PARENT_NODE: u64 = 2000;
 
+/// Number of nodes the consumer labels between two cancellation checks and progress reports.
+const CHECKPOINT_NODES: u64 = 1 << 16;
+
 const NODE_WORDS: usize = NODE_SIZE / size_of::<u32>();
 const SHA_BLOCK_SIZE: usize = 64;
 
@@ -53,10 +59,71 @@ const SHA256_INITIAL_DIGEST: [u32; 8] = [
     0x5be0_cd19,
 ];
 
+/// Where the producers and the consumer get the parents of each node from.
+enum Parents<'a, H: Hasher> {
+    /// Read from the parent cache, through a sliding window of the mmapped file.
+    Cache(CacheReader<u32>),
+    /// Computed by the producers from the graph, see `sdr_parents_on_the_fly`. The parents are
+    /// stored next to the node's slot in the ring buffer, for the consumer to fill in the base
+    /// parents which were not ready yet.
+    Graph {
+        graph: &'a StackedBucketGraph<H>,
+        consumer: AtomicU64,
+    },
+}
+
+impl<'a, H: Hasher> Parents<'a, H> {
+    fn get_consumer(&self) -> u64 {
+        match self {
+            Parents::Cache(cache) => cache.get_consumer(),
+            Parents::Graph { consumer, .. } => consumer.load(SeqCst),
+        }
+    }
+
+    fn store_consumer(&self, val: u64) {
+        match self {
+            Parents::Cache(cache) => cache.store_consumer(val),
+            Parents::Graph { consumer, .. } => consumer.store(val, SeqCst),
+        }
+    }
+
+    /// Safety: see `CacheReader::increment_consumer`.
+    unsafe fn increment_consumer(&self) {
+        match self {
+            Parents::Cache(cache) => cache.increment_consumer(),
+            Parents::Graph { consumer, .. } => {
+                consumer.fetch_add(1, SeqCst);
+            }
+        }
+    }
+
+    /// The number of nodes whose parents are available at once.
+    fn window_nodes(&self) -> usize {
+        match self {
+            Parents::Cache(cache) => cache.window_nodes(),
+            Parents::Graph { graph, .. } => graph.size(),
+        }
+    }
+
+    fn start_reset(&self) -> Result<()> {
+        match self {
+            Parents::Cache(cache) => cache.start_reset(),
+            Parents::Graph { .. } => Ok(()),
+        }
+    }
+
+    fn finish_reset(&self) -> Result<()> {
+        match self {
+            Parents::Cache(cache) => cache.finish_reset(),
+            Parents::Graph { .. } => Ok(()),
+        }
+    }
+}
+
 #[inline]
-fn fill_buffer(
+fn fill_buffer<H: Hasher>(
     cur_node: u64,
-    parents_cache: &CacheReader<u32>,
+    parents: &Parents<'_, H>,
     mut cur_parent: &[u32], // parents for this node
     layer_labels: &UnsafeSlice<'_, u32>,
     exp_labels: Option<&UnsafeSlice<'_, u32>>, // None for layer0
@@ -85,7 +152,7 @@ fn fill_buffer(
         // which we know is not ready and will be filled in the main loop
         for k in 0..BASE_DEGREE - 1 {
             unsafe {
-                if cur_parent[0] as u64 >= parents_cache.get_consumer() {
+                if cur_parent[0] as u64 >= parents.get_consumer() {
                     // Node is not ready
                     base_parent_missing.set(k);
                 } else {
@@ -138,8 +205,8 @@ fn fill_buffer(
 //                         be filled in. This is an array of size lookahead.
 // - is_layer0    - Indicates first (no expander parents) or subsequent layer
 #[allow(clippy::too_many_arguments)]
-fn create_label_runner(
-    parents_cache: &CacheReader<u32>,
+fn create_label_runner<H: Hasher>(
+    parents: &Parents<'_, H>,
     layer_labels: &UnsafeSlice<'_, u32>,
     exp_labels: Option<&UnsafeSlice<'_, u32>>, // None for layer 0
     num_nodes: u64,
@@ -149,10 +216,16 @@ fn create_label_runner(
     lookahead: u64,
     ring_buf: &RingBuf,
     base_parent_missing: &UnsafeSlice<'_, BitMask>,
+    slot_parents: &UnsafeSlice<'_, [u32; DEGREE]>,
+    cancel: &CancellationToken,
 ) {
     info!("created label runner");
     // Label data bytes per node
     loop {
+        if cancel.is_cancelled() {
+            return;
+        }
+
         // Get next work items
         let work = cur_awaiting.fetch_add(stride, SeqCst);
         if work >= num_nodes {
@@ -171,27 +244,34 @@ fn create_label_runner(
             let cur_slot = (cur_node - 1) % lookahead;
 
             // Don't overrun the buffer
-            while cur_node > (parents_cache.get_consumer() + lookahead - 1) {
+            while cur_node > (parents.get_consumer() + lookahead - 1) {
+                if cancel.is_cancelled() {
+                    return;
+                }
                 thread::sleep(Duration::from_micros(10));
             }
 
             let buf = unsafe { ring_buf.slot_mut(cur_slot as usize) };
             let bpm = unsafe { base_parent_missing.get_mut(cur_slot as usize) };
 
-            let pc = unsafe { parents_cache.slice_at(cur_node as usize * DEGREE as usize) };
-            fill_buffer(
-                cur_node,
-                parents_cache,
-                pc,
-                &layer_labels,
-                exp_labels,
-                buf,
-                bpm,
-            );
+            let pc = match parents {
+                Parents::Cache(cache) => unsafe { cache.slice_at(cur_node as usize * DEGREE) },
+                Parents::Graph { graph, .. } => {
+                    let node_parents = unsafe { slot_parents.get_mut(cur_slot as usize) };
+                    graph
+                        .parents(cur_node as usize, node_parents)
+                        .expect("graph parents failure");
+                    &node_parents[..]
+                }
+            };
+            fill_buffer(cur_node, parents, pc, &layer_labels, exp_labels, buf, bpm);
         }
 
         // Wait for the previous node to finish
         while work > (cur_producer.load(SeqCst) + 1) {
+            if cancel.is_cancelled() {
+                return;
+            }
             thread::sleep(Duration::from_micros(10));
         }
 
@@ -200,26 +280,29 @@ fn create_label_runner(
     }
 }
 
-fn create_layer_labels(
-    parents_cache: &CacheReader<u32>,
+#[allow(clippy::too_many_arguments)]
+fn create_layer_labels<H: Hasher>(
+    parents: &Parents<'_, H>,
     replica_id: &[u8],
     layer_labels: &mut MmapMut,
     exp_labels: Option<&mut MmapMut>,
     num_nodes: u64,
     cur_layer: u32,
     core_group: Arc<Option<MutexGuard<'_, Vec<CoreIndex>>>>,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressObserver,
 ) {
     info!("Creating labels for layer {}", cur_layer);
     // num_producers is the number of producer threads
     let (lookahead, num_producers, producer_stride) = {
-        let settings = &SETTINGS;
-        let lookahead = settings.multicore_sdr_lookahead;
-        let num_producers = settings.multicore_sdr_producers;
+        let config = settings::current();
+        let lookahead = config.multicore_sdr_lookahead;
+        let num_producers = config.multicore_sdr_producers;
         // NOTE: Stride must not exceed the number of nodes in parents_cache's window. If it does, the process will deadlock
         // with producers and consumers waiting for each other.
-        let producer_stride = settings
+        let producer_stride = config
             .multicore_sdr_producer_stride
-            .min(parents_cache.window_nodes() as u64);
+            .min(parents.window_nodes() as u64);
 
         (lookahead, num_producers, producer_stride)
     };
@@ -228,6 +311,10 @@ fn create_layer_labels(
 
     let mut ring_buf = RingBuf::new(BYTES_PER_NODE, lookahead);
     let mut base_parent_missing = vec![BitMask::default(); lookahead];
+    let mut slot_parents = match parents {
+        Parents::Cache(_) => Vec::new(),
+        Parents::Graph { .. } => vec![[0u32; DEGREE]; lookahead],
+    };
 
     // Fill in the fixed portion of all buffers
     for buf in ring_buf.iter_slot_mut() {
@@ -249,6 +336,7 @@ fn create_layer_labels(
         UnsafeSlice::from_slice(m.as_mut_slice_of::<u32>().expect("failed as mut slice of"))
     });
     let base_parent_missing = UnsafeSlice::from_slice(&mut base_parent_missing);
+    let slot_parents = UnsafeSlice::from_slice(&mut slot_parents);
 
     crossbeam::thread::scope(|s| {
         let mut runners = Vec::with_capacity(num_producers);
@@ -260,6 +348,7 @@ fn create_layer_labels(
             let cur_awaiting = &cur_awaiting;
             let ring_buf = &ring_buf;
             let base_parent_missing = &base_parent_missing;
+            let slot_parents = &slot_parents;
 
             let core_index = if let Some(cg) = &*core_group {
                 cg.get(i + 1)
@@ -274,7 +363,7 @@ fn create_layer_labels(
                 let _cleanup_handle = core_index.map(|c| bind_core(*c));
 
                 create_label_runner(
-                    parents_cache,
+                    parents,
                     layer_labels,
                     exp_labels,
                     num_nodes,
@@ -284,12 +373,17 @@ fn create_layer_labels(
                     lookahead as u64,
                     ring_buf,
                     base_parent_missing,
+                    slot_parents,
+                    cancel,
                 )
             }));
         }
 
         let mut cur_node_ptr = unsafe { layer_labels.as_mut_slice() };
-        let mut cur_parent_ptr = unsafe { parents_cache.consumer_slice_at(DEGREE) };
+        let mut cur_parent_ptr: &[u32] = match parents {
+            Parents::Cache(cache) => unsafe { cache.consumer_slice_at(DEGREE) },
+            Parents::Graph { .. } => &[],
+        };
         let mut cur_parent_ptr_offset = DEGREE;
 
         // Calculate node 0 (special case with no parents)
@@ -313,14 +407,19 @@ fn create_layer_labels(
         // Calculate nodes 1 to n
 
         // Skip first node.
-        parents_cache.store_consumer(1);
+        parents.store_consumer(1);
         let mut i = 1;
-        while i < num_nodes {
+        let mut next_checkpoint = CHECKPOINT_NODES;
+        'nodes: while i < num_nodes {
             // Ensure next buffer is ready
             let mut counted = false;
             let mut producer_val = cur_producer.load(SeqCst);
 
             while producer_val < i {
+                // The producers stop as well, so the remaining labels are never produced.
+                if cancel.is_cancelled() {
+                    break 'nodes;
+                }
                 if !counted {
                     counted = true;
                     count_not_ready += 1;
@@ -332,24 +431,34 @@ fn create_layer_labels(
             // Process as many nodes as are ready
             let ready_count = producer_val - i + 1;
             for _count in 0..ready_count {
-                // If we have used up the last cache window's parent data, get some more.
-                if cur_parent_ptr.is_empty() {
-                    // Safety: values read from `cur_parent_ptr` before calling `increment_consumer`
-                    // must not be read again after.
-                    unsafe {
-                        cur_parent_ptr = parents_cache.consumer_slice_at(cur_parent_ptr_offset);
+                let node_parents = match parents {
+                    Parents::Cache(cache) => {
+                        // If we have used up the last cache window's parent data, get some more.
+                        if cur_parent_ptr.is_empty() {
+                            // Safety: values read from `cur_parent_ptr` before calling
+                            // `increment_consumer` must not be read again after.
+                            unsafe {
+                                cur_parent_ptr = cache.consumer_slice_at(cur_parent_ptr_offset);
+                            }
+                        }
+
+                        let node_parents = &cur_parent_ptr[..DEGREE];
+                        cur_parent_ptr = &cur_parent_ptr[DEGREE..];
+                        cur_parent_ptr_offset += DEGREE;
+                        node_parents
                     }
-                }
+                    Parents::Graph { .. } => unsafe { &slot_parents.get(cur_slot)[..] },
+                };
 
                 cur_node_ptr = &mut cur_node_ptr[8..];
                 // Grab the current slot of the ring_buf
                 let buf = unsafe { ring_buf.slot_mut(cur_slot) };
                 // Fill in the base parents
-                for k in 0..BASE_DEGREE {
-                    let bpm = unsafe { base_parent_missing.get(cur_slot) };
+                let bpm = unsafe { base_parent_missing.get(cur_slot) };
+                for (k, parent) in node_parents[..BASE_DEGREE].iter().enumerate() {
                     if bpm.get(k) {
                         let source = unsafe {
-                            let start = cur_parent_ptr[0] as usize * NODE_WORDS;
+                            let start = *parent as usize * NODE_WORDS;
                             let end = start + NODE_WORDS;
                             &layer_labels.as_slice()[start..end]
                         };
@@ -357,14 +466,9 @@ fn create_layer_labels(
                         buf[64 + (NODE_SIZE * k)..64 + (NODE_SIZE * (k + 1))]
                             .copy_from_slice(source.as_byte_slice());
                     }
-                    cur_parent_ptr = &cur_parent_ptr[1..];
-                    cur_parent_ptr_offset += 1;
                 }
 
                 // Expanders are already all filled in (layer 1 doesn't use expanders)
-                cur_parent_ptr = &cur_parent_ptr[EXP_DEGREE..];
-                cur_parent_ptr_offset += EXP_DEGREE;
-
                 if cur_layer == 1 {
                     // Six rounds of all base parents
                     for _j in 0..6 {
@@ -418,11 +522,23 @@ fn create_layer_labels(
                 // This will happen above because `parents_cache` will now be empty, if we have
                 // correctly advanced it so far.
                 unsafe {
-                    parents_cache.increment_consumer();
+                    parents.increment_consumer();
                 }
                 i += 1;
                 cur_slot = (cur_slot + 1) % lookahead;
             }
+
+            if i >= next_checkpoint {
+                if cancel.is_cancelled() {
+                    break;
+                }
+                progress.on_event(ProgressEvent::NodesLabeled {
+                    layer: cur_layer as usize,
+                    nodes: i,
+                    total: num_nodes,
+                });
+                next_checkpoint = i + CHECKPOINT_NODES;
+            }
         }
 
         debug!("PRODUCER NOT READY: {} times", count_not_ready);
@@ -437,22 +553,25 @@ fn create_layer_labels(
 #[allow(clippy::type_complexity)]
 pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]>>(
     graph: &StackedBucketGraph<Tree::Hasher>,
-    parents_cache: &ParentCache,
+    parents_cache: Option<&ParentCache>,
     layers: usize,
     replica_id: T,
     config: StoreConfig,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressObserver,
 ) -> Result<(Labels<Tree>, Vec<LayerState>)> {
     info!("create labels");
 
-    let layer_states = prepare_layers::<Tree>(graph, &config, layers);
+    let (layer_states, mut manifest) =
+        prepare_layers::<Tree, _>(graph, &config, layers, &replica_id)?;
 
     let sector_size = graph.size() * NODE_SIZE;
     let node_count = graph.size() as u64;
-    let cache_window_nodes = SETTINGS.sdr_parents_cache_size as usize;
+    let cache_window_nodes = settings::current().sdr_parents_cache_size as usize;
 
     let default_cache_size = DEGREE * 4 * cache_window_nodes;
 
-    let core_group = Arc::new(checkout_core_group());
+    let core_group = Arc::new(checkout_core_group()?);
 
     // When `_cleanup_handle` is dropped, the previous binding of thread will be restored.
     let _cleanup_handle = (*core_group).as_ref().map(|group| {
@@ -467,44 +586,75 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
         sector_size,
         DEGREE,
         Some(default_cache_size as usize),
-        &parents_cache.path,
+        parents_cache.map(|cache| cache.path.as_path()),
     )?;
+    let parents = match parents_cache {
+        Some(cache) => Parents::Cache(cache),
+        None => Parents::Graph {
+            graph,
+            consumer: AtomicU64::new(0),
+        },
+    };
 
     for (layer, layer_state) in (1..=layers).zip(layer_states.iter()) {
         info!("Layer {}", layer);
+        cancel.check()?;
+        progress.on_event(ProgressEvent::LayerStarted { layer, layers });
 
         if layer_state.generated {
             info!("skipping layer {}, already generated", layer);
 
             // load the already generated layer into exp_labels
             read_layer(&layer_state.config, &mut exp_labels)?;
+            progress.on_event(ProgressEvent::NodesLabeled {
+                layer,
+                nodes: node_count,
+                total: node_count,
+            });
             continue;
         }
 
         // Cache reset happens in two parts.
         // The second part (the finish) happens before each layer but the first.
         if layers != 1 {
-            parents_cache.finish_reset()?;
+            parents.finish_reset()?;
         }
 
-        create_layer_labels(
-            &parents_cache,
-            &replica_id.as_ref(),
-            &mut layer_labels,
-            if layer == 1 {
-                None
-            } else {
-                Some(&mut exp_labels)
+        measure_op_with_tags(
+            Operation::LabelLayer,
+            &[("layer", layer.to_string())],
+            || {
+                create_layer_labels(
+                    &parents,
+                    &replica_id.as_ref(),
+                    &mut layer_labels,
+                    if layer == 1 {
+                        None
+                    } else {
+                        Some(&mut exp_labels)
+                    },
+                    node_count,
+                    layer as u32,
+                    core_group.clone(),
+                    cancel,
+                    progress,
+                )
             },
-            node_count,
-            layer as u32,
-            core_group.clone(),
         );
 
+        // An interrupted layer is incomplete, so it is dropped instead of stored. Completed
+        // layers stay on disk and are reused when labeling is resumed.
+        cancel.check()?;
+        progress.on_event(ProgressEvent::NodesLabeled {
+            layer,
+            nodes: node_count,
+            total: node_count,
+        });
+
         // Cache reset happens in two parts.
         // The first part (the start) happens after each layer but the last.
         if layer != layers {
-            parents_cache.start_reset()?;
+            parents.start_reset()?;
         }
 
         mem::swap(&mut layer_labels, &mut exp_labels);
@@ -513,6 +663,9 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
 
             info!("  storing labels on disk");
             write_layer(&exp_labels, layer_config).context("failed to store labels")?;
+            manifest
+                .record(layer, &exp_labels, layer_config)
+                .context("failed to update layer manifest")?;
 
             info!(
                 "  generated layer {} store with id {}",
@@ -533,7 +686,7 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
 #[allow(clippy::type_complexity)]
 pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]>>(
     graph: &StackedBucketGraph<Tree::Hasher>,
-    parents_cache: &ParentCache,
+    parents_cache: Option<&ParentCache>,
     layers: usize,
     replica_id: T,
     config: StoreConfig,
@@ -546,11 +699,11 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
 
     let sector_size = graph.size() * NODE_SIZE;
     let node_count = graph.size() as u64;
-    let cache_window_nodes = (&SETTINGS.sdr_parents_cache_size / 2) as usize;
+    let cache_window_nodes = (settings::current().sdr_parents_cache_size / 2) as usize;
 
     let default_cache_size = DEGREE * 4 * cache_window_nodes;
 
-    let core_group = Arc::new(checkout_core_group());
+    let core_group = Arc::new(checkout_core_group()?);
 
     // When `_cleanup_handle` is dropped, the previous binding of thread will be restored.
     let _cleanup_handle = (*core_group).as_ref().map(|group| {
@@ -565,8 +718,15 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
         sector_size,
         DEGREE,
         Some(default_cache_size as usize),
-        &parents_cache.path,
+        parents_cache.map(|cache| cache.path.as_path()),
     )?;
+    let parents = match parents_cache {
+        Some(cache) => Parents::Cache(cache),
+        None => Parents::Graph {
+            graph,
+            consumer: AtomicU64::new(0),
+        },
+    };
 
     for layer in 1..=layers {
         info!("Layer {}", layer);
@@ -574,11 +734,11 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
         // Cache reset happens in two parts.
         // The second part (the finish) happens before each layer but the first.
         if layers != 1 {
-            parents_cache.finish_reset()?;
+            parents.finish_reset()?;
         }
 
         create_layer_labels(
-            &parents_cache,
+            &parents,
             &replica_id.as_ref(),
             &mut layer_labels,
             if layer == 1 {
@@ -589,12 +749,14 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
             node_count,
             layer as u32,
             core_group.clone(),
+            &CancellationToken::default(),
+            &NoProgress,
         );
 
         // Cache reset happens in two parts.
         // The first part (the start) happens after each layer but the last.
         if layer != layers {
-            parents_cache.start_reset()?;
+            parents.start_reset()?;
         }
 
         {
@@ -642,6 +804,8 @@ mod tests {
     use storage_proofs_core::{api_version::ApiVersion, merkle::LCTree};
     use tempfile::tempdir;
 
+    use crate::stacked::vanilla::graph::EXP_DEGREE;
+
     #[test]
     fn test_create_labels() {
         let layers = 11;
@@ -723,13 +887,6 @@ mod tests {
     ) {
         let nodes = sector_size / NODE_SIZE;
 
-        let cache_dir = tempdir().expect("tempdir failure");
-        let config = StoreConfig::new(
-            cache_dir.path(),
-            CacheKey::CommDTree.to_string(),
-            nodes.trailing_zeros() as usize,
-        );
-
         let graph = StackedBucketGraph::<PoseidonHasher>::new(
             None,
             nodes,
@@ -741,18 +898,32 @@ mod tests {
         .expect("stacked bucket graph new failed");
         let cache = graph.parent_cache().expect("parent_cache failed");
 
-        let labels = create_labels_for_decoding::<LCTree<PoseidonHasher, U8, U0, U2>, _>(
-            &graph, &cache, layers, replica_id, config,
-        )
-        .expect("create_labels_for_decoding failed");
-
-        let final_labels = labels
-            .labels_for_last_layer()
-            .expect("labels_for_last_layer failed");
-        let last_label = final_labels
-            .read_at(final_labels.len() - 1)
-            .expect("read_at");
-        dbg!(&last_label);
-        assert_eq!(expected_last_label.into_repr(), last_label.0);
+        // The parents computed on the fly must result in the same labels as the parent cache.
+        for parents_cache in &[Some(&cache), None] {
+            // Use a fresh cache dir for each pass, so the layers are not loaded from the last one.
+            let cache_dir = tempdir().expect("tempdir failure");
+            let config = StoreConfig::new(
+                cache_dir.path(),
+                CacheKey::CommDTree.to_string(),
+                nodes.trailing_zeros() as usize,
+            );
+
+            let labels = create_labels_for_decoding::<LCTree<PoseidonHasher, U8, U0, U2>, _>(
+                &graph,
+                *parents_cache,
+                layers,
+                replica_id,
+                config,
+            )
+            .expect("create_labels_for_decoding failed");
+
+            let final_labels = labels
+                .labels_for_last_layer()
+                .expect("labels_for_last_layer failed");
+            let last_label = final_labels
+                .read_at(final_labels.len() - 1)
+                .expect("read_at");
+            assert_eq!(expected_last_label.into_repr(), last_label.0);
+        }
     }
 }
@@ -1,15 +1,19 @@
 use std::marker::PhantomData;
 use std::mem;
+use std::ops::Range;
 
-use anyhow::{Context, Result};
-use filecoin_hashers::Hasher;
+use anyhow::{ensure, Context, Result};
+use filecoin_hashers::{Domain, Hasher};
 use generic_array::typenum::Unsigned;
 use log::info;
 use merkletree::store::{DiskStore, Store, StoreConfig};
 use sha2raw::Sha256;
 use storage_proofs_core::{
+    cancel::CancellationToken,
     drgraph::Graph,
+    measurements::{measure_op_with_tags, Operation},
     merkle::MerkleTreeTrait,
+    progress::{ProgressEvent, ProgressObserver},
     util::{data_at_node_offset, NODE_SIZE},
 };
 
@@ -20,65 +24,105 @@ use crate::stacked::vanilla::{
     Labels, LabelsCache, StackedBucketGraph,
 };
 
+/// Number of nodes labeled between two cancellation checks and progress reports.
+const CHECKPOINT_NODES: usize = 1 << 16;
+
 #[allow(clippy::type_complexity)]
 pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]>>(
     graph: &StackedBucketGraph<Tree::Hasher>,
-    parents_cache: &mut ParentCache,
+    mut parents_cache: Option<&mut ParentCache>,
     layers: usize,
     replica_id: T,
     config: StoreConfig,
+    cancel: &CancellationToken,
+    progress: &dyn ProgressObserver,
 ) -> Result<(Labels<Tree>, Vec<LayerState>)> {
     info!("generate labels");
 
-    let layer_states = prepare_layers::<Tree>(graph, &config, layers);
+    let (layer_states, mut manifest) =
+        prepare_layers::<Tree, _>(graph, &config, layers, &replica_id)?;
 
     let layer_size = graph.size() * NODE_SIZE;
     // NOTE: this means we currently keep 2x sector size around, to improve speed.
     let mut layer_labels = vec![0u8; layer_size]; // Buffer for labels of the current layer
     let mut exp_labels = vec![0u8; layer_size]; // Buffer for labels of the previous layer, needed for expander parents
+    let node_count = graph.size() as u64;
+    let report = |layer, node: usize| {
+        progress.on_event(ProgressEvent::NodesLabeled {
+            layer,
+            nodes: node as u64,
+            total: node_count,
+        })
+    };
 
     for (layer, layer_state) in (1..=layers).zip(layer_states.iter()) {
         info!("generating layer: {}", layer);
+        cancel.check()?;
+        progress.on_event(ProgressEvent::LayerStarted { layer, layers });
         if layer_state.generated {
             info!("skipping layer {}, already generated", layer);
 
             // load the already generated layer into exp_labels
             read_layer(&layer_state.config, &mut exp_labels)?;
+            report(layer, graph.size());
             continue;
         }
 
-        parents_cache.reset()?;
-
-        if layer == 1 {
-            for node in 0..graph.size() {
-                create_label(
-                    graph,
-                    Some(parents_cache),
-                    &replica_id,
-                    &mut layer_labels,
-                    layer,
-                    node,
-                )?;
-            }
-        } else {
-            for node in 0..graph.size() {
-                create_label_exp(
-                    graph,
-                    Some(parents_cache),
-                    &replica_id,
-                    &exp_labels,
-                    &mut layer_labels,
-                    layer,
-                    node,
-                )?;
-            }
+        if let Some(parents_cache) = parents_cache.as_deref_mut() {
+            parents_cache.reset()?;
         }
 
+        // An interrupted layer is only held in memory, so nothing partial reaches the disk.
+        measure_op_with_tags(
+            Operation::LabelLayer,
+            &[("layer", layer.to_string())],
+            || -> Result<()> {
+                if layer == 1 {
+                    for node in 0..graph.size() {
+                        if node % CHECKPOINT_NODES == 0 {
+                            cancel.check()?;
+                            report(layer, node);
+                        }
+                        create_label(
+                            graph,
+                            parents_cache.as_deref_mut(),
+                            &replica_id,
+                            &mut layer_labels,
+                            layer,
+                            node,
+                        )?;
+                    }
+                } else {
+                    for node in 0..graph.size() {
+                        if node % CHECKPOINT_NODES == 0 {
+                            cancel.check()?;
+                            report(layer, node);
+                        }
+                        create_label_exp(
+                            graph,
+                            parents_cache.as_deref_mut(),
+                            &replica_id,
+                            &exp_labels,
+                            &mut layer_labels,
+                            layer,
+                            node,
+                        )?;
+                    }
+                }
+                Ok(())
+            },
+        )?;
+
+        report(layer, graph.size());
+
         // Write the result to disk to avoid keeping it in memory all the time.
         let layer_config = &layer_state.config;
 
         info!("  storing labels on disk");
         write_layer(&layer_labels, layer_config).context("failed to store labels")?;
+        manifest
+            .record(layer, &layer_labels, layer_config)
+            .context("failed to update layer manifest")?;
 
         info!(
             "  generated layer {} store with id {}",
@@ -101,7 +145,7 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
 #[allow(clippy::type_complexity)]
 pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]>>(
     graph: &StackedBucketGraph<Tree::Hasher>,
-    parents_cache: &mut ParentCache,
+    mut parents_cache: Option<&mut ParentCache>,
     layers: usize,
     replica_id: T,
     config: StoreConfig,
@@ -119,13 +163,15 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
     for layer in 1..=layers {
         info!("generating layer: {}", layer);
 
-        parents_cache.reset()?;
+        if let Some(parents_cache) = parents_cache.as_deref_mut() {
+            parents_cache.reset()?;
+        }
 
         if layer == 1 {
             for node in 0..graph.size() {
                 create_label(
                     graph,
-                    Some(parents_cache),
+                    parents_cache.as_deref_mut(),
                     &replica_id,
                     &mut layer_labels,
                     layer,
@@ -136,7 +182,7 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
             for node in 0..graph.size() {
                 create_label_exp(
                     graph,
-                    Some(parents_cache),
+                    parents_cache.as_deref_mut(),
                     &replica_id,
                     &exp_labels,
                     &mut layer_labels,
@@ -170,6 +216,81 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
     Ok(LabelsCache::<Tree> { labels })
 }
 
+/// Generates the last layer labels of the nodes in `nodes`, without storing any layer on disk.
+///
+/// The expander parents of a node are spread over the whole previous layer, so for any range
+/// but the first node, almost all of the second to last layer, and all of the layers before it,
+/// are required. Those layers are labeled in full, and only the last layer, whose base parents
+/// all precede the labeled node, stops at the end of the range. As in
+/// `create_labels_for_decoding`, two layer sized buffers are kept in memory.
+pub fn create_labels_for_decoding_range<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]>>(
+    graph: &StackedBucketGraph<Tree::Hasher>,
+    parents_cache: &mut ParentCache,
+    layers: usize,
+    replica_id: T,
+    nodes: Range<usize>,
+) -> Result<Vec<<Tree::Hasher as Hasher>::Domain>> {
+    info!("generate labels for nodes {:?}", nodes);
+
+    ensure!(layers > 0, "at least one layer is required");
+    ensure!(
+        nodes.start < nodes.end && nodes.end <= graph.size(),
+        "invalid node range {:?} for graph of size {}",
+        nodes,
+        graph.size()
+    );
+
+    let layer_size = graph.size() * NODE_SIZE;
+    let mut layer_labels = vec![0u8; layer_size]; // Buffer for labels of the current layer
+    let mut exp_labels = vec![0u8; layer_size]; // Buffer for labels of the previous layer, needed for expander parents
+
+    for layer in 1..=layers {
+        let node_count = if layer == layers {
+            nodes.end
+        } else {
+            graph.size()
+        };
+        info!("generating layer: {} ({} nodes)", layer, node_count);
+
+        parents_cache.reset()?;
+
+        if layer == 1 {
+            for node in 0..node_count {
+                create_label(
+                    graph,
+                    Some(parents_cache),
+                    &replica_id,
+                    &mut layer_labels,
+                    layer,
+                    node,
+                )?;
+            }
+        } else {
+            for node in 0..node_count {
+                create_label_exp(
+                    graph,
+                    Some(parents_cache),
+                    &replica_id,
+                    &exp_labels,
+                    &mut layer_labels,
+                    layer,
+                    node,
+                )?;
+            }
+        }
+
+        mem::swap(&mut layer_labels, &mut exp_labels);
+    }
+
+    // After the final swap, the last layer labels are held in `exp_labels`.
+    nodes
+        .map(|node| {
+            let start = data_at_node_offset(node);
+            <Tree::Hasher as Hasher>::Domain::try_from_bytes(&exp_labels[start..start + NODE_SIZE])
+        })
+        .collect()
+}
+
 pub fn create_label<H: Hasher, T: AsRef<[u8]>>(
     graph: &StackedBucketGraph<H>,
     cache: Option<&mut ParentCache>,
@@ -1,6 +1,7 @@
 use std::convert::{TryFrom, TryInto};
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
+use std::path::PathBuf;
 
 use anyhow::ensure;
 use filecoin_hashers::Hasher;
@@ -16,12 +17,12 @@ use storage_proofs_core::{
     drgraph::{BucketGraph, Graph, BASE_DEGREE},
     error::Result,
     parameter_cache::ParameterSetMetadata,
-    settings::SETTINGS,
+    settings,
     util::NODE_SIZE,
     PoRepID,
 };
 
-use crate::stacked::vanilla::cache::ParentCache;
+use crate::stacked::vanilla::cache::{cache_path, ParentCache};
 
 /// The expansion degree used for Stacked Graphs.
 pub const EXP_DEGREE: usize = 8;
@@ -135,7 +136,7 @@ where
     /// Returns a reference to the parent cache.
     pub fn parent_cache(&self) -> Result<ParentCache> {
         // Number of nodes to be cached in memory
-        let default_cache_size = SETTINGS.sdr_parents_cache_size;
+        let default_cache_size = settings::current().sdr_parents_cache_size;
         let cache_entries = self.size() as u32;
         let cache_size = cache_entries.min(default_cache_size);
 
@@ -143,6 +144,22 @@ where
 
         ParentCache::new(cache_size, cache_entries, self)
     }
+
+    /// Returns the path of the parent cache of this graph, which may not exist yet.
+    pub fn parent_cache_path(&self) -> PathBuf {
+        cache_path(self.size() as u32, self)
+    }
+
+    /// Replaces the parent cache of this graph with a newly generated one, e.g. after
+    /// `verify_parent_cache` found it to be corrupt.
+    pub fn regenerate_parent_cache(&self) -> Result<ParentCache> {
+        let default_cache_size = settings::current().sdr_parents_cache_size;
+        let cache_entries = self.size() as u32;
+        let cache_size = cache_entries.min(default_cache_size);
+
+        ParentCache::regenerate(cache_size, cache_entries, self, &self.parent_cache_path())
+    }
+
     pub fn copy_parents_data_exp(
         &self,
         node: u32,
@@ -1,6 +1,12 @@
 use bellperson::bls::Fr;
-use filecoin_hashers::{POSEIDON_CONSTANTS_11, POSEIDON_CONSTANTS_2};
+use ff::Field;
+use filecoin_hashers::{PoseidonArity, POSEIDON_CONSTANTS_11, POSEIDON_CONSTANTS_2};
+use generic_array::typenum::Unsigned;
 use neptune::poseidon::Poseidon;
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSlice, ParallelSliceMut};
+
+/// The number of preimages hashed by a single Poseidon instance in `hash_preimages`.
+const HASH_PREIMAGES_CHUNK: usize = 1 << 10;
 
 /// Hash all elements in the given column.
 pub fn hash_single_column(column: &[Fr]) -> Fr {
@@ -16,3 +22,122 @@ pub fn hash_single_column(column: &[Fr]) -> Fr {
         _ => panic!("unsupported column size: {}", column.len()),
     }
 }
+
+/// Hashes the preimages of `A` elements each, stored one after another in `preimages`, into
+/// `hashes` on all cores.
+///
+/// This is plain scalar Poseidon, one preimage after another: the preimages are split into
+/// chunks across threads, and each thread only saves the setup cost by resetting a single
+/// Poseidon instance for its chunk, instead of creating one per preimage as `hash_single_column`
+/// does.
+pub fn hash_preimages<A: PoseidonArity>(preimages: &[Fr], hashes: &mut [Fr]) {
+    let arity = A::to_usize();
+    assert_eq!(
+        preimages.len(),
+        hashes.len() * arity,
+        "invalid number of preimages"
+    );
+
+    preimages
+        .par_chunks(arity * HASH_PREIMAGES_CHUNK)
+        .zip(hashes.par_chunks_mut(HASH_PREIMAGES_CHUNK))
+        .for_each(|(preimages, hashes)| {
+            let mut hasher = Poseidon::new(A::PARAMETERS());
+            for (preimage, hash) in preimages.chunks(arity).zip(hashes.iter_mut()) {
+                hasher.reset();
+                for el in preimage {
+                    hasher.input(*el).expect("too many preimage elements");
+                }
+                *hash = hasher.hash();
+            }
+        });
+}
+
+/// Builds the rows of a merkle tree of arity `A` above `leaves` with `hash_preimages`, returned
+/// one after another from the bottom up to the root, which is the last element.
+pub fn build_tree_rows<A: PoseidonArity>(leaves: &[Fr]) -> Vec<Fr> {
+    let arity = A::to_usize();
+
+    let mut rows_len = 0;
+    let mut width = leaves.len();
+    while width > 1 {
+        assert_eq!(width % arity, 0, "invalid number of leaves");
+        width /= arity;
+        rows_len += width;
+    }
+
+    let mut rows = vec![Fr::zero(); rows_len];
+    if rows.is_empty() {
+        return rows;
+    }
+
+    let mut width = leaves.len() / arity;
+    hash_preimages::<A>(leaves, &mut rows[..width]);
+
+    let mut row_start = 0;
+    while width > 1 {
+        let (row, next_rows) = rows[row_start..].split_at_mut(width);
+        let next_width = width / arity;
+        hash_preimages::<A>(row, &mut next_rows[..next_width]);
+
+        row_start += width;
+        width = next_width;
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use filecoin_hashers::poseidon::PoseidonHasher;
+    use fr32::fr_into_bytes;
+    use generic_array::typenum::{U11, U2, U8};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::{
+        merkle::{create_base_merkle_tree, MerkleTree, MerkleTreeTrait},
+        TEST_SEED,
+    };
+
+    #[test]
+    fn test_hash_preimages_columns() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+        for layers in &[2, 11] {
+            // Not a multiple of the chunk size, to cover a partial chunk.
+            let columns_len = HASH_PREIMAGES_CHUNK + 3;
+            let columns: Vec<Fr> = (0..columns_len * layers).map(|_| Fr::random(rng)).collect();
+
+            let mut hashes = vec![Fr::zero(); columns_len];
+            match *layers {
+                2 => hash_preimages::<U2>(&columns, &mut hashes),
+                11 => hash_preimages::<U11>(&columns, &mut hashes),
+                _ => unreachable!(),
+            }
+
+            for (column, hash) in columns.chunks(*layers).zip(hashes.iter()) {
+                assert_eq!(hash, &hash_single_column(column));
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_tree_rows() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+        let leaves_len = 8 * 8 * 8;
+        let leaves: Vec<Fr> = (0..leaves_len).map(|_| Fr::random(rng)).collect();
+        let data: Vec<u8> = leaves.iter().flat_map(fr_into_bytes).collect();
+
+        let rows = build_tree_rows::<U8>(&leaves);
+        assert_eq!(rows.len(), 8 * 8 + 8 + 1);
+
+        let tree =
+            create_base_merkle_tree::<MerkleTree<PoseidonHasher, U8>>(None, leaves_len, &data)
+                .expect("failed to create tree");
+        let root: Fr = tree.root().into();
+        assert_eq!(rows.last(), Some(&root));
+    }
+}
@@ -295,13 +295,17 @@ fn allocate_layer(sector_size: usize) -> Result<MmapMut> {
     }
 }
 
+/// Allocates the layers for labeling, and opens the parent cache at `cache_path` if the parents
+/// are not computed on the fly.
 pub fn setup_create_label_memory(
     sector_size: usize,
     degree: usize,
     window_size: Option<usize>,
-    cache_path: &Path,
-) -> Result<(CacheReader<u32>, MmapMut, MmapMut)> {
-    let parents_cache = CacheReader::new(cache_path, window_size, degree)?;
+    cache_path: Option<&Path>,
+) -> Result<(Option<CacheReader<u32>>, MmapMut, MmapMut)> {
+    let parents_cache = cache_path
+        .map(|cache_path| CacheReader::new(cache_path, window_size, degree))
+        .transpose()?;
     let layer_labels = allocate_layer(sector_size)?;
     let exp_labels = allocate_layer(sector_size)?;
 
@@ -22,6 +22,9 @@ mod proof_scheme;
 #[cfg(feature = "multicore-sdr")]
 mod utils;
 
+pub use cache::{
+    list_parent_caches, parent_cache_dir, verify_parent_cache, ParentCacheInfo, ParentCacheStatus,
+};
 pub use challenges::{ChallengeRequirements, LayerChallenges};
 pub use column::Column;
 pub use column_proof::ColumnProof;
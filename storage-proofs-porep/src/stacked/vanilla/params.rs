@@ -27,7 +27,8 @@ use storage_proofs_core::{
 };
 
 use crate::stacked::vanilla::{
-    Column, ColumnProof, EncodingProof, LabelingProof, LayerChallenges, StackedBucketGraph,
+    create_label::LayerManifest, Column, ColumnProof, EncodingProof, LabelingProof,
+    LayerChallenges, StackedBucketGraph,
 };
 
 pub const BINARY_ARITY: usize = 2;
@@ -124,15 +125,73 @@ pub struct PublicInputs<T: Domain, S: Domain> {
 }
 
 impl<T: Domain, S: Domain> PublicInputs<T, S> {
+    /// Derives the challenges of partition `partition_k`.
+    ///
+    /// Non-interactive challenges are derived from CommR, which is why `tau` must be set for
+    /// them, and `seed` is ignored. Synthetic challenges are derived from CommR as well, and
+    /// `seed` selects which of them are proven. Fails if `tau` is missing for either.
     pub fn challenges(
         &self,
         layer_challenges: &LayerChallenges,
         leaves: usize,
         partition_k: Option<usize>,
+    ) -> Result<Vec<usize>> {
+        let k = partition_k.unwrap_or(0);
+
+        let challenges = if layer_challenges.is_non_interactive() {
+            let comm_r = self.comm_r("non-interactive")?;
+            let mut seed = [0u8; 32];
+            seed.copy_from_slice(AsRef::<[u8]>::as_ref(&comm_r));
+
+            layer_challenges.derive::<T>(leaves, &self.replica_id, &seed, k as u8)
+        } else if layer_challenges.is_synthetic() {
+            let comm_r = self.comm_r("synthetic")?;
+
+            self.synthetic_indexes(layer_challenges, partition_k)
+                .into_iter()
+                .map(|index| {
+                    layer_challenges.derive_synthetic::<T>(leaves, &self.replica_id, &comm_r, index)
+                })
+                .collect()
+        } else {
+            layer_challenges.derive::<T>(leaves, &self.replica_id, &self.seed, k as u8)
+        };
+
+        Ok(challenges)
+    }
+
+    /// Returns the indexes of the synthetic challenges selected by `seed` for partition
+    /// `partition_k`.
+    pub fn synthetic_indexes(
+        &self,
+        layer_challenges: &LayerChallenges,
+        partition_k: Option<usize>,
     ) -> Vec<usize> {
         let k = partition_k.unwrap_or(0);
+        layer_challenges.derive_synthetic_indexes::<T>(&self.replica_id, &self.seed, k as u8)
+    }
 
-        layer_challenges.derive::<T>(leaves, &self.replica_id, &self.seed, k as u8)
+    /// Derives all synthetic challenges, which are proven ahead of the seed. Fails if `tau` is
+    /// missing.
+    pub fn all_synthetic_challenges(
+        &self,
+        layer_challenges: &LayerChallenges,
+        leaves: usize,
+    ) -> Result<Vec<usize>> {
+        let comm_r = self.comm_r("synthetic")?;
+
+        Ok((0..layer_challenges.synthetic_count())
+            .map(|index| {
+                layer_challenges.derive_synthetic::<T>(leaves, &self.replica_id, &comm_r, index)
+            })
+            .collect())
+    }
+
+    fn comm_r(&self, challenges: &str) -> Result<T> {
+        self.tau
+            .as_ref()
+            .map(|tau| tau.comm_r)
+            .with_context(|| format!("tau is required for {} challenges", challenges))
     }
 }
 
@@ -470,8 +529,18 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAux<Tree, G> {
         }
         trace!("tree c deleted");
 
-        for i in 0..t_aux.labels.labels.len() {
-            let cur_config = t_aux.labels.labels[i].clone();
+        t_aux.clear_labels()
+    }
+
+    // 'clear_labels' discards the persisted layer data only, which is
+    // no longer required once the synthetic proofs have been generated.
+    pub fn clear_labels(&self) -> Result<()> {
+        let cached = |config: &StoreConfig| {
+            Path::new(&StoreConfig::data_path(&config.path, &config.id)).exists()
+        };
+
+        for i in 0..self.labels.labels.len() {
+            let cur_config = self.labels.labels[i].clone();
             if cached(&cur_config) {
                 DiskStore::<<Tree::Hasher as Hasher>::Domain>::delete(cur_config)
                     .with_context(|| format!("labels {}", i))?;
@@ -479,6 +548,15 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAux<Tree, G> {
             }
         }
 
+        if let Some(config) = self.labels.labels.first() {
+            let manifest_path = LayerManifest::path(&config.path);
+            if manifest_path.exists() {
+                remove_file(&manifest_path)
+                    .with_context(|| format!("Failed to delete {:?}", &manifest_path))?;
+                trace!("layer manifest deleted");
+            }
+        }
+
         Ok(())
     }
 }
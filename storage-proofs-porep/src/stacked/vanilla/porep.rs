@@ -5,6 +5,7 @@ use merkletree::store::StoreConfig;
 use storage_proofs_core::{
     error::Result,
     merkle::{BinaryMerkleTree, MerkleTreeTrait},
+    util::NODE_SIZE,
     Data,
 };
 
@@ -64,12 +65,21 @@ impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> PoRep<'a, Tre
     }
 
     fn extract(
-        _pp: &PublicParams<Tree>,
-        _replica_id: &<Tree::Hasher as Hasher>::Domain,
-        _data: &mut [u8],
-        _node: usize,
+        pp: &PublicParams<Tree>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        data: &mut [u8],
+        node: usize,
         _config: Option<StoreConfig>,
     ) -> Result<()> {
-        unimplemented!();
+        let start = node * NODE_SIZE;
+        let end = start + NODE_SIZE;
+
+        Self::extract_range(
+            &pp.graph,
+            &pp.layer_challenges,
+            replica_id,
+            &mut data[start..end],
+            node..node + 1,
+        )
     }
 }
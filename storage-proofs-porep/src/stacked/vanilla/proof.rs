@@ -1,35 +1,46 @@
+use std::any::TypeId;
+use std::cmp::{max, min};
 use std::fs;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
 use std::marker::PhantomData;
+use std::ops::Range;
 use std::panic::panic_any;
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
-use anyhow::Context;
-use bincode::deserialize;
+use anyhow::{bail, ensure, Context};
+use bellperson::bls::Fr;
+use bincode::{deserialize, serialize};
 use fdlimit::raise_fd_limit;
-use filecoin_hashers::{Domain, HashFunction, Hasher, PoseidonArity};
+use ff::Field;
+use filecoin_hashers::{poseidon::PoseidonHasher, Domain, HashFunction, Hasher, PoseidonArity};
+use fr32::fr_into_bytes;
 use generic_array::typenum::{Unsigned, U0, U11, U2, U8};
 use lazy_static::lazy_static;
 use log::{error, info, trace};
+use mapr::MmapOptions;
 use merkletree::{
     merkle::{get_merkle_tree_len, is_merkle_tree_size_valid},
-    store::{Store, StoreConfig},
+    store::{DiskStore, Store, StoreConfig},
 };
 use rayon::prelude::{
     IndexedParallelIterator, IntoParallelIterator, ParallelIterator, ParallelSliceMut,
 };
 use storage_proofs_core::{
     cache_key::CacheKey,
+    cancel::CancellationToken,
     data::Data,
     drgraph::Graph,
     error::Result,
-    measurements::{measure_op, Operation},
+    measurements::{measure_op, measure_op_with_tags, Operation},
     merkle::{
         create_disk_tree, create_lc_tree, get_base_tree_count, split_config,
         split_config_and_replica, BinaryMerkleTree, DiskTree, LCTree, MerkleProofTrait, MerkleTree,
         MerkleTreeTrait,
     },
-    settings::SETTINGS,
+    progress::{NoProgress, ProgressEvent, ProgressObserver},
+    run_options::RunOptions,
+    settings,
     util::{default_rows_to_discard, NODE_SIZE},
 };
 use yastl::Pool;
@@ -37,15 +48,16 @@ use yastl::Pool;
 use crate::{
     encode::{decode, encode},
     stacked::vanilla::{
+        cache::ParentCache,
         challenges::LayerChallenges,
         column::Column,
         create_label,
         graph::StackedBucketGraph,
-        hash::hash_single_column,
+        hash::{build_tree_rows, hash_preimages},
         params::{
-            get_node, Labels, LabelsCache, PersistentAux, Proof, PublicInputs, PublicParams,
-            ReplicaColumnProof, Tau, TemporaryAux, TemporaryAuxCache, TransformedLayers,
-            BINARY_ARITY,
+            get_node, Labels, LabelsCache, PersistentAux, PrivateInputs, Proof, PublicInputs,
+            PublicParams, ReplicaColumnProof, Tau, TemporaryAux, TemporaryAuxCache,
+            TransformedLayers, BINARY_ARITY,
         },
         EncodingProof, LabelingProof,
     },
@@ -54,6 +66,22 @@ use crate::{
 
 pub const TOTAL_PARENTS: usize = 37;
 
+/// The number of nodes decoded at once when regenerating tree_d.
+const DECODE_CHUNK_NODES: usize = 1 << 16;
+
+/// The number of hashed batches queued for the tree writer thread, beyond the one being written.
+const TREE_WRITER_QUEUE_LEN: usize = 1;
+
+/// The number of synthetic challenges proven at once, which bounds the proofs held in memory.
+const SYNTHETIC_PROOFS_CHUNK: usize = 1 << 10;
+
+/// Identifies a synthetic proofs file, followed by the version of its layout.
+const SYNTHETIC_PROOFS_MAGIC: [u8; 4] = *b"SYNP";
+const SYNTHETIC_PROOFS_VERSION: u32 = 1;
+/// The length of the synthetic proofs file header: the magic, the version, the number of proofs
+/// and the length of each proof.
+const SYNTHETIC_PROOFS_HEADER_LEN: usize = 4 + 4 + 8 + 8;
+
 lazy_static! {
     /// Ensure that only one `TreeBuilder` or `ColumnTreeBuilder` uses the GPU at a time.
     /// Curently, this is accomplished by only instantiating at most one at a time.
@@ -99,6 +127,27 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             t_aux.tree_d.root()
         );
 
+        (0..partition_count)
+            .map(|k| {
+                trace!("proving partition {}/{}", k + 1, partition_count);
+
+                // Derive the set of challenges we are proving over.
+                let challenges = pub_inputs.challenges(layer_challenges, graph_size, Some(k))?;
+
+                Self::prove_challenges(graph, pub_inputs, p_aux, t_aux, layers, challenges)
+            })
+            .collect()
+    }
+
+    /// Generates the vanilla proofs of `challenges`.
+    fn prove_challenges(
+        graph: &StackedBucketGraph<Tree::Hasher>,
+        pub_inputs: &PublicInputs<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
+        p_aux: &PersistentAux<<Tree::Hasher as Hasher>::Domain>,
+        t_aux: &TemporaryAuxCache<Tree, G>,
+        layers: usize,
+        challenges: Vec<usize>,
+    ) -> Result<Vec<Proof<Tree, G>>> {
         let get_drg_parents_columns = |x: usize| -> Result<Vec<Column<Tree::Hasher>>> {
             let base_degree = graph.base_graph().degree();
 
@@ -129,144 +178,325 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 .collect()
         };
 
-        (0..partition_count)
-            .map(|k| {
-                trace!("proving partition {}/{}", k + 1, partition_count);
+        // Stacked commitment specifics
+        challenges
+            .into_par_iter()
+            .enumerate()
+            .map(|(challenge_index, challenge)| {
+                trace!(" challenge {} ({})", challenge, challenge_index);
+                assert!(challenge < graph.size(), "Invalid challenge");
+                assert!(challenge > 0, "Invalid challenge");
+
+                // Initial data layer openings (c_X in Comm_D)
+                let comm_d_proof = t_aux.tree_d.gen_proof(challenge)?;
+                assert!(comm_d_proof.validate(challenge));
+
+                // Stacked replica column openings
+                let rcp = {
+                    let (c_x, drg_parents, exp_parents) = {
+                        assert_eq!(p_aux.comm_c, t_aux.tree_c.root());
+                        let tree_c = &t_aux.tree_c;
+
+                        // All labels in C_X
+                        trace!("  c_x");
+                        let c_x = t_aux.column(challenge as u32)?.into_proof(tree_c)?;
+
+                        // All labels in the DRG parents.
+                        trace!("  drg_parents");
+                        let drg_parents = get_drg_parents_columns(challenge)?
+                            .into_iter()
+                            .map(|column| column.into_proof(tree_c))
+                            .collect::<Result<_>>()?;
+
+                        // Labels for the expander parents
+                        trace!("  exp_parents");
+                        let exp_parents = get_exp_parents_columns(challenge)?
+                            .into_iter()
+                            .map(|column| column.into_proof(tree_c))
+                            .collect::<Result<_>>()?;
+
+                        (c_x, drg_parents, exp_parents)
+                    };
 
-                // Derive the set of challenges we are proving over.
-                let challenges = pub_inputs.challenges(layer_challenges, graph_size, Some(k));
+                    ReplicaColumnProof {
+                        c_x,
+                        drg_parents,
+                        exp_parents,
+                    }
+                };
+
+                // Final replica layer openings
+                trace!("final replica layer openings");
+                let comm_r_last_proof = t_aux
+                    .tree_r_last
+                    .gen_cached_proof(challenge, Some(t_aux.tree_r_last_config_rows_to_discard))?;
+
+                debug_assert!(comm_r_last_proof.validate(challenge));
+
+                // Labeling Proofs Layer 1..l
+                let mut labeling_proofs = Vec::with_capacity(layers);
+                let mut encoding_proof = None;
+
+                for layer in 1..=layers {
+                    trace!("  encoding proof layer {}", layer,);
+                    let parents_data: Vec<<Tree::Hasher as Hasher>::Domain> = if layer == 1 {
+                        let mut parents = vec![0; graph.base_graph().degree()];
+                        graph.base_parents(challenge, &mut parents)?;
+
+                        parents
+                            .into_par_iter()
+                            .map(|parent| t_aux.domain_node_at_layer(layer, parent))
+                            .collect::<Result<_>>()?
+                    } else {
+                        let mut parents = vec![0; graph.degree()];
+                        graph.parents(challenge, &mut parents)?;
+                        let base_parents_count = graph.base_graph().degree();
+
+                        parents
+                            .into_par_iter()
+                            .enumerate()
+                            .map(|(i, parent)| {
+                                if i < base_parents_count {
+                                    // parents data for base parents is from the current layer
+                                    t_aux.domain_node_at_layer(layer, parent)
+                                } else {
+                                    // parents data for exp parents is from the previous layer
+                                    t_aux.domain_node_at_layer(layer - 1, parent)
+                                }
+                            })
+                            .collect::<Result<_>>()?
+                    };
 
-                // Stacked commitment specifics
-                challenges
-                    .into_par_iter()
-                    .enumerate()
-                    .map(|(challenge_index, challenge)| {
-                        trace!(" challenge {} ({})", challenge, challenge_index);
-                        assert!(challenge < graph.size(), "Invalid challenge");
-                        assert!(challenge > 0, "Invalid challenge");
-
-                        // Initial data layer openings (c_X in Comm_D)
-                        let comm_d_proof = t_aux.tree_d.gen_proof(challenge)?;
-                        assert!(comm_d_proof.validate(challenge));
-
-                        // Stacked replica column openings
-                        let rcp = {
-                            let (c_x, drg_parents, exp_parents) = {
-                                assert_eq!(p_aux.comm_c, t_aux.tree_c.root());
-                                let tree_c = &t_aux.tree_c;
-
-                                // All labels in C_X
-                                trace!("  c_x");
-                                let c_x = t_aux.column(challenge as u32)?.into_proof(tree_c)?;
-
-                                // All labels in the DRG parents.
-                                trace!("  drg_parents");
-                                let drg_parents = get_drg_parents_columns(challenge)?
-                                    .into_iter()
-                                    .map(|column| column.into_proof(tree_c))
-                                    .collect::<Result<_>>()?;
-
-                                // Labels for the expander parents
-                                trace!("  exp_parents");
-                                let exp_parents = get_exp_parents_columns(challenge)?
-                                    .into_iter()
-                                    .map(|column| column.into_proof(tree_c))
-                                    .collect::<Result<_>>()?;
-
-                                (c_x, drg_parents, exp_parents)
-                            };
+                    // repeat parents
+                    let mut parents_data_full = vec![Default::default(); TOTAL_PARENTS];
+                    for chunk in parents_data_full.chunks_mut(parents_data.len()) {
+                        chunk.copy_from_slice(&parents_data[..chunk.len()]);
+                    }
 
-                            ReplicaColumnProof {
-                                c_x,
-                                drg_parents,
-                                exp_parents,
-                            }
-                        };
+                    let proof = LabelingProof::<Tree::Hasher>::new(
+                        layer as u32,
+                        challenge as u64,
+                        parents_data_full.clone(),
+                    );
 
-                        // Final replica layer openings
-                        trace!("final replica layer openings");
-                        let comm_r_last_proof = t_aux.tree_r_last.gen_cached_proof(
-                            challenge,
-                            Some(t_aux.tree_r_last_config_rows_to_discard),
-                        )?;
+                    {
+                        let labeled_node = rcp.c_x.get_node_at_layer(layer)?;
+                        assert!(
+                            proof.verify(&pub_inputs.replica_id, &labeled_node),
+                            "Invalid encoding proof generated at layer {}",
+                            layer,
+                        );
+                        trace!("Valid encoding proof generated at layer {}", layer);
+                    }
 
-                        debug_assert!(comm_r_last_proof.validate(challenge));
+                    labeling_proofs.push(proof);
 
-                        // Labeling Proofs Layer 1..l
-                        let mut labeling_proofs = Vec::with_capacity(layers);
-                        let mut encoding_proof = None;
+                    if layer == layers {
+                        encoding_proof = Some(EncodingProof::new(
+                            layer as u32,
+                            challenge as u64,
+                            parents_data_full,
+                        ));
+                    }
+                }
 
-                        for layer in 1..=layers {
-                            trace!("  encoding proof layer {}", layer,);
-                            let parents_data: Vec<<Tree::Hasher as Hasher>::Domain> = if layer == 1
-                            {
-                                let mut parents = vec![0; graph.base_graph().degree()];
-                                graph.base_parents(challenge, &mut parents)?;
+                Ok(Proof {
+                    comm_d_proofs: comm_d_proof,
+                    replica_column_proofs: rcp,
+                    comm_r_last_proof,
+                    labeling_proofs,
+                    encoding_proof: encoding_proof.expect("invalid tapering"),
+                })
+            })
+            .collect()
+    }
 
-                                parents
-                                    .into_par_iter()
-                                    .map(|parent| t_aux.domain_node_at_layer(layer, parent))
-                                    .collect::<Result<_>>()?
-                            } else {
-                                let mut parents = vec![0; graph.degree()];
-                                graph.parents(challenge, &mut parents)?;
-                                let base_parents_count = graph.base_graph().degree();
-
-                                parents
-                                    .into_par_iter()
-                                    .enumerate()
-                                    .map(|(i, parent)| {
-                                        if i < base_parents_count {
-                                            // parents data for base parents is from the current layer
-                                            t_aux.domain_node_at_layer(layer, parent)
-                                        } else {
-                                            // parents data for exp parents is from the previous layer
-                                            t_aux.domain_node_at_layer(layer - 1, parent)
-                                        }
-                                    })
-                                    .collect::<Result<_>>()?
-                            };
+    /// Generates the vanilla proofs of all synthetic challenges and writes them to
+    /// `proofs_path`, so that the proofs selected by the seed can later be read with
+    /// `read_synthetic_proofs`, without the layers.
+    ///
+    /// The proofs are written after a header holding their number and length, in order of their
+    /// synthetic index, each serialized to the same number of bytes. The file is written under a
+    /// temporary name and only moved to `proofs_path` once complete, so an interrupted run never
+    /// leaves a partial file there.
+    pub fn prove_synthetic_challenges(
+        pub_params: &PublicParams<Tree>,
+        pub_inputs: &PublicInputs<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
+        priv_inputs: &PrivateInputs<Tree, G>,
+        proofs_path: &Path,
+    ) -> Result<()> {
+        let layer_challenges = &pub_params.layer_challenges;
+        ensure!(
+            layer_challenges.is_synthetic(),
+            "challenges are not synthetic"
+        );
 
-                            // repeat parents
-                            let mut parents_data_full = vec![Default::default(); TOTAL_PARENTS];
-                            for chunk in parents_data_full.chunks_mut(parents_data.len()) {
-                                chunk.copy_from_slice(&parents_data[..chunk.len()]);
-                            }
+        let layers = layer_challenges.layers();
+        assert!(layers > 0);
+        assert_eq!(priv_inputs.t_aux.labels.len(), layers);
 
-                            let proof = LabelingProof::<Tree::Hasher>::new(
-                                layer as u32,
-                                challenge as u64,
-                                parents_data_full.clone(),
-                            );
+        // Sanity checks on restored trees.
+        assert!(pub_inputs.tau.is_some());
+        assert_eq!(
+            pub_inputs.tau.as_ref().expect("as_ref failure").comm_d,
+            priv_inputs.t_aux.tree_d.root()
+        );
 
-                            {
-                                let labeled_node = rcp.c_x.get_node_at_layer(layer)?;
-                                assert!(
-                                    proof.verify(&pub_inputs.replica_id, &labeled_node),
-                                    "Invalid encoding proof generated at layer {}",
-                                    layer,
-                                );
-                                trace!("Valid encoding proof generated at layer {}", layer);
-                            }
+        let challenges =
+            pub_inputs.all_synthetic_challenges(layer_challenges, pub_params.graph.size())?;
 
-                            labeling_proofs.push(proof);
+        let mut tmp_path = proofs_path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
 
-                            if layer == layers {
-                                encoding_proof = Some(EncodingProof::new(
-                                    layer as u32,
-                                    challenge as u64,
-                                    parents_data_full,
-                                ));
-                            }
-                        }
+        let result = Self::write_synthetic_proofs(
+            pub_params,
+            pub_inputs,
+            priv_inputs,
+            &challenges,
+            &tmp_path,
+        )
+        .and_then(|_| {
+            fs::rename(&tmp_path, proofs_path)
+                .with_context(|| format!("could not move {:?} to {:?}", tmp_path, proofs_path))
+        });
+        if result.is_err() && tmp_path.exists() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+
+        result
+    }
+
+    fn write_synthetic_proofs(
+        pub_params: &PublicParams<Tree>,
+        pub_inputs: &PublicInputs<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
+        priv_inputs: &PrivateInputs<Tree, G>,
+        challenges: &[usize],
+        path: &Path,
+    ) -> Result<()> {
+        let layers = pub_params.layer_challenges.layers();
+        let file =
+            fs::File::create(path).with_context(|| format!("could not create file {:?}", path))?;
+        let mut writer = BufWriter::new(file);
+        // The proof length is only known once the first proof is serialized.
+        writer
+            .write_all(&[0; SYNTHETIC_PROOFS_HEADER_LEN])
+            .with_context(|| format!("could not write to file {:?}", path))?;
+        let mut proof_len = None;
+
+        for (i, chunk) in challenges.chunks(SYNTHETIC_PROOFS_CHUNK).enumerate() {
+            trace!(
+                "proving synthetic challenges {}/{}",
+                i * SYNTHETIC_PROOFS_CHUNK + chunk.len(),
+                challenges.len()
+            );
+            let proofs = Self::prove_challenges(
+                &pub_params.graph,
+                pub_inputs,
+                &priv_inputs.p_aux,
+                &priv_inputs.t_aux,
+                layers,
+                chunk.to_vec(),
+            )?;
+
+            for proof in &proofs {
+                let proof_bytes = serialize(proof)?;
+                let expected_len = *proof_len.get_or_insert(proof_bytes.len());
+                ensure!(
+                    proof_bytes.len() == expected_len,
+                    "synthetic proofs must have the same size"
+                );
+                writer
+                    .write_all(&proof_bytes)
+                    .with_context(|| format!("could not write to file {:?}", path))?;
+            }
+        }
+
+        let mut header = Vec::with_capacity(SYNTHETIC_PROOFS_HEADER_LEN);
+        header.extend_from_slice(&SYNTHETIC_PROOFS_MAGIC);
+        header.extend_from_slice(&SYNTHETIC_PROOFS_VERSION.to_le_bytes());
+        header.extend_from_slice(&(challenges.len() as u64).to_le_bytes());
+        header.extend_from_slice(&(proof_len.unwrap_or(0) as u64).to_le_bytes());
+        writer
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| writer.write_all(&header))
+            .and_then(|_| writer.flush())
+            .with_context(|| format!("could not write to file {:?}", path))?;
+        writer
+            .get_ref()
+            .sync_all()
+            .with_context(|| format!("could not sync file {:?}", path))?;
 
-                        Ok(Proof {
-                            comm_d_proofs: comm_d_proof,
-                            replica_column_proofs: rcp,
-                            comm_r_last_proof,
-                            labeling_proofs,
-                            encoding_proof: encoding_proof.expect("invalid tapering"),
-                        })
+        Ok(())
+    }
+
+    /// Reads the synthetic proofs selected by the seed of `pub_inputs` from `proofs_path`,
+    /// as written by `prove_synthetic_challenges`.
+    pub fn read_synthetic_proofs(
+        pub_params: &PublicParams<Tree>,
+        pub_inputs: &PublicInputs<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
+        partition_count: usize,
+        proofs_path: &Path,
+    ) -> Result<Vec<Vec<Proof<Tree, G>>>> {
+        let layer_challenges = &pub_params.layer_challenges;
+        ensure!(
+            layer_challenges.is_synthetic(),
+            "challenges are not synthetic"
+        );
+
+        let mut file = fs::File::open(proofs_path)
+            .with_context(|| format!("could not open file {:?}", proofs_path))?;
+        let file_len = file.metadata()?.len();
+
+        let mut header = [0u8; SYNTHETIC_PROOFS_HEADER_LEN];
+        file.read_exact(&mut header)
+            .with_context(|| format!("could not read synthetic proofs header {:?}", proofs_path))?;
+        let field = |range: Range<usize>| {
+            let mut bytes = [0u8; 8];
+            bytes[..range.len()].copy_from_slice(&header[range]);
+            u64::from_le_bytes(bytes)
+        };
+        ensure!(
+            header[..4] == SYNTHETIC_PROOFS_MAGIC,
+            "{:?} is not a synthetic proofs file",
+            proofs_path
+        );
+        let version = field(4..8);
+        ensure!(
+            version == u64::from(SYNTHETIC_PROOFS_VERSION),
+            "unsupported synthetic proofs file version {} in {:?}",
+            version,
+            proofs_path
+        );
+        let count = field(8..16);
+        let proof_len = field(16..24);
+        ensure!(
+            count == layer_challenges.synthetic_count() as u64
+                && proof_len > 0
+                && Some(file_len)
+                    == count
+                        .checked_mul(proof_len)
+                        .and_then(|len| len.checked_add(SYNTHETIC_PROOFS_HEADER_LEN as u64)),
+            "invalid synthetic proofs file {:?}: {} proofs of {} bytes in {} bytes",
+            proofs_path,
+            count,
+            proof_len,
+            file_len
+        );
+        let proof_len = proof_len as usize;
+        let mut proof_bytes = vec![0u8; proof_len];
+
+        (0..partition_count)
+            .map(|k| {
+                pub_inputs
+                    .synthetic_indexes(layer_challenges, Some(k))
+                    .into_iter()
+                    .map(|index| {
+                        let offset = SYNTHETIC_PROOFS_HEADER_LEN + index * proof_len;
+                        file.seek(SeekFrom::Start(offset as u64))?;
+                        file.read_exact(&mut proof_bytes)
+                            .with_context(|| format!("could not read synthetic proof {}", index))?;
+                        deserialize(&proof_bytes).map_err(Into::into)
                     })
                     .collect()
             })
@@ -307,34 +537,92 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         Ok(())
     }
 
+    /// Decodes the sealed nodes in `nodes`, without storing the labels on disk and with the last
+    /// layer only labeled up to the end of the range, see `create_labels_for_decoding_range`.
+    /// `data` holds exactly the sealed bytes of `nodes` and is decoded in place.
+    pub fn extract_range(
+        graph: &StackedBucketGraph<Tree::Hasher>,
+        layer_challenges: &LayerChallenges,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        data: &mut [u8],
+        nodes: Range<usize>,
+    ) -> Result<()> {
+        trace!("extract_range {:?}", nodes);
+
+        ensure!(
+            data.len() == nodes.len() * NODE_SIZE,
+            "data length {} does not match node range {:?}",
+            data.len(),
+            nodes
+        );
+
+        let mut parent_cache = graph.parent_cache()?;
+        let keys = create_label::single::create_labels_for_decoding_range::<Tree, _>(
+            graph,
+            &mut parent_cache,
+            layer_challenges.layers(),
+            replica_id,
+            nodes,
+        )?;
+
+        for (key, encoded_node_bytes) in keys.into_iter().zip(data.chunks_mut(NODE_SIZE)) {
+            let encoded_node =
+                <Tree::Hasher as Hasher>::Domain::try_from_bytes(encoded_node_bytes)?;
+            let data_node = decode::<<Tree::Hasher as Hasher>::Domain>(key, encoded_node);
+
+            // store result in the data
+            encoded_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&data_node));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the parent cache used for labeling, or `None` if the parents are computed on the
+    /// fly from the graph instead. This follows `sdr_parents_on_the_fly` of the current settings,
+    /// so it can be chosen per seal by running it under `settings::with_config`.
+    fn sdr_parent_cache(graph: &StackedBucketGraph<Tree::Hasher>) -> Result<Option<ParentCache>> {
+        if settings::current().sdr_parents_on_the_fly {
+            info!("computing parents on the fly");
+            Ok(None)
+        } else {
+            graph.parent_cache().map(Some)
+        }
+    }
+
     /// Generates the layers as needed for encoding.
     pub fn generate_labels_for_encoding(
         graph: &StackedBucketGraph<Tree::Hasher>,
         layer_challenges: &LayerChallenges,
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         config: StoreConfig,
+        cancel: &CancellationToken,
+        progress: &dyn ProgressObserver,
     ) -> Result<(Labels<Tree>, Vec<LayerState>)> {
-        let mut parent_cache = graph.parent_cache()?;
+        let mut parent_cache = Self::sdr_parent_cache(graph)?;
 
         #[cfg(feature = "multicore-sdr")]
         {
-            if SETTINGS.use_multicore_sdr {
+            if settings::current().use_multicore_sdr {
                 info!("multi core replication");
                 create_label::multi::create_labels_for_encoding(
                     graph,
-                    &parent_cache,
+                    parent_cache.as_ref(),
                     layer_challenges.layers(),
                     replica_id,
                     config,
+                    cancel,
+                    progress,
                 )
             } else {
                 info!("single core replication");
                 create_label::single::create_labels_for_encoding(
                     graph,
-                    &mut parent_cache,
+                    parent_cache.as_mut(),
                     layer_challenges.layers(),
                     replica_id,
                     config,
+                    cancel,
+                    progress,
                 )
             }
         }
@@ -344,10 +632,12 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             info!("single core replication");
             create_label::single::create_labels_for_encoding(
                 graph,
-                &mut parent_cache,
+                parent_cache.as_mut(),
                 layer_challenges.layers(),
                 replica_id,
                 config,
+                cancel,
+                progress,
             )
         }
     }
@@ -359,15 +649,15 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         config: StoreConfig,
     ) -> Result<LabelsCache<Tree>> {
-        let mut parent_cache = graph.parent_cache()?;
+        let mut parent_cache = Self::sdr_parent_cache(graph)?;
 
         #[cfg(feature = "multicore-sdr")]
         {
-            if SETTINGS.use_multicore_sdr {
+            if settings::current().use_multicore_sdr {
                 info!("multi core replication");
                 create_label::multi::create_labels_for_decoding(
                     graph,
-                    &parent_cache,
+                    parent_cache.as_ref(),
                     layer_challenges.layers(),
                     replica_id,
                     config,
@@ -376,7 +666,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 info!("single core replication");
                 create_label::single::create_labels_for_decoding(
                     graph,
-                    &mut parent_cache,
+                    parent_cache.as_mut(),
                     layer_challenges.layers(),
                     replica_id,
                     config,
@@ -389,7 +679,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             info!("single core replication");
             create_label::single::create_labels_for_decoding(
                 graph,
-                &mut parent_cache,
+                parent_cache.as_mut(),
                 layer_challenges.layers(),
                 replica_id,
                 config,
@@ -416,6 +706,60 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         Ok(tree)
     }
 
+    /// Generates tree_c over the columns of `labels`, picking the column arity from the number
+    /// of layers, and returns its root.
+    fn build_tree_c(
+        layers: usize,
+        nodes_count: usize,
+        tree_count: usize,
+        configs: Vec<StoreConfig>,
+        labels: &LabelsCache<Tree>,
+        cancel: &CancellationToken,
+        progress: &dyn ProgressObserver,
+    ) -> Result<<Tree::Hasher as Hasher>::Domain> {
+        let tree_c_root = match layers {
+            2 => {
+                let tree_c = Self::generate_tree_c::<U2, Tree::Arity>(
+                    layers,
+                    nodes_count,
+                    tree_count,
+                    configs,
+                    labels,
+                    cancel,
+                    progress,
+                )?;
+                tree_c.root()
+            }
+            8 => {
+                let tree_c = Self::generate_tree_c::<U8, Tree::Arity>(
+                    layers,
+                    nodes_count,
+                    tree_count,
+                    configs,
+                    labels,
+                    cancel,
+                    progress,
+                )?;
+                tree_c.root()
+            }
+            11 => {
+                let tree_c = Self::generate_tree_c::<U11, Tree::Arity>(
+                    layers,
+                    nodes_count,
+                    tree_count,
+                    configs,
+                    labels,
+                    cancel,
+                    progress,
+                )?;
+                tree_c.root()
+            }
+            _ => panic_any("Unsupported column arity"),
+        };
+
+        Ok(tree_c_root)
+    }
+
     #[cfg(any(feature = "gpu"))]
     fn generate_tree_c<ColumnArity, TreeArity>(
         layers: usize,
@@ -423,18 +767,22 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_count: usize,
         configs: Vec<StoreConfig>,
         labels: &LabelsCache<Tree>,
+        cancel: &CancellationToken,
+        progress: &dyn ProgressObserver,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         ColumnArity: 'static + PoseidonArity,
         TreeArity: PoseidonArity,
     {
-        if SETTINGS.use_gpu_column_builder {
+        if settings::current().use_gpu_column_builder {
             Self::generate_tree_c_gpu::<ColumnArity, TreeArity>(
                 layers,
                 nodes_count,
                 tree_count,
                 configs,
                 labels,
+                cancel,
+                progress,
             )
         } else {
             Self::generate_tree_c_cpu::<ColumnArity, TreeArity>(
@@ -443,6 +791,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 tree_count,
                 configs,
                 labels,
+                cancel,
+                progress,
             )
         }
     }
@@ -454,6 +804,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_count: usize,
         configs: Vec<StoreConfig>,
         labels: &LabelsCache<Tree>,
+        cancel: &CancellationToken,
+        progress: &dyn ProgressObserver,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         ColumnArity: 'static + PoseidonArity,
@@ -465,6 +817,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             tree_count,
             configs,
             labels,
+            cancel,
+            progress,
         )
     }
 
@@ -476,6 +830,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_count: usize,
         configs: Vec<StoreConfig>,
         labels: &LabelsCache<Tree>,
+        cancel: &CancellationToken,
+        progress: &dyn ProgressObserver,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         ColumnArity: 'static + PoseidonArity,
@@ -495,6 +851,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         };
 
         info!("generating tree c using the GPU");
+        cancel.check()?;
         // Build the tree for CommC
         measure_op(Operation::GenerateTreeC, || {
             info!("Building column hashes");
@@ -508,9 +865,10 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             // Override these values with care using environment variables:
             // FIL_PROOFS_MAX_GPU_COLUMN_BATCH_SIZE, FIL_PROOFS_MAX_GPU_TREE_BATCH_SIZE, and
             // FIL_PROOFS_COLUMN_WRITE_BATCH_SIZE respectively.
-            let max_gpu_column_batch_size = SETTINGS.max_gpu_column_batch_size as usize;
-            let max_gpu_tree_batch_size = SETTINGS.max_gpu_tree_batch_size as usize;
-            let column_write_batch_size = SETTINGS.column_write_batch_size as usize;
+            let config = settings::current();
+            let max_gpu_column_batch_size = config.max_gpu_column_batch_size as usize;
+            let max_gpu_tree_batch_size = config.max_gpu_tree_batch_size as usize;
+            let column_write_batch_size = config.column_write_batch_size as usize;
 
             // This channel will receive batches of columns and add them to the ColumnTreeBuilder.
             let (builder_tx, builder_rx) = channel();
@@ -646,7 +1004,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     }
                 });
 
-                for config in &configs {
+                for (i, config) in configs.iter().enumerate() {
                     let (base_data, tree_data) = writer_rx
                         .recv()
                         .expect("failed to receive base_data, tree_data for tree_c");
@@ -717,6 +1075,12 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                         .sync()
                         .expect("store sync failure");
                     trace!("done writing tree_c store data");
+
+                    progress.on_event(ProgressEvent::TreeBatchFinished {
+                        tree: CacheKey::CommCTree,
+                        batch: i,
+                        batches: tree_count,
+                    });
                 }
             });
 
@@ -732,61 +1096,113 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_count: usize,
         configs: Vec<StoreConfig>,
         labels: &LabelsCache<Tree>,
+        cancel: &CancellationToken,
+        progress: &dyn ProgressObserver,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         ColumnArity: PoseidonArity,
         TreeArity: PoseidonArity,
     {
+        use crossbeam::channel::bounded;
+
         info!("generating tree c using the CPU");
         measure_op(Operation::GenerateTreeC, || {
             info!("Building column hashes");
 
-            let mut trees = Vec::with_capacity(tree_count);
+            // Columns are read, hashed and persisted in batches of 'column_write_batch_size'
+            // nodes, which can be overridden with FIL_PROOFS_COLUMN_WRITE_BATCH_SIZE.
+            let batch_size = tree_batch_size::<TreeArity>(nodes_count);
+
             for (i, config) in configs.iter().enumerate() {
-                let mut hashes: Vec<<Tree::Hasher as Hasher>::Domain> =
-                    vec![<Tree::Hasher as Hasher>::Domain::default(); nodes_count];
-
-                THREAD_POOL.scoped(|s| {
-                    let n = num_cpus::get();
-
-                    // only split if we have at least two elements per thread
-                    let num_chunks = if n > nodes_count * 2 { 1 } else { n };
-
-                    // chunk into n chunks
-                    let chunk_size = (nodes_count as f64 / num_chunks as f64).ceil() as usize;
-
-                    // calculate all n chunks in parallel
-                    for (chunk, hashes_chunk) in hashes.chunks_mut(chunk_size).enumerate() {
-                        let labels = &labels;
-
-                        s.execute(move || {
-                            for (j, hash) in hashes_chunk.iter_mut().enumerate() {
-                                let data: Vec<_> = (1..=layers)
-                                    .map(|layer| {
-                                        let store = labels.labels_for_layer(layer);
-                                        let el: <Tree::Hasher as Hasher>::Domain = store
-                                            .read_at((i * nodes_count) + j + chunk * chunk_size)
-                                            .expect("store read_at failure");
-                                        el.into()
-                                    })
-                                    .collect();
+                cancel.check()?;
+
+                let mut hashes = vec![Fr::zero(); nodes_count];
 
-                                *hash = hash_single_column(&data).into();
+                if is_poseidon_tree::<Tree>() {
+                    let store = create_tree_store::<<Tree::Hasher as Hasher>::Domain>(
+                        config,
+                        Tree::Arity::to_usize(),
+                    )?;
+
+                    crossbeam::thread::scope(|s| -> Result<()> {
+                        // This channel will receive the tree data to be written to disk, while
+                        // the next batch is hashed. It is bounded so that a slow disk stalls the
+                        // hashing instead of queueing up copies of the batches.
+                        let (writer_tx, writer_rx) =
+                            bounded::<(usize, Vec<Fr>)>(TREE_WRITER_QUEUE_LEN);
+                        let writer = s.spawn(move |_| -> Result<()> {
+                            let mut store = store;
+                            for (offset, data) in writer_rx {
+                                write_tree_data(&mut store, offset, &data)?;
                             }
+                            store.sync()
                         });
-                    }
-                });
 
-                info!("building base tree_c {}/{}", i + 1, tree_count);
-                trees.push(
+                        measure_op_with_tags(
+                            Operation::ColumnHashing,
+                            &[("tree", i.to_string())],
+                            || {
+                                Self::hash_columns::<ColumnArity, _>(
+                                    layers,
+                                    nodes_count,
+                                    i,
+                                    batch_size,
+                                    labels,
+                                    &mut hashes,
+                                    |offset, batch| {
+                                        writer_tx
+                                            .send((offset, batch.to_vec()))
+                                            .expect("failed to send tree_c base data");
+                                    },
+                                )
+                            },
+                        )?;
+
+                        info!("building base tree_c {}/{}", i + 1, tree_count);
+                        let rows = build_tree_rows::<TreeArity>(&hashes);
+                        ensure!(
+                            nodes_count + rows.len() == config.size.expect("config size failure"),
+                            "invalid tree_c size"
+                        );
+                        writer_tx
+                            .send((nodes_count, rows))
+                            .expect("failed to send tree_c tree data");
+
+                        drop(writer_tx);
+                        writer.join().expect("join failed")
+                    })
+                    .expect("crossbeam scope failure")?;
+                } else {
+                    measure_op_with_tags(
+                        Operation::ColumnHashing,
+                        &[("tree", i.to_string())],
+                        || {
+                            Self::hash_columns::<ColumnArity, _>(
+                                layers,
+                                nodes_count,
+                                i,
+                                batch_size,
+                                labels,
+                                &mut hashes,
+                                |_, _| {},
+                            )
+                        },
+                    )?;
+
+                    info!("building base tree_c {}/{}", i + 1, tree_count);
                     DiskTree::<Tree::Hasher, Tree::Arity, U0, U0>::from_par_iter_with_config(
-                        hashes.into_par_iter(),
+                        hashes.into_par_iter().map(Into::into),
                         config.clone(),
-                    ),
-                );
-            }
+                    )
+                    .with_context(|| format!("failed tree_c CPU {}/{}", i + 1, tree_count))?;
+                }
 
-            assert_eq!(tree_count, trees.len());
+                progress.on_event(ProgressEvent::TreeBatchFinished {
+                    tree: CacheKey::CommCTree,
+                    batch: i,
+                    batches: tree_count,
+                });
+            }
 
             create_disk_tree::<
                 DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
@@ -794,6 +1210,61 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         })
     }
 
+    /// Hashes the columns of the `tree_index`th tree into `hashes` in batches of `batch_size`
+    /// nodes, passing each hashed batch and its node offset to `on_batch`.
+    fn hash_columns<ColumnArity, F>(
+        layers: usize,
+        nodes_count: usize,
+        tree_index: usize,
+        batch_size: usize,
+        labels: &LabelsCache<Tree>,
+        hashes: &mut [Fr],
+        mut on_batch: F,
+    ) -> Result<()>
+    where
+        ColumnArity: PoseidonArity,
+        F: FnMut(usize, &[Fr]),
+    {
+        ensure!(
+            ColumnArity::to_usize() == layers,
+            "invalid column arity for {} layers",
+            layers
+        );
+
+        let mut node_index = 0;
+        while node_index != nodes_count {
+            let chunk_len = min(nodes_count - node_index, batch_size);
+            let start = tree_index * nodes_count + node_index;
+
+            let layer_labels = (1..=layers)
+                .map(|layer| {
+                    labels
+                        .labels_for_layer(layer)
+                        .read_range(start..start + chunk_len)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            // Lay out the columns one after another, as expected by `hash_preimages`.
+            let mut columns = vec![Fr::zero(); chunk_len * layers];
+            columns
+                .par_chunks_mut(layers)
+                .enumerate()
+                .for_each(|(node, column)| {
+                    for (el, labels) in column.iter_mut().zip(&layer_labels) {
+                        *el = labels[node].into();
+                    }
+                });
+
+            let batch = &mut hashes[node_index..node_index + chunk_len];
+            hash_preimages::<ColumnArity>(&columns, batch);
+            on_batch(node_index, batch);
+
+            node_index += chunk_len;
+        }
+
+        Ok(())
+    }
+
     #[cfg(any(feature = "gpu"))]
     fn generate_tree_r_last<TreeArity>(
         data: &mut Data<'_>,
@@ -802,11 +1273,13 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_r_last_config: StoreConfig,
         replica_path: PathBuf,
         labels: &LabelsCache<Tree>,
+        cancel: &CancellationToken,
+        progress: &dyn ProgressObserver,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         TreeArity: PoseidonArity,
     {
-        if SETTINGS.use_gpu_tree_builder {
+        if settings::current().use_gpu_tree_builder {
             Self::generate_tree_r_last_gpu::<TreeArity>(
                 data,
                 nodes_count,
@@ -814,6 +1287,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 tree_r_last_config,
                 replica_path,
                 labels,
+                cancel,
+                progress,
             )
         } else {
             Self::generate_tree_r_last_cpu::<TreeArity>(
@@ -823,6 +1298,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 tree_r_last_config,
                 replica_path,
                 labels,
+                cancel,
+                progress,
             )
         }
     }
@@ -835,6 +1312,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_r_last_config: StoreConfig,
         replica_path: PathBuf,
         labels: &LabelsCache<Tree>,
+        cancel: &CancellationToken,
+        progress: &dyn ProgressObserver,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         TreeArity: PoseidonArity,
@@ -846,6 +1325,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             tree_r_last_config,
             replica_path,
             labels,
+            cancel,
+            progress,
         )
     }
 
@@ -857,6 +1338,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_r_last_config: StoreConfig,
         replica_path: PathBuf,
         labels: &LabelsCache<Tree>,
+        cancel: &CancellationToken,
+        progress: &dyn ProgressObserver,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         TreeArity: PoseidonArity,
@@ -865,6 +1348,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         use std::cmp::min;
         use std::fs::OpenOptions;
         use std::io::Write;
+        use std::sync::atomic::{AtomicUsize, Ordering};
 
         use bellperson::bls::Fr;
         use fr32::fr_into_bytes;
@@ -885,22 +1369,31 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         let last_layer_labels = labels.labels_for_last_layer()?;
 
         info!("generating tree r last using the GPU");
-        let max_gpu_tree_batch_size = SETTINGS.max_gpu_tree_batch_size as usize;
+        let max_gpu_tree_batch_size = settings::current().max_gpu_tree_batch_size as usize;
 
         // This channel will receive batches of leaf nodes and add them to the TreeBuilder.
         let (builder_tx, builder_rx) = channel::<(Vec<Fr>, bool)>();
         let config_count = configs.len(); // Don't move config into closure below.
         let configs = &configs;
         let tree_r_last_config = &tree_r_last_config;
+        // The number of replica nodes encoded so far, to decode them back on cancellation.
+        let encoded_nodes = &AtomicUsize::new(0);
+        let encode_data = &mut *data;
+        let mut trees_written = 0;
 
         THREAD_POOL.scoped(|s| {
             // This channel will receive the finished tree data to be written to disk.
             let (writer_tx, writer_rx) = channel::<Vec<Fr>>();
 
+            // On cancellation, the leaves stop being sent, which ends the builder and the
+            // writer below once they drained their channels.
             s.execute(move || {
                 for i in 0..config_count {
                     let mut node_index = 0;
                     while node_index != nodes_count {
+                        if cancel.is_cancelled() {
+                            return;
+                        }
                         let chunked_nodes_count =
                             min(nodes_count - node_index, max_gpu_tree_batch_size);
                         let start = (i * nodes_count) + node_index;
@@ -932,7 +1425,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                                     bytes_into_fr(&chunk).expect("Could not create Fr from bytes.")
                                 })
                                 .zip(
-                                    data.as_mut()[(start * NODE_SIZE)..(end * NODE_SIZE)]
+                                    encode_data.as_mut()[(start * NODE_SIZE)..(end * NODE_SIZE)]
                                         .par_chunks_mut(NODE_SIZE),
                                 )
                                 .map(|(key, data_node_bytes)| {
@@ -963,6 +1456,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
                         let encoded: Vec<_> =
                             encoded_data.into_par_iter().map(|x| x.into()).collect();
+                        encoded_nodes.store(end, Ordering::SeqCst);
 
                         let is_final = node_index == nodes_count;
                         builder_tx
@@ -987,8 +1481,11 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 // Loop until all trees for all configs have been built.
                 for i in 0..config_count {
                     loop {
-                        let (encoded, is_final) =
-                            builder_rx.recv().expect("failed to recv encoded data");
+                        let (encoded, is_final) = match builder_rx.recv() {
+                            Ok(batch) => batch,
+                            // The leaves stopped early, as tree building was cancelled.
+                            Err(_) => return,
+                        };
 
                         // Just add non-final leaf batches.
                         if !is_final {
@@ -1014,10 +1511,12 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 }
             });
 
-            for config in configs.iter() {
-                let tree_data = writer_rx
-                    .recv()
-                    .expect("failed to receive tree_data for tree_r_last");
+            for (i, config) in configs.iter().enumerate() {
+                let tree_data = match writer_rx.recv() {
+                    Ok(tree_data) => tree_data,
+                    // The trees stopped early, as tree building was cancelled.
+                    Err(_) => break,
+                };
 
                 let tree_data_len = tree_data.len();
                 let cache_size = get_merkle_tree_cache_size(
@@ -1052,9 +1551,29 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     .expect("failed to open file for tree_r_last");
                 f.write_all(&flat_tree_data)
                     .expect("failed to wrote tree_r_last data");
+                trees_written += 1;
+
+                progress.on_event(ProgressEvent::TreeBatchFinished {
+                    tree: CacheKey::CommRLastTree,
+                    batch: i,
+                    batches: tree_count,
+                });
             }
         });
 
+        Self::check_cancel_encoding(
+            cancel,
+            data,
+            last_layer_labels,
+            encoded_nodes.load(Ordering::SeqCst),
+        )?;
+        ensure!(
+            trees_written == config_count,
+            "only {}/{} base trees of tree_r_last were built",
+            trees_written,
+            config_count
+        );
+
         create_lc_tree::<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>(
             tree_r_last_config.size.expect("config size failure"),
             &configs,
@@ -1069,6 +1588,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_r_last_config: StoreConfig,
         replica_path: PathBuf,
         labels: &LabelsCache<Tree>,
+        cancel: &CancellationToken,
+        progress: &dyn ProgressObserver,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         TreeArity: PoseidonArity,
@@ -1085,27 +1606,13 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
         info!("generating tree r last using the CPU");
         let size = Store::len(last_layer_labels);
+        let batch_size = tree_batch_size::<TreeArity>(size / tree_count);
 
         let mut start = 0;
         let mut end = size / tree_count;
 
         for (i, config) in configs.iter().enumerate() {
-            let encoded_data = last_layer_labels
-                .read_range(start..end)?
-                .into_par_iter()
-                .zip(
-                    data.as_mut()[(start * NODE_SIZE)..(end * NODE_SIZE)].par_chunks_mut(NODE_SIZE),
-                )
-                .map(|(key, data_node_bytes)| {
-                    let data_node =
-                        <Tree::Hasher as Hasher>::Domain::try_from_bytes(data_node_bytes)
-                            .expect("try from bytes failed");
-                    let encoded_node = encode::<<Tree::Hasher as Hasher>::Domain>(key, data_node);
-                    data_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&encoded_node));
-
-                    encoded_node
-                });
-
+            Self::check_cancel_encoding(cancel, data, last_layer_labels, start)?;
             info!(
                 "building base tree_r_last with CPU {}/{}",
                 i + 1,
@@ -1125,11 +1632,44 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     .expect("failed to remove tree_r_last_store_path");
             }
 
-            LCTree::<Tree::Hasher, Tree::Arity, U0, U0>::from_par_iter_with_config(
-                encoded_data,
-                config.clone(),
-            )
-            .with_context(|| format!("failed tree_r_last CPU {}/{}", i + 1, tree_count))?;
+            if is_poseidon_tree::<Tree>() {
+                // Encode the replica in batches, hashing each batch into the first tree row,
+                // as the leaves themselves are read from the replica.
+                let arity = TreeArity::to_usize();
+                let mut tree_data = vec![Fr::zero(); (end - start) / arity];
+                for batch_start in (start..end).step_by(batch_size) {
+                    Self::check_cancel_encoding(cancel, data, last_layer_labels, batch_start)?;
+                    let batch_end = min(batch_start + batch_size, end);
+                    let encoded = Self::encode_replica_nodes(
+                        data,
+                        last_layer_labels,
+                        batch_start..batch_end,
+                    )?
+                    .into_par_iter()
+                    .map(Into::into)
+                    .collect::<Vec<Fr>>();
+                    hash_preimages::<TreeArity>(
+                        &encoded,
+                        &mut tree_data[(batch_start - start) / arity..(batch_end - start) / arity],
+                    );
+                }
+                write_lc_tree_cache::<TreeArity>(tree_data, config)?;
+            } else {
+                let encoded_data = Self::encode_replica_nodes(data, last_layer_labels, start..end)?
+                    .into_par_iter();
+
+                LCTree::<Tree::Hasher, Tree::Arity, U0, U0>::from_par_iter_with_config(
+                    encoded_data,
+                    config.clone(),
+                )
+                .with_context(|| format!("failed tree_r_last CPU {}/{}", i + 1, tree_count))?;
+            }
+
+            progress.on_event(ProgressEvent::TreeBatchFinished {
+                tree: CacheKey::CommRLastTree,
+                batch: i,
+                batches: tree_count,
+            });
 
             start = end;
             end += size / tree_count;
@@ -1142,6 +1682,78 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         )
     }
 
+    /// Encodes the replica `nodes` in place with the last layer labels, returning the encoded
+    /// nodes.
+    fn encode_replica_nodes(
+        data: &mut Data<'_>,
+        last_layer_labels: &DiskStore<<Tree::Hasher as Hasher>::Domain>,
+        nodes: Range<usize>,
+    ) -> Result<Vec<<Tree::Hasher as Hasher>::Domain>> {
+        let data_bytes = &mut data.as_mut()[(nodes.start * NODE_SIZE)..(nodes.end * NODE_SIZE)];
+
+        Ok(last_layer_labels
+            .read_range(nodes)?
+            .into_par_iter()
+            .zip(data_bytes.par_chunks_mut(NODE_SIZE))
+            .map(|(key, data_node_bytes)| {
+                let data_node = <Tree::Hasher as Hasher>::Domain::try_from_bytes(data_node_bytes)
+                    .expect("try from bytes failed");
+                let encoded_node = encode::<<Tree::Hasher as Hasher>::Domain>(key, data_node);
+                data_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&encoded_node));
+
+                encoded_node
+            })
+            .collect())
+    }
+
+    /// Decodes the replica `nodes` in place with the last layer labels, undoing
+    /// `encode_replica_nodes`.
+    fn decode_replica_nodes(
+        data: &mut Data<'_>,
+        last_layer_labels: &DiskStore<<Tree::Hasher as Hasher>::Domain>,
+        nodes: Range<usize>,
+    ) -> Result<()> {
+        let data_bytes = &mut data.as_mut()[(nodes.start * NODE_SIZE)..(nodes.end * NODE_SIZE)];
+
+        data_bytes
+            .par_chunks_mut(DECODE_CHUNK_NODES * NODE_SIZE)
+            .enumerate()
+            .try_for_each(|(chunk_index, chunk)| -> Result<()> {
+                let start = nodes.start + chunk_index * DECODE_CHUNK_NODES;
+                let keys = last_layer_labels.read_range(start..start + chunk.len() / NODE_SIZE)?;
+
+                for (key, node_bytes) in keys.into_iter().zip(chunk.chunks_mut(NODE_SIZE)) {
+                    let encoded_node =
+                        <Tree::Hasher as Hasher>::Domain::try_from_bytes(node_bytes)?;
+                    let data_node = decode::<<Tree::Hasher as Hasher>::Domain>(key, encoded_node);
+                    node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&data_node));
+                }
+
+                Ok(())
+            })
+    }
+
+    /// Returns `Error::Cancelled` once `cancel` is observed while tree_r_last is built, after
+    /// decoding the first `encoded_nodes` nodes of the replica back, so that it is left as it
+    /// was.
+    fn check_cancel_encoding(
+        cancel: &CancellationToken,
+        data: &mut Data<'_>,
+        last_layer_labels: &DiskStore<<Tree::Hasher as Hasher>::Domain>,
+        encoded_nodes: usize,
+    ) -> Result<()> {
+        if let Err(err) = cancel.check() {
+            info!(
+                "tree_r_last cancelled, decoding {} replica nodes",
+                encoded_nodes
+            );
+            Self::decode_replica_nodes(data, last_layer_labels, 0..encoded_nodes)?;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn transform_and_replicate_layers(
         graph: &StackedBucketGraph<Tree::Hasher>,
         layer_challenges: &LayerChallenges,
@@ -1153,8 +1765,15 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
     ) -> Result<TransformedLayers<Tree, G>> {
         // Generate key layers.
         let labels = measure_op(Operation::EncodeWindowTimeAll, || {
-            Self::generate_labels_for_encoding(graph, layer_challenges, replica_id, config.clone())
-                .context("failed to generate labels")
+            Self::generate_labels_for_encoding(
+                graph,
+                layer_challenges,
+                replica_id,
+                config.clone(),
+                &CancellationToken::default(),
+                &NoProgress,
+            )
+            .context("failed to generate labels")
         })?
         .0;
 
@@ -1166,11 +1785,69 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             config,
             replica_path,
             labels,
+            &CancellationToken::default(),
+            &NoProgress,
         )
         .context("failed to transform")
     }
 
+    /// Builds tree_c, tree_d and tree_r_last from the labels and encodes the replica.
+    ///
+    /// `cancel` is checked before each tree, before every base tree of tree_c and tree_r_last,
+    /// and between the batches hashed on the CPU. The replica is encoded in place while tree_r_last
+    /// is built, so on cancellation the nodes encoded so far are decoded back, leaving the
+    /// replica as it was, and the trees written so far are removed.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn transform_and_replicate_layers_inner(
+        graph: &StackedBucketGraph<Tree::Hasher>,
+        layer_challenges: &LayerChallenges,
+        data: Data<'_>,
+        data_tree: Option<BinaryMerkleTree<G>>,
+        config: StoreConfig,
+        replica_path: PathBuf,
+        label_configs: Labels<Tree>,
+        cancel: &CancellationToken,
+        progress: &dyn ProgressObserver,
+    ) -> Result<TransformedLayers<Tree, G>> {
+        // A tree_d passed in belongs to the caller, only the trees built here are cleaned up.
+        let mut tree_keys = vec![CacheKey::CommCTree, CacheKey::CommRLastTree];
+        if data_tree.is_none() {
+            tree_keys.push(CacheKey::CommDTree);
+        }
+
+        let result = Self::build_trees_and_encode(
+            graph,
+            layer_challenges,
+            data,
+            data_tree,
+            config.clone(),
+            replica_path,
+            label_configs,
+            cancel,
+            progress,
+        );
+
+        if result.is_err() && cancel.is_cancelled() {
+            info!("replication cancelled, removing partially built trees");
+            let tree_count = get_base_tree_count::<Tree>();
+            for key in tree_keys {
+                let tree_config = StoreConfig::from_config(&config, key.to_string(), None);
+                let configs = if key == CacheKey::CommDTree {
+                    vec![tree_config]
+                } else {
+                    split_config(tree_config, tree_count)?
+                };
+                for tree_config in &configs {
+                    remove_store_data(tree_config)?;
+                }
+            }
+        }
+
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build_trees_and_encode(
         graph: &StackedBucketGraph<Tree::Hasher>,
         layer_challenges: &LayerChallenges,
         mut data: Data<'_>,
@@ -1178,6 +1855,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         config: StoreConfig,
         replica_path: PathBuf,
         label_configs: Labels<Tree>,
+        cancel: &CancellationToken,
+        progress: &dyn ProgressObserver,
     ) -> Result<TransformedLayers<Tree, G>> {
         trace!("transform_and_replicate_layers");
         let nodes_count = graph.size();
@@ -1254,41 +1933,20 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             None => error!("Failed to raise the fd limit"),
         };
 
-        let tree_c_root = match layers {
-            2 => {
-                let tree_c = Self::generate_tree_c::<U2, Tree::Arity>(
-                    layers,
-                    nodes_count,
-                    tree_count,
-                    configs,
-                    &labels,
-                )?;
-                tree_c.root()
-            }
-            8 => {
-                let tree_c = Self::generate_tree_c::<U8, Tree::Arity>(
-                    layers,
-                    nodes_count,
-                    tree_count,
-                    configs,
-                    &labels,
-                )?;
-                tree_c.root()
-            }
-            11 => {
-                let tree_c = Self::generate_tree_c::<U11, Tree::Arity>(
-                    layers,
-                    nodes_count,
-                    tree_count,
-                    configs,
-                    &labels,
-                )?;
-                tree_c.root()
-            }
-            _ => panic_any("Unsupported column arity"),
-        };
+        cancel.check()?;
+        let tree_c_root = Self::build_tree_c(
+            layers,
+            nodes_count,
+            tree_count,
+            configs,
+            &labels,
+            cancel,
+            progress,
+        )?;
         info!("tree_c done");
 
+        cancel.check()?;
+
         // Build the MerkleTree over the original data (if needed).
         let tree_d = match data_tree {
             Some(t) => {
@@ -1300,9 +1958,16 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             None => {
                 trace!("building merkle tree for the original data");
                 data.ensure_data()?;
-                measure_op(Operation::CommD, || {
+                let tree_d = measure_op(Operation::CommD, || {
                     Self::build_binary_tree::<G>(data.as_ref(), tree_d_config.clone())
-                })?
+                })?;
+                progress.on_event(ProgressEvent::TreeBatchFinished {
+                    tree: CacheKey::CommDTree,
+                    batch: 0,
+                    batches: 1,
+                });
+
+                tree_d
             }
         };
         tree_d_config.size = Some(tree_d.len());
@@ -1313,6 +1978,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         let tree_d_root = tree_d.root();
         drop(tree_d);
 
+        cancel.check()?;
+
         // Encode original data into the last layer.
         info!("building tree_r_last");
         let tree_r_last = measure_op(Operation::GenerateTreeRLast, || {
@@ -1323,6 +1990,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 tree_r_last_config.clone(),
                 replica_path.clone(),
                 &labels,
+                cancel,
+                progress,
             )
             .context("failed to generate tree_r_last")
         })?;
@@ -1361,11 +2030,31 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         pp: &'a PublicParams<Tree>,
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         config: StoreConfig,
+    ) -> Result<Labels<Tree>> {
+        Self::replicate_phase1_with_options(pp, replica_id, config, &RunOptions::default())
+    }
+
+    /// Phase1 of replication, reporting the labeling progress to `options.progress`. Returns
+    /// `Error::Cancelled` once `options.cancel` is observed. Layers completed before that are
+    /// kept on disk, and are reused by the next run.
+    pub fn replicate_phase1_with_options(
+        pp: &'a PublicParams<Tree>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        config: StoreConfig,
+        options: &RunOptions<'_>,
     ) -> Result<Labels<Tree>> {
         info!("replicate_phase1");
+        let _config = options.enter_config();
 
         let labels = measure_op(Operation::EncodeWindowTimeAll, || {
-            Self::generate_labels_for_encoding(&pp.graph, &pp.layer_challenges, replica_id, config)
+            Self::generate_labels_for_encoding(
+                &pp.graph,
+                &pp.layer_challenges,
+                replica_id,
+                config,
+                &options.cancel,
+                options.progress,
+            )
         })?
         .0;
 
@@ -1384,8 +2073,36 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
     ) -> Result<(
         <Self as PoRep<'a, Tree::Hasher, G>>::Tau,
         <Self as PoRep<'a, Tree::Hasher, G>>::ProverAux,
+    )> {
+        Self::replicate_phase2_with_options(
+            pp,
+            label_configs,
+            data,
+            data_tree,
+            config,
+            replica_path,
+            &RunOptions::default(),
+        )
+    }
+
+    /// Phase2 of replication, reporting every finished base tree to `options.progress`. Returns
+    /// `Error::Cancelled` once `options.cancel` is observed, and removes the trees it built so
+    /// far.
+    #[allow(clippy::type_complexity)]
+    pub fn replicate_phase2_with_options(
+        pp: &'a PublicParams<Tree>,
+        label_configs: Labels<Tree>,
+        data: Data<'a>,
+        data_tree: BinaryMerkleTree<G>,
+        config: StoreConfig,
+        replica_path: PathBuf,
+        options: &RunOptions<'_>,
+    ) -> Result<(
+        <Self as PoRep<'a, Tree::Hasher, G>>::Tau,
+        <Self as PoRep<'a, Tree::Hasher, G>>::ProverAux,
     )> {
         info!("replicate_phase2");
+        let _config = options.enter_config();
 
         let (tau, paux, taux) = Self::transform_and_replicate_layers_inner(
             &pp.graph,
@@ -1395,11 +2112,115 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             config,
             replica_path,
             label_configs,
+            &options.cancel,
+            options.progress,
         )?;
 
         Ok((tau, (paux, taux)))
     }
 
+    /// Rebuilds tree_c from the layer labels persisted in `t_aux`, and replaces any existing
+    /// tree_c files with it if its root matches `comm_c`.
+    ///
+    /// The tree is built next to the existing files, which are left untouched if building it
+    /// fails or its root does not match.
+    pub fn regenerate_tree_c(
+        t_aux: &TemporaryAux<Tree, G>,
+        comm_c: &<Tree::Hasher as Hasher>::Domain,
+    ) -> Result<()> {
+        info!("regenerate_tree_c");
+
+        let layers = t_aux.labels.len();
+        ensure!(layers > 0, "no layer labels to regenerate tree_c from");
+
+        let tree_count = get_base_tree_count::<Tree>();
+        let nodes_count = Store::len(&t_aux.labels.labels_for_layer(1)?) / tree_count;
+
+        let configs = split_config(t_aux.tree_c_config.clone(), tree_count)?;
+        let tmp_configs = split_config(regenerate_config(&t_aux.tree_c_config), tree_count)?;
+        for tmp_config in &tmp_configs {
+            remove_store_data(tmp_config)?;
+        }
+
+        let labels = LabelsCache::<Tree>::new(&t_aux.labels).context("failed to open labels")?;
+
+        let root = measure_op(Operation::GenerateTreeC, || {
+            Self::build_tree_c(
+                layers,
+                nodes_count,
+                tree_count,
+                tmp_configs.clone(),
+                &labels,
+                &CancellationToken::default(),
+                &NoProgress,
+            )
+        });
+
+        replace_store_data(&tmp_configs, &configs, root, comm_c, "comm_c")
+    }
+
+    /// Rebuilds tree_d by decoding the sealed replica with the last layer labels persisted in
+    /// `t_aux`, and replaces any existing tree_d file with it if its root matches `comm_d`.
+    ///
+    /// The replica is mapped copy-on-write, so it is never modified. The tree is built next to
+    /// the existing file, which is left untouched if building it fails or its root does not
+    /// match.
+    pub fn regenerate_tree_d(
+        t_aux: &TemporaryAux<Tree, G>,
+        replica_path: &Path,
+        comm_d: &<G as Hasher>::Domain,
+    ) -> Result<()> {
+        info!("regenerate_tree_d");
+
+        let last_layer_labels = t_aux.labels.labels_for_last_layer()?;
+        let nodes_count = Store::len(&last_layer_labels);
+
+        let replica = fs::File::open(replica_path)
+            .with_context(|| format!("could not open replica_path={:?}", replica_path))?;
+        let mut data = unsafe {
+            MmapOptions::new()
+                .map_copy(&replica)
+                .with_context(|| format!("could not mmap replica_path={:?}", replica_path))?
+        };
+        ensure!(
+            data.len() == nodes_count * NODE_SIZE,
+            "replica size {} does not match the {} labelled nodes",
+            data.len(),
+            nodes_count
+        );
+
+        data.par_chunks_mut(DECODE_CHUNK_NODES * NODE_SIZE)
+            .enumerate()
+            .try_for_each(|(chunk_index, chunk)| -> Result<()> {
+                let start = chunk_index * DECODE_CHUNK_NODES;
+                let keys = last_layer_labels.read_range(start..start + chunk.len() / NODE_SIZE)?;
+
+                for (key, node_bytes) in keys.into_iter().zip(chunk.chunks_mut(NODE_SIZE)) {
+                    let encoded_node =
+                        <Tree::Hasher as Hasher>::Domain::try_from_bytes(node_bytes)?;
+                    let data_node = decode::<<Tree::Hasher as Hasher>::Domain>(key, encoded_node);
+                    node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&data_node));
+                }
+
+                Ok(())
+            })?;
+
+        let tmp_config = regenerate_config(&t_aux.tree_d_config);
+        remove_store_data(&tmp_config)?;
+
+        let root = measure_op(Operation::CommD, || {
+            Self::build_binary_tree::<G>(&data, tmp_config.clone()).map(|tree_d| tree_d.root())
+        });
+
+        replace_store_data(
+            &[tmp_config],
+            &[t_aux.tree_d_config.clone()],
+            root,
+            comm_d,
+            "comm_d",
+        )
+    }
+
     // Assumes data is all zeros.
     // Replica path is used to create configs, but is not read.
     // Instead new zeros are provided (hence the need for replica to be all zeros).
@@ -1432,9 +2253,9 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             tree_count,
         )?;
 
-        if SETTINGS.use_gpu_tree_builder {
+        if settings::current().use_gpu_tree_builder {
             info!("generating tree r last using the GPU");
-            let max_gpu_tree_batch_size = SETTINGS.max_gpu_tree_batch_size as usize;
+            let max_gpu_tree_batch_size = settings::current().max_gpu_tree_batch_size as usize;
 
             let _gpu_lock = GPU_LOCK.lock().expect("failed to get gpu lock");
             let batcher = Some(
@@ -1652,3 +2473,178 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         Ok((comm_r, p_aux))
     }
 }
+
+/// Removes the data file of the store described by `config`, if present, so that rebuilding it
+/// does not reopen a stale file.
+fn remove_store_data(config: &StoreConfig) -> Result<()> {
+    let path = StoreConfig::data_path(&config.path, &config.id);
+    if path.exists() {
+        fs::remove_file(&path).with_context(|| format!("could not remove {:?}", path))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the config of the store into which the store described by `config` is regenerated,
+/// before it replaces it.
+fn regenerate_config(config: &StoreConfig) -> StoreConfig {
+    StoreConfig {
+        id: format!("{}-regenerate", config.id),
+        ..config.clone()
+    }
+}
+
+/// Moves the data files of the regenerated stores of `tmp_configs` over the ones of `configs` if
+/// the regenerated `root` is the `expected` one, and removes them otherwise.
+fn replace_store_data<D: Domain>(
+    tmp_configs: &[StoreConfig],
+    configs: &[StoreConfig],
+    root: Result<D>,
+    expected: &D,
+    name: &str,
+) -> Result<()> {
+    match root {
+        Ok(root) if root == *expected => {}
+        result => {
+            for tmp_config in tmp_configs {
+                remove_store_data(tmp_config)?;
+            }
+            let root = result?;
+            bail!(
+                "regenerated root {:?} does not match {} {:?}",
+                root,
+                name,
+                expected
+            );
+        }
+    }
+
+    for (tmp_config, config) in tmp_configs.iter().zip(configs) {
+        let from = StoreConfig::data_path(&tmp_config.path, &tmp_config.id);
+        let to = StoreConfig::data_path(&config.path, &config.id);
+        fs::rename(&from, &to).with_context(|| format!("could not move {:?} to {:?}", from, to))?;
+    }
+
+    Ok(())
+}
+
+/// Returns true if the trees of `Tree` are hashed with Poseidon, which allows building them with
+/// the `hash` helpers.
+fn is_poseidon_tree<Tree: 'static + MerkleTreeTrait>() -> bool {
+    TypeId::of::<Tree::Hasher>() == TypeId::of::<PoseidonHasher>()
+}
+
+/// The number of nodes hashed and persisted at once when building a tree on the CPU, a multiple
+/// of the tree arity.
+fn tree_batch_size<TreeArity: Unsigned>(nodes_count: usize) -> usize {
+    let arity = TreeArity::to_usize();
+    let column_write_batch_size = settings::current().column_write_batch_size as usize;
+
+    min(max(column_write_batch_size / arity, 1) * arity, nodes_count)
+}
+
+/// Builds the rows of a base tree of arity `A` above `first_row`, the hashes of its leaves, and
+/// persists them to the data file of `config` as a level cache store would, without the leaves
+/// and the other discarded rows.
+fn write_lc_tree_cache<A: PoseidonArity>(first_row: Vec<Fr>, config: &StoreConfig) -> Result<()> {
+    use merkletree::merkle::{get_merkle_tree_cache_size, get_merkle_tree_leafs};
+
+    let arity = A::to_usize();
+    let mut tree_data = first_row;
+    let rows = build_tree_rows::<A>(&tree_data);
+    tree_data.extend(rows);
+
+    // Only the rows above the discarded ones are persisted.
+    let cache_size = get_merkle_tree_cache_size(
+        get_merkle_tree_leafs(config.size.expect("config size failure"), arity)?,
+        arity,
+        config.rows_to_discard,
+    )?;
+    ensure!(
+        cache_size <= tree_data.len(),
+        "invalid tree_r_last cache size"
+    );
+
+    let path = StoreConfig::data_path(&config.path, &config.id);
+    trace!(
+        "persisting tree r of len {} with {} rows to discard at path {:?}",
+        cache_size,
+        config.rows_to_discard,
+        path
+    );
+    let mut f = BufWriter::new(fs::File::create(&path)?);
+    for el in &tree_data[tree_data.len() - cache_size..] {
+        f.write_all(&fr_into_bytes(el))?;
+    }
+    f.flush()?;
+
+    Ok(())
+}
+
+/// Creates an empty store for the tree described by `config`, replacing any existing one.
+fn create_tree_store<D: Domain>(config: &StoreConfig, arity: usize) -> Result<DiskStore<D>> {
+    remove_store_data(config)?;
+
+    DiskStore::new_with_config(
+        config.size.expect("config size failure"),
+        arity,
+        config.clone(),
+    )
+    .with_context(|| format!("could not create tree store {:?}", config.id))
+}
+
+/// Writes the tree nodes `data` to `store`, starting at node `offset`.
+fn write_tree_data<D: Domain>(store: &mut DiskStore<D>, offset: usize, data: &[Fr]) -> Result<()> {
+    let buf = data
+        .iter()
+        .flat_map(|el| fr_into_bytes(el))
+        .collect::<Vec<u8>>();
+
+    store.copy_from_slice(&buf, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use filecoin_hashers::poseidon::PoseidonDomain;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::TEST_SEED;
+
+    #[test]
+    fn test_write_lc_tree_cache() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+        let leaves_len = 8 * 8 * 8 * 8;
+        let leaves: Vec<Fr> = (0..leaves_len).map(|_| Fr::random(rng)).collect();
+        let cache_dir = tempfile::tempdir().expect("failed to create tempdir");
+        let store_config = |id: &str| {
+            let mut config = StoreConfig::new(
+                cache_dir.path(),
+                id.to_string(),
+                default_rows_to_discard(leaves_len, 8),
+            );
+            config.size = Some(get_merkle_tree_len(leaves_len, 8).expect("invalid tree size"));
+            config
+        };
+
+        let expected_config = store_config("expected");
+        LCTree::<PoseidonHasher, U8, U0, U0>::from_par_iter_with_config(
+            leaves.clone().into_par_iter().map(PoseidonDomain::from),
+            expected_config.clone(),
+        )
+        .expect("failed to build tree");
+
+        let config = store_config("written");
+        let mut first_row = vec![Fr::zero(); leaves_len / 8];
+        hash_preimages::<U8>(&leaves, &mut first_row);
+        write_lc_tree_cache::<U8>(first_row, &config).expect("failed to write tree");
+
+        let read = |config: &StoreConfig| {
+            fs::read(StoreConfig::data_path(&config.path, &config.id))
+                .expect("failed to read tree data")
+        };
+        assert_eq!(read(&config), read(&expected_config));
+    }
+}
@@ -89,6 +89,10 @@ impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'
             return Ok(false);
         };
 
+        let partition_challenges = (0..partition_proofs.len())
+            .map(|k| pub_inputs.challenges(&pub_params.layer_challenges, graph.size(), Some(k)))
+            .collect::<Result<Vec<_>>>()?;
+
         let res = partition_proofs.par_iter().enumerate().all(|(k, proofs)| {
             trace!(
                 "verifying partition proof {}/{}",
@@ -107,8 +111,7 @@ impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'
                 return false;
             }
 
-            let challenges =
-                pub_inputs.challenges(&pub_params.layer_challenges, graph.size(), Some(k));
+            let challenges = &partition_challenges[k];
 
             proofs.par_iter().enumerate().all(|(i, proof)| {
                 trace!("verify challenge {}/{}", i + 1, challenges.len());
@@ -0,0 +1,340 @@
+use bellperson::{
+    bls::{Bls12, Fr},
+    gadgets::num::AllocatedNum,
+    Circuit, ConstraintSystem, SynthesisError,
+};
+use filecoin_hashers::{HashFunction, Hasher, PoseidonArity};
+use generic_array::typenum::{U0, U2};
+use storage_proofs_core::{
+    compound_proof::CircuitComponent,
+    gadgets::{
+        constraint,
+        por::{AuthPath, PoRCircuit},
+        variables::Root,
+    },
+    merkle::{DiskStore, MerkleProofTrait, MerkleTreeTrait, MerkleTreeWrapper},
+};
+
+use crate::update::vanilla::{ChallengeProof as VanillaChallengeProof, PublicParams};
+
+type TreeAuthPath<T> = AuthPath<
+    <T as MerkleTreeTrait>::Hasher,
+    <T as MerkleTreeTrait>::Arity,
+    <T as MerkleTreeTrait>::SubTreeArity,
+    <T as MerkleTreeTrait>::TopTreeArity,
+>;
+
+/// Proof for a single challenge.
+#[derive(Debug)]
+pub struct ChallengeProof<Tree: MerkleTreeTrait, G: Hasher> {
+    /// The value of the challenged node in the sector key.
+    pub key_leaf: Option<Fr>,
+    /// Inclusion path of the challenged node in the `tree_r_last` of the sector key.
+    pub comm_r_last_old_path: TreeAuthPath<Tree>,
+    /// The value of the challenged node in the new data.
+    pub data_leaf: Option<Fr>,
+    /// Inclusion path of the challenged node in the new tree D.
+    pub comm_d_new_path: AuthPath<G, U2, U0, U0>,
+    /// The value of the challenged node in the new replica.
+    pub replica_leaf: Option<Fr>,
+    /// Inclusion path of the challenged node in the `tree_r_last` of the new replica.
+    pub comm_r_last_new_path: TreeAuthPath<Tree>,
+}
+
+// We must manually implement Clone for all types generic over MerkleTreeTrait (instead of using
+// #[derive(Clone)]) because derive(Clone) will only expand for MerkleTreeTrait types that also
+// implement Clone. Not every MerkleTreeTrait type is Clone-able because not all merkel Store's are
+// Clone-able, therefore deriving Clone would impl Clone for less than all possible Tree types.
+impl<Tree: MerkleTreeTrait, G: 'static + Hasher> Clone for ChallengeProof<Tree, G> {
+    fn clone(&self) -> Self {
+        ChallengeProof {
+            key_leaf: self.key_leaf,
+            comm_r_last_old_path: self.comm_r_last_old_path.clone(),
+            data_leaf: self.data_leaf,
+            comm_d_new_path: self.comm_d_new_path.clone(),
+            replica_leaf: self.replica_leaf,
+            comm_r_last_new_path: self.comm_r_last_new_path.clone(),
+        }
+    }
+}
+
+impl<Tree: MerkleTreeTrait, G: 'static + Hasher> ChallengeProof<Tree, G> {
+    /// Create an empty proof, used in `blank_circuit`s.
+    pub fn empty(params: &PublicParams) -> Self {
+        ChallengeProof {
+            key_leaf: None,
+            comm_r_last_old_path: AuthPath::blank(params.nodes),
+            data_leaf: None,
+            comm_d_new_path: AuthPath::blank(params.nodes),
+            replica_leaf: None,
+            comm_r_last_new_path: AuthPath::blank(params.nodes),
+        }
+    }
+
+    /// Circuit synthesis.
+    pub fn synthesize<CS: ConstraintSystem<Bls12>>(
+        self,
+        mut cs: CS,
+        comm_d_new: &AllocatedNum<Bls12>,
+        comm_r_last_old: &AllocatedNum<Bls12>,
+        comm_r_last_new: &AllocatedNum<Bls12>,
+        rho: &AllocatedNum<Bls12>,
+    ) -> Result<(), SynthesisError> {
+        let ChallengeProof {
+            key_leaf,
+            comm_r_last_old_path,
+            data_leaf,
+            comm_d_new_path,
+            replica_leaf,
+            comm_r_last_new_path,
+        } = self;
+
+        // PrivateInput: data_leaf
+        let data_leaf_num = AllocatedNum::alloc(cs.namespace(|| "data_leaf"), || {
+            data_leaf.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        // enforce inclusion of the data leaf in the new tree D
+        enforce_inclusion(
+            cs.namespace(|| "comm_d_new_inclusion"),
+            comm_d_new_path,
+            comm_d_new,
+            &data_leaf_num,
+        )?;
+
+        // PrivateInput: key_leaf
+        let key_leaf_num = AllocatedNum::alloc(cs.namespace(|| "key_leaf"), || {
+            key_leaf.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        // enforce inclusion of the key leaf in the old tree R
+        enforce_inclusion(
+            cs.namespace(|| "comm_r_last_old_inclusion"),
+            comm_r_last_old_path,
+            comm_r_last_old,
+            &key_leaf_num,
+        )?;
+
+        // PrivateInput: replica_leaf
+        let replica_leaf_num = AllocatedNum::alloc(cs.namespace(|| "replica_leaf"), || {
+            replica_leaf.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        // enforce inclusion of the replica leaf in the new tree R
+        enforce_inclusion(
+            cs.namespace(|| "comm_r_last_new_inclusion"),
+            comm_r_last_new_path,
+            comm_r_last_new,
+            &replica_leaf_num,
+        )?;
+
+        // enforce replica_leaf = key_leaf + data_leaf * rho
+        cs.enforce(
+            || "encoding",
+            |lc| lc + data_leaf_num.get_variable(),
+            |lc| lc + rho.get_variable(),
+            |lc| lc + replica_leaf_num.get_variable() - key_leaf_num.get_variable(),
+        );
+
+        Ok(())
+    }
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> From<VanillaChallengeProof<Tree, G>>
+    for ChallengeProof<Tree, G>
+where
+    Tree::Hasher: 'static,
+{
+    fn from(vanilla_proof: VanillaChallengeProof<Tree, G>) -> Self {
+        let VanillaChallengeProof {
+            proof_r_old,
+            proof_d_new,
+            proof_r_new,
+        } = vanilla_proof;
+
+        ChallengeProof {
+            key_leaf: Some(proof_r_old.leaf().into()),
+            comm_r_last_old_path: proof_r_old.as_options().into(),
+            data_leaf: Some(proof_d_new.leaf().into()),
+            comm_d_new_path: proof_d_new.as_options().into(),
+            replica_leaf: Some(proof_r_new.leaf().into()),
+            comm_r_last_new_path: proof_r_new.as_options().into(),
+        }
+    }
+}
+
+/// Sector update of an empty sector.
+///
+/// # Public Inputs
+///
+/// * `comm_r_old`, `comm_d_new` and `comm_r_new`
+/// * for each challenge, the packed path bits of the openings in the new tree D, the old tree R
+///   and the new tree R
+pub struct EmptySectorUpdateCircuit<Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> {
+    pub(crate) comm_r_old: Option<<Tree::Hasher as Hasher>::Domain>,
+    pub(crate) comm_d_new: Option<G::Domain>,
+    pub(crate) comm_r_new: Option<<Tree::Hasher as Hasher>::Domain>,
+    pub(crate) comm_c: Option<<Tree::Hasher as Hasher>::Domain>,
+    pub(crate) comm_r_last_old: Option<<Tree::Hasher as Hasher>::Domain>,
+    pub(crate) comm_r_last_new: Option<<Tree::Hasher as Hasher>::Domain>,
+
+    // one proof per challenge
+    pub(crate) proofs: Vec<ChallengeProof<Tree, G>>,
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> Clone for EmptySectorUpdateCircuit<Tree, G> {
+    fn clone(&self) -> Self {
+        EmptySectorUpdateCircuit {
+            comm_r_old: self.comm_r_old,
+            comm_d_new: self.comm_d_new,
+            comm_r_new: self.comm_r_new,
+            comm_c: self.comm_c,
+            comm_r_last_old: self.comm_r_last_old,
+            comm_r_last_new: self.comm_r_last_new,
+            proofs: self.proofs.clone(),
+        }
+    }
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> CircuitComponent for EmptySectorUpdateCircuit<Tree, G> {
+    type ComponentPrivateInputs = ();
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> Circuit<Bls12> for EmptySectorUpdateCircuit<Tree, G> {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let EmptySectorUpdateCircuit {
+            comm_r_old,
+            comm_d_new,
+            comm_r_new,
+            comm_c,
+            comm_r_last_old,
+            comm_r_last_new,
+            proofs,
+        } = self;
+
+        // Allocate comm_r_old as Fr
+        let comm_r_old_num = AllocatedNum::alloc(cs.namespace(|| "comm_r_old"), || {
+            comm_r_old
+                .map(Into::into)
+                .ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        // make comm_r_old a public input
+        comm_r_old_num.inputize(cs.namespace(|| "comm_r_old_input"))?;
+
+        // Allocate comm_d_new as Fr
+        let comm_d_new_num = AllocatedNum::alloc(cs.namespace(|| "comm_d_new"), || {
+            comm_d_new
+                .map(Into::into)
+                .ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        // make comm_d_new a public input
+        comm_d_new_num.inputize(cs.namespace(|| "comm_d_new_input"))?;
+
+        // Allocate comm_r_new as Fr
+        let comm_r_new_num = AllocatedNum::alloc(cs.namespace(|| "comm_r_new"), || {
+            comm_r_new
+                .map(Into::into)
+                .ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        // make comm_r_new a public input
+        comm_r_new_num.inputize(cs.namespace(|| "comm_r_new_input"))?;
+
+        // Allocate comm_c as Fr
+        let comm_c_num = AllocatedNum::alloc(cs.namespace(|| "comm_c"), || {
+            comm_c
+                .map(Into::into)
+                .ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        // Allocate comm_r_last_old as Fr
+        let comm_r_last_old_num = AllocatedNum::alloc(cs.namespace(|| "comm_r_last_old"), || {
+            comm_r_last_old
+                .map(Into::into)
+                .ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        // Allocate comm_r_last_new as Fr
+        let comm_r_last_new_num = AllocatedNum::alloc(cs.namespace(|| "comm_r_last_new"), || {
+            comm_r_last_new
+                .map(Into::into)
+                .ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        // Verify comm_r_old = H(comm_c || comm_r_last_old)
+        {
+            let hash_num = <Tree::Hasher as Hasher>::Function::hash2_circuit(
+                cs.namespace(|| "H_comm_c_comm_r_last_old"),
+                &comm_c_num,
+                &comm_r_last_old_num,
+            )?;
+
+            constraint::equal(
+                cs,
+                || "enforce comm_r_old = H(comm_c || comm_r_last_old)",
+                &comm_r_old_num,
+                &hash_num,
+            );
+        }
+
+        // Verify comm_r_new = H(comm_c || comm_r_last_new)
+        {
+            let hash_num = <Tree::Hasher as Hasher>::Function::hash2_circuit(
+                cs.namespace(|| "H_comm_c_comm_r_last_new"),
+                &comm_c_num,
+                &comm_r_last_new_num,
+            )?;
+
+            constraint::equal(
+                cs,
+                || "enforce comm_r_new = H(comm_c || comm_r_last_new)",
+                &comm_r_new_num,
+                &hash_num,
+            );
+        }
+
+        // rho = H(comm_d_new || comm_r_old)
+        let rho_num = <Tree::Hasher as Hasher>::Function::hash2_circuit(
+            cs.namespace(|| "rho"),
+            &comm_d_new_num,
+            &comm_r_old_num,
+        )?;
+
+        for (i, proof) in proofs.into_iter().enumerate() {
+            proof.synthesize(
+                &mut cs.namespace(|| format!("challenge_{}", i)),
+                &comm_d_new_num,
+                &comm_r_last_old_num,
+                &comm_r_last_new_num,
+                &rho_num,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Enforce the inclusion of the given path, to the given leaf and the root.
+fn enforce_inclusion<H, U, V, W, CS: ConstraintSystem<Bls12>>(
+    cs: CS,
+    path: AuthPath<H, U, V, W>,
+    root: &AllocatedNum<Bls12>,
+    leaf: &AllocatedNum<Bls12>,
+) -> Result<(), SynthesisError>
+where
+    H: 'static + Hasher,
+    U: 'static + PoseidonArity,
+    V: 'static + PoseidonArity,
+    W: 'static + PoseidonArity,
+{
+    let root = Root::from_allocated::<CS>(root.clone());
+    let leaf = Root::from_allocated::<CS>(leaf.clone());
+
+    PoRCircuit::<MerkleTreeWrapper<H, DiskStore<H::Domain>, U, V, W>>::synthesize(
+        cs, leaf, path, root, true,
+    )?;
+
+    Ok(())
+}
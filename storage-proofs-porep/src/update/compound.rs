@@ -0,0 +1,166 @@
+use std::marker::PhantomData;
+
+use anyhow::ensure;
+use bellperson::{
+    bls::{Bls12, Fr},
+    Circuit,
+};
+use filecoin_hashers::Hasher;
+use storage_proofs_core::{
+    compound_proof::{CircuitComponent, CompoundProof},
+    error::Result,
+    gadgets::por::PoRCompound,
+    merkle::{BinaryMerkleTree, MerkleTreeTrait},
+    parameter_cache::{CacheableParameters, ParameterSetMetadata},
+    por::{self, PoR},
+    proof::ProofScheme,
+};
+
+use crate::update::{
+    circuit::{ChallengeProof, EmptySectorUpdateCircuit},
+    vanilla::EmptySectorUpdate,
+};
+
+#[allow(dead_code)]
+pub struct EmptySectorUpdateCompound<Tree: MerkleTreeTrait, G: Hasher> {
+    partitions: Option<usize>,
+    _t: PhantomData<Tree>,
+    _g: PhantomData<G>,
+}
+
+impl<C: Circuit<Bls12>, P: ParameterSetMetadata, Tree: MerkleTreeTrait, G: Hasher>
+    CacheableParameters<C, P> for EmptySectorUpdateCompound<Tree, G>
+{
+    fn cache_prefix() -> String {
+        format!("empty-sector-update-{}-{}", Tree::display(), G::name())
+    }
+}
+
+impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher>
+    CompoundProof<'a, EmptySectorUpdate<'a, Tree, G>, EmptySectorUpdateCircuit<Tree, G>>
+    for EmptySectorUpdateCompound<Tree, G>
+{
+    fn generate_public_inputs(
+        pub_in: &<EmptySectorUpdate<'_, Tree, G> as ProofScheme<'_>>::PublicInputs,
+        pub_params: &<EmptySectorUpdate<'_, Tree, G> as ProofScheme<'_>>::PublicParams,
+        k: Option<usize>,
+    ) -> Result<Vec<Fr>> {
+        let mut inputs = Vec::new();
+
+        inputs.push(pub_in.comm_r_old.into());
+        inputs.push(pub_in.comm_d_new.into());
+        inputs.push(pub_in.comm_r_new.into());
+
+        let por_setup_params = por::SetupParams {
+            leaves: pub_params.nodes,
+            private: true,
+        };
+
+        let por_params = PoR::<Tree>::setup(&por_setup_params)?;
+        let por_params_d = PoR::<BinaryMerkleTree<G>>::setup(&por_setup_params)?;
+
+        let challenges = pub_in.challenges(pub_params.challenges_count, pub_params.nodes, k);
+
+        for challenge in challenges.into_iter() {
+            // Inclusion Proof: data node in comm_d_new
+            inputs.extend(generate_inclusion_inputs::<BinaryMerkleTree<G>>(
+                &por_params_d,
+                challenge,
+                k,
+            )?);
+
+            // Inclusion Proof: sector key node in comm_r_last_old
+            inputs.extend(generate_inclusion_inputs::<Tree>(
+                &por_params,
+                challenge,
+                k,
+            )?);
+
+            // Inclusion Proof: replica node in comm_r_last_new
+            inputs.extend(generate_inclusion_inputs::<Tree>(
+                &por_params,
+                challenge,
+                k,
+            )?);
+        }
+
+        Ok(inputs)
+    }
+
+    fn circuit<'b>(
+        public_inputs: &'b <EmptySectorUpdate<'_, Tree, G> as ProofScheme<'_>>::PublicInputs,
+        _component_private_inputs: <EmptySectorUpdateCircuit<Tree, G> as CircuitComponent>::ComponentPrivateInputs,
+        vanilla_proof: &'b <EmptySectorUpdate<'_, Tree, G> as ProofScheme<'_>>::Proof,
+        _public_params: &'b <EmptySectorUpdate<'_, Tree, G> as ProofScheme<'_>>::PublicParams,
+        _partition_k: Option<usize>,
+    ) -> Result<EmptySectorUpdateCircuit<Tree, G>> {
+        ensure!(
+            !vanilla_proof.challenge_proofs.is_empty(),
+            "Cannot create a circuit with no vanilla proofs"
+        );
+
+        let comm_r_last_old = vanilla_proof.comm_r_last_old();
+        let comm_r_last_new = vanilla_proof.comm_r_last_new();
+
+        // ensure consistency
+        ensure!(
+            vanilla_proof
+                .challenge_proofs
+                .iter()
+                .all(|p| p.proof_r_old.root() == comm_r_last_old),
+            "inconsistent comm_r_last_olds"
+        );
+        ensure!(
+            vanilla_proof
+                .challenge_proofs
+                .iter()
+                .all(|p| p.proof_r_new.root() == comm_r_last_new),
+            "inconsistent comm_r_last_news"
+        );
+
+        Ok(EmptySectorUpdateCircuit {
+            comm_r_old: Some(public_inputs.comm_r_old),
+            comm_d_new: Some(public_inputs.comm_d_new),
+            comm_r_new: Some(public_inputs.comm_r_new),
+            comm_c: Some(vanilla_proof.comm_c),
+            comm_r_last_old: Some(comm_r_last_old),
+            comm_r_last_new: Some(comm_r_last_new),
+            proofs: vanilla_proof
+                .challenge_proofs
+                .iter()
+                .cloned()
+                .map(Into::into)
+                .collect(),
+        })
+    }
+
+    fn blank_circuit(
+        public_params: &<EmptySectorUpdate<'_, Tree, G> as ProofScheme<'_>>::PublicParams,
+    ) -> EmptySectorUpdateCircuit<Tree, G> {
+        EmptySectorUpdateCircuit {
+            comm_r_old: None,
+            comm_d_new: None,
+            comm_r_new: None,
+            comm_c: None,
+            comm_r_last_old: None,
+            comm_r_last_new: None,
+            proofs: (0..public_params.challenges_count)
+                .map(|_challenge_index| ChallengeProof::empty(public_params))
+                .collect(),
+        }
+    }
+}
+
+/// Helper to generate public inputs for inclusion proofs.
+fn generate_inclusion_inputs<Tree: 'static + MerkleTreeTrait>(
+    por_params: &por::PublicParams,
+    challenge: usize,
+    k: Option<usize>,
+) -> Result<Vec<Fr>> {
+    let pub_inputs = por::PublicInputs::<<Tree::Hasher as Hasher>::Domain> {
+        challenge,
+        commitment: None,
+    };
+
+    PoRCompound::<Tree>::generate_public_inputs(&pub_inputs, por_params, k)
+}
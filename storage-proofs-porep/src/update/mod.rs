@@ -0,0 +1,7 @@
+mod circuit;
+mod compound;
+mod vanilla;
+
+pub use circuit::EmptySectorUpdateCircuit;
+pub use compound::EmptySectorUpdateCompound;
+pub use vanilla::*;
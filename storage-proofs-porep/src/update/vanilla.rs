@@ -0,0 +1,519 @@
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context};
+use bellperson::bls::Fr;
+use ff::Field;
+use filecoin_hashers::{Domain, HashFunction, Hasher};
+use generic_array::typenum::{Unsigned, U0, U2};
+use log::{info, trace};
+use merkletree::{merkle::get_merkle_tree_len, store::StoreConfig};
+use num_bigint::BigUint;
+use num_traits::cast::ToPrimitive;
+use rayon::prelude::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+    ParallelSlice, ParallelSliceMut,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use storage_proofs_core::{
+    error::Result,
+    merkle::{
+        create_lc_tree, get_base_tree_count, split_config_and_replica, BinaryMerkleTree, LCTree,
+        MerkleProof, MerkleProofTrait, MerkleTreeTrait,
+    },
+    parameter_cache::ParameterSetMetadata,
+    proof::ProofScheme,
+    util::NODE_SIZE,
+};
+
+use crate::stacked::ChallengeRequirements;
+
+#[derive(Debug, Clone)]
+pub struct SetupParams {
+    /// Number of nodes in the sector.
+    pub nodes: usize,
+    /// Number of challenges per partition.
+    pub challenges_count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PublicParams {
+    pub nodes: usize,
+    pub challenges_count: usize,
+}
+
+impl ParameterSetMetadata for PublicParams {
+    fn identifier(&self) -> String {
+        format!(
+            "empty_sector_update::PublicParams{{ nodes: {}, challenges_count: {} }}",
+            self.nodes, self.challenges_count
+        )
+    }
+
+    fn sector_size(&self) -> u64 {
+        (self.nodes * NODE_SIZE) as u64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PublicInputs<T: Domain, S: Domain> {
+    /// The CommR of the sector before the update.
+    #[serde(bound = "")]
+    pub comm_r_old: T,
+    /// The CommD of the new data.
+    #[serde(bound = "")]
+    pub comm_d_new: S,
+    /// The CommR of the sector after the update.
+    #[serde(bound = "")]
+    pub comm_r_new: T,
+    /// Partition index
+    pub k: Option<usize>,
+}
+
+impl<T: Domain, S: Domain> PublicInputs<T, S> {
+    /// Derives the challenged nodes of partition `partition_k`.
+    pub fn challenges(
+        &self,
+        challenges_count: usize,
+        leaves: usize,
+        partition_k: Option<usize>,
+    ) -> Vec<usize> {
+        let k = partition_k.unwrap_or(0);
+
+        (0..challenges_count)
+            .map(|i| {
+                let j: u32 = ((challenges_count * k) + i) as u32;
+
+                let hash = Sha256::new()
+                    .chain(self.comm_r_old.into_bytes())
+                    .chain(self.comm_d_new.into_bytes())
+                    .chain(self.comm_r_new.into_bytes())
+                    .chain(&j.to_le_bytes())
+                    .finalize();
+
+                let big_challenge = BigUint::from_bytes_le(hash.as_ref());
+                let big_mod_challenge = big_challenge % leaves;
+                big_mod_challenge
+                    .to_usize()
+                    .expect("`big_mod_challenge` exceeds size of `usize`")
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct PrivateInputs<Tree: MerkleTreeTrait, G: Hasher> {
+    /// The CommC of the sector, shared by the old and the new replica.
+    pub comm_c: <Tree::Hasher as Hasher>::Domain,
+    /// The `tree_r_last` of the sector key, i.e. of the replica before the update.
+    pub tree_r_old: LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    pub tree_r_old_rows_to_discard: usize,
+    /// The tree over the new data.
+    pub tree_d_new: BinaryMerkleTree<G>,
+    /// The `tree_r_last` of the new replica.
+    pub tree_r_new: LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    pub tree_r_new_rows_to_discard: usize,
+}
+
+/// Openings of a single challenged node in the old replica, the new data and the new replica.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChallengeProof<Tree: MerkleTreeTrait, G: Hasher> {
+    #[serde(bound(
+        serialize = "MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>: Serialize",
+        deserialize = "MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>: Deserialize<'de>"
+    ))]
+    pub proof_r_old: MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    #[serde(bound(
+        serialize = "MerkleProof<G, U2>: Serialize",
+        deserialize = "MerkleProof<G, U2>: Deserialize<'de>"
+    ))]
+    pub proof_d_new: MerkleProof<G, U2>,
+    #[serde(bound(
+        serialize = "MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>: Serialize",
+        deserialize = "MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>: Deserialize<'de>"
+    ))]
+    pub proof_r_new: MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> Clone for ChallengeProof<Tree, G> {
+    fn clone(&self) -> Self {
+        Self {
+            proof_r_old: self.proof_r_old.clone(),
+            proof_d_new: self.proof_d_new.clone(),
+            proof_r_new: self.proof_r_new.clone(),
+        }
+    }
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> ChallengeProof<Tree, G> {
+    /// Verifies the openings of `challenge`, and that the new replica node encodes the new data
+    /// node with the sector key node.
+    pub fn verify(&self, challenge: usize, rho: <Tree::Hasher as Hasher>::Domain) -> bool {
+        if !self.proof_r_old.validate(challenge)
+            || !self.proof_d_new.validate(challenge)
+            || !self.proof_r_new.validate(challenge)
+        {
+            return false;
+        }
+
+        let data: Fr = self.proof_d_new.leaf().into();
+        encode_node(self.proof_r_old.leaf(), data.into(), rho) == self.proof_r_new.leaf()
+    }
+}
+
+/// Proof for a single partition.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Proof<Tree: MerkleTreeTrait, G: Hasher> {
+    #[serde(bound = "")]
+    pub comm_c: <Tree::Hasher as Hasher>::Domain,
+    #[serde(bound(
+        serialize = "ChallengeProof<Tree, G>: Serialize",
+        deserialize = "ChallengeProof<Tree, G>: Deserialize<'de>"
+    ))]
+    pub challenge_proofs: Vec<ChallengeProof<Tree, G>>,
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> Clone for Proof<Tree, G> {
+    fn clone(&self) -> Self {
+        Self {
+            comm_c: self.comm_c,
+            challenge_proofs: self.challenge_proofs.clone(),
+        }
+    }
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> Proof<Tree, G> {
+    pub fn comm_r_last_old(&self) -> <Tree::Hasher as Hasher>::Domain {
+        self.challenge_proofs[0].proof_r_old.root()
+    }
+
+    pub fn comm_r_last_new(&self) -> <Tree::Hasher as Hasher>::Domain {
+        self.challenge_proofs[0].proof_r_new.root()
+    }
+}
+
+/// Returns `H(comm_d_new || comm_r_old)`, the factor each new data node is multiplied with
+/// before it is added to the sector key.
+pub fn phi<H: Hasher, D: Domain>(comm_d_new: &D, comm_r_old: &H::Domain) -> H::Domain {
+    let comm_d_new: Fr = (*comm_d_new).into();
+    H::Function::hash2(&comm_d_new.into(), comm_r_old)
+}
+
+/// Encodes a single node: `key + data * rho`.
+pub fn encode_node<T: Domain>(key: T, data: T, rho: T) -> T {
+    let mut result: Fr = data.into();
+    let rho: Fr = rho.into();
+    let key: Fr = key.into();
+
+    result.mul_assign(&rho);
+    result.add_assign(&key);
+    result.into()
+}
+
+/// Decodes a single node: `(replica - key) * rho_inv`.
+pub fn decode_node<T: Domain>(key: T, replica: T, rho_inv: T) -> T {
+    let mut result: Fr = replica.into();
+    let rho_inv: Fr = rho_inv.into();
+    let key: Fr = key.into();
+
+    result.sub_assign(&key);
+    result.mul_assign(&rho_inv);
+    result.into()
+}
+
+/// Sector update of a sealed committed-capacity sector.
+///
+/// The replica of an empty sector, the sector key, consists of its last-layer labels. New data
+/// is encoded into it node by node as `key + data * rho`, where `rho = H(comm_d_new || comm_r_old)`,
+/// without repeating the labeling. The updated sector keeps its CommC, and gets a new
+/// `comm_r_last` and CommR.
+#[derive(Debug)]
+pub struct EmptySectorUpdate<'a, Tree: MerkleTreeTrait, G: Hasher> {
+    _a: PhantomData<&'a Tree>,
+    _b: PhantomData<&'a G>,
+}
+
+impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> EmptySectorUpdate<'a, Tree, G> {
+    /// Encodes `data` into `sector_key` and writes the result to `replica`, which is backed by the
+    /// file at `replica_path`. The `tree_r_last` of the new replica is stored in
+    /// `tree_r_last_config`.
+    ///
+    /// Returns the new `comm_r_last` and CommR.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode_into(
+        nodes_count: usize,
+        tree_r_last_config: StoreConfig,
+        replica_path: PathBuf,
+        comm_c: <Tree::Hasher as Hasher>::Domain,
+        comm_r_old: <Tree::Hasher as Hasher>::Domain,
+        comm_d_new: G::Domain,
+        sector_key: &[u8],
+        data: &[u8],
+        replica: &mut [u8],
+    ) -> Result<(
+        <Tree::Hasher as Hasher>::Domain,
+        <Tree::Hasher as Hasher>::Domain,
+    )> {
+        let sector_bytes = nodes_count * NODE_SIZE;
+        ensure!(
+            sector_key.len() == sector_bytes,
+            "sector key must be {} bytes",
+            sector_bytes
+        );
+        ensure!(
+            data.len() == sector_bytes,
+            "data must be {} bytes",
+            sector_bytes
+        );
+        ensure!(
+            replica.len() == sector_bytes,
+            "replica must be {} bytes",
+            sector_bytes
+        );
+
+        let rho = phi::<Tree::Hasher, _>(&comm_d_new, &comm_r_old);
+
+        info!("encoding new data into the sector key");
+        replica
+            .par_chunks_mut(NODE_SIZE)
+            .zip(sector_key.par_chunks(NODE_SIZE))
+            .zip(data.par_chunks(NODE_SIZE))
+            .try_for_each(|((replica_node, key_node), data_node)| -> Result<()> {
+                let key = <Tree::Hasher as Hasher>::Domain::try_from_bytes(key_node)?;
+                let data = <Tree::Hasher as Hasher>::Domain::try_from_bytes(data_node)?;
+                let encoded = encode_node(key, data, rho);
+                replica_node.copy_from_slice(AsRef::<[u8]>::as_ref(&encoded));
+
+                Ok(())
+            })?;
+
+        let tree_r_last =
+            Self::build_tree_r_last(nodes_count, tree_r_last_config, replica_path, replica)?;
+        let comm_r_last = tree_r_last.root();
+        let comm_r = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last);
+
+        Ok((comm_r_last, comm_r))
+    }
+
+    /// Recovers the data encoded into `replica` with `sector_key`, and writes it to `data`.
+    pub fn decode_from(
+        comm_r_old: <Tree::Hasher as Hasher>::Domain,
+        comm_d_new: G::Domain,
+        sector_key: &[u8],
+        replica: &[u8],
+        data: &mut [u8],
+    ) -> Result<()> {
+        ensure!(
+            sector_key.len() == replica.len() && data.len() == replica.len(),
+            "sector key, replica and data must have the same length"
+        );
+
+        let rho: Fr = phi::<Tree::Hasher, _>(&comm_d_new, &comm_r_old).into();
+        let rho_inv: <Tree::Hasher as Hasher>::Domain =
+            rho.inverse().context("rho must not be zero")?.into();
+
+        info!("decoding new data from the replica");
+        data.par_chunks_mut(NODE_SIZE)
+            .zip(sector_key.par_chunks(NODE_SIZE))
+            .zip(replica.par_chunks(NODE_SIZE))
+            .try_for_each(|((data_node, key_node), replica_node)| -> Result<()> {
+                let key = <Tree::Hasher as Hasher>::Domain::try_from_bytes(key_node)?;
+                let replica = <Tree::Hasher as Hasher>::Domain::try_from_bytes(replica_node)?;
+                let decoded = decode_node(key, replica, rho_inv);
+                data_node.copy_from_slice(AsRef::<[u8]>::as_ref(&decoded));
+
+                Ok(())
+            })
+    }
+
+    /// Builds the `tree_r_last` over `replica`, which is backed by the file at `replica_path`.
+    /// Only the cached rows are written to `tree_r_last_config`, the leafs are read back from the
+    /// replica file.
+    pub fn build_tree_r_last(
+        nodes_count: usize,
+        tree_r_last_config: StoreConfig,
+        replica_path: PathBuf,
+        replica: &[u8],
+    ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>> {
+        let tree_count = get_base_tree_count::<Tree>();
+        let base_tree_leafs = nodes_count / tree_count;
+        let base_tree_size = get_merkle_tree_len(base_tree_leafs, Tree::Arity::to_usize())?;
+
+        let mut tree_r_last_config = tree_r_last_config;
+        tree_r_last_config.size = Some(base_tree_size);
+
+        let (configs, replica_config) = split_config_and_replica(
+            tree_r_last_config,
+            replica_path,
+            base_tree_leafs,
+            tree_count,
+        )?;
+
+        for (i, (config, nodes)) in configs
+            .iter()
+            .zip(replica.chunks(base_tree_leafs * NODE_SIZE))
+            .enumerate()
+        {
+            trace!("building base tree_r_last {}/{}", i + 1, tree_count);
+
+            // Remove the tree_r_last store if it exists already
+            let store_path = StoreConfig::data_path(&config.path, &config.id);
+            if Path::new(&store_path).exists() {
+                std::fs::remove_file(&store_path).with_context(|| {
+                    format!("could not remove tree_r_last store {:?}", store_path)
+                })?;
+            }
+
+            let leafs = nodes.par_chunks(NODE_SIZE).map(|node| {
+                <Tree::Hasher as Hasher>::Domain::try_from_bytes(node)
+                    .expect("try from bytes failed")
+            });
+            LCTree::<Tree::Hasher, Tree::Arity, U0, U0>::from_par_iter_with_config(
+                leafs,
+                config.clone(),
+            )
+            .with_context(|| format!("failed tree_r_last {}/{}", i + 1, tree_count))?;
+        }
+
+        create_lc_tree::<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>(
+            base_tree_size,
+            &configs,
+            &replica_config,
+        )
+    }
+}
+
+impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'a>
+    for EmptySectorUpdate<'c, Tree, G>
+{
+    type PublicParams = PublicParams;
+    type SetupParams = SetupParams;
+    type PublicInputs = PublicInputs<<Tree::Hasher as Hasher>::Domain, G::Domain>;
+    type PrivateInputs = PrivateInputs<Tree, G>;
+    type Proof = Proof<Tree, G>;
+    type Requirements = ChallengeRequirements;
+
+    fn setup(sp: &Self::SetupParams) -> Result<Self::PublicParams> {
+        ensure!(sp.nodes > 0, "nodes must not be 0");
+        ensure!(sp.challenges_count > 0, "challenges_count must not be 0");
+
+        Ok(PublicParams {
+            nodes: sp.nodes,
+            challenges_count: sp.challenges_count,
+        })
+    }
+
+    fn prove(
+        pub_params: &Self::PublicParams,
+        pub_inputs: &Self::PublicInputs,
+        priv_inputs: &Self::PrivateInputs,
+    ) -> Result<Self::Proof> {
+        let PrivateInputs {
+            comm_c,
+            tree_r_old,
+            tree_r_old_rows_to_discard,
+            tree_d_new,
+            tree_r_new,
+            tree_r_new_rows_to_discard,
+        } = priv_inputs;
+
+        // Sanity checks on the restored trees.
+        ensure!(
+            tree_d_new.root() == pub_inputs.comm_d_new,
+            "tree_d_new does not match comm_d_new"
+        );
+        ensure!(
+            <Tree::Hasher as Hasher>::Function::hash2(comm_c, &tree_r_old.root())
+                == pub_inputs.comm_r_old,
+            "tree_r_old does not match comm_r_old"
+        );
+
+        let challenges =
+            pub_inputs.challenges(pub_params.challenges_count, pub_params.nodes, pub_inputs.k);
+
+        let challenge_proofs = challenges
+            .into_par_iter()
+            .map(|challenge| -> Result<_> {
+                trace!(" challenge {}", challenge);
+
+                Ok(ChallengeProof {
+                    proof_r_old: tree_r_old
+                        .gen_cached_proof(challenge, Some(*tree_r_old_rows_to_discard))?,
+                    proof_d_new: tree_d_new.gen_proof(challenge)?,
+                    proof_r_new: tree_r_new
+                        .gen_cached_proof(challenge, Some(*tree_r_new_rows_to_discard))?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Proof {
+            comm_c: *comm_c,
+            challenge_proofs,
+        })
+    }
+
+    fn verify(
+        pub_params: &Self::PublicParams,
+        pub_inputs: &Self::PublicInputs,
+        proof: &Self::Proof,
+    ) -> Result<bool> {
+        if proof.challenge_proofs.len() != pub_params.challenges_count {
+            return Ok(false);
+        }
+
+        let comm_r_last_old = proof.comm_r_last_old();
+        let comm_r_last_new = proof.comm_r_last_new();
+
+        trace!("verify comm_r_old and comm_r_new");
+        let hash_comm_r =
+            |comm_r_last| <Tree::Hasher as Hasher>::Function::hash2(&proof.comm_c, &comm_r_last);
+        if hash_comm_r(comm_r_last_old) != pub_inputs.comm_r_old
+            || hash_comm_r(comm_r_last_new) != pub_inputs.comm_r_new
+        {
+            return Ok(false);
+        }
+
+        let rho = phi::<Tree::Hasher, _>(&pub_inputs.comm_d_new, &pub_inputs.comm_r_old);
+        let challenges =
+            pub_inputs.challenges(pub_params.challenges_count, pub_params.nodes, pub_inputs.k);
+
+        let res = proof
+            .challenge_proofs
+            .par_iter()
+            .zip(challenges.into_par_iter())
+            .all(|(challenge_proof, challenge)| {
+                trace!("verify challenge {}", challenge);
+
+                challenge_proof.proof_r_old.root() == comm_r_last_old
+                    && challenge_proof.proof_d_new.root() == pub_inputs.comm_d_new
+                    && challenge_proof.proof_r_new.root() == comm_r_last_new
+                    && challenge_proof.verify(challenge, rho)
+            });
+
+        Ok(res)
+    }
+
+    fn with_partition(pub_in: Self::PublicInputs, k: Option<usize>) -> Self::PublicInputs {
+        PublicInputs {
+            comm_r_old: pub_in.comm_r_old,
+            comm_d_new: pub_in.comm_d_new,
+            comm_r_new: pub_in.comm_r_new,
+            k,
+        }
+    }
+
+    fn satisfies_requirements(
+        public_params: &PublicParams,
+        requirements: &ChallengeRequirements,
+        partitions: usize,
+    ) -> bool {
+        let partition_challenges = public_params.challenges_count;
+
+        assert_eq!(
+            partition_challenges.checked_mul(partitions),
+            Some(partition_challenges * partitions)
+        );
+        partition_challenges * partitions >= requirements.minimum_challenges
+    }
+}
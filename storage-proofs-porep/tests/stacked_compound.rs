@@ -1,3 +1,5 @@
+use std::sync::Mutex;
+
 use bellperson::{
     bls::Fr,
     util_cs::{metric_cs::MetricCS, test_cs::TestConstraintSystem},
@@ -16,6 +18,8 @@ use storage_proofs_core::{
     compound_proof::{self, CompoundProof},
     drgraph::BASE_DEGREE,
     merkle::{get_base_tree_count, DiskTree, MerkleTreeTrait},
+    progress::ProgressEvent,
+    run_options::RunOptions,
     test_helper::setup_replica,
     util::default_rows_to_discard,
     TEST_SEED,
@@ -54,7 +58,7 @@ fn test_stacked_compound<Tree: 'static + MerkleTreeTrait>() {
     let expansion_degree = EXP_DEGREE;
     let num_layers = 2;
     let layer_challenges = LayerChallenges::new(num_layers, 1);
-    let partition_count = 1;
+    let partition_count = 2;
 
     let rng = &mut XorShiftRng::from_seed(TEST_SEED);
 
@@ -180,14 +184,34 @@ fn test_stacked_compound<Tree: 'static + MerkleTreeTrait>() {
     // Discard cached MTs that are no longer needed.
     TemporaryAux::<Tree, Sha256Hasher>::clear_temp(t_aux_orig).expect("t_aux delete failed");
 
-    let proof = StackedCompound::prove(
+    // Prove one partition at a time, so that each one is reported as soon as it is done.
+    let events = Mutex::new(Vec::new());
+    let observer = |event: ProgressEvent| {
+        events.lock().expect("events lock poisoned").push(event);
+    };
+    let options = RunOptions {
+        progress: &observer,
+        partition_batch_size: Some(1),
+        ..Default::default()
+    };
+    let proof = StackedCompound::prove_with_options(
         &public_params,
         &public_inputs,
         &private_inputs,
         &blank_groth_params,
+        &options,
     )
     .expect("failed while proving");
 
+    let events = events.into_inner().expect("events lock poisoned");
+    let expected: Vec<_> = (0..partition_count)
+        .map(|partition| ProgressEvent::PartitionProved {
+            partition,
+            partitions: partition_count,
+        })
+        .collect();
+    assert_eq!(events, expected);
+
     let verified = StackedCompound::verify(
         &public_params,
         &public_inputs,
@@ -1,4 +1,5 @@
 use std::fs::remove_file;
+use std::sync::{Arc, Mutex};
 
 use bellperson::bls::{Fr, FrRepr};
 use ff::{Field, PrimeField};
@@ -14,9 +15,16 @@ use rand_xorshift::XorShiftRng;
 use storage_proofs_core::{
     api_version::ApiVersion,
     cache_key::CacheKey,
+    cancel::CancellationToken,
     drgraph::BASE_DEGREE,
-    merkle::{get_base_tree_count, DiskTree, MerkleTreeTrait},
+    error::Error,
+    merkle::{
+        create_base_merkle_tree, get_base_tree_count, BinaryMerkleTree, DiskTree, MerkleTreeTrait,
+    },
+    progress::{NoProgress, ProgressEvent},
     proof::ProofScheme,
+    run_options::RunOptions,
+    settings::{with_config, ProofsConfig, SETTINGS},
     table_tests,
     test_helper::setup_replica,
     util::{default_rows_to_discard, NODE_SIZE},
@@ -24,8 +32,8 @@ use storage_proofs_core::{
 };
 use storage_proofs_porep::{
     stacked::{
-        LayerChallenges, PrivateInputs, PublicInputs, SetupParams, StackedBucketGraph, StackedDrg,
-        TemporaryAux, TemporaryAuxCache, BINARY_ARITY, EXP_DEGREE,
+        create_label::LayerManifest, LayerChallenges, PrivateInputs, PublicInputs, SetupParams,
+        StackedBucketGraph, StackedDrg, TemporaryAux, TemporaryAuxCache, BINARY_ARITY, EXP_DEGREE,
     },
     PoRep,
 };
@@ -137,6 +145,8 @@ fn test_extract_all<Tree: 'static + MerkleTreeTrait>() {
         &layer_challenges,
         &replica_id,
         config.clone(),
+        &CancellationToken::default(),
+        &NoProgress,
     )
     .expect("label generation failed");
     for state in &label_states {
@@ -155,6 +165,8 @@ fn test_extract_all<Tree: 'static + MerkleTreeTrait>() {
         &layer_challenges,
         &replica_id,
         config.clone(),
+        &CancellationToken::default(),
+        &NoProgress,
     )
     .expect("label generation failed");
     for state in &label_states[..off] {
@@ -179,6 +191,84 @@ fn test_extract_all<Tree: 'static + MerkleTreeTrait>() {
     cache_dir.close().expect("Failed to remove cache dir");
 }
 
+#[test]
+fn test_stacked_porep_extract_range_sha256_base_8() {
+    test_extract_range::<DiskTree<Sha256Hasher, U8, U0, U0>>();
+}
+
+#[test]
+fn test_stacked_porep_extract_range_poseidon_top_8_8_2() {
+    test_extract_range::<DiskTree<PoseidonHasher, U8, U8, U2>>();
+}
+
+fn test_extract_range<Tree: 'static + MerkleTreeTrait>() {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let replica_id: <Tree::Hasher as Hasher>::Domain =
+        <Tree::Hasher as Hasher>::Domain::random(rng);
+    let nodes = 64 * get_base_tree_count::<Tree>();
+
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| {
+            let v = <Tree::Hasher as Hasher>::Domain::random(rng);
+            v.into_bytes()
+        })
+        .collect();
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+
+    let replica_path = cache_dir.path().join("replica-path");
+    let mut mmapped_data = setup_replica(&data, &replica_path);
+
+    let layer_challenges = LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5);
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges: layer_challenges.clone(),
+        api_version: ApiVersion::V1_1_0,
+    };
+
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+    StackedDrg::<Tree, Blake2sHasher>::replicate(
+        &pp,
+        &replica_id,
+        (mmapped_data.as_mut()).into(),
+        None,
+        config,
+        replica_path,
+    )
+    .expect("replication failed");
+
+    let sealed = mmapped_data.as_ref().to_vec();
+    assert_ne!(data, sealed, "replication did not change data");
+
+    for range in &[0..1, 3..9, nodes / 2..nodes / 2 + 5, nodes - 4..nodes] {
+        let bytes = range.start * NODE_SIZE..range.end * NODE_SIZE;
+        let mut decoded = sealed[bytes.clone()].to_vec();
+
+        StackedDrg::<Tree, Blake2sHasher>::extract_range(
+            &pp.graph,
+            &layer_challenges,
+            &replica_id,
+            &mut decoded,
+            range.clone(),
+        )
+        .expect("failed to extract range");
+
+        assert_eq!(&data[bytes], &decoded[..]);
+    }
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
 #[test]
 fn test_stacked_porep_resume_seal() {
     // pretty_env_logger::try_init().ok();
@@ -268,6 +358,8 @@ fn test_stacked_porep_resume_seal() {
         &layer_challenges,
         &replica_id,
         config.clone(),
+        &CancellationToken::default(),
+        &NoProgress,
     )
     .expect("label generation failed");
     let off = label_states.len() - 3;
@@ -307,6 +399,304 @@ fn test_stacked_porep_resume_seal() {
     cache_dir.close().expect("Failed to remove cache dir");
 }
 
+#[test]
+fn test_stacked_porep_resume_seal_rejects_modified_layer() {
+    type Tree = DiskTree<Sha256Hasher, U8, U0, U0>;
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let replica_id = <Sha256Hasher as Hasher>::Domain::random(rng);
+    let other_replica_id = <Sha256Hasher as Hasher>::Domain::random(rng);
+    let nodes = 64 * get_base_tree_count::<Tree>();
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+
+    let layer_challenges = LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5);
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges: layer_challenges.clone(),
+        api_version: ApiVersion::V1_1_0,
+    };
+
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+    let generate = |replica_id| {
+        let (_, label_states) = StackedDrg::<Tree, Blake2sHasher>::generate_labels_for_encoding(
+            &pp.graph,
+            &layer_challenges,
+            replica_id,
+            config.clone(),
+            &CancellationToken::default(),
+            &NoProgress,
+        )
+        .expect("label generation failed");
+        label_states
+    };
+
+    let label_states = generate(&replica_id);
+    assert!(label_states.iter().all(|state| !state.generated));
+
+    // Modify a single byte of a layer, keeping its size intact.
+    let modified = 4;
+    let layer_config = &label_states[modified].config;
+    let layer_path = StoreConfig::data_path(&layer_config.path, &layer_config.id);
+    let mut layer = std::fs::read(&layer_path).expect("failed to read layer");
+    layer[7] ^= 1;
+    std::fs::write(&layer_path, &layer).expect("failed to write layer");
+
+    // Only the layers before the modified one may be reused.
+    let label_states = generate(&replica_id);
+    for state in &label_states[..modified] {
+        assert!(state.generated);
+    }
+    for state in &label_states[modified..] {
+        assert!(!state.generated);
+    }
+
+    // Layers of a different replica must never be reused.
+    let label_states = generate(&other_replica_id);
+    assert!(label_states.iter().all(|state| !state.generated));
+
+    // Caches written before manifests existed are checked by size only, and recorded in a new
+    // manifest.
+    remove_file(LayerManifest::path(cache_dir.path())).expect("failed to delete manifest");
+    let label_states = generate(&other_replica_id);
+    assert!(label_states.iter().all(|state| state.generated));
+    let manifest = LayerManifest::load(cache_dir.path())
+        .expect("failed to load manifest")
+        .expect("missing manifest");
+    assert_eq!(manifest.layers.len(), DEFAULT_STACKED_LAYERS);
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
+#[test]
+fn test_stacked_porep_labeling_progress() {
+    type Tree = DiskTree<Sha256Hasher, U8, U0, U0>;
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let replica_id = <Sha256Hasher as Hasher>::Domain::random(rng);
+    let nodes = 64 * get_base_tree_count::<Tree>();
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+
+    let layer_challenges = LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5);
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges,
+        api_version: ApiVersion::V1_1_0,
+    };
+
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+    let events = Mutex::new(Vec::new());
+    let observer = |event: ProgressEvent| {
+        events.lock().expect("events lock poisoned").push(event);
+    };
+    let options = RunOptions {
+        progress: &observer,
+        ..Default::default()
+    };
+    StackedDrg::<Tree, Blake2sHasher>::replicate_phase1_with_options(
+        &pp,
+        &replica_id,
+        config,
+        &options,
+    )
+    .expect("label generation failed");
+
+    let events = events.into_inner().expect("events lock poisoned");
+    for layer in 1..=DEFAULT_STACKED_LAYERS {
+        assert!(events.contains(&ProgressEvent::LayerStarted {
+            layer,
+            layers: DEFAULT_STACKED_LAYERS,
+        }));
+        assert!(events.contains(&ProgressEvent::NodesLabeled {
+            layer,
+            nodes: nodes as u64,
+            total: nodes as u64,
+        }));
+    }
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
+#[test]
+fn test_stacked_porep_cancelled_labeling() {
+    type Tree = DiskTree<Sha256Hasher, U8, U0, U0>;
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let replica_id = <Sha256Hasher as Hasher>::Domain::random(rng);
+    let nodes = 64 * get_base_tree_count::<Tree>();
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+
+    let layer_challenges = LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5);
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges: layer_challenges.clone(),
+        api_version: ApiVersion::V1_1_0,
+    };
+
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+    let options = RunOptions::default();
+    options.cancel.cancel();
+    let err = StackedDrg::<Tree, Blake2sHasher>::replicate_phase1_with_options(
+        &pp,
+        &replica_id,
+        config.clone(),
+        &options,
+    )
+    .expect_err("cancelled labeling succeeded");
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::Cancelled)
+    ));
+
+    let layer_files = glob(&format!("{}/*layer*", cache_dir.path().display()))
+        .expect("glob failure")
+        .count();
+    assert_eq!(layer_files, 0, "cancelled labeling left layer files behind");
+
+    // A later run without cancellation generates all layers.
+    let (_, label_states) = StackedDrg::<Tree, Blake2sHasher>::generate_labels_for_encoding(
+        &pp.graph,
+        &layer_challenges,
+        &replica_id,
+        config,
+        &CancellationToken::default(),
+        &NoProgress,
+    )
+    .expect("label generation failed");
+    assert!(label_states.iter().all(|state| !state.generated));
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
+#[test]
+fn test_stacked_porep_cancelled_tree_r_last() {
+    type Tree = DiskTree<PoseidonHasher, U8, U8, U0>;
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let replica_id = <PoseidonHasher as Hasher>::Domain::random(rng);
+    let nodes = 64 * get_base_tree_count::<Tree>();
+
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| <PoseidonHasher as Hasher>::Domain::random(rng).into_bytes())
+        .collect();
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let mut config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+    let replica_path = cache_dir.path().join("replica-path");
+    let mut mmapped_data = setup_replica(&data, &replica_path);
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+        api_version: ApiVersion::V1_1_0,
+    };
+
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+    let labels = StackedDrg::<Tree, Blake2sHasher>::replicate_phase1_with_options(
+        &pp,
+        &replica_id,
+        config.clone(),
+        &RunOptions::default(),
+    )
+    .expect("label generation failed");
+    let data_tree = create_base_merkle_tree::<BinaryMerkleTree<Blake2sHasher>>(
+        Some(config.clone()),
+        nodes,
+        &data,
+    )
+    .expect("failed to build tree_d");
+    config.size = Some(data_tree.len());
+
+    // Cancel once the first base tree of tree_r_last, and so part of the replica, is done.
+    let cancel = CancellationToken::default();
+    let observer = |event: ProgressEvent| {
+        if let ProgressEvent::TreeBatchFinished {
+            tree: CacheKey::CommRLastTree,
+            ..
+        } = event
+        {
+            cancel.cancel();
+        }
+    };
+    let options = RunOptions {
+        cancel: cancel.clone(),
+        progress: &observer,
+        ..Default::default()
+    };
+    let err = StackedDrg::<Tree, Blake2sHasher>::replicate_phase2_with_options(
+        &pp,
+        labels,
+        (mmapped_data.as_mut()).into(),
+        data_tree,
+        config,
+        replica_path,
+        &options,
+    )
+    .expect_err("cancelled tree building succeeded");
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::Cancelled)
+    ));
+
+    assert!(
+        mmapped_data[..] == data[..],
+        "cancelled tree building left the replica encoded"
+    );
+    let tree_r_last_files = glob(&format!(
+        "{}/*{}*",
+        cache_dir.path().display(),
+        CacheKey::CommRLastTree
+    ))
+    .expect("glob failure")
+    .count();
+    assert_eq!(
+        tree_r_last_files, 0,
+        "cancelled tree building left tree_r_last files behind"
+    );
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
 table_tests! {
     test_prove_verify_fixed {
        test_stacked_porep_prove_verify(64);
@@ -341,6 +731,22 @@ fn test_prove_verify_fixed(n: usize) {
     test_prove_verify::<DiskTree<PoseidonHasher, U8, U8, U2>>(n, challenges);
 }
 
+#[test]
+fn test_stacked_porep_non_interactive_prove_verify() {
+    let challenges = LayerChallenges::new_non_interactive(DEFAULT_STACKED_LAYERS, 5);
+
+    test_prove_verify::<DiskTree<Sha256Hasher, U8, U0, U0>>(64, challenges.clone());
+    test_prove_verify::<DiskTree<PoseidonHasher, U8, U8, U2>>(64, challenges);
+}
+
+#[test]
+fn test_stacked_porep_synthetic_prove_verify() {
+    let challenges = LayerChallenges::new_synthetic(DEFAULT_STACKED_LAYERS, 5, 32);
+
+    test_prove_verify::<DiskTree<Sha256Hasher, U8, U0, U0>>(64, challenges.clone());
+    test_prove_verify::<DiskTree<PoseidonHasher, U8, U8, U2>>(64, challenges);
+}
+
 fn test_prove_verify<Tree: 'static + MerkleTreeTrait>(n: usize, challenges: LayerChallenges) {
     // This will be called multiple times, only the first one succeeds, and that is ok.
     // femme::pretty::Logger::new()
@@ -432,6 +838,72 @@ fn test_prove_verify<Tree: 'static + MerkleTreeTrait>(n: usize, challenges: Laye
     )
     .expect("failed to verify partition proofs");
 
+    if pp.layer_challenges.is_non_interactive() {
+        // Non-interactive challenges are derived from comm_r, so the seed does not matter.
+        let other_seed_inputs = PublicInputs {
+            seed: rng.gen(),
+            ..pub_inputs.clone()
+        };
+        assert!(StackedDrg::<Tree, Blake2sHasher>::verify_all_partitions(
+            &pp,
+            &other_seed_inputs,
+            all_partition_proofs,
+        )
+        .expect("failed to verify partition proofs"));
+    }
+
+    if pp.layer_challenges.is_synthetic() {
+        let proofs_path = cache_dir
+            .path()
+            .join(CacheKey::SyntheticPoRepProofs.to_string());
+        StackedDrg::<Tree, Blake2sHasher>::prove_synthetic_challenges(
+            &pp,
+            &pub_inputs,
+            &priv_inputs,
+            &proofs_path,
+        )
+        .expect("failed to generate synthetic proofs");
+
+        // The selected synthetic proofs do not need the layers.
+        t_aux_orig.clear_labels().expect("failed to clear labels");
+        let synthetic_proofs = StackedDrg::<Tree, Blake2sHasher>::read_synthetic_proofs(
+            &pp,
+            &pub_inputs,
+            partitions,
+            &proofs_path,
+        )
+        .expect("failed to read synthetic proofs");
+
+        assert_eq!(
+            bincode::serialize(&synthetic_proofs).expect("serialize failed"),
+            bincode::serialize(all_partition_proofs).expect("serialize failed"),
+            "synthetic proofs differ from the proofs of the selected challenges"
+        );
+        assert!(StackedDrg::<Tree, Blake2sHasher>::verify_all_partitions(
+            &pp,
+            &pub_inputs,
+            &synthetic_proofs,
+        )
+        .expect("failed to verify synthetic proofs"));
+
+        // A truncated proofs file is rejected rather than misread.
+        let proofs_len = std::fs::metadata(&proofs_path)
+            .expect("failed to stat synthetic proofs")
+            .len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&proofs_path)
+            .and_then(|file| file.set_len(proofs_len - 1))
+            .expect("failed to truncate synthetic proofs");
+        assert!(StackedDrg::<Tree, Blake2sHasher>::read_synthetic_proofs(
+            &pp,
+            &pub_inputs,
+            partitions,
+            &proofs_path,
+        )
+        .is_err());
+    }
+
     // Discard cached MTs that are no longer needed.
     TemporaryAux::<Tree, Blake2sHasher>::clear_temp(t_aux_orig).expect("t_aux delete failed");
 
@@ -542,13 +1014,6 @@ fn test_generate_labels_aux(
 ) {
     let nodes = sector_size / NODE_SIZE;
 
-    let cache_dir = tempdir().expect("tempdir failure");
-    let config = StoreConfig::new(
-        cache_dir.path(),
-        CacheKey::CommDTree.to_string(),
-        nodes.trailing_zeros() as usize,
-    );
-
     let graph = StackedBucketGraph::<PoseidonHasher>::new(
         None,
         nodes,
@@ -561,21 +1026,44 @@ fn test_generate_labels_aux(
 
     let unused_layer_challenges = LayerChallenges::new(layers, 0);
 
-    let labels = StackedDrg::<
-        // Although not generally correct for every size, the hasher shape is not used,
-        // so for purposes of testing label creation, it is safe to supply a dummy.
-        DiskTree<PoseidonHasher, U8, U8, U2>,
-        Sha256Hasher,
-    >::generate_labels_for_decoding(
-        &graph,
-        &unused_layer_challenges,
-        &<PoseidonHasher as Hasher>::Domain::try_from_bytes(&replica_id).unwrap(),
-        config,
-    )
-    .unwrap();
+    // The labels must not depend on whether the parents are read from the cache or computed.
+    for sdr_parents_on_the_fly in &[false, true] {
+        // Use a fresh cache dir for each pass, so the layers are not loaded from the last one.
+        let cache_dir = tempdir().expect("tempdir failure");
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            nodes.trailing_zeros() as usize,
+        );
+        let settings = Arc::new(ProofsConfig {
+            use_multicore_sdr: true,
+            sdr_parents_on_the_fly: *sdr_parents_on_the_fly,
+            ..(**SETTINGS).clone()
+        });
+
+        let labels = with_config(Some(&settings), || {
+            StackedDrg::<
+                // Although not generally correct for every size, the hasher shape is not used,
+                // so for purposes of testing label creation, it is safe to supply a dummy.
+                DiskTree<PoseidonHasher, U8, U8, U2>,
+                Sha256Hasher,
+            >::generate_labels_for_decoding(
+                &graph,
+                &unused_layer_challenges,
+                &<PoseidonHasher as Hasher>::Domain::try_from_bytes(&replica_id).unwrap(),
+                config,
+            )
+        })
+        .unwrap();
 
-    let final_labels = labels.labels_for_last_layer().unwrap();
-    let last_label = final_labels.read_at(nodes - 1).unwrap();
+        let final_labels = labels.labels_for_last_layer().unwrap();
+        let last_label = final_labels.read_at(nodes - 1).unwrap();
 
-    assert_eq!(expected_last_label.into_repr(), last_label.0);
+        assert_eq!(
+            expected_last_label.into_repr(),
+            last_label.0,
+            "sdr_parents_on_the_fly: {}",
+            sdr_parents_on_the_fly
+        );
+    }
 }
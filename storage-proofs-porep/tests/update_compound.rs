@@ -0,0 +1,230 @@
+use bellperson::{
+    bls::Fr,
+    util_cs::{metric_cs::MetricCS, test_cs::TestConstraintSystem},
+    Circuit,
+};
+use ff::Field;
+use filecoin_hashers::{poseidon::PoseidonHasher, sha256::Sha256Hasher, HashFunction, Hasher};
+use fr32::fr_into_bytes;
+use generic_array::typenum::{Unsigned, U0, U2, U4, U8};
+use merkletree::store::StoreConfig;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    compound_proof::{self, CompoundProof},
+    merkle::{
+        create_base_merkle_tree, get_base_tree_count, BinaryMerkleTree, DiskTree, MerkleTreeTrait,
+    },
+    test_helper::setup_replica,
+    util::{default_rows_to_discard, NODE_SIZE},
+    TEST_SEED,
+};
+use storage_proofs_porep::{
+    stacked::{ChallengeRequirements, BINARY_ARITY},
+    update::{
+        EmptySectorUpdate, EmptySectorUpdateCompound, PrivateInputs, PublicInputs, SetupParams,
+    },
+};
+use tempfile::tempdir;
+
+#[test]
+#[ignore]
+fn test_empty_sector_update_compound_poseidon_base_8() {
+    test_empty_sector_update_compound::<DiskTree<PoseidonHasher, U8, U0, U0>>();
+}
+
+#[test]
+#[ignore]
+fn test_empty_sector_update_compound_poseidon_sub_8_4() {
+    test_empty_sector_update_compound::<DiskTree<PoseidonHasher, U8, U4, U0>>();
+}
+
+#[test]
+#[ignore]
+fn test_empty_sector_update_compound_poseidon_top_8_4_2() {
+    test_empty_sector_update_compound::<DiskTree<PoseidonHasher, U8, U4, U2>>();
+}
+
+fn test_empty_sector_update_compound<Tree: 'static + MerkleTreeTrait>() {
+    let nodes = 8 * get_base_tree_count::<Tree>();
+    let base_tree_leafs = nodes / get_base_tree_count::<Tree>();
+    let rows_to_discard = default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize());
+    let partition_count = 1;
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let sector_key: Vec<u8> = (0..nodes)
+        .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+        .collect();
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+        .collect();
+
+    let setup_params = compound_proof::SetupParams {
+        vanilla_params: SetupParams {
+            nodes,
+            challenges_count: 2,
+        },
+        partitions: Some(partition_count),
+        priority: false,
+    };
+    let public_params = EmptySectorUpdateCompound::setup(&setup_params).expect("setup failed");
+
+    // Build the trees of the sector key.
+    let sector_key_cache = tempdir().unwrap();
+    let sector_key_path = sector_key_cache.path().join("sector-key");
+    let _sector_key_mmap = setup_replica(&sector_key, &sector_key_path);
+    let tree_r_old = EmptySectorUpdate::<Tree, Sha256Hasher>::build_tree_r_last(
+        nodes,
+        StoreConfig::new(
+            sector_key_cache.path(),
+            CacheKey::CommRLastTree.to_string(),
+            rows_to_discard,
+        ),
+        sector_key_path,
+        &sector_key,
+    )
+    .expect("failed to build tree_r_old");
+
+    let comm_c = <Tree::Hasher as Hasher>::Domain::from(Fr::random(rng));
+    let comm_r_old = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_old.root());
+
+    // Encode the new data.
+    let cache_dir = tempdir().unwrap();
+    let tree_d_new = create_base_merkle_tree::<BinaryMerkleTree<Sha256Hasher>>(
+        Some(StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        )),
+        nodes,
+        &data,
+    )
+    .expect("failed to build tree_d_new");
+    let comm_d_new = tree_d_new.root();
+
+    let replica_path = cache_dir.path().join("replica-path");
+    let mut replica = setup_replica(&vec![0; nodes * NODE_SIZE], &replica_path);
+    let tree_r_new_config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommRLastTree.to_string(),
+        rows_to_discard,
+    );
+    let (_, comm_r_new) = EmptySectorUpdate::<Tree, Sha256Hasher>::encode_into(
+        nodes,
+        tree_r_new_config.clone(),
+        replica_path.clone(),
+        comm_c,
+        comm_r_old,
+        comm_d_new,
+        &sector_key,
+        &data,
+        &mut replica,
+    )
+    .expect("encoding failed");
+    let tree_r_new = EmptySectorUpdate::<Tree, Sha256Hasher>::build_tree_r_last(
+        nodes,
+        tree_r_new_config,
+        replica_path,
+        &replica,
+    )
+    .expect("failed to build tree_r_new");
+
+    let public_inputs = PublicInputs {
+        comm_r_old,
+        comm_d_new,
+        comm_r_new,
+        k: None,
+    };
+
+    let private_inputs = PrivateInputs::<Tree, Sha256Hasher> {
+        comm_c,
+        tree_r_old,
+        tree_r_old_rows_to_discard: rows_to_discard,
+        tree_d_new,
+        tree_r_new,
+        tree_r_new_rows_to_discard: rows_to_discard,
+    };
+
+    {
+        let (circuit, inputs) = EmptySectorUpdateCompound::circuit_for_test(
+            &public_params,
+            &public_inputs,
+            &private_inputs,
+        )
+        .unwrap();
+
+        let mut cs = TestConstraintSystem::new();
+
+        circuit.synthesize(&mut cs).expect("failed to synthesize");
+
+        if !cs.is_satisfied() {
+            panic!(
+                "failed to satisfy: {:?}",
+                cs.which_is_unsatisfied().unwrap()
+            );
+        }
+        assert!(
+            cs.verify(&inputs),
+            "verification failed with TestContraintSystem and generated inputs"
+        );
+    }
+
+    // Use this to debug differences between blank and regular circuit generation.
+    {
+        let (circuit1, _inputs) = EmptySectorUpdateCompound::circuit_for_test(
+            &public_params,
+            &public_inputs,
+            &private_inputs,
+        )
+        .unwrap();
+        let blank_circuit = <EmptySectorUpdateCompound<Tree, Sha256Hasher> as CompoundProof<
+            EmptySectorUpdate<'_, Tree, Sha256Hasher>,
+            _,
+        >>::blank_circuit(&public_params.vanilla_params);
+
+        let mut cs_blank = MetricCS::new();
+        blank_circuit
+            .synthesize(&mut cs_blank)
+            .expect("failed to synthesize");
+
+        let a = cs_blank.pretty_print_list();
+
+        let mut cs1 = TestConstraintSystem::new();
+        circuit1.synthesize(&mut cs1).expect("failed to synthesize");
+        let b = cs1.pretty_print_list();
+
+        for (i, (a, b)) in a.chunks(100).zip(b.chunks(100)).enumerate() {
+            assert_eq!(a, b, "failed at chunk {}", i);
+        }
+    }
+
+    let blank_groth_params = <EmptySectorUpdateCompound<Tree, Sha256Hasher> as CompoundProof<
+        EmptySectorUpdate<'_, Tree, Sha256Hasher>,
+        _,
+    >>::groth_params(Some(rng), &public_params.vanilla_params)
+    .expect("failed to generate groth params");
+
+    let proof = EmptySectorUpdateCompound::prove(
+        &public_params,
+        &public_inputs,
+        &private_inputs,
+        &blank_groth_params,
+    )
+    .expect("failed while proving");
+
+    let verified = EmptySectorUpdateCompound::verify(
+        &public_params,
+        &public_inputs,
+        &proof,
+        &ChallengeRequirements {
+            minimum_challenges: 1,
+        },
+    )
+    .expect("failed while verifying");
+
+    assert!(verified);
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
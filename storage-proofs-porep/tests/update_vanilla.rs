@@ -0,0 +1,173 @@
+use bellperson::bls::Fr;
+use ff::Field;
+use filecoin_hashers::{
+    poseidon::PoseidonHasher, sha256::Sha256Hasher, Domain, HashFunction, Hasher,
+};
+use fr32::fr_into_bytes;
+use generic_array::typenum::{Unsigned, U0, U2, U4, U8};
+use merkletree::store::StoreConfig;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::{
+        create_base_merkle_tree, get_base_tree_count, BinaryMerkleTree, DiskTree, MerkleTreeTrait,
+    },
+    proof::ProofScheme,
+    test_helper::setup_replica,
+    util::{default_rows_to_discard, NODE_SIZE},
+    TEST_SEED,
+};
+use storage_proofs_porep::{
+    stacked::BINARY_ARITY,
+    update::{EmptySectorUpdate, PrivateInputs, PublicInputs, SetupParams},
+};
+use tempfile::tempdir;
+
+#[test]
+fn test_empty_sector_update_poseidon_base_8() {
+    test_empty_sector_update::<DiskTree<PoseidonHasher, U8, U0, U0>>();
+}
+
+#[test]
+fn test_empty_sector_update_poseidon_sub_8_4() {
+    test_empty_sector_update::<DiskTree<PoseidonHasher, U8, U4, U0>>();
+}
+
+#[test]
+fn test_empty_sector_update_poseidon_top_8_4_2() {
+    test_empty_sector_update::<DiskTree<PoseidonHasher, U8, U4, U2>>();
+}
+
+fn test_empty_sector_update<Tree: 'static + MerkleTreeTrait>() {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let nodes = 64 * get_base_tree_count::<Tree>();
+    let base_tree_leafs = nodes / get_base_tree_count::<Tree>();
+    let rows_to_discard = default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize());
+
+    // The sector key is the replica of a sealed empty sector, which are random field elements.
+    let sector_key: Vec<u8> = (0..nodes)
+        .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+        .collect();
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+        .collect();
+
+    let sector_key_cache = tempdir().expect("tempdir failure");
+    let sector_key_path = sector_key_cache.path().join("sector-key");
+    let _sector_key_mmap = setup_replica(&sector_key, &sector_key_path);
+
+    let tree_r_old_config = StoreConfig::new(
+        sector_key_cache.path(),
+        CacheKey::CommRLastTree.to_string(),
+        rows_to_discard,
+    );
+    let tree_r_old = EmptySectorUpdate::<Tree, Sha256Hasher>::build_tree_r_last(
+        nodes,
+        tree_r_old_config,
+        sector_key_path,
+        &sector_key,
+    )
+    .expect("failed to build tree_r_old");
+
+    let comm_c = <Tree::Hasher as Hasher>::Domain::random(rng);
+    let comm_r_old = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_old.root());
+
+    let replica_cache = tempdir().expect("tempdir failure");
+    let tree_d_config = StoreConfig::new(
+        replica_cache.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+    let tree_d_new = create_base_merkle_tree::<BinaryMerkleTree<Sha256Hasher>>(
+        Some(tree_d_config),
+        nodes,
+        &data,
+    )
+    .expect("failed to build tree_d_new");
+    let comm_d_new = tree_d_new.root();
+
+    let replica_path = replica_cache.path().join("replica");
+    let mut replica = setup_replica(&vec![0; nodes * NODE_SIZE], &replica_path);
+
+    let tree_r_new_config = StoreConfig::new(
+        replica_cache.path(),
+        CacheKey::CommRLastTree.to_string(),
+        rows_to_discard,
+    );
+    let (comm_r_last_new, comm_r_new) = EmptySectorUpdate::<Tree, Sha256Hasher>::encode_into(
+        nodes,
+        tree_r_new_config.clone(),
+        replica_path.clone(),
+        comm_c,
+        comm_r_old,
+        comm_d_new,
+        &sector_key,
+        &data,
+        &mut replica,
+    )
+    .expect("encoding failed");
+    assert_ne!(&data[..], &replica[..], "encoding did not change data");
+
+    let mut decoded = vec![0; nodes * NODE_SIZE];
+    EmptySectorUpdate::<Tree, Sha256Hasher>::decode_from(
+        comm_r_old,
+        comm_d_new,
+        &sector_key,
+        &replica,
+        &mut decoded,
+    )
+    .expect("decoding failed");
+    assert_eq!(data, decoded, "failed to decode the new data");
+
+    let tree_r_new = EmptySectorUpdate::<Tree, Sha256Hasher>::build_tree_r_last(
+        nodes,
+        tree_r_new_config,
+        replica_path,
+        &replica,
+    )
+    .expect("failed to build tree_r_new");
+    assert_eq!(tree_r_new.root(), comm_r_last_new);
+
+    let pub_params = EmptySectorUpdate::<Tree, Sha256Hasher>::setup(&SetupParams {
+        nodes,
+        challenges_count: 5,
+    })
+    .expect("setup failed");
+
+    let pub_inputs = PublicInputs {
+        comm_r_old,
+        comm_d_new,
+        comm_r_new,
+        k: Some(0),
+    };
+
+    let priv_inputs = PrivateInputs::<Tree, Sha256Hasher> {
+        comm_c,
+        tree_r_old,
+        tree_r_old_rows_to_discard: rows_to_discard,
+        tree_d_new,
+        tree_r_new,
+        tree_r_new_rows_to_discard: rows_to_discard,
+    };
+
+    let proof =
+        EmptySectorUpdate::<Tree, Sha256Hasher>::prove(&pub_params, &pub_inputs, &priv_inputs)
+            .expect("failed to prove");
+    assert!(
+        EmptySectorUpdate::<Tree, Sha256Hasher>::verify(&pub_params, &pub_inputs, &proof)
+            .expect("failed to verify"),
+        "failed to verify"
+    );
+
+    // The proof must not verify for different data.
+    let wrong_pub_inputs = PublicInputs {
+        comm_d_new: <Sha256Hasher as Hasher>::Domain::random(rng),
+        ..pub_inputs
+    };
+    assert!(
+        !EmptySectorUpdate::<Tree, Sha256Hasher>::verify(&pub_params, &wrong_pub_inputs, &proof)
+            .expect("failed to verify"),
+        "verified the proof for the wrong comm_d_new"
+    );
+}
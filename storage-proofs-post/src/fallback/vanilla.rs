@@ -1,7 +1,7 @@
 use std::collections::BTreeSet;
 use std::marker::PhantomData;
 
-use anyhow::ensure;
+use anyhow::{ensure, Context};
 use bellperson::bls::Fr;
 use byteorder::{ByteOrder, LittleEndian};
 use filecoin_hashers::{Domain, HashFunction, Hasher};
@@ -14,12 +14,13 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use storage_proofs_core::{
     api_version::ApiVersion,
+    cancel::CancellationToken,
     error::{Error, Result},
     merkle::{MerkleProof, MerkleProofTrait, MerkleTreeTrait, MerkleTreeWrapper},
     parameter_cache::ParameterSetMetadata,
     proof::ProofScheme,
     sector::SectorId,
-    util::{default_rows_to_discard, NODE_SIZE},
+    util::NODE_SIZE,
 };
 
 #[derive(Debug, Clone)]
@@ -95,6 +96,8 @@ pub struct PrivateSector<'a, Tree: MerkleTreeTrait> {
     >,
     pub comm_c: <Tree::Hasher as Hasher>::Domain,
     pub comm_r_last: <Tree::Hasher as Hasher>::Domain,
+    /// The number of rows discarded from the cached base trees of `tree`.
+    pub rows_to_discard: usize,
 }
 
 #[derive(Debug)]
@@ -270,7 +273,6 @@ pub fn vanilla_proof<Tree: MerkleTreeTrait>(
     let tree = priv_sector.tree;
 
     let tree_leafs = tree.leafs();
-    let rows_to_discard = default_rows_to_discard(tree_leafs, Tree::Arity::to_usize());
 
     trace!(
         "Generating proof for tree leafs {} and arity {}",
@@ -281,16 +283,7 @@ pub fn vanilla_proof<Tree: MerkleTreeTrait>(
     let inclusion_proofs = (0..challenges.len())
         .into_par_iter()
         .map(|challenged_leaf_index| {
-            let challenged_leaf = challenges[challenged_leaf_index];
-            let proof = tree.gen_cached_proof(challenged_leaf as usize, Some(rows_to_discard))?;
-
-            ensure!(
-                proof.validate(challenged_leaf as usize) && proof.root() == priv_sector.comm_r_last,
-                "Generated vanilla proof for sector {} is invalid",
-                sector_id
-            );
-
-            Ok(proof)
+            challenged_leaf_proof(sector_id, priv_sector, challenges[challenged_leaf_index])
         })
         .collect::<Result<Vec<_>>>()?;
 
@@ -303,6 +296,69 @@ pub fn vanilla_proof<Tree: MerkleTreeTrait>(
     })
 }
 
+/// Generates the same proof as `vanilla_proof`, but reads the challenged leaves one at a time
+/// in ascending order, so that the reads of a sector are sequential on disk. Returns
+/// `Error::Cancelled` if `cancel` is observed before one of the reads.
+pub fn vanilla_proof_sequential<Tree: MerkleTreeTrait>(
+    sector_id: SectorId,
+    priv_inputs: &PrivateInputs<'_, Tree>,
+    challenges: &[u64],
+    cancel: &CancellationToken,
+) -> Result<Proof<Tree::Proof>> {
+    ensure!(
+        priv_inputs.sectors.len() == 1,
+        "vanilla_proof_sequential called with multiple sector proofs"
+    );
+
+    let priv_sector = &priv_inputs.sectors[0];
+
+    let mut order: Vec<usize> = (0..challenges.len()).collect();
+    order.sort_by_key(|&i| challenges[i]);
+
+    let mut proofs = order
+        .into_iter()
+        .map(|i| {
+            cancel.check()?;
+            let proof = challenged_leaf_proof(sector_id, priv_sector, challenges[i])?;
+            Ok((i, proof))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Restore the order of the challenges.
+    proofs.sort_by_key(|(i, _)| *i);
+    let inclusion_proofs = proofs.into_iter().map(|(_, proof)| proof).collect();
+
+    Ok(Proof {
+        sectors: vec![SectorProof {
+            inclusion_proofs,
+            comm_c: priv_sector.comm_c,
+            comm_r_last: priv_sector.comm_r_last,
+        }],
+    })
+}
+
+/// Generates the inclusion proof of a single challenged leaf and checks it against the sector.
+fn challenged_leaf_proof<Tree: MerkleTreeTrait>(
+    sector_id: SectorId,
+    priv_sector: &PrivateSector<'_, Tree>,
+    challenged_leaf: u64,
+) -> Result<MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>> {
+    let proof = priv_sector
+        .tree
+        .gen_cached_proof(challenged_leaf as usize, Some(priv_sector.rows_to_discard))?;
+
+    if !proof.validate(challenged_leaf as usize) || proof.root() != priv_sector.comm_r_last {
+        return Err(Error::InvalidCommitment).with_context(|| {
+            format!(
+                "Generated vanilla proof for sector {} is invalid",
+                sector_id
+            )
+        });
+    }
+
+    Ok(proof)
+}
+
 impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree> {
     type PublicParams = PublicParams;
     type SetupParams = SetupParams;
@@ -381,8 +437,7 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree>
                     let sector_id = pub_sector.id;
                     let tree = priv_sector.tree;
                     let tree_leafs = tree.leafs();
-                    let rows_to_discard =
-                        default_rows_to_discard(tree_leafs, Tree::Arity::to_usize());
+                    let rows_to_discard = priv_sector.rows_to_discard;
 
                     trace!(
                         "Generating proof for tree leafs {} and arity {}",
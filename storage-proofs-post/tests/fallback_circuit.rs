@@ -5,7 +5,7 @@ use bellperson::{
 };
 use ff::Field;
 use filecoin_hashers::{poseidon::PoseidonHasher, Domain, HashFunction, Hasher};
-use generic_array::typenum::{U0, U2, U4, U8};
+use generic_array::typenum::{Unsigned, U0, U2, U4, U8};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
 use storage_proofs_core::{
@@ -14,7 +14,7 @@ use storage_proofs_core::{
     error::Result,
     merkle::{generate_tree, get_base_tree_count, LCTree, MerkleTreeTrait, OctMerkleTree},
     proof::ProofScheme,
-    util::NODE_SIZE,
+    util::{default_rows_to_discard, NODE_SIZE},
     TEST_SEED,
 };
 use storage_proofs_post::fallback::{
@@ -65,6 +65,7 @@ fn test_fallback_post<Tree: 'static + MerkleTreeTrait>(
     let rng = &mut XorShiftRng::from_seed(TEST_SEED);
 
     let leaves = 64 * get_base_tree_count::<Tree>();
+    let rows_to_discard = default_rows_to_discard(64, Tree::Arity::to_usize());
     let sector_size = leaves * NODE_SIZE;
     let randomness = <Tree::Hasher as Hasher>::Domain::random(rng);
     let prover_id = <Tree::Hasher as Hasher>::Domain::random(rng);
@@ -96,6 +97,7 @@ fn test_fallback_post<Tree: 'static + MerkleTreeTrait>(
             tree,
             comm_c,
             comm_r_last,
+            rows_to_discard,
         });
 
         let comm_r = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last);
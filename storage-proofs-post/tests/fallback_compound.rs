@@ -3,7 +3,7 @@ use bellperson::{
     Circuit,
 };
 use filecoin_hashers::{poseidon::PoseidonHasher, Domain, HashFunction, Hasher};
-use generic_array::typenum::{U0, U2, U4, U8};
+use generic_array::typenum::{Unsigned, U0, U2, U4, U8};
 use pretty_assertions::assert_eq;
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
@@ -11,7 +11,7 @@ use storage_proofs_core::{
     api_version::ApiVersion,
     compound_proof::{self, CompoundProof},
     merkle::{generate_tree, get_base_tree_count, LCTree, MerkleTreeTrait},
-    util::NODE_SIZE,
+    util::{default_rows_to_discard, NODE_SIZE},
     TEST_SEED,
 };
 use storage_proofs_post::fallback::{
@@ -73,6 +73,7 @@ fn fallback_post<Tree: 'static + MerkleTreeTrait>(
     let rng = &mut XorShiftRng::from_seed(TEST_SEED);
 
     let leaves = 64 * get_base_tree_count::<Tree>();
+    let rows_to_discard = default_rows_to_discard(64, Tree::Arity::to_usize());
     let sector_size = (leaves * NODE_SIZE) as u64;
     let randomness = <Tree::Hasher as Hasher>::Domain::random(rng);
     let prover_id = <Tree::Hasher as Hasher>::Domain::random(rng);
@@ -108,6 +109,7 @@ fn fallback_post<Tree: 'static + MerkleTreeTrait>(
             tree,
             comm_c,
             comm_r_last,
+            rows_to_discard,
         });
 
         let comm_r = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last);
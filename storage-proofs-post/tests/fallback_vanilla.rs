@@ -1,5 +1,5 @@
 use filecoin_hashers::{poseidon::PoseidonHasher, Domain, HashFunction, Hasher};
-use generic_array::typenum::{U0, U2, U4, U8};
+use generic_array::typenum::{Unsigned, U0, U2, U4, U8};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
 use storage_proofs_core::{
@@ -8,7 +8,7 @@ use storage_proofs_core::{
     merkle::{generate_tree, get_base_tree_count, LCTree, MerkleTreeTrait},
     proof::ProofScheme,
     sector::SectorId,
-    util::NODE_SIZE,
+    util::{default_rows_to_discard, NODE_SIZE},
     TEST_SEED,
 };
 use storage_proofs_post::fallback::{self, FallbackPoSt, PrivateSector, PublicSector};
@@ -121,6 +121,7 @@ fn test_fallback_post<Tree: MerkleTreeTrait>(
     let rng = &mut XorShiftRng::from_seed(TEST_SEED);
 
     let leaves = 64 * get_base_tree_count::<Tree>();
+    let rows_to_discard = default_rows_to_discard(64, Tree::Arity::to_usize());
     let sector_size = leaves * NODE_SIZE;
 
     let pub_params = fallback::PublicParams {
@@ -151,6 +152,7 @@ fn test_fallback_post<Tree: MerkleTreeTrait>(
             tree,
             comm_c,
             comm_r_last,
+            rows_to_discard,
         });
 
         let comm_r = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last);
@@ -292,6 +294,7 @@ fn test_invalid_fallback_post<Tree: MerkleTreeTrait>(
     let rng = &mut XorShiftRng::from_seed(TEST_SEED);
 
     let leaves = 64 * get_base_tree_count::<Tree>();
+    let rows_to_discard = default_rows_to_discard(64, Tree::Arity::to_usize());
     let sector_size = leaves * NODE_SIZE;
 
     let pub_params = fallback::PublicParams {
@@ -333,6 +336,7 @@ fn test_invalid_fallback_post<Tree: MerkleTreeTrait>(
             tree: if make_faulty { &wrong_tree } else { tree },
             comm_c,
             comm_r_last,
+            rows_to_discard,
         });
 
         let comm_r = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last);